@@ -0,0 +1,612 @@
+// Optional local HTTP API (bound to 127.0.0.1 unless other addresses are allowed).
+//
+// Endpoints (with the token scope they need):
+//   GET /         Mobile-friendly page to submit URLs and watch progress (public)
+//   GET /metrics  Prometheus text-format counters for all jobs (read)
+//   GET /status   Running job, progress, queue and last log lines as JSON (read)
+//   POST /jobs    Queues `{"url": ..., "name": ...}` with the app's output settings (submit)
+//   DELETE /jobs/<n>  Removes the queue job at position n of `/status` unless it runs (admin)
+//   POST /cancel  Cancels every running download (admin)
+//
+// Without configured tokens only loopback clients are served, with full access. Once tokens
+// exist, every request needs `Authorization: Bearer <token>` with a scope covering the endpoint.
+// Requests naming a foreign `Host` (DNS rebinding) or coming from another site's page (`Origin`)
+// are refused, and jobs must be posted as `application/json`, which pages can't send cross-site.
+
+use anyhow::{Result, anyhow};
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime::Runtime;
+use tokio::sync::{Semaphore, mpsc};
+use tokio_util::sync::CancellationToken;
+
+use crate::downloader::DownloadMessage;
+use crate::downloader::http::normalize_url;
+use crate::downloader::stats;
+
+pub const DEFAULT_API_PORT: u16 = 8787;
+pub const DEFAULT_API_BIND_ADDRESSES: &str = "127.0.0.1";
+/// Requests larger than this are rejected (only small requests are expected)
+const MAX_REQUEST_BYTES: usize = 8 * 1024;
+/// A connection must send its request and take the response within this time
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+/// Connections served at once; further ones are closed right away
+const MAX_CONNECTIONS: usize = 32;
+const SUBMIT_PAGE: &str = include_str!("./assets/submit.html");
+
+/// What a token may do
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ApiScope {
+    /// Endpoints that add jobs (e.g. a phone-based submit page); nothing can be read or cancelled
+    Submit,
+    /// Read-only endpoints (metrics)
+    #[default]
+    Read,
+    /// Every endpoint, including cancelling downloads and removing queue jobs
+    Admin,
+}
+
+impl ApiScope {
+    pub const ALL: [ApiScope; 3] = [ApiScope::Submit, ApiScope::Read, ApiScope::Admin];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            ApiScope::Submit => "Submit only",
+            ApiScope::Read => "Read only",
+            ApiScope::Admin => "Admin",
+        }
+    }
+
+    fn allows(self, needed: ApiScope) -> bool {
+        self == ApiScope::Admin || self == needed
+    }
+}
+
+/// An access token of the API, persisted in the settings
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiToken {
+    /// Who the token was handed out to, e.g. "phone"
+    pub name: String,
+    pub token: String,
+    pub scope: ApiScope,
+}
+
+impl ApiToken {
+    /// A new token with 128 random bits from the OS.
+    pub fn generate(name: &str, scope: ApiScope) -> Result<Self> {
+        let mut bytes = [0u8; 16];
+        getrandom::fill(&mut bytes).map_err(|e| anyhow!("No OS randomness for a token: {}", e))?;
+        Ok(Self {
+            name: name.to_string(),
+            token: hex::encode(bytes),
+            scope,
+        })
+    }
+
+    /// Compares in constant time, so response timing doesn't reveal how much of a guess matched.
+    fn matches(&self, bearer: &str) -> bool {
+        let (token, bearer) = (self.token.as_bytes(), bearer.as_bytes());
+        token.len() == bearer.len()
+            && token
+                .iter()
+                .zip(bearer)
+                .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+                == 0
+    }
+}
+
+/// Where and for whom the API is served
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApiConfig {
+    pub port: u16,
+    pub bind_addresses: Vec<IpAddr>,
+    pub tokens: Vec<ApiToken>,
+}
+
+impl ApiConfig {
+    /// `addresses` is a comma-separated allowlist of local addresses to listen on.
+    pub fn new(port: u16, addresses: &str, tokens: &[ApiToken]) -> Result<Self> {
+        let bind_addresses = addresses
+            .split(',')
+            .map(str::trim)
+            .filter(|address| !address.is_empty())
+            .map(|address| {
+                address
+                    .parse()
+                    .map_err(|_| anyhow!("Invalid API bind address: {}", address))
+            })
+            .collect::<Result<Vec<IpAddr>>>()?;
+        if bind_addresses.is_empty() {
+            return Err(anyhow!("No API bind address is allowed"));
+        }
+        Ok(Self {
+            port,
+            bind_addresses,
+            tokens: tokens.to_vec(),
+        })
+    }
+
+    /// Base URLs the API is reachable at.
+    pub fn urls(&self) -> Vec<String> {
+        self.bind_addresses
+            .iter()
+            .map(|address| format!("http://{}", SocketAddr::new(*address, self.port)))
+            .collect()
+    }
+
+    /// Whether `host` (a `Host` header value, `address:port`) names this server: one of the
+    /// bound addresses, any IP address when bound to all interfaces, or `localhost` on loopback.
+    fn serves_host(&self, host: &str) -> bool {
+        let Ok(url) = Url::parse(&format!("http://{}", host)) else {
+            return false;
+        };
+        if url.port_or_known_default() != Some(self.port) || url.path() != "/" {
+            return false;
+        }
+        let Some(name) = url.host_str() else {
+            return false;
+        };
+        let Ok(ip) = name.trim_matches(['[', ']']).parse::<IpAddr>() else {
+            return name.eq_ignore_ascii_case("localhost")
+                && self.bind_addresses.iter().any(IpAddr::is_loopback);
+        };
+        self.bind_addresses
+            .iter()
+            .any(|bound| *bound == ip || bound.is_unspecified())
+    }
+
+    /// Scope of a request from `peer`, or None if it may not use the API at all.
+    fn scope_of(&self, peer: IpAddr, bearer: Option<&str>) -> Option<ApiScope> {
+        if self.tokens.is_empty() {
+            return peer.is_loopback().then_some(ApiScope::Admin);
+        }
+        let bearer = bearer?;
+        self.tokens
+            .iter()
+            .find(|token| token.matches(bearer))
+            .map(|token| token.scope)
+    }
+}
+
+/// Status the API reports at `/status`; the app refreshes it while the server runs
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ApiStatus {
+    pub running: bool,
+    /// 0.0 to 1.0
+    pub progress: f32,
+    /// Name of the running download
+    pub current: Option<String>,
+    pub queue: Vec<ApiQueueJob>,
+    /// Last lines of the log
+    pub log: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ApiQueueJob {
+    pub name: String,
+    pub state: String,
+}
+
+/// Body of `POST /jobs`
+#[derive(Debug, Deserialize)]
+struct SubmitRequest {
+    url: String,
+    #[serde(default)]
+    name: String,
+}
+
+/// State the connections share with the app
+struct Shared {
+    config: ApiConfig,
+    status: Mutex<ApiStatus>,
+    /// Submitted jobs go to the app as `DownloadMessage::ApiJobSubmitted`
+    submit: mpsc::Sender<DownloadMessage>,
+    /// Limits the connections served at once to `MAX_CONNECTIONS`
+    connections: Arc<Semaphore>,
+}
+
+/// Running API server; stops listening when dropped.
+pub struct ApiServer {
+    shared: Arc<Shared>,
+    shutdown: CancellationToken,
+}
+
+impl ApiServer {
+    /// Binds every allowed address on the port and serves requests on the given runtime.
+    pub fn start(
+        runtime: &Runtime,
+        config: ApiConfig,
+        submit: mpsc::Sender<DownloadMessage>,
+    ) -> Result<Self> {
+        let _guard = runtime.enter();
+        let mut listeners = Vec::with_capacity(config.bind_addresses.len());
+        for address in &config.bind_addresses {
+            let socket = SocketAddr::new(*address, config.port);
+            let std_listener = std::net::TcpListener::bind(socket)
+                .map_err(|e| anyhow!("Failed to bind {}: {}", socket, e))?;
+            std_listener.set_nonblocking(true)?;
+            listeners.push(TcpListener::from_std(std_listener)?);
+        }
+
+        let shared = Arc::new(Shared {
+            config,
+            status: Mutex::new(ApiStatus::default()),
+            submit,
+            connections: Arc::new(Semaphore::new(MAX_CONNECTIONS)),
+        });
+        let shutdown = CancellationToken::new();
+        for listener in listeners {
+            runtime.spawn(serve(listener, shared.clone(), shutdown.clone()));
+        }
+        Ok(Self { shared, shutdown })
+    }
+
+    pub fn config(&self) -> &ApiConfig {
+        &self.shared.config
+    }
+
+    /// Replaces the status served at `/status`.
+    pub fn publish(&self, status: ApiStatus) {
+        *self.shared.status.lock().unwrap_or_else(|e| e.into_inner()) = status;
+    }
+}
+
+impl Drop for ApiServer {
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+    }
+}
+
+async fn serve(listener: TcpListener, shared: Arc<Shared>, shutdown: CancellationToken) {
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => return,
+            accepted = listener.accept() => {
+                let Ok((stream, peer)) = accepted else {
+                    continue;
+                };
+                // Over the limit the connection is dropped unanswered
+                let Ok(permit) = shared.connections.clone().try_acquire_owned() else {
+                    continue;
+                };
+                let shared = shared.clone();
+                tokio::spawn(async move {
+                    // A client that sends or reads slowly (slowloris) is cut off
+                    let _ = tokio::time::timeout(
+                        CONNECTION_TIMEOUT,
+                        handle_connection(stream, peer.ip(), shared),
+                    )
+                    .await;
+                    drop(permit);
+                });
+            }
+        }
+    }
+}
+
+/// Status and message for a request another site could have made through the user's browser:
+/// a `Host` that isn't this server, an `Origin` of another site, or a job that isn't JSON.
+fn reject_foreign<'a>(
+    config: &ApiConfig,
+    method: &str,
+    path: &str,
+    header: impl Fn(&str) -> Option<&'a str>,
+) -> Option<(&'static str, &'static str)> {
+    if !header("host").is_some_and(|host| config.serves_host(host)) {
+        return Some(("421 Misdirected Request", "Unknown Host\n"));
+    }
+    if let Some(origin) = header("origin")
+        && !origin
+            .strip_prefix("http://")
+            .is_some_and(|host| config.serves_host(host))
+    {
+        return Some(("403 Forbidden", "Cross-origin requests are not allowed\n"));
+    }
+    if (method, path) == ("POST", "/jobs")
+        && !header("content-type").is_some_and(|value| {
+            value
+                .split(';')
+                .next()
+                .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("application/json"))
+        })
+    {
+        return Some((
+            "415 Unsupported Media Type",
+            "Jobs must be sent as application/json\n",
+        ));
+    }
+    None
+}
+
+/// Scope an endpoint needs; None for public or unknown endpoints.
+fn required_scope(method: &str, path: &str) -> Option<ApiScope> {
+    match (method, path) {
+        ("GET", "/metrics") | ("GET", "/status") => Some(ApiScope::Read),
+        ("POST", "/jobs") => Some(ApiScope::Submit),
+        ("POST", "/cancel") => Some(ApiScope::Admin),
+        ("DELETE", path) if path.starts_with("/jobs/") => Some(ApiScope::Admin),
+        _ => None,
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, peer: IpAddr, shared: Arc<Shared>) {
+    let mut buf = vec![0u8; MAX_REQUEST_BYTES];
+    let mut len = 0;
+    // Read until the end of the request headers
+    let header_end = loop {
+        if let Some(end) = buf[..len].windows(4).position(|w| w == b"\r\n\r\n") {
+            break end + 4;
+        }
+        if len == buf.len() {
+            respond(&mut stream, "413 Payload Too Large", "text/plain", "").await;
+            return;
+        }
+        match stream.read(&mut buf[len..]).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => len += n,
+        }
+    };
+
+    let head = String::from_utf8_lossy(&buf[..header_end]).into_owned();
+    let mut request_line = head.lines().next().unwrap_or("").split_whitespace();
+    let method = request_line.next().unwrap_or("");
+    let path = request_line.next().unwrap_or("");
+    let header = |wanted: &str| {
+        head.lines().skip(1).find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.trim()
+                .eq_ignore_ascii_case(wanted)
+                .then_some(value.trim())
+        })
+    };
+    if let Some((status, message)) = reject_foreign(&shared.config, method, path, header) {
+        respond(&mut stream, status, "text/plain", message).await;
+        return;
+    }
+    let bearer = header("authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim);
+
+    if let Some(needed) = required_scope(method, path) {
+        let Some(scope) = shared.config.scope_of(peer, bearer) else {
+            respond(
+                &mut stream,
+                "401 Unauthorized",
+                "text/plain",
+                "Unauthorized\n",
+            )
+            .await;
+            return;
+        };
+        if !scope.allows(needed) {
+            respond(&mut stream, "403 Forbidden", "text/plain", "Forbidden\n").await;
+            return;
+        }
+    }
+
+    // The body (POST /jobs) follows the headers
+    let content_length: usize = header("content-length")
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0);
+    if header_end + content_length > buf.len() {
+        respond(&mut stream, "413 Payload Too Large", "text/plain", "").await;
+        return;
+    }
+    while len < header_end + content_length {
+        match stream.read(&mut buf[len..]).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => len += n,
+        }
+    }
+    let body = &buf[header_end..header_end + content_length];
+
+    match (method, path) {
+        ("GET", "/") => {
+            respond(
+                &mut stream,
+                "200 OK",
+                "text/html; charset=utf-8",
+                SUBMIT_PAGE,
+            )
+            .await;
+        }
+        ("GET", "/metrics") => {
+            let body = stats::global().snapshot().to_prometheus();
+            respond(
+                &mut stream,
+                "200 OK",
+                "text/plain; version=0.0.4; charset=utf-8",
+                &body,
+            )
+            .await;
+        }
+        ("GET", "/status") => {
+            let status = shared
+                .status
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone();
+            let body = serde_json::to_string(&status).unwrap_or_default();
+            respond(&mut stream, "200 OK", "application/json", &body).await;
+        }
+        ("POST", "/jobs") => {
+            let request = match serde_json::from_slice::<SubmitRequest>(body) {
+                Ok(request) => request,
+                Err(e) => {
+                    let message = format!("Invalid request: {}\n", e);
+                    respond(&mut stream, "400 Bad Request", "text/plain", &message).await;
+                    return;
+                }
+            };
+            let url = match normalize_url(request.url.trim()) {
+                Ok(url) => url,
+                Err(e) => {
+                    let message = format!("{}\n", e);
+                    respond(&mut stream, "400 Bad Request", "text/plain", &message).await;
+                    return;
+                }
+            };
+            let message = DownloadMessage::ApiJobSubmitted {
+                url,
+                name: request.name.trim().to_string(),
+            };
+            forward(&mut stream, &shared, message, "Queued\n").await
+        }
+        ("POST", "/cancel") => {
+            forward(
+                &mut stream,
+                &shared,
+                DownloadMessage::ApiCancelRequested,
+                "Cancelling\n",
+            )
+            .await
+        }
+        ("DELETE", path) if path.starts_with("/jobs/") => {
+            let Ok(position) = path["/jobs/".len()..].parse() else {
+                respond(
+                    &mut stream,
+                    "400 Bad Request",
+                    "text/plain",
+                    "Expected /jobs/<position>\n",
+                )
+                .await;
+                return;
+            };
+            let message = DownloadMessage::ApiJobRemoved { position };
+            forward(&mut stream, &shared, message, "Removing\n").await
+        }
+        ("GET", _) => respond(&mut stream, "404 Not Found", "text/plain", "Not Found\n").await,
+        _ => {
+            respond(
+                &mut stream,
+                "405 Method Not Allowed",
+                "text/plain",
+                "Method Not Allowed\n",
+            )
+            .await
+        }
+    }
+}
+
+/// Hands a request over to the app and answers `202 Accepted` with `accepted`.
+async fn forward(
+    stream: &mut TcpStream,
+    shared: &Shared,
+    message: DownloadMessage,
+    accepted: &str,
+) {
+    match shared.submit.send(message).await {
+        Ok(()) => respond(stream, "202 Accepted", "text/plain", accepted).await,
+        Err(_) => {
+            respond(
+                stream,
+                "503 Service Unavailable",
+                "text/plain",
+                "The app is shutting down\n",
+            )
+            .await
+        }
+    }
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await.ok();
+    stream.shutdown().await.ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn config() -> ApiConfig {
+        ApiConfig::new(8787, "127.0.0.1, 192.168.1.20", &[]).unwrap()
+    }
+
+    /// `reject_foreign` for a request with the given headers (names in lower case).
+    fn check(method: &str, path: &str, headers: &[(&str, &str)]) -> Option<&'static str> {
+        let headers: HashMap<_, _> = headers.iter().copied().collect();
+        reject_foreign(&config(), method, path, |name| headers.get(name).copied())
+            .map(|(status, _)| status)
+    }
+
+    const JSON: (&str, &str) = ("content-type", "application/json");
+
+    #[test]
+    fn accepts_same_origin_json_jobs() {
+        let headers = [
+            ("host", "127.0.0.1:8787"),
+            ("origin", "http://127.0.0.1:8787"),
+            JSON,
+        ];
+        assert_eq!(check("POST", "/jobs", &headers), None);
+        assert_eq!(check("GET", "/status", &[("host", "localhost:8787")]), None);
+        assert_eq!(check("GET", "/", &[("host", "192.168.1.20:8787")]), None);
+    }
+
+    #[test]
+    fn rejects_foreign_hosts() {
+        // DNS rebinding: the browser sends the attacker's name as Host
+        let rebound = [("host", "evil.example:8787"), JSON];
+        assert_eq!(
+            check("POST", "/jobs", &rebound),
+            Some("421 Misdirected Request")
+        );
+        assert!(check("GET", "/status", &[("host", "127.0.0.1:9999")]).is_some());
+        assert!(check("GET", "/status", &[("host", "10.0.0.1:8787")]).is_some());
+        assert!(check("GET", "/status", &[]).is_some());
+    }
+
+    #[test]
+    fn rejects_cross_origin_requests() {
+        let headers = [
+            ("host", "127.0.0.1:8787"),
+            ("origin", "https://evil.example"),
+            JSON,
+        ];
+        assert_eq!(check("POST", "/jobs", &headers), Some("403 Forbidden"));
+        let opaque = [("host", "127.0.0.1:8787"), ("origin", "null")];
+        assert_eq!(check("POST", "/cancel", &opaque), Some("403 Forbidden"));
+    }
+
+    #[test]
+    fn rejects_jobs_that_are_not_json() {
+        // A cross-site "simple" request can only carry text/plain, form or multipart bodies
+        let headers = [("host", "127.0.0.1:8787"), ("content-type", "text/plain")];
+        assert_eq!(
+            check("POST", "/jobs", &headers),
+            Some("415 Unsupported Media Type")
+        );
+        assert!(check("POST", "/jobs", &[("host", "127.0.0.1:8787")]).is_some());
+        let with_charset = [
+            ("host", "127.0.0.1:8787"),
+            ("content-type", "application/json; charset=utf-8"),
+        ];
+        assert_eq!(check("POST", "/jobs", &with_charset), None);
+    }
+
+    #[test]
+    fn tokens_are_random_and_compared_exactly() {
+        let token = ApiToken::generate("phone", ApiScope::Submit).unwrap();
+        assert_eq!(token.token.len(), 32);
+        assert_ne!(
+            token.token,
+            ApiToken::generate("phone", ApiScope::Submit).unwrap().token
+        );
+        assert!(token.matches(&token.token.clone()));
+        assert!(!token.matches(&token.token[..31]));
+        assert!(!token.matches(""));
+    }
+}
@@ -0,0 +1,788 @@
+// Command-line representation of a download job.
+// The flags produced here are the same ones accepted by the headless mode.
+
+use anyhow::{Result, anyhow, bail};
+use std::path::{Path, PathBuf};
+
+use crate::config_file;
+use crate::downloader::hls_parser::{ClipWindow, SampleLength, TimeRange, TrackMetadata};
+use crate::downloader::http::normalize_url;
+use crate::downloader::limits::PlaylistLimits;
+use crate::downloader::live::LiveStart;
+use crate::downloader::preview::PreviewKind;
+use crate::downloader::subtitles::SubtitleMode;
+use crate::downloader::url_rewrite::RewriteRule;
+use crate::downloader::util::{
+    DEFAULT_OUTPUT_NAME, expand_output_subfolder, expand_output_template,
+};
+use crate::downloader::{DEFAULT_CONCURRENT_DOWNLOADS, DownloadConfig};
+use crate::runtime::RuntimeTuning;
+
+pub const BIN_NAME: &str = "hls-downloader";
+
+/// Stands in for the key in generated command lines; the user fills in `HLSDL_KEY`
+const REDACTED_KEY: &str = "HLSDL_KEY='<key hex>'";
+
+pub const USAGE: &str = "\
+Usage: hls-downloader [OPTIONS] <PLAYLIST_URL>
+       <playlist> | hls-downloader [OPTIONS] --base-url <URL>
+       hls-downloader            (no arguments: start the GUI)
+
+PLAYLIST_URL without a scheme (example.com/video.m3u8) is completed to https://.
+With --base-url and no PLAYLIST_URL (or PLAYLIST_URL `-`), the playlist is read from stdin.
+
+Options:
+      --output-dir <DIR>        Directory for the output file [default: .]
+  -o, --output-name <NAME>      Output file name; may use {name}, {host}, {date} [default: output]
+      --subfolder <TEMPLATE>    Sub-folder of the output dir created on demand, e.g.
+                                {host}/{yyyy-mm} (also {date}, {yyyy}, {mm}, {name})
+      --format <FORMAT>         mp4, mkv, webm or ts [default: mp4]
+      --concurrency <N>         Parallel segment downloads of this job [default: 4]
+  -H, --header <NAME: VALUE>    Extra request header (repeatable)
+      --proxy <URL>             Proxy for all requests (overrides HTTP(S)_PROXY)
+      --propagate-query         Add the playlist URL's query (?token=...) to segment/key URLs
+                                on the same host
+      --key-file <PATH>         Decryption key to use instead of downloading the key file
+                                (raw 16/32 bytes or hex text)
+      --key <HEX>               Same, given inline (visible in the process list; prefer
+                                --key-file or HLSDL_KEY)
+      --iv <HEX>                IV to use instead of the playlist's
+      --max-segments <N>        Refuse playlists with more segments [default: 200000]
+      --max-playlist-bytes <N>  Refuse larger playlist responses [default: 67108864]
+      --max-keys <N>            Refuse playlists rotating through more keys [default: 1000]
+      --accept-content-type <T> Accepted segment Content-Type, e.g. video/* (repeatable);
+                                others are rejected [default: any, warning outside video/*,
+                                audio/*, application/octet-stream, text/vnd.trolltech.linguist]
+      --rewrite-url <RULE>      Rewrite segment URLs before fetching, as `REGEX => REPLACEMENT`
+                                ($1 inserts a capture group; repeatable, applied in order)
+      --playlist-text <TEXT>    Playlist content; PLAYLIST_URL is then only the base URL
+      --base-url <URL>          URL the playlist on stdin (or --playlist-text) is resolved
+                                against, e.g. https://cdn.example.com/show/index.m3u8
+      --subtitles <URL|FILE>    Add a subtitle track
+      --burn-subtitles          Burn the subtitles into the video instead of soft-muxing
+      --subtitle-sidecar <vtt|srt>
+                                Save the subtitles next to the video instead (without
+                                --subtitles: the master playlist's subtitle rendition)
+      --fix-timestamps          Repair PTS/DTS discontinuities
+      --fill-gaps               Insert black video/silence for #EXT-X-GAP segments
+      --skip-ads                Leave out ad breaks (SCTE-35 DATERANGE, CUE-OUT/CUE-IN)
+      --exclude-segments <LIST> Skip segments by media sequence number, e.g. 0-3,17
+      --clip-start <TIME>       Only the part from this wall-clock time, by
+                                #EXT-X-PROGRAM-DATE-TIME (e.g. 2024-05-01T20:00:00Z)
+      --clip-end <TIME>         Only the part before this time (a live recording stops there)
+      --start-time <POS>        Only the segments from this position, e.g. 1:02:30
+      --end-time <POS>          Only the segments up to this position
+      --exact-trim              Cut the output exactly at --start-time/--end-time (re-encodes)
+      --reencode                Re-encode when the format cannot hold the stream's codecs
+                                (e.g. H.264/AAC to webm: VP9/Opus) instead of failing
+      --sample <N|Ns>           Only the first N segments (or N seconds), saved as
+                                <name>.sample.<format>, to check the stream first
+      --keep-workspace          Keep segments and a manifest for later repair
+      --normalize-audio         Two-pass loudness normalization
+      --extract-captions        Save CEA-608/708 closed captions as <name>.cc.srt
+      --verify-concat           Verify every chunk after concatenation
+      --report <FILE>           Write per-segment timings (.json for JSON, CSV otherwise)
+      --on-complete <CMD>       Run a shell command after the download (job in HLSDL_* vars)
+      --webhook <URL>           POST the job context as JSON after the download
+      --stream-to-player        Serve the download as a live TS on localhost
+      --live                    Record a live playlist until it ends (Ctrl+C stops and keeps it)
+      --append                  With --live, continue the earlier recording in the workspace
+      --live-start <window|edge>
+                                With --live, start at the oldest listed segment (window) or
+                                at the newest one (edge) [default: window]
+      --patient                 With --live, pause through origin outages (5xx, timeouts)
+                                instead of finishing the recording
+      --quality <Q>             Master playlist variant: best, worst, 720p or list number
+      --audio <NAME|LANG|URL>   Alternative audio rendition (or audio playlist URL)
+      --subs <NAME|LANG>        Subtitle rendition of a master playlist
+      --audio-track <META>      Track metadata for the --audio playlist, e.g.
+                                language=ja,default,name=Japanese
+      --subtitle-track <META>   Track metadata for the --subtitles track (same form)
+      --background-remux        In a queue, remux while the next job downloads (a single
+                                run always waits for the remux)
+  -y, --yes                     Never prompt; use --quality or the best variant
+      --inspect                 Dry run: parse the playlist without downloading media; the
+                                report goes to stderr, the segment URLs to stdout
+      --preview <strip|clip>    Only render a preview from the master playlist's I-frame
+                                playlist: <name>.preview.jpg (strip) or .mp4 (clip)
+      --error-json <FILE>       On failure, write {exit_code, kind, message} as JSON
+      --diagnostics <FILE>      When the job ends, write a diagnostics bundle for bug reports
+                                (version, job without credentials, error summary, log)
+      --background-priority     Run at low CPU/disk priority (nice/ionice, background QoS)
+      --worker-threads <N>      Async runtime worker threads [default: one per CPU core]
+      --blocking-threads <N>    Upper bound of the blocking pool (decryption, disk IO)
+                                [default: 512]
+      --config <FILE>           Defaults file [default: <config dir>/hls-downloader/hlsdl.toml]
+      --profile <NAME>          Apply the [profiles.NAME] section of the config file
+      --completions <SHELL>     Print a completion script (bash, zsh, fish, powershell)
+  -h, --help                    Print this help
+  -V, --version                 Print the version
+
+Environment:
+  HTTP_PROXY, HTTPS_PROXY, ALL_PROXY, NO_PROXY   Proxy settings
+  HLSDL_UA                                        Default User-Agent header
+  HLSDL_KEY                                       Decryption key (hex), like --key
+
+Exit codes:
+  0 success, 1 other failure, 2 invalid arguments, 3 network, 4 decryption,
+  5 FFmpeg, 6 invalid playlist, 130 cancelled
+";
+
+/// What the command line asks for
+pub enum CliCommand {
+    Help,
+    Version,
+    Download(Box<DownloadConfig>, CliOptions),
+    /// Print the completion script for a shell
+    Completions(String),
+}
+
+/// Headless-only options that are not part of the job itself
+#[derive(Debug, Default)]
+pub struct CliOptions {
+    /// Rendition choices for a master playlist, resolved once the playlist has been fetched
+    pub audio: Option<String>,
+    pub subs: Option<String>,
+    /// Skip the interactive picker
+    pub assume_yes: bool,
+    /// Render a quick preview instead of downloading
+    pub preview: Option<PreviewKind>,
+    /// Only parse the playlist and list its segments (dry run)
+    pub inspect: bool,
+    /// Where to write a JSON failure report
+    pub error_json: Option<PathBuf>,
+    /// Where to write the diagnostics bundle once the job ends
+    pub diagnostics: Option<PathBuf>,
+    /// Lower the process priority for the run
+    pub background_priority: bool,
+    /// Thread counts of the tokio runtime
+    pub runtime: RuntimeTuning,
+    /// PLAYLIST_URL had no scheme and `https://` was added
+    pub scheme_added: bool,
+    /// The playlist content comes from stdin (`-` or `--base-url` alone)
+    pub playlist_from_stdin: bool,
+}
+
+/// Value following `name` in raw arguments, for options needed before full parsing.
+pub fn flag_value(args: &[String], name: &str) -> Option<String> {
+    args.iter()
+        .position(|arg| arg == name)
+        .and_then(|i| args.get(i + 1))
+        .cloned()
+}
+
+/// Parses the arguments of a headless run (without the program name).
+///
+/// Defaults come from the config file (see `config_file`); command-line flags override them.
+pub fn parse_args(args: Vec<String>) -> Result<CliCommand> {
+    let mut config = DownloadConfig {
+        playlist_url: String::new(),
+        output_location: ".".to_string(),
+        output_filename: DEFAULT_OUTPUT_NAME.to_string(),
+        concurrency: DEFAULT_CONCURRENT_DOWNLOADS as usize,
+        output_format: "mp4".to_string(),
+        headers: Vec::new(),
+        fix_timestamps: false,
+        fill_gaps: false,
+        skip_ads: false,
+        propagate_query: false,
+        excluded_segments: Vec::new(),
+        background_remux: false,
+        keep_workspace: false,
+        playlist_text: None,
+        normalize_audio: false,
+        extract_captions: false,
+        verify_concat: false,
+        subtitle_url: None,
+        subtitle_mode: SubtitleMode::SoftMux,
+        subtitle_track: None,
+        stream_to_player: false,
+        quality: None,
+        audio_url: None,
+        audio_track: None,
+        proxy: None,
+        live: false,
+        append_recording: false,
+        live_start: LiveStart::default(),
+        patient_live: false,
+        clip_start: None,
+        clip_end: None,
+        time_start: None,
+        time_end: None,
+        exact_trim: false,
+        sample: None,
+        reencode: false,
+        accepted_content_types: Vec::new(),
+        url_rewrites: Vec::new(),
+        report_path: None,
+        key_hex: None,
+        iv_hex: None,
+        on_complete: None,
+        webhook_url: None,
+        output_subfolder: None,
+        limits: PlaylistLimits::default(),
+    };
+    let skips_config = ["-h", "--help", "--completions"];
+    if !args.iter().any(|arg| skips_config.contains(&arg.as_str())) {
+        let config_path = flag_value(&args, "--config").map(PathBuf::from);
+        let profile = flag_value(&args, "--profile");
+        config_file::load(config_path.as_deref(), profile.as_deref())?.apply(&mut config);
+    }
+    let mut cli_headers = Vec::new();
+    let mut cli_content_types = Vec::new();
+    let mut cli_rewrites = Vec::new();
+    let mut options = CliOptions::default();
+    let mut burn_subtitles = false;
+    let mut sidecar = None;
+    let mut url = None;
+    let mut base_url = None;
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| anyhow!("Missing value for {}", name))
+        };
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(CliCommand::Help),
+            "-V" | "--version" => return Ok(CliCommand::Version),
+            "--completions" => return Ok(CliCommand::Completions(value(&arg)?.to_lowercase())),
+            "--output-dir" => config.output_location = value(&arg)?,
+            "-o" | "--output-name" => config.output_filename = value(&arg)?,
+            "--subfolder" => config.output_subfolder = Some(value(&arg)?),
+            "--format" => config.output_format = value(&arg)?.to_lowercase(),
+            "--concurrency" => {
+                config.concurrency = value(&arg)?
+                    .parse()
+                    .map_err(|e| anyhow!("Invalid --concurrency: {}", e))?
+            }
+            "-H" | "--header" => {
+                let header = value(&arg)?;
+                let (name, value) = header.split_once(':').ok_or_else(|| {
+                    anyhow!("Invalid header (expected `Name: value`): {}", header)
+                })?;
+                cli_headers.push((name.trim().to_string(), value.trim().to_string()));
+            }
+            "--proxy" => config.proxy = Some(value(&arg)?),
+            "--key" => config.key_hex = Some(value(&arg)?),
+            "--key-file" => config.key_hex = Some(read_key_file(Path::new(&value(&arg)?))?),
+            "--iv" => config.iv_hex = Some(value(&arg)?),
+            "--accept-content-type" => cli_content_types.push(value(&arg)?),
+            "--rewrite-url" => cli_rewrites.push(RewriteRule::parse(&value(&arg)?)?),
+            "--max-segments" => {
+                config.limits.max_segments = value(&arg)?
+                    .parse()
+                    .map_err(|e| anyhow!("Invalid --max-segments: {}", e))?
+            }
+            "--max-playlist-bytes" => {
+                config.limits.max_playlist_bytes = value(&arg)?
+                    .parse()
+                    .map_err(|e| anyhow!("Invalid --max-playlist-bytes: {}", e))?
+            }
+            "--max-keys" => {
+                config.limits.max_keys = value(&arg)?
+                    .parse()
+                    .map_err(|e| anyhow!("Invalid --max-keys: {}", e))?
+            }
+            // Already applied before parsing
+            "--config" | "--profile" => {
+                value(&arg)?;
+            }
+            "--playlist-text" => config.playlist_text = Some(value(&arg)?),
+            "--base-url" => base_url = Some(value(&arg)?),
+            "--subtitles" => config.subtitle_url = Some(value(&arg)?),
+            "--burn-subtitles" => burn_subtitles = true,
+            "--subtitle-sidecar" => {
+                sidecar = Some(match value(&arg)?.to_lowercase().as_str() {
+                    "vtt" => SubtitleMode::SidecarVtt,
+                    "srt" => SubtitleMode::SidecarSrt,
+                    other => bail!("Unsupported --subtitle-sidecar: {} (vtt or srt)", other),
+                })
+            }
+            "--fix-timestamps" => config.fix_timestamps = true,
+            "--fill-gaps" => config.fill_gaps = true,
+            "--skip-ads" => config.skip_ads = true,
+            "--propagate-query" => config.propagate_query = true,
+            "--exclude-segments" => config.excluded_segments = parse_index_list(&value(&arg)?)?,
+            "--clip-start" => config.clip_start = Some(value(&arg)?),
+            "--clip-end" => config.clip_end = Some(value(&arg)?),
+            "--start-time" => config.time_start = Some(value(&arg)?),
+            "--end-time" => config.time_end = Some(value(&arg)?),
+            "--exact-trim" => config.exact_trim = true,
+            "--reencode" => config.reencode = true,
+            "--sample" => {
+                let length = value(&arg)?;
+                config.sample = Some(
+                    SampleLength::parse(&length)
+                        .ok_or_else(|| anyhow!("Invalid --sample: {} (e.g. 3 or 30s)", length))?,
+                );
+            }
+            "--keep-workspace" => config.keep_workspace = true,
+            "--normalize-audio" => config.normalize_audio = true,
+            "--extract-captions" => config.extract_captions = true,
+            "--verify-concat" => config.verify_concat = true,
+            "--report" => config.report_path = Some(value(&arg)?),
+            "--on-complete" => config.on_complete = Some(value(&arg)?),
+            "--webhook" => config.webhook_url = Some(value(&arg)?),
+            "--stream-to-player" => config.stream_to_player = true,
+            "--live" => config.live = true,
+            "--append" => config.append_recording = true,
+            "--patient" => config.patient_live = true,
+            "--live-start" => {
+                let start = value(&arg)?;
+                config.live_start = LiveStart::parse(&start).ok_or_else(|| {
+                    anyhow!("Unsupported --live-start: {} (window or edge)", start)
+                })?;
+            }
+            "--quality" => config.quality = Some(value(&arg)?),
+            "--audio" => {
+                let audio = value(&arg)?;
+                if audio.contains("://") {
+                    config.audio_url = Some(audio);
+                } else {
+                    options.audio = Some(audio);
+                }
+            }
+            "--audio-track" => config.audio_track = Some(track_metadata(&arg, &value(&arg)?)?),
+            "--subtitle-track" => {
+                config.subtitle_track = Some(track_metadata(&arg, &value(&arg)?)?)
+            }
+            "--background-remux" => config.background_remux = true,
+            "--subs" => options.subs = Some(value(&arg)?),
+            "-y" | "--yes" => options.assume_yes = true,
+            "--inspect" => options.inspect = true,
+            "--preview" => {
+                let kind = value(&arg)?;
+                options.preview =
+                    Some(PreviewKind::parse(&kind).ok_or_else(|| {
+                        anyhow!("Unsupported --preview: {} (strip or clip)", kind)
+                    })?);
+            }
+            "--error-json" => options.error_json = Some(PathBuf::from(value(&arg)?)),
+            "--diagnostics" => options.diagnostics = Some(PathBuf::from(value(&arg)?)),
+            "--background-priority" => options.background_priority = true,
+            "--worker-threads" => {
+                options.runtime.worker_threads = value(&arg)?
+                    .parse()
+                    .map_err(|e| anyhow!("Invalid --worker-threads: {}", e))?
+            }
+            "--blocking-threads" => {
+                options.runtime.max_blocking_threads = value(&arg)?
+                    .parse()
+                    .map_err(|e| anyhow!("Invalid --blocking-threads: {}", e))?
+            }
+            flag if flag.starts_with('-') && flag.len() > 1 => bail!("Unknown option: {}", flag),
+            _ if url.is_none() => url = Some(arg),
+            _ => bail!("Unexpected argument: {}", arg),
+        }
+    }
+
+    // `-` (or --base-url alone) reads the playlist from stdin; the base URL takes its place
+    let from_stdin = url.as_deref() == Some("-")
+        || (url.is_none() && base_url.is_some() && config.playlist_text.is_none());
+    if from_stdin && config.playlist_text.is_some() {
+        bail!("--playlist-text and a playlist on stdin (-) cannot be combined");
+    }
+    options.playlist_from_stdin = from_stdin;
+    let url = match (url.filter(|url| url != "-"), base_url) {
+        (Some(_), Some(_)) => bail!("Give either PLAYLIST_URL or --base-url, not both"),
+        (Some(url), None) | (None, Some(url)) => url,
+        (None, None) if from_stdin => {
+            bail!("Reading the playlist from stdin needs --base-url to resolve segment URLs")
+        }
+        (None, None) => bail!("Missing PLAYLIST_URL"),
+    };
+    config.playlist_url = normalize_url(&url)?;
+    options.scheme_added = config.playlist_url != url.trim();
+    // Headers from the command line replace configured ones with the same name
+    config.headers.retain(|(name, _)| {
+        !cli_headers
+            .iter()
+            .any(|(cli_name, _)| cli_name.eq_ignore_ascii_case(name))
+    });
+    config.headers.extend(cli_headers);
+    if !cli_content_types.is_empty() {
+        config.accepted_content_types = cli_content_types;
+    }
+    if !cli_rewrites.is_empty() {
+        config.url_rewrites = cli_rewrites;
+    }
+    config.output_filename = expand_output_template(&config.output_filename, &config.playlist_url);
+    if let Some(template) = &config.output_subfolder {
+        expand_output_subfolder(template, &config.playlist_url)?;
+    }
+    match (burn_subtitles, sidecar) {
+        (true, Some(_)) => bail!("--burn-subtitles and --subtitle-sidecar cannot be combined"),
+        (true, None) => config.subtitle_mode = SubtitleMode::BurnIn,
+        (false, Some(mode)) => config.subtitle_mode = mode,
+        (false, None) => {}
+    }
+    if !["mp4", "mkv", "webm", "ts"].contains(&config.output_format.as_str()) {
+        bail!("Unsupported --format: {}", config.output_format);
+    }
+    if config.concurrency == 0 {
+        bail!("--concurrency must be at least 1");
+    }
+    ClipWindow::parse(config.clip_start.as_deref(), config.clip_end.as_deref())?;
+    TimeRange::parse(config.time_start.as_deref(), config.time_end.as_deref())?;
+    Ok(CliCommand::Download(Box::new(config), options))
+}
+
+/// Serializes a job into an equivalent command-line invocation of this tool.
+pub fn to_command_line(config: &DownloadConfig) -> String {
+    let mut args: Vec<String> = vec![BIN_NAME.to_string(), shell_quote(&config.playlist_url)];
+
+    if !config.output_location.is_empty() {
+        args.push("--output-dir".to_string());
+        args.push(shell_quote(&config.output_location));
+    }
+    if !config.output_filename.is_empty() {
+        args.push("--output-name".to_string());
+        args.push(shell_quote(&config.output_filename));
+    }
+    if let Some(template) = &config.output_subfolder {
+        args.push("--subfolder".to_string());
+        args.push(shell_quote(template));
+    }
+    args.push("--format".to_string());
+    args.push(shell_quote(&config.output_format));
+    args.push("--concurrency".to_string());
+    args.push(config.concurrency.to_string());
+    if config.fix_timestamps {
+        args.push("--fix-timestamps".to_string());
+    }
+    if config.fill_gaps {
+        args.push("--fill-gaps".to_string());
+    }
+    if config.skip_ads {
+        args.push("--skip-ads".to_string());
+    }
+    if !config.excluded_segments.is_empty() {
+        args.push("--exclude-segments".to_string());
+        args.push(format_index_list(&config.excluded_segments));
+    }
+    if let Some(start) = &config.clip_start {
+        args.push("--clip-start".to_string());
+        args.push(shell_quote(start));
+    }
+    if let Some(end) = &config.clip_end {
+        args.push("--clip-end".to_string());
+        args.push(shell_quote(end));
+    }
+    if let Some(start) = &config.time_start {
+        args.push("--start-time".to_string());
+        args.push(shell_quote(start));
+    }
+    if let Some(end) = &config.time_end {
+        args.push("--end-time".to_string());
+        args.push(shell_quote(end));
+    }
+    if config.exact_trim {
+        args.push("--exact-trim".to_string());
+    }
+    if config.reencode {
+        args.push("--reencode".to_string());
+    }
+    if let Some(sample) = &config.sample {
+        args.push("--sample".to_string());
+        args.push(sample.name());
+    }
+    if config.keep_workspace {
+        args.push("--keep-workspace".to_string());
+    }
+    if config.normalize_audio {
+        args.push("--normalize-audio".to_string());
+    }
+    if config.extract_captions {
+        args.push("--extract-captions".to_string());
+    }
+    if config.stream_to_player {
+        args.push("--stream-to-player".to_string());
+    }
+    if config.verify_concat {
+        args.push("--verify-concat".to_string());
+    }
+    if config.live {
+        args.push("--live".to_string());
+    }
+    if config.append_recording {
+        args.push("--append".to_string());
+    }
+    if config.patient_live {
+        args.push("--patient".to_string());
+    }
+    if config.live_start != LiveStart::default() {
+        args.push("--live-start".to_string());
+        args.push(config.live_start.name().to_string());
+    }
+    if let Some(text) = &config.playlist_text {
+        args.push("--playlist-text".to_string());
+        args.push(shell_quote(text));
+    }
+    // The key itself never ends up in a command that is logged or shared
+    if config.key_hex.is_some() {
+        args.insert(0, REDACTED_KEY.to_string());
+    }
+    if let Some(iv) = &config.iv_hex {
+        args.push("--iv".to_string());
+        args.push(shell_quote(iv));
+    }
+    if let Some(path) = &config.report_path {
+        args.push("--report".to_string());
+        args.push(shell_quote(path));
+    }
+    if let Some(command) = &config.on_complete {
+        args.push("--on-complete".to_string());
+        args.push(shell_quote(command));
+    }
+    if let Some(url) = &config.webhook_url {
+        args.push("--webhook".to_string());
+        args.push(shell_quote(url));
+    }
+    if let Some(proxy) = &config.proxy {
+        args.push("--proxy".to_string());
+        args.push(shell_quote(proxy));
+    }
+    if config.propagate_query {
+        args.push("--propagate-query".to_string());
+    }
+    let defaults = PlaylistLimits::default();
+    if config.limits.max_segments != defaults.max_segments {
+        args.push("--max-segments".to_string());
+        args.push(config.limits.max_segments.to_string());
+    }
+    if config.limits.max_playlist_bytes != defaults.max_playlist_bytes {
+        args.push("--max-playlist-bytes".to_string());
+        args.push(config.limits.max_playlist_bytes.to_string());
+    }
+    if config.limits.max_keys != defaults.max_keys {
+        args.push("--max-keys".to_string());
+        args.push(config.limits.max_keys.to_string());
+    }
+    for content_type in &config.accepted_content_types {
+        args.push("--accept-content-type".to_string());
+        args.push(shell_quote(content_type));
+    }
+    for rule in &config.url_rewrites {
+        args.push("--rewrite-url".to_string());
+        args.push(shell_quote(&rule.to_string()));
+    }
+    if let Some(quality) = &config.quality {
+        args.push("--quality".to_string());
+        args.push(shell_quote(quality));
+    }
+    if let Some(url) = &config.audio_url {
+        args.push("--audio".to_string());
+        args.push(shell_quote(url));
+    }
+    if let Some(track) = &config.audio_track {
+        args.push("--audio-track".to_string());
+        args.push(shell_quote(&track.to_spec()));
+    }
+    if let Some(url) = &config.subtitle_url {
+        args.push("--subtitles".to_string());
+        args.push(shell_quote(url));
+        if config.subtitle_mode == SubtitleMode::BurnIn {
+            args.push("--burn-subtitles".to_string());
+        }
+    }
+    if let Some(extension) = config.subtitle_mode.sidecar_extension() {
+        args.push("--subtitle-sidecar".to_string());
+        args.push(extension.to_string());
+    }
+    if let Some(track) = &config.subtitle_track {
+        args.push("--subtitle-track".to_string());
+        args.push(shell_quote(&track.to_spec()));
+    }
+    if config.background_remux {
+        args.push("--background-remux".to_string());
+    }
+    for (name, value) in &config.headers {
+        args.push("-H".to_string());
+        args.push(shell_quote(&format!("{}: {}", name, value)));
+    }
+
+    args.join(" ")
+}
+
+/// Key for `--key-file`, as hex: the file holds the raw key (16 or 32 bytes) or its hex text.
+fn read_key_file(path: &Path) -> Result<String> {
+    let bytes = std::fs::read(path)
+        .map_err(|e| anyhow!("Failed to read key file {}: {}", path.display(), e))?;
+    if matches!(bytes.len(), 16 | 32) {
+        return Ok(hex::encode(bytes));
+    }
+    Ok(String::from_utf8_lossy(&bytes).trim().to_string())
+}
+
+/// Track metadata given to `--audio-track` / `--subtitle-track`.
+fn track_metadata(flag: &str, spec: &str) -> Result<TrackMetadata> {
+    TrackMetadata::parse(spec).ok_or_else(|| {
+        anyhow!(
+            "Invalid {}: {} (e.g. language=ja,default,name=Japanese)",
+            flag,
+            spec
+        )
+    })
+}
+
+/// Parses a list of segment indices and ranges like `0-3,17`.
+fn parse_index_list(list: &str) -> Result<Vec<usize>> {
+    let parse = |s: &str| {
+        s.trim()
+            .parse::<usize>()
+            .map_err(|_| anyhow!("Invalid --exclude-segments entry: {}", s))
+    };
+    let mut indices = Vec::new();
+    for item in list.split(',').filter(|item| !item.trim().is_empty()) {
+        match item.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (parse(start)?, parse(end)?);
+                if start > end {
+                    bail!("Invalid --exclude-segments range: {}", item);
+                }
+                indices.extend(start..=end);
+            }
+            None => indices.push(parse(item)?),
+        }
+    }
+    Ok(indices)
+}
+
+/// Writes indices back in the compact form read by `parse_index_list`.
+fn format_index_list(indices: &[usize]) -> String {
+    let mut sorted = indices.to_vec();
+    sorted.sort_unstable();
+    sorted.dedup();
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for index in sorted {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == index => *end = index,
+            _ => ranges.push((index, index)),
+        }
+    }
+    ranges
+        .iter()
+        .map(|&(start, end)| {
+            if start == end {
+                start.to_string()
+            } else {
+                format!("{}-{}", start, end)
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Quotes a value for POSIX shells, leaving simple tokens untouched.
+fn shell_quote(value: &str) -> String {
+    let is_safe = !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-./:=@%+,".contains(c));
+    if is_safe {
+        value.to_string()
+    } else {
+        format!("'{}'", value.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses `args` against an empty config file, so the user's own defaults don't leak in.
+    fn parse(args: &[&str]) -> DownloadConfig {
+        let dir = tempfile::tempdir().unwrap();
+        let config_path = dir.path().join("config.toml");
+        std::fs::write(&config_path, "").unwrap();
+        let mut args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        args.push("--config".to_string());
+        args.push(config_path.to_string_lossy().into_owned());
+        match parse_args(args).unwrap() {
+            CliCommand::Download(config, _) => *config,
+            _ => panic!("not a download command"),
+        }
+    }
+
+    #[test]
+    fn keys_are_read_from_files_and_never_echoed() {
+        let dir = tempfile::tempdir().unwrap();
+        let raw = dir.path().join("raw.key");
+        std::fs::write(&raw, [0xabu8; 16]).unwrap();
+        let text = dir.path().join("hex.key");
+        std::fs::write(&text, format!("{}\n", "cd".repeat(16))).unwrap();
+
+        let url = "https://example.com/index.m3u8";
+        let config = parse(&[url, "--key-file", &raw.to_string_lossy()]);
+        assert_eq!(config.key_hex, Some("ab".repeat(16)));
+        let config = parse(&[url, "--key-file", &text.to_string_lossy()]);
+        assert_eq!(config.key_hex, Some("cd".repeat(16)));
+
+        let command = to_command_line(&config);
+        assert!(!command.contains(&"cd".repeat(16)));
+        assert!(command.starts_with("HLSDL_KEY="));
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(!json.contains("key_hex"));
+    }
+
+    #[test]
+    fn command_line_reproduces_the_job() {
+        let config = parse(&[
+            "https://example.com/show/index.m3u8",
+            "--output-dir",
+            "/tmp/videos",
+            "-o",
+            "episode 1",
+            "--subfolder",
+            "{host}",
+            "--format",
+            "mkv",
+            "--concurrency",
+            "6",
+            "-H",
+            "Referer: https://example.com/",
+            "--proxy",
+            "socks5://127.0.0.1:1080",
+            "--iv",
+            "000102030405060708090a0b0c0d0e0f",
+            "--exclude-segments",
+            "0-3,17",
+            "--start-time",
+            "1:00",
+            "--keep-workspace",
+            "--fix-timestamps",
+            "--propagate-query",
+            "--max-keys",
+            "5",
+            "--rewrite-url",
+            "^http: => https:",
+            "--audio",
+            "https://example.com/show/audio/ja.m3u8",
+            "--audio-track",
+            "language=ja,default,name=Japanese, dubbed",
+            "--subtitles",
+            "https://example.com/show/subs/en.m3u8",
+            "--subtitle-track",
+            "language=en,name=English",
+            "--subtitle-sidecar",
+            "srt",
+            "--background-remux",
+        ]);
+        assert!(config.background_remux);
+        assert_eq!(
+            config.audio_track.as_ref().and_then(|t| t.name.as_deref()),
+            Some("Japanese, dubbed")
+        );
+
+        let command = to_command_line(&config);
+        let mut args = shell_words::split(&command).unwrap();
+        assert_eq!(args.remove(0), BIN_NAME);
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let reparsed = parse(&args);
+        assert_eq!(
+            serde_json::to_value(&reparsed).unwrap(),
+            serde_json::to_value(&config).unwrap()
+        );
+    }
+}
@@ -0,0 +1,465 @@
+// Shell completion scripts for the headless command line (`--completions <SHELL>`).
+// Generated from the option table below; keep it in sync with `cli::USAGE`.
+
+use anyhow::{Result, bail};
+
+use crate::OUTPUT_FORMATS;
+use crate::cli::BIN_NAME;
+
+/// What follows an option on the command line
+#[derive(Clone, Copy)]
+enum Value {
+    None,
+    Any,
+    File,
+    Dir,
+    Choice(&'static [&'static str]),
+}
+
+/// (long name, short name, value, description); descriptions avoid `:` `[` `]` and quotes
+const OPTIONS: &[(&str, Option<char>, Value, &str)] = &[
+    (
+        "output-dir",
+        None,
+        Value::Dir,
+        "Directory for the output file",
+    ),
+    ("output-name", Some('o'), Value::Any, "Output file name"),
+    (
+        "subfolder",
+        None,
+        Value::Any,
+        "Sub-folder template, e.g. {host}/{yyyy-mm}",
+    ),
+    (
+        "format",
+        None,
+        Value::Choice(&OUTPUT_FORMATS),
+        "Output format",
+    ),
+    (
+        "concurrency",
+        None,
+        Value::Any,
+        "Parallel segment downloads",
+    ),
+    ("header", Some('H'), Value::Any, "Extra request header"),
+    ("proxy", None, Value::Any, "Proxy for all requests"),
+    (
+        "propagate-query",
+        None,
+        Value::None,
+        "Add playlist query tokens to segment URLs",
+    ),
+    ("key-file", None, Value::File, "Decryption key file"),
+    ("key", None, Value::Any, "Decryption key (hex)"),
+    ("iv", None, Value::Any, "Decryption IV (hex)"),
+    (
+        "max-segments",
+        None,
+        Value::Any,
+        "Segment limit per playlist",
+    ),
+    (
+        "max-playlist-bytes",
+        None,
+        Value::Any,
+        "Playlist size limit",
+    ),
+    ("max-keys", None, Value::Any, "Key download limit"),
+    (
+        "accept-content-type",
+        None,
+        Value::Any,
+        "Accepted segment Content-Type",
+    ),
+    (
+        "rewrite-url",
+        None,
+        Value::Any,
+        "Segment URL rewrite rule (REGEX => REPLACEMENT)",
+    ),
+    ("playlist-text", None, Value::Any, "Playlist content"),
+    (
+        "base-url",
+        None,
+        Value::Any,
+        "Base URL of the playlist read from stdin",
+    ),
+    ("subtitles", None, Value::File, "Add a subtitle track"),
+    (
+        "burn-subtitles",
+        None,
+        Value::None,
+        "Burn the subtitles into the video",
+    ),
+    (
+        "subtitle-sidecar",
+        None,
+        Value::Choice(&["vtt", "srt"]),
+        "Save the subtitles next to the video",
+    ),
+    (
+        "fix-timestamps",
+        None,
+        Value::None,
+        "Repair PTS/DTS discontinuities",
+    ),
+    (
+        "fill-gaps",
+        None,
+        Value::None,
+        "Fill #EXT-X-GAP segments with black/silence",
+    ),
+    ("skip-ads", None, Value::None, "Leave out marked ad breaks"),
+    (
+        "exclude-segments",
+        None,
+        Value::Any,
+        "Skip segments by sequence number (e.g. 0-3,17)",
+    ),
+    (
+        "clip-start",
+        None,
+        Value::Any,
+        "Wall-clock start (PROGRAM-DATE-TIME)",
+    ),
+    (
+        "clip-end",
+        None,
+        Value::Any,
+        "Wall-clock end (PROGRAM-DATE-TIME)",
+    ),
+    (
+        "start-time",
+        None,
+        Value::Any,
+        "Start position, e.g. 1:02:30",
+    ),
+    ("end-time", None, Value::Any, "End position, e.g. 1:05:00"),
+    (
+        "exact-trim",
+        None,
+        Value::None,
+        "Cut exactly at the times (re-encodes)",
+    ),
+    (
+        "reencode",
+        None,
+        Value::None,
+        "Re-encode codecs the format cannot hold",
+    ),
+    (
+        "sample",
+        None,
+        Value::Any,
+        "Only the first N segments (or Ns)",
+    ),
+    (
+        "keep-workspace",
+        None,
+        Value::None,
+        "Keep segments for later repair",
+    ),
+    (
+        "normalize-audio",
+        None,
+        Value::None,
+        "Two-pass loudness normalization",
+    ),
+    (
+        "extract-captions",
+        None,
+        Value::None,
+        "Save closed captions as SRT",
+    ),
+    (
+        "verify-concat",
+        None,
+        Value::None,
+        "Verify chunks after concatenation",
+    ),
+    ("report", None, Value::File, "Write per-segment timings"),
+    (
+        "on-complete",
+        None,
+        Value::Any,
+        "Command run after the download",
+    ),
+    (
+        "webhook",
+        None,
+        Value::Any,
+        "URL the job context is posted to",
+    ),
+    (
+        "stream-to-player",
+        None,
+        Value::None,
+        "Serve the download on localhost",
+    ),
+    (
+        "live",
+        None,
+        Value::None,
+        "Record a live playlist until it ends",
+    ),
+    (
+        "append",
+        None,
+        Value::None,
+        "Continue the earlier live recording",
+    ),
+    (
+        "live-start",
+        None,
+        Value::Choice(&["window", "edge"]),
+        "Where a live recording starts",
+    ),
+    (
+        "patient",
+        None,
+        Value::None,
+        "Pause live polling through origin outages",
+    ),
+    (
+        "quality",
+        None,
+        Value::Choice(&["best", "worst", "1080p", "720p", "480p"]),
+        "Master playlist variant",
+    ),
+    ("audio", None, Value::Any, "Alternative audio rendition"),
+    ("subs", None, Value::Any, "Subtitle rendition"),
+    (
+        "audio-track",
+        None,
+        Value::Any,
+        "Metadata of the audio track",
+    ),
+    (
+        "subtitle-track",
+        None,
+        Value::Any,
+        "Metadata of the subtitle track",
+    ),
+    (
+        "background-remux",
+        None,
+        Value::None,
+        "Remux while the next job downloads",
+    ),
+    ("yes", Some('y'), Value::None, "Never prompt"),
+    (
+        "inspect",
+        None,
+        Value::None,
+        "Dry run: only parse the playlist",
+    ),
+    (
+        "preview",
+        None,
+        Value::Choice(&["strip", "clip"]),
+        "Only render an I-frame preview",
+    ),
+    ("error-json", None, Value::File, "Write failures as JSON"),
+    (
+        "diagnostics",
+        None,
+        Value::File,
+        "Write a diagnostics bundle",
+    ),
+    (
+        "background-priority",
+        None,
+        Value::None,
+        "Run at low CPU/disk priority",
+    ),
+    (
+        "worker-threads",
+        None,
+        Value::Any,
+        "Async runtime worker threads",
+    ),
+    (
+        "blocking-threads",
+        None,
+        Value::Any,
+        "Upper bound of the blocking pool",
+    ),
+    ("config", None, Value::File, "Defaults file"),
+    ("profile", None, Value::Any, "Config file profile"),
+    (
+        "completions",
+        None,
+        Value::Choice(&SHELLS),
+        "Print a shell completion script",
+    ),
+    ("help", Some('h'), Value::None, "Print help"),
+    ("version", Some('V'), Value::None, "Print the version"),
+];
+
+pub const SHELLS: [&str; 4] = ["bash", "zsh", "fish", "powershell"];
+
+/// Completion script for `shell`.
+pub fn script(shell: &str) -> Result<String> {
+    match shell {
+        "bash" => Ok(bash()),
+        "zsh" => Ok(zsh()),
+        "fish" => Ok(fish()),
+        "powershell" | "pwsh" => Ok(powershell()),
+        other => bail!(
+            "Unsupported shell for --completions: {} (expected {})",
+            other,
+            SHELLS.join(", ")
+        ),
+    }
+}
+
+/// All spellings of the options, e.g. `--header -H`
+fn all_flags() -> Vec<String> {
+    OPTIONS
+        .iter()
+        .flat_map(|(long, short, _, _)| {
+            std::iter::once(format!("--{}", long)).chain(short.map(|c| format!("-{}", c)))
+        })
+        .collect()
+}
+
+/// `--header|-H` pattern for case statements
+fn case_pattern(long: &str, short: Option<char>) -> String {
+    match short {
+        Some(c) => format!("--{}|-{}", long, c),
+        None => format!("--{}", long),
+    }
+}
+
+fn bash() -> String {
+    let function = format!("_{}", BIN_NAME.replace('-', "_"));
+    let mut cases = String::new();
+    for (long, short, value, _) in OPTIONS {
+        let reply = match value {
+            Value::None => continue,
+            Value::Any => "return 0".to_string(),
+            Value::File => "COMPREPLY=($(compgen -f -- \"$cur\")); return 0".to_string(),
+            Value::Dir => "COMPREPLY=($(compgen -d -- \"$cur\")); return 0".to_string(),
+            Value::Choice(choices) => format!(
+                "COMPREPLY=($(compgen -W \"{}\" -- \"$cur\")); return 0",
+                choices.join(" ")
+            ),
+        };
+        cases.push_str(&format!(
+            "        {})\n            {}\n            ;;\n",
+            case_pattern(long, *short),
+            reply
+        ));
+    }
+    format!(
+        "{function}() {{
+    local cur prev
+    cur=\"${{COMP_WORDS[COMP_CWORD]}}\"
+    prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"
+    case \"$prev\" in
+{cases}    esac
+    if [[ \"$cur\" == -* ]]; then
+        COMPREPLY=($(compgen -W \"{flags}\" -- \"$cur\"))
+    fi
+}}
+complete -F {function} {bin}
+",
+        function = function,
+        cases = cases,
+        flags = all_flags().join(" "),
+        bin = BIN_NAME,
+    )
+}
+
+fn zsh() -> String {
+    let mut specs = String::new();
+    for (long, short, value, description) in OPTIONS {
+        let action = match value {
+            Value::None => String::new(),
+            Value::Any => format!(":{}:", long),
+            Value::File => ":file:_files".to_string(),
+            Value::Dir => ":directory:_files -/".to_string(),
+            Value::Choice(choices) => format!(":{}:({})", long, choices.join(" ")),
+        };
+        // Repeatable options may be given more than once
+        let repeat = if *long == "header" { "*" } else { "" };
+        let spec = match short {
+            // Unquoted braces: the shell expands them into one spec per spelling
+            Some(c) if repeat.is_empty() => {
+                format!("{{--{},-{}}}'[{}]{}'", long, c, description, action)
+            }
+            Some(c) => format!(
+                "'{}'{{--{},-{}}}'[{}]{}'",
+                repeat, long, c, description, action
+            ),
+            None => format!("'{}--{}[{}]{}'", repeat, long, description, action),
+        };
+        specs.push_str(&format!("    {} \\\n", spec));
+    }
+    format!(
+        "#compdef {bin}
+
+_arguments -s \\
+{specs}    '1:playlist URL:_urls'
+",
+        bin = BIN_NAME,
+        specs = specs,
+    )
+}
+
+fn fish() -> String {
+    let mut lines = String::new();
+    for (long, short, value, description) in OPTIONS {
+        let mut line = format!("complete -c {} -l {}", BIN_NAME, long);
+        if let Some(c) = short {
+            line.push_str(&format!(" -s {}", c));
+        }
+        line.push_str(&format!(" -d '{}'", description));
+        match value {
+            Value::None => {}
+            Value::Any => line.push_str(" -r -f"),
+            Value::File => line.push_str(" -r -F"),
+            Value::Dir => line.push_str(" -r -f -a '(__fish_complete_directories)'"),
+            Value::Choice(choices) => line.push_str(&format!(" -r -f -a '{}'", choices.join(" "))),
+        }
+        lines.push_str(&line);
+        lines.push('\n');
+    }
+    lines
+}
+
+fn powershell() -> String {
+    let mut cases = String::new();
+    for (long, short, value, _) in OPTIONS {
+        if let Value::Choice(choices) = value {
+            let quoted: Vec<String> = choices.iter().map(|c| format!("'{}'", c)).collect();
+            let mut labels = vec![format!("'--{}'", long)];
+            labels.extend(short.map(|c| format!("'-{}'", c)));
+            cases.push_str(&format!(
+                "        {{ $_ -in @({}) }} {{ $candidates = @({}) }}\n",
+                labels.join(", "),
+                quoted.join(", ")
+            ));
+        }
+    }
+    let flags: Vec<String> = all_flags().iter().map(|f| format!("'{}'", f)).collect();
+    format!(
+        "Register-ArgumentCompleter -Native -CommandName '{bin}' -ScriptBlock {{
+    param($wordToComplete, $commandAst, $cursorPosition)
+    $elements = @($commandAst.CommandElements | ForEach-Object {{ $_.ToString() }})
+    $prev = if ($wordToComplete) {{ $elements[-2] }} else {{ $elements[-1] }}
+    $candidates = @({flags})
+    switch ($prev) {{
+{cases}    }}
+    $candidates | Where-Object {{ $_ -like \"$wordToComplete*\" }} | ForEach-Object {{
+        [System.Management.Automation.CompletionResult]::new($_, $_, 'ParameterValue', $_)
+    }}
+}}
+",
+        bin = BIN_NAME,
+        flags = flags.join(", "),
+        cases = cases,
+    )
+}
@@ -0,0 +1,174 @@
+// Defaults for headless runs read from `hlsdl.toml`.
+// Keys use the same names as the job settings (`DownloadConfig`); `[profiles.<name>]` sections
+// override the top level when selected with `--profile`.
+
+use anyhow::{Result, anyhow};
+use dirs::config_dir;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::downloader::DownloadConfig;
+use crate::downloader::url_rewrite::RewriteRule;
+
+pub const CONFIG_FILE_NAME: &str = "hlsdl.toml";
+
+/// One layer of defaults (the top level or a profile); unset keys fall through.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ConfigDefaults {
+    pub output_location: Option<String>,
+    /// May contain `{name}`, `{host}` and `{date}` placeholders
+    pub output_filename: Option<String>,
+    /// Sub-folder of `output_location`, e.g. `{host}/{yyyy-mm}`
+    pub output_subfolder: Option<String>,
+    pub output_format: Option<String>,
+    pub concurrency: Option<usize>,
+    /// `Name = "value"` pairs sent with every request
+    pub headers: BTreeMap<String, String>,
+    pub proxy: Option<String>,
+    /// Segment `Content-Type` patterns such as `video/*`
+    pub accepted_content_types: Option<Vec<String>>,
+    /// `[[url_rewrites]]` tables with `pattern` and `replacement`
+    pub url_rewrites: Option<Vec<RewriteRule>>,
+    /// Anti-abuse caps (see `PlaylistLimits`)
+    pub max_segments: Option<usize>,
+    pub max_playlist_bytes: Option<u64>,
+    pub max_keys: Option<usize>,
+    pub quality: Option<String>,
+    pub fix_timestamps: Option<bool>,
+    pub fill_gaps: Option<bool>,
+    pub skip_ads: Option<bool>,
+    pub reencode: Option<bool>,
+    pub propagate_query: Option<bool>,
+    pub keep_workspace: Option<bool>,
+    pub normalize_audio: Option<bool>,
+    pub extract_captions: Option<bool>,
+    pub verify_concat: Option<bool>,
+    /// Shell command run after each download
+    pub on_complete: Option<String>,
+    pub webhook_url: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+struct ConfigFile {
+    #[serde(flatten)]
+    defaults: ConfigDefaults,
+    profiles: BTreeMap<String, ConfigDefaults>,
+}
+
+/// `<config_dir>/hls-downloader/hlsdl.toml`, next to the GUI's settings.json
+pub fn default_config_path() -> Option<PathBuf> {
+    config_dir().map(|base| base.join("hls-downloader").join(CONFIG_FILE_NAME))
+}
+
+/// Loads the defaults from `path` (or the default location, if it exists) with `profile` applied.
+pub fn load(path: Option<&Path>, profile: Option<&str>) -> Result<ConfigDefaults> {
+    let path = match path {
+        Some(path) => Some(path.to_path_buf()),
+        None => default_config_path().filter(|path| path.is_file()),
+    };
+    let file = match &path {
+        Some(path) => {
+            let text = fs::read_to_string(path)
+                .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?;
+            toml_edit::de::from_str::<ConfigFile>(&text)
+                .map_err(|e| anyhow!("Invalid config file {}: {}", path.display(), e))?
+        }
+        None => ConfigFile::default(),
+    };
+
+    let Some(profile) = profile else {
+        return Ok(file.defaults);
+    };
+    let overrides = file.profiles.get(profile).ok_or_else(|| {
+        anyhow!(
+            "Profile '{}' not found in {}",
+            profile,
+            path.as_deref()
+                .map_or(CONFIG_FILE_NAME.to_string(), |p| p.display().to_string())
+        )
+    })?;
+    Ok(file.defaults.overlay(overrides.clone()))
+}
+
+impl ConfigDefaults {
+    /// `self` with every key set in `other` replaced (headers are merged by name).
+    fn overlay(mut self, other: ConfigDefaults) -> ConfigDefaults {
+        self.headers.extend(other.headers);
+        ConfigDefaults {
+            output_location: other.output_location.or(self.output_location),
+            output_filename: other.output_filename.or(self.output_filename),
+            output_subfolder: other.output_subfolder.or(self.output_subfolder),
+            output_format: other.output_format.or(self.output_format),
+            concurrency: other.concurrency.or(self.concurrency),
+            headers: self.headers,
+            proxy: other.proxy.or(self.proxy),
+            accepted_content_types: other.accepted_content_types.or(self.accepted_content_types),
+            url_rewrites: other.url_rewrites.or(self.url_rewrites),
+            max_segments: other.max_segments.or(self.max_segments),
+            max_playlist_bytes: other.max_playlist_bytes.or(self.max_playlist_bytes),
+            max_keys: other.max_keys.or(self.max_keys),
+            quality: other.quality.or(self.quality),
+            fix_timestamps: other.fix_timestamps.or(self.fix_timestamps),
+            fill_gaps: other.fill_gaps.or(self.fill_gaps),
+            skip_ads: other.skip_ads.or(self.skip_ads),
+            reencode: other.reencode.or(self.reencode),
+            propagate_query: other.propagate_query.or(self.propagate_query),
+            keep_workspace: other.keep_workspace.or(self.keep_workspace),
+            normalize_audio: other.normalize_audio.or(self.normalize_audio),
+            extract_captions: other.extract_captions.or(self.extract_captions),
+            verify_concat: other.verify_concat.or(self.verify_concat),
+            on_complete: other.on_complete.or(self.on_complete),
+            webhook_url: other.webhook_url.or(self.webhook_url),
+        }
+    }
+
+    /// Writes the configured values into a job (before command-line flags are applied).
+    pub fn apply(self, config: &mut DownloadConfig) {
+        if let Some(value) = self.output_location {
+            config.output_location = value;
+        }
+        if let Some(value) = self.output_filename {
+            config.output_filename = value;
+        }
+        config.output_subfolder = self.output_subfolder.or(config.output_subfolder.take());
+        if let Some(value) = self.output_format {
+            config.output_format = value.to_lowercase();
+        }
+        if let Some(value) = self.concurrency {
+            config.concurrency = value;
+        }
+        config.headers.extend(self.headers);
+        config.proxy = self.proxy.or(config.proxy.take());
+        if let Some(value) = self.accepted_content_types {
+            config.accepted_content_types = value;
+        }
+        if let Some(value) = self.url_rewrites {
+            config.url_rewrites = value;
+        }
+        if let Some(value) = self.max_segments {
+            config.limits.max_segments = value;
+        }
+        if let Some(value) = self.max_playlist_bytes {
+            config.limits.max_playlist_bytes = value;
+        }
+        if let Some(value) = self.max_keys {
+            config.limits.max_keys = value;
+        }
+        config.quality = self.quality.or(config.quality.take());
+        config.fix_timestamps = self.fix_timestamps.unwrap_or(config.fix_timestamps);
+        config.fill_gaps = self.fill_gaps.unwrap_or(config.fill_gaps);
+        config.skip_ads = self.skip_ads.unwrap_or(config.skip_ads);
+        config.reencode = self.reencode.unwrap_or(config.reencode);
+        config.propagate_query = self.propagate_query.unwrap_or(config.propagate_query);
+        config.keep_workspace = self.keep_workspace.unwrap_or(config.keep_workspace);
+        config.normalize_audio = self.normalize_audio.unwrap_or(config.normalize_audio);
+        config.extract_captions = self.extract_captions.unwrap_or(config.extract_captions);
+        config.verify_concat = self.verify_concat.unwrap_or(config.verify_concat);
+        config.on_complete = self.on_complete.or(config.on_complete.take());
+        config.webhook_url = self.webhook_url.or(config.webhook_url.take());
+    }
+}
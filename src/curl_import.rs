@@ -0,0 +1,152 @@
+// Importer for "Copy as cURL" strings from browser devtools.
+
+use anyhow::{Result, anyhow};
+
+/// URL and headers extracted from a curl command line
+pub struct CurlImport {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+}
+
+/// Options whose value is irrelevant for a download job but must be skipped
+const IGNORED_OPTIONS_WITH_VALUE: &[&str] = &[
+    "-X",
+    "--request",
+    "-d",
+    "--data",
+    "--data-raw",
+    "--data-binary",
+    "--data-urlencode",
+    "-o",
+    "--output",
+    "-u",
+    "--user",
+    "-x",
+    "--proxy",
+    "-m",
+    "--max-time",
+    "--connect-timeout",
+];
+
+/// Parses a bash-style curl command (as copied from Chrome/Firefox devtools).
+pub fn parse_curl_command(command: &str) -> Result<CurlImport> {
+    // Devtools output uses backslash line continuations
+    let joined = command.replace("\\\r\n", " ").replace("\\\n", " ");
+    let words =
+        shell_words::split(&joined).map_err(|e| anyhow!("Could not parse command: {}", e))?;
+
+    let mut words = words.into_iter();
+    match words.next() {
+        Some(first) if first == "curl" || first.ends_with("/curl") || first == "curl.exe" => {}
+        _ => return Err(anyhow!("Not a curl command (it must start with `curl`)")),
+    }
+
+    let mut url: Option<String> = None;
+    let mut headers: Vec<(String, String)> = Vec::new();
+
+    while let Some(word) = words.next() {
+        let mut value_for = |option: &str| {
+            words
+                .next()
+                .ok_or_else(|| anyhow!("Missing value for {}", option))
+        };
+
+        match word.as_str() {
+            "-H" | "--header" => {
+                let header = value_for(&word)?;
+                // HTTP/2 pseudo headers (`:authority: ...`) are set by the HTTP client itself
+                if header.starts_with(':') {
+                    continue;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    headers.push((name.trim().to_string(), value.trim().to_string()));
+                }
+            }
+            "-b" | "--cookie" => headers.push(("Cookie".to_string(), value_for(&word)?)),
+            "-A" | "--user-agent" => headers.push(("User-Agent".to_string(), value_for(&word)?)),
+            "-e" | "--referer" => headers.push(("Referer".to_string(), value_for(&word)?)),
+            "--url" => url = Some(value_for(&word)?),
+            option if IGNORED_OPTIONS_WITH_VALUE.contains(&option) => {
+                value_for(option)?;
+            }
+            option if option.starts_with('-') => {} // Flags such as --compressed, -L, -k
+            _ => {
+                if url.is_none() {
+                    url = Some(word);
+                }
+            }
+        }
+    }
+
+    // Transport-level headers are handled by the HTTP client itself
+    headers.retain(|(name, _)| {
+        !matches!(
+            name.to_ascii_lowercase().as_str(),
+            "host" | "content-length" | "accept-encoding" | "connection"
+        )
+    });
+
+    let url = url.ok_or_else(|| anyhow!("No URL found in curl command"))?;
+    Ok(CurlImport { url, headers })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn devtools_command_gives_url_and_headers() {
+        let command = "curl 'https://cdn.example.com/live/index.m3u8?token=a%2Fb' \\\n  \
+             -H 'accept: */*' \\\n  \
+             -H 'referer: https://www.example.com/watch' \\\n  \
+             -H 'user-agent: Mozilla/5.0 (X11; Linux x86_64)' \\\n  \
+             -b 'session=abc; theme=dark' \\\n  \
+             --compressed";
+        let import = parse_curl_command(command).unwrap();
+        assert_eq!(
+            import.url,
+            "https://cdn.example.com/live/index.m3u8?token=a%2Fb"
+        );
+        assert_eq!(
+            import.headers,
+            vec![
+                ("accept".to_string(), "*/*".to_string()),
+                (
+                    "referer".to_string(),
+                    "https://www.example.com/watch".to_string()
+                ),
+                (
+                    "user-agent".to_string(),
+                    "Mozilla/5.0 (X11; Linux x86_64)".to_string()
+                ),
+                ("Cookie".to_string(), "session=abc; theme=dark".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn option_values_are_not_taken_for_the_url() {
+        let import = parse_curl_command(
+            "curl -X GET --data-raw '{}' -A agent -e https://ref.example/ \
+             --url https://example.com/a.m3u8 -H 'Host: example.com' -H ':authority: x' \
+             -H 'Accept-Encoding: gzip' https://example.com/ignored.m3u8",
+        )
+        .unwrap();
+        assert_eq!(import.url, "https://example.com/a.m3u8");
+        assert_eq!(
+            import.headers,
+            vec![
+                ("User-Agent".to_string(), "agent".to_string()),
+                ("Referer".to_string(), "https://ref.example/".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn malformed_commands_are_rejected() {
+        assert!(parse_curl_command("wget https://example.com/a.m3u8").is_err());
+        assert!(parse_curl_command("curl -H 'Referer: x'").is_err());
+        assert!(parse_curl_command("curl https://example.com -H").is_err());
+        assert!(parse_curl_command("curl 'https://example.com").is_err());
+    }
+}
@@ -0,0 +1,161 @@
+// Concurrent analysis of a list of playlist URLs before they are queued: variants, duration and
+// an estimated size per URL.
+
+use anyhow::{Result, anyhow};
+use egui::Context as EguiContext;
+use futures::stream::{self, StreamExt};
+use reqwest::{Client, Url};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use super::DownloadMessage;
+use super::cancel;
+use super::hls_parser::{MasterPlaylist, fetch_playlist_text, parse_m3u8, parse_master_playlist};
+use super::http::build_client;
+use super::limits::PlaylistLimits;
+
+/// Playlists analyzed at the same time
+const BATCH_ANALYZE_CONCURRENCY: usize = 8;
+
+/// Result of analyzing one URL of a batch
+#[derive(Debug, Clone)]
+pub struct BatchEntry {
+    pub duration_secs: f64,
+    pub segment_count: usize,
+    /// Variants to choose from (None for a media playlist)
+    pub master: Option<MasterPlaylist>,
+    /// Size estimate of a media playlist, from its first segment's Content-Length
+    pub media_size: Option<u64>,
+}
+
+impl BatchEntry {
+    /// Estimated download size for a variant (index into `master.variants`).
+    pub fn estimated_size(&self, variant: usize) -> Option<u64> {
+        match &self.master {
+            Some(master) => {
+                let bandwidth = master.variants.get(variant)?.bandwidth;
+                (bandwidth > 0).then(|| (bandwidth as f64 * self.duration_secs / 8.0) as u64)
+            }
+            None => self.media_size,
+        }
+    }
+
+    /// Index of the highest-bandwidth variant (the default choice).
+    pub fn best_variant(&self) -> usize {
+        self.master
+            .as_ref()
+            .and_then(|master| {
+                master
+                    .variants
+                    .iter()
+                    .enumerate()
+                    .max_by_key(|(_, v)| v.bandwidth)
+                    .map(|(i, _)| i)
+            })
+            .unwrap_or(0)
+    }
+
+    /// Index of the variant a quality ladder picks (see `MasterPlaylist::select_ladder`).
+    pub fn ladder_variant(&self, ladder: &[String]) -> usize {
+        self.master
+            .as_ref()
+            .and_then(|master| {
+                let chosen = master.select_ladder(ladder)?;
+                master.variants.iter().position(|v| std::ptr::eq(v, chosen))
+            })
+            .unwrap_or_else(|| self.best_variant())
+    }
+}
+
+async fn analyze_url(client: &Client, url: &str, cancel: &CancellationToken) -> Result<BatchEntry> {
+    // Rows are analyzed before any job (and its limits) exists
+    let limits = PlaylistLimits::default();
+    let playlist_url = Url::parse(url.trim()).map_err(|e| anyhow!("Invalid URL: {}", e))?;
+    let fetched = fetch_playlist_text(client, &playlist_url, &limits, &|_| {}, cancel).await?;
+    let master = parse_master_playlist(&fetched.body, &fetched.url)?;
+
+    // All variants share the duration; the best one is read to measure it
+    let (media_url, media_body) = match master.as_ref().and_then(|m| m.select("best")) {
+        Some(variant) => {
+            let media = fetch_playlist_text(client, &variant.uri, &limits, &|_| {}, cancel).await?;
+            (media.url, media.body)
+        }
+        None => (fetched.url, fetched.body),
+    };
+    let (segments, _) = parse_m3u8(&media_body, &media_url, &limits, &|_| {})?;
+    let duration_secs = segments.iter().map(|s| s.duration).sum::<f64>();
+
+    let ranged_size = segments
+        .iter()
+        .map(|s| s.byte_range.map(|range| range.length))
+        .sum::<Option<u64>>();
+    let media_size = if master.is_some() {
+        None
+    } else if ranged_size.is_some() {
+        // #EXT-X-BYTERANGE segments state their exact size
+        ranged_size
+    } else {
+        let first_url = media_url.join(&segments[0].path)?;
+        let head = cancel::or_cancel(cancel, async { Ok(client.head(first_url).send().await?) })
+            .await
+            .ok();
+        head.and_then(|response| response.content_length())
+            .map(|length| length * segments.len() as u64)
+    };
+
+    Ok(BatchEntry {
+        duration_secs,
+        segment_count: segments.len(),
+        master,
+        media_size,
+    })
+}
+
+/// Analyzes `urls` with bounded concurrency, reporting each row as `BatchAnalyzed`.
+pub async fn run_batch_analyze_job(
+    urls: Vec<String>,
+    headers: Vec<(String, String)>,
+    cancel: CancellationToken,
+    sender: mpsc::Sender<DownloadMessage>,
+    ctx: EguiContext,
+) -> Result<()> {
+    let client = build_client(&headers, None)?;
+    let total = urls.len();
+    let mut done = 0;
+    let mut failed = 0;
+
+    let mut results = stream::iter(urls.into_iter().enumerate())
+        .map(|(row, url)| {
+            let client = &client;
+            let cancel = &cancel;
+            async move { (row, analyze_url(client, &url, cancel).await) }
+        })
+        .buffer_unordered(BATCH_ANALYZE_CONCURRENCY);
+
+    while let Some((row, result)) = results.next().await {
+        cancel::check(&cancel)?;
+        done += 1;
+        failed += usize::from(result.is_err());
+        sender
+            .send(DownloadMessage::BatchAnalyzed {
+                row,
+                result: result.map(Box::new).map_err(|e| e.to_string()),
+            })
+            .await
+            .ok();
+        sender
+            .send(DownloadMessage::Progress(done as f32 / total as f32))
+            .await
+            .ok();
+        ctx.request_repaint();
+    }
+
+    sender
+        .send(DownloadMessage::Log(format!(
+            "✅ Analyzed {} playlist(s), {} failed.",
+            total, failed
+        )))
+        .await
+        .ok();
+    Ok(())
+}
@@ -0,0 +1,310 @@
+// Cooperative cancellation shared by all long-running job stages.
+
+use anyhow::Result;
+use std::fmt;
+use tokio_util::sync::CancellationToken;
+
+/// Error returned by a stage that stopped because its job was cancelled.
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+/// Returns `Err(Cancelled)` once the token has been cancelled.
+pub fn check(cancel: &CancellationToken) -> Result<()> {
+    if cancel.is_cancelled() {
+        Err(Cancelled.into())
+    } else {
+        Ok(())
+    }
+}
+
+/// Runs `future` until it completes or the token is cancelled, whichever happens first.
+pub async fn or_cancel<T>(
+    cancel: &CancellationToken,
+    future: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    tokio::select! {
+        biased;
+        _ = cancel.cancelled() => Err(Cancelled.into()),
+        result = future => result,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::downloader::content_type::ContentTypeRules;
+    use crate::downloader::error_summary::ErrorSummary;
+    use crate::downloader::hls_parser::{Segment, download_and_parse_m3u3};
+    use crate::downloader::limits::PlaylistLimits;
+    use crate::downloader::pause::PauseGate;
+    use crate::downloader::report::SegmentReport;
+    use crate::downloader::segment_io::{concatenate_segments, download_segments_concurrently};
+    use reqwest::{Client, Url};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+    use tokio::io::AsyncWriteExt;
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc;
+
+    /// Delay before cancelling, and the deadline a stage has to stop afterwards
+    const CANCEL_AFTER: Duration = Duration::from_millis(100);
+    const STOP_WITHIN: Duration = Duration::from_secs(2);
+
+    /// HTTP server on localhost that accepts connections but never answers.
+    async fn stalled_server() -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut open = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                open.push(socket);
+            }
+        });
+        Url::parse(&format!("http://{}/", addr)).unwrap()
+    }
+
+    /// Serves `playlist` for every request path ending in `.m3u8` and stalls everything else.
+    async fn playlist_only_server(playlist: &'static str) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut buf = vec![0u8; 4096];
+                    let n = tokio::io::AsyncReadExt::read(&mut socket, &mut buf)
+                        .await
+                        .unwrap_or(0);
+                    let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                    if request.lines().next().unwrap_or("").contains(".m3u8") {
+                        let response = format!(
+                            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                            playlist.len(),
+                            playlist
+                        );
+                        socket.write_all(response.as_bytes()).await.ok();
+                    } else {
+                        // Keep the connection open without responding
+                        tokio::time::sleep(Duration::from_secs(3600)).await;
+                    }
+                });
+            }
+        });
+        Url::parse(&format!("http://{}/index.m3u8", addr)).unwrap()
+    }
+
+    fn cancel_later(cancel: &CancellationToken) {
+        let cancel = cancel.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(CANCEL_AFTER).await;
+            cancel.cancel();
+        });
+    }
+
+    fn assert_cancelled<T>(result: Result<T>, started: Instant) {
+        let elapsed = started.elapsed();
+        match result {
+            Err(e) => assert!(e.is::<Cancelled>(), "expected Cancelled, got: {}", e),
+            Ok(_) => panic!("stage completed instead of being cancelled"),
+        }
+        assert!(
+            elapsed < CANCEL_AFTER + STOP_WITHIN,
+            "stage took {:?} to stop",
+            elapsed
+        );
+    }
+
+    #[tokio::test]
+    async fn playlist_stage_stops_on_cancel() {
+        let url = stalled_server().await.join("index.m3u8").unwrap();
+        let cancel = CancellationToken::new();
+        cancel_later(&cancel);
+
+        let started = Instant::now();
+        let result = download_and_parse_m3u3(
+            &Client::new(),
+            &url,
+            &PlaylistLimits::default(),
+            &|_| {},
+            &cancel,
+        )
+        .await;
+        assert_cancelled(result, started);
+    }
+
+    #[tokio::test]
+    async fn segment_stage_stops_on_cancel() {
+        let playlist_url =
+            playlist_only_server("#EXTM3U\n#EXTINF:4,\na.ts\n#EXTINF:4,\nb.ts\n").await;
+        let client = Client::new();
+        let cancel = CancellationToken::new();
+        let (segments, _) = download_and_parse_m3u3(
+            &client,
+            &playlist_url,
+            &PlaylistLimits::default(),
+            &|_| {},
+            &cancel,
+        )
+        .await
+        .unwrap();
+        let total = segments.len();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (sender, _receiver) = mpsc::channel(100);
+        cancel_later(&cancel);
+
+        let started = Instant::now();
+        let result = download_segments_concurrently(
+            &client,
+            &playlist_url,
+            segments,
+            Vec::new(),
+            total,
+            2,
+            temp_dir.path().to_path_buf(),
+            Arc::new(ErrorSummary::default()),
+            Arc::new(ContentTypeRules::default()),
+            Arc::new(SegmentReport::default()),
+            &cancel,
+            &PauseGate::default(),
+            sender,
+            egui::Context::default(),
+        )
+        .await;
+        assert_cancelled(result, started);
+    }
+
+    #[tokio::test]
+    async fn segment_stage_does_not_start_when_already_cancelled() {
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let segments = (0..50)
+            .map(|index| Segment {
+                path: format!("seg{}.ts", index),
+                index,
+                sequence: index as u64,
+                duration: 1.0,
+                key: None,
+                byte_range: None,
+                init: None,
+                discontinuity: false,
+                gap: false,
+                ad: false,
+                program_date_time: None,
+            })
+            .collect();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (sender, _receiver) = mpsc::channel(100);
+
+        let started = Instant::now();
+        let result = download_segments_concurrently(
+            &Client::new(),
+            &stalled_server().await,
+            segments,
+            Vec::new(),
+            50,
+            4,
+            temp_dir.path().to_path_buf(),
+            Arc::new(ErrorSummary::default()),
+            Arc::new(ContentTypeRules::default()),
+            Arc::new(SegmentReport::default()),
+            &cancel,
+            &PauseGate::default(),
+            sender,
+            egui::Context::default(),
+        )
+        .await;
+        assert_cancelled(result, started);
+    }
+
+    #[tokio::test]
+    async fn paused_segment_stage_stops_on_cancel() {
+        let playlist_url = playlist_only_server("#EXTM3U\n#EXTINF:4,\na.ts\n").await;
+        let client = Client::new();
+        let cancel = CancellationToken::new();
+        let (segments, _) = download_and_parse_m3u3(
+            &client,
+            &playlist_url,
+            &PlaylistLimits::default(),
+            &|_| {},
+            &cancel,
+        )
+        .await
+        .unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (sender, _receiver) = mpsc::channel(100);
+        let pause = PauseGate::default();
+        pause.pause();
+        cancel_later(&cancel);
+
+        let started = Instant::now();
+        let result = download_segments_concurrently(
+            &client,
+            &playlist_url,
+            segments,
+            Vec::new(),
+            1,
+            1,
+            temp_dir.path().to_path_buf(),
+            Arc::new(ErrorSummary::default()),
+            Arc::new(ContentTypeRules::default()),
+            Arc::new(SegmentReport::default()),
+            &cancel,
+            &pause,
+            sender,
+            egui::Context::default(),
+        )
+        .await;
+        assert_cancelled(result, started);
+    }
+
+    #[test]
+    fn concat_stage_stops_on_cancel() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let paths: Vec<_> = (0..20)
+            .map(|i| {
+                let path = temp_dir.path().join(format!("{}.ts", i));
+                std::fs::write(&path, vec![0x47u8; 188]).unwrap();
+                path
+            })
+            .collect();
+        let output = temp_dir.path().join("merged.ts");
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+
+        let started = Instant::now();
+        let result = concatenate_segments(&paths, &output, false, &cancel);
+        assert_cancelled(result, started);
+        // Nothing beyond the first chunk may have been written
+        assert!(std::fs::metadata(&output).map_or(0, |m| m.len()) <= 188);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn remux_stage_kills_process_on_cancel() {
+        use crate::downloader::segment_io::run_progress_command;
+        use std::process::Command;
+
+        let cancel = CancellationToken::new();
+        let canceller = {
+            let cancel = cancel.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(CANCEL_AFTER);
+                cancel.cancel();
+            })
+        };
+
+        let mut command = Command::new("sleep");
+        command.arg("30");
+        let started = Instant::now();
+        let result = run_progress_command(command, &cancel, |_| {});
+        canceller.join().unwrap();
+        assert_cancelled(result, started);
+    }
+}
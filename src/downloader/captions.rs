@@ -0,0 +1,112 @@
+// Closed caption extraction: CEA-608/708 captions carried inside the video stream (US
+// broadcast style) are decoded by FFmpeg's `subcc` output of the `movie` source and written
+// as an SRT file next to the output.
+
+use anyhow::{Result, anyhow};
+use egui::Context as EguiContext;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use super::DownloadMessage;
+use super::cancel::Cancelled;
+use super::failure;
+use super::segment_io::{ffmpeg_progress_command, run_progress_command};
+
+/// Characters that would need filtergraph escaping in the `movie=` file name
+const FILTER_SPECIAL: &[char] = &['\'', '\\', ':', ',', ';', '[', ']', '='];
+
+/// Sidecar path for the captions of `output` (`video.mp4` -> `video.cc.srt`)
+pub fn captions_path(output: &Path) -> PathBuf {
+    output.with_extension("cc.srt")
+}
+
+/// Decodes the closed captions of `input` into `output` (SRT).
+///
+/// Returns false (and leaves no file) when the video carries no captions.
+pub fn extract_captions(
+    input: &Path,
+    output: &Path,
+    cancel: &CancellationToken,
+    on_progress: impl Fn(f32),
+) -> Result<bool> {
+    // Run from the input's directory so the filter argument is a plain file name; both paths
+    // are made absolute first so relative ones still resolve from there
+    let input = std::path::absolute(input)?;
+    let output = std::path::absolute(output)?;
+    let dir = input
+        .parent()
+        .ok_or_else(|| anyhow!("Invalid caption input path"))?;
+    let name = input
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid caption input path"))?
+        .to_string_lossy()
+        .into_owned();
+    if name.contains(FILTER_SPECIAL) {
+        return Err(anyhow!(
+            "Cannot read captions from {}: unsupported characters in the file name",
+            name
+        ));
+    }
+
+    let mut command = ffmpeg_progress_command()?;
+    command.current_dir(dir);
+    command
+        .args(["-f", "lavfi", "-i", &format!("movie={}[out0+subcc]", name)])
+        .args(["-map", "0:s", "-c:s", "srt", "-y"])
+        .arg(&output);
+    let run = run_progress_command(command, cancel, on_progress)?;
+    if !run.success {
+        let _ = std::fs::remove_file(&output);
+        return Err(failure::ffmpeg(format!(
+            "Caption extraction failed: {}",
+            run.stderr
+        )));
+    }
+    // The subcc stream always exists; without caption data the SRT stays empty
+    let found = std::fs::metadata(&output).is_ok_and(|meta| meta.len() > 0);
+    if !found {
+        let _ = std::fs::remove_file(&output);
+    }
+    Ok(found)
+}
+
+/// Runs `extract_captions` off the async runtime. Only cancellation is an error: a stream
+/// without captions, or a failed extraction, leaves the download itself intact.
+pub async fn run_captions_step(
+    input: PathBuf,
+    output: PathBuf,
+    cancel: CancellationToken,
+    sender: mpsc::Sender<DownloadMessage>,
+    ctx: EguiContext,
+) -> Result<()> {
+    sender
+        .send(DownloadMessage::Log(
+            "💬 Extracting closed captions (CEA-608/708)...".to_string(),
+        ))
+        .await
+        .ok();
+    ctx.request_repaint();
+
+    let progress_sender = sender.clone();
+    let progress_ctx = ctx.clone();
+    let caption_output = output.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        extract_captions(&input, &caption_output, &cancel, |p| {
+            progress_sender.try_send(DownloadMessage::Progress(p)).ok();
+            progress_ctx.request_repaint();
+        })
+    })
+    .await
+    .map_err(|e| anyhow!("Caption blocking task failed to join: {}", e))?;
+
+    let message = match result {
+        Ok(true) => format!("✅ Closed captions saved as {}.", output.display()),
+        Ok(false) => "-> The video stream carries no closed captions.".to_string(),
+        Err(e) if e.is::<Cancelled>() => return Err(e),
+        Err(e) => format!("⚠️ Warning: {}", e),
+    };
+    sender.send(DownloadMessage::Log(message)).await.ok();
+    ctx.request_repaint();
+    Ok(())
+}
@@ -0,0 +1,128 @@
+// "Save for later": resolves the playlist, its variant and key while the session (cookies,
+// signed URLs) is still valid, and stores a job that no longer needs the original page.
+
+use anyhow::{Result, anyhow};
+use egui::Context as EguiContext;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use super::hls_parser::{
+    allows_local_keys, download_key_file, fetch_playlist_text, parse_m3u8, resolve_media_playlist,
+};
+use super::http::build_client;
+use super::{DownloadConfig, DownloadMessage, KeyOverride, cancel};
+
+/// A job captured for a later download, with what was learned while capturing it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CapturedJob {
+    /// Ready to run: media playlist URL and content, audio rendition and key filled in
+    pub config: DownloadConfig,
+    pub duration_secs: f64,
+    pub segment_count: usize,
+    /// The key was downloaded while capturing and is stored in `config.key_hex`; like the key,
+    /// not saved to disk, so a reloaded entry downloads its key again
+    #[serde(skip)]
+    pub key_saved: bool,
+    /// UTC date of the capture (YYYY-MM-DD)
+    #[serde(default)]
+    pub captured_on: String,
+}
+
+/// Fetches the media playlist (and its key) of `config` and reports the job as `Captured`.
+pub async fn run_capture_job(
+    mut config: DownloadConfig,
+    cancel: CancellationToken,
+    sender: mpsc::Sender<DownloadMessage>,
+    ctx: EguiContext,
+) -> Result<()> {
+    let send_log = |msg: String| {
+        sender.try_send(DownloadMessage::Log(msg)).ok();
+        ctx.request_repaint();
+    };
+
+    let limits = config.limits;
+    let playlist_url =
+        Url::parse(&config.playlist_url).map_err(|e| anyhow!("Invalid URL: {}", e))?;
+    let client = build_client(&config.headers, config.proxy.as_deref())?;
+    let (base_url, body) = match config.playlist_text.take() {
+        Some(text) => (playlist_url, text),
+        None => {
+            let fetched =
+                fetch_playlist_text(&client, &playlist_url, &limits, &send_log, &cancel).await?;
+            (fetched.url, fetched.body)
+        }
+    };
+    let media = resolve_media_playlist(
+        &client,
+        &base_url,
+        body,
+        config.quality.as_deref(),
+        &limits,
+        &send_log,
+        &cancel,
+    )
+    .await?;
+    let (segments, infos) = parse_m3u8(&media.body, &media.url, &limits, &send_log)?;
+    if !media.body.contains("#EXT-X-ENDLIST") {
+        send_log("⚠️ Warning: Live playlist; only the segments listed now are saved.".to_string());
+    }
+
+    let key_override = KeyOverride::from_config(&config)?;
+    let mut key_saved = false;
+    match infos.as_slice() {
+        [] => {}
+        [info] if key_override.is_empty() => {
+            let key = cancel::or_cancel(
+                &cancel,
+                download_key_file(
+                    &client,
+                    &info.key_url,
+                    &info.method,
+                    allows_local_keys(&config.playlist_url, config.playlist_text.is_some()),
+                    &send_log,
+                ),
+            )
+            .await?;
+            config.key_hex = Some(hex::encode(key.as_bytes()));
+            key_saved = true;
+            send_log("-> Key saved with the job.".to_string());
+        }
+        [_] => {}
+        _ => send_log(format!(
+            "⚠️ Warning: The playlist rotates between {} keys; they are downloaded when the job runs.",
+            infos.len()
+        )),
+    }
+
+    let duration_secs = segments.iter().map(|s| s.duration).sum::<f64>();
+    send_log(format!(
+        "-> Captured {} segments ({:.0} s) from {}",
+        segments.len(),
+        duration_secs,
+        media.url
+    ));
+    config.playlist_url = media.url.to_string();
+    config.playlist_text = Some(media.body);
+    config.quality = None;
+    if config.audio_url.is_none() {
+        config.audio_url = media.audio_url.map(|url| url.to_string());
+        config.audio_track = media.audio_track;
+    }
+    config.live = false;
+
+    sender.send(DownloadMessage::Progress(1.0)).await.ok();
+    sender
+        .send(DownloadMessage::Captured(Box::new(CapturedJob {
+            config,
+            duration_secs,
+            segment_count: segments.len(),
+            key_saved,
+            captured_on: String::new(),
+        })))
+        .await
+        .ok();
+    ctx.request_repaint();
+    Ok(())
+}
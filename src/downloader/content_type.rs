@@ -0,0 +1,123 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Segment content types expected when none are configured (others are only warned about)
+pub const DEFAULT_SEGMENT_CONTENT_TYPES: [&str; 4] = [
+    "video/*",
+    "audio/*",
+    "application/octet-stream",
+    // What `.ts` maps to (Qt Linguist) in the MIME databases of many web servers
+    "text/vnd.trolltech.linguist",
+];
+
+/// Media types a segment response may declare in `Content-Type`.
+///
+/// A CDN that starts answering with an HTML or JSON error page (still `200 OK`) would otherwise
+/// end up as a corrupt segment in the output. Responses without the header are always accepted.
+/// Only configured patterns are enforced: many CDNs label segments `binary/octet-stream`,
+/// `application/mp4` or `text/plain`, so other types than the defaults are merely warned about.
+pub struct ContentTypeRules {
+    /// Lowercase patterns: `type/subtype`, `type/*` or `*/*`
+    accepted: Vec<String>,
+    /// Whether the patterns were configured, i.e. other types are rejected
+    enforced: bool,
+    /// Set once an unexpected type has been warned about
+    warned: AtomicBool,
+}
+
+impl ContentTypeRules {
+    /// Rules for the configured patterns; an empty list means the defaults.
+    pub fn new(patterns: &[String]) -> Self {
+        let accepted: Vec<String> = patterns
+            .iter()
+            .map(|pattern| pattern.trim().to_ascii_lowercase())
+            .filter(|pattern| !pattern.is_empty())
+            .collect();
+        if accepted.is_empty() {
+            return Self::default();
+        }
+        Self {
+            accepted,
+            enforced: true,
+            warned: AtomicBool::new(false),
+        }
+    }
+
+    /// Whether a type that `accepts` refuses rejects the response (configured patterns), or is
+    /// only worth a warning (defaults).
+    pub fn enforced(&self) -> bool {
+        self.enforced
+    }
+
+    /// True the first time it is called, so an unexpected type is warned about once per job.
+    pub fn first_warning(&self) -> bool {
+        !self.warned.swap(true, Ordering::Relaxed)
+    }
+
+    /// Whether a `Content-Type` header value (parameters such as `charset` are ignored) matches.
+    pub fn accepts(&self, content_type: &str) -> bool {
+        let media_type = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        if media_type.is_empty() {
+            return true;
+        }
+        self.accepted
+            .iter()
+            .any(|pattern| match pattern.strip_suffix("/*") {
+                Some("*") => true,
+                Some(kind) => media_type
+                    .split_once('/')
+                    .is_some_and(|(media_kind, _)| media_kind == kind),
+                None => *pattern == media_type,
+            })
+    }
+}
+
+impl Default for ContentTypeRules {
+    fn default() -> Self {
+        Self {
+            accepted: DEFAULT_SEGMENT_CONTENT_TYPES
+                .iter()
+                .map(|pattern| pattern.to_string())
+                .collect(),
+            enforced: false,
+            warned: AtomicBool::new(false),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_match_media_types_but_are_not_enforced() {
+        let rules = ContentTypeRules::default();
+        assert!(rules.accepts("video/MP2T"));
+        assert!(rules.accepts("audio/aac; charset=binary"));
+        assert!(rules.accepts("application/octet-stream"));
+        assert!(rules.accepts("text/vnd.trolltech.linguist"));
+        assert!(rules.accepts(""));
+        assert!(!rules.accepts("binary/octet-stream"));
+        assert!(!rules.accepts("text/html; charset=utf-8"));
+        assert!(!rules.enforced());
+        assert!(rules.first_warning());
+        assert!(!rules.first_warning());
+    }
+
+    #[test]
+    fn configured_patterns_are_enforced() {
+        let rules = ContentTypeRules::new(&[" Video/* ".to_string(), "text/plain".to_string()]);
+        assert!(rules.enforced());
+        assert!(rules.accepts("video/mp4"));
+        assert!(rules.accepts("text/plain;charset=us-ascii"));
+        assert!(!rules.accepts("audio/aac"));
+        assert!(!rules.accepts("video"));
+        assert!(ContentTypeRules::new(&["*/*".to_string()]).accepts("application/json"));
+        // Blank patterns fall back to the (unenforced) defaults
+        assert!(!ContentTypeRules::new(&[" ".to_string()]).enforced());
+    }
+}
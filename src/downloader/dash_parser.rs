@@ -0,0 +1,637 @@
+// MPEG-DASH support: parses an MPD manifest and turns the picked video and audio
+// representations into media playlists, so the HLS pipeline (segment download, init sections,
+// byte ranges, separate audio muxed in by FFmpeg) handles them unchanged.
+
+use anyhow::{Result, anyhow};
+use reqwest::Url;
+use roxmltree::{Document, Node};
+use std::fmt::Write;
+
+use super::failure::{self, FailureKind};
+use super::hls_parser::{
+    ByteRange, InitSection, MasterPlaylist, MediaPlaylist, TrackMetadata, Variant,
+};
+use super::limits::PlaylistLimits;
+
+/// Whether a fetched manifest is an MPD rather than an M3U8 playlist.
+pub fn is_mpd(body: &str) -> bool {
+    let head = body.trim_start_matches('\u{feff}').trim_start();
+    (head.starts_with("<?xml") || head.starts_with("<MPD")) && body.contains("<MPD")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DashKind {
+    Video,
+    Audio,
+    Other,
+}
+
+/// One fragment of a representation
+#[derive(Debug, Clone, PartialEq)]
+pub struct DashSegment {
+    pub url: Url,
+    /// Seconds
+    pub duration: f64,
+    pub byte_range: Option<ByteRange>,
+}
+
+/// A `<Representation>` with its segments resolved to absolute URLs
+#[derive(Debug, Clone)]
+pub struct Representation {
+    pub id: String,
+    pub kind: DashKind,
+    pub bandwidth: u64,
+    pub resolution: Option<(u32, u32)>,
+    pub codecs: Option<String>,
+    /// `lang` of the adaptation set
+    pub language: Option<String>,
+    /// Init section with an absolute URI
+    pub init: Option<InitSection>,
+    pub segments: Vec<DashSegment>,
+}
+
+impl Representation {
+    /// The representation as an HLS media playlist with absolute URIs.
+    pub fn to_media_playlist(&self) -> String {
+        let target = self
+            .segments
+            .iter()
+            .map(|s| s.duration)
+            .fold(0.0, f64::max)
+            .ceil();
+        let mut text = format!(
+            "#EXTM3U\n#EXT-X-VERSION:7\n#EXT-X-TARGETDURATION:{}\n#EXT-X-PLAYLIST-TYPE:VOD\n",
+            target
+        );
+        if let Some(init) = &self.init {
+            let _ = write!(text, "#EXT-X-MAP:URI=\"{}\"", init.uri);
+            if let Some(range) = init.byte_range {
+                let _ = write!(text, ",BYTERANGE=\"{}@{}\"", range.length, range.offset);
+            }
+            text.push('\n');
+        }
+        for segment in &self.segments {
+            let _ = writeln!(text, "#EXTINF:{:.6},", segment.duration);
+            if let Some(range) = segment.byte_range {
+                let _ = writeln!(text, "#EXT-X-BYTERANGE:{}@{}", range.length, range.offset);
+            }
+            let _ = writeln!(text, "{}", segment.url);
+        }
+        text.push_str("#EXT-X-ENDLIST\n");
+        text
+    }
+
+    fn label(&self) -> String {
+        let mut label = match (self.resolution, &self.language) {
+            (Some((width, height)), _) => format!("{}x{}", width, height),
+            (None, Some(language)) => language.clone(),
+            (None, None) => self.id.clone(),
+        };
+        label.push_str(&format!(", {} kbps", self.bandwidth / 1000));
+        if let Some(codecs) = &self.codecs {
+            label.push_str(&format!(", {}", codecs));
+        }
+        label
+    }
+}
+
+/// The parts of an MPD the downloader uses
+#[derive(Debug, Clone, Default)]
+pub struct DashManifest {
+    /// `type="dynamic"`: a live presentation
+    pub live: bool,
+    /// Some adaptation set declares `<ContentProtection>` (DRM)
+    pub protected: bool,
+    pub period_count: usize,
+    /// Representations of the first period
+    pub representations: Vec<Representation>,
+}
+
+impl DashManifest {
+    /// Parses the MPD text; relative URLs are resolved against `mpd_url` and the `<BaseURL>`s.
+    pub fn parse(body: &str, mpd_url: &Url, limits: &PlaylistLimits) -> Result<Self> {
+        Self::parse_document(body, mpd_url, limits).map_err(|e| match FailureKind::of(&e) {
+            FailureKind::Other => failure::parse(e),
+            _ => e,
+        })
+    }
+
+    fn parse_document(body: &str, mpd_url: &Url, limits: &PlaylistLimits) -> Result<Self> {
+        let document = Document::parse(body).map_err(|e| anyhow!("Invalid MPD manifest: {}", e))?;
+        let mpd = document.root_element();
+        if mpd.tag_name().name() != "MPD" {
+            return Err(anyhow!(
+                "Invalid MPD manifest: the root element is not <MPD>"
+            ));
+        }
+        let mut manifest = DashManifest {
+            live: mpd.attribute("type") == Some("dynamic"),
+            ..Default::default()
+        };
+        let mpd_base = base_url(mpd, mpd_url)?;
+        let presentation_duration = mpd
+            .attribute("mediaPresentationDuration")
+            .and_then(parse_iso_duration);
+        let periods: Vec<Node> = children(mpd, "Period").collect();
+        manifest.period_count = periods.len();
+        let period = *periods
+            .first()
+            .ok_or_else(|| anyhow!("The MPD manifest has no <Period>"))?;
+        let period_base = base_url(period, &mpd_base)?;
+        let period_duration = period
+            .attribute("duration")
+            .and_then(parse_iso_duration)
+            .or(presentation_duration);
+
+        for set in children(period, "AdaptationSet") {
+            manifest.protected |= children(set, "ContentProtection").next().is_some();
+            let set_base = base_url(set, &period_base)?;
+            for representation in children(set, "Representation") {
+                manifest.protected |= children(representation, "ContentProtection")
+                    .next()
+                    .is_some();
+                let attribute = |name: &str| {
+                    representation
+                        .attribute(name)
+                        .or_else(|| set.attribute(name))
+                };
+                let base = base_url(representation, &set_base)?;
+                let id = representation
+                    .attribute("id")
+                    .unwrap_or_default()
+                    .to_string();
+                let bandwidth = attribute("bandwidth")
+                    .and_then(|b| b.parse().ok())
+                    .unwrap_or(0);
+                let mime = attribute("mimeType").unwrap_or_default();
+                let content_type = set.attribute("contentType").unwrap_or_default();
+                let kind = if mime.starts_with("video/") || content_type == "video" {
+                    DashKind::Video
+                } else if mime.starts_with("audio/") || content_type == "audio" {
+                    DashKind::Audio
+                } else {
+                    DashKind::Other
+                };
+                let resolution = match (attribute("width"), attribute("height")) {
+                    (Some(width), Some(height)) => width.parse().ok().zip(height.parse().ok()),
+                    _ => None,
+                };
+                let vars = TemplateVars { id: &id, bandwidth };
+                // The innermost segment description wins; templates inherit missing attributes
+                let templates: Vec<Node> = [representation, set, period]
+                    .into_iter()
+                    .filter_map(|node| children(node, "SegmentTemplate").next())
+                    .collect();
+                let list = [representation, set, period]
+                    .into_iter()
+                    .find_map(|node| children(node, "SegmentList").next());
+                let (init, segments) = if !templates.is_empty() {
+                    template_segments(&templates, &base, &vars, period_duration, limits)?
+                } else if let Some(list) = list {
+                    list_segments(list, &base)?
+                } else {
+                    // SegmentBase or a plain BaseURL: the whole file is one fragment
+                    let duration = period_duration.ok_or_else(|| {
+                        anyhow!("Representation {} has no segments and no duration", id)
+                    })?;
+                    let segment = DashSegment {
+                        url: base.clone(),
+                        duration,
+                        byte_range: None,
+                    };
+                    (None, vec![segment])
+                };
+                limits.check_segments(segments.len())?;
+                manifest.representations.push(Representation {
+                    id,
+                    kind,
+                    bandwidth,
+                    resolution,
+                    codecs: attribute("codecs").map(str::to_string),
+                    language: set.attribute("lang").map(str::to_string),
+                    init,
+                    segments,
+                });
+            }
+        }
+        Ok(manifest)
+    }
+
+    fn of_kind(&self, kind: DashKind) -> impl Iterator<Item = &Representation> {
+        self.representations.iter().filter(move |r| r.kind == kind)
+    }
+
+    /// Picks the video representation by `quality` (as for a master playlist) and the audio
+    /// representation with the highest bandwidth.
+    pub fn select(&self, quality: &str) -> Option<(&Representation, Option<&Representation>)> {
+        let videos: Vec<&Representation> = self.of_kind(DashKind::Video).collect();
+        let best_audio = self.of_kind(DashKind::Audio).max_by_key(|r| r.bandwidth);
+        if videos.is_empty() {
+            // An audio-only presentation
+            return best_audio.map(|audio| (audio, None));
+        }
+        // Reuses the variant selection of master playlists; the URIs are not used
+        let placeholder = Url::parse("dash:representation").ok()?;
+        let master = MasterPlaylist {
+            variants: videos
+                .iter()
+                .map(|r| Variant {
+                    uri: placeholder.clone(),
+                    bandwidth: r.bandwidth,
+                    resolution: r.resolution,
+                    codecs: r.codecs.clone(),
+                    audio_group: None,
+                    subtitles_group: None,
+                })
+                .collect(),
+            ..Default::default()
+        };
+        let chosen = master.select(quality)?;
+        let position = master
+            .variants
+            .iter()
+            .position(|v| std::ptr::eq(v, chosen))?;
+        Some((videos[position], best_audio))
+    }
+}
+
+/// Resolves an MPD to the media playlist of the picked video representation, with the picked
+/// audio representation as its separate audio rendition.
+pub fn resolve_mpd(
+    mpd_url: &Url,
+    body: &str,
+    quality: Option<&str>,
+    limits: &PlaylistLimits,
+    send_log: &impl Fn(String),
+) -> Result<MediaPlaylist> {
+    let manifest = DashManifest::parse(body, mpd_url, limits)?;
+    if manifest.live {
+        return Err(anyhow!(
+            "Live (dynamic) MPD manifests are not supported, only on-demand ones"
+        ));
+    }
+    if manifest.protected {
+        send_log(
+            "⚠️ Warning: The MPD declares <ContentProtection> (DRM); the downloaded segments will \
+             likely not play."
+                .to_string(),
+        );
+    }
+    if manifest.period_count > 1 {
+        send_log(format!(
+            "⚠️ Warning: The MPD has {} periods; only the first one is downloaded.",
+            manifest.period_count
+        ));
+    }
+    let quality = quality.unwrap_or("best");
+    let (main, audio) = manifest
+        .select(quality)
+        .ok_or_else(|| anyhow!("No DASH representation matches quality '{}'", quality))?;
+    send_log(format!(
+        "-> DASH manifest with {} video representation(s), selected {} ({})",
+        manifest.of_kind(DashKind::Video).count(),
+        main.label(),
+        quality
+    ));
+    if let Some(audio) = audio {
+        send_log(format!("-> Audio representation: {}", audio.label()));
+    }
+    Ok(MediaPlaylist {
+        url: mpd_url.clone(),
+        body: main.to_media_playlist(),
+        audio_url: audio.map(|_| mpd_url.clone()),
+        audio_track: audio.map(|audio| TrackMetadata {
+            language: audio.language.clone(),
+            name: None,
+            default: true,
+        }),
+        audio_body: audio.map(Representation::to_media_playlist),
+        resolution: main.resolution,
+        bandwidth: Some(main.bandwidth),
+        subtitles: None,
+    })
+}
+
+fn children<'a, 'input>(
+    node: Node<'a, 'input>,
+    name: &'static str,
+) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children()
+        .filter(move |child| child.is_element() && child.tag_name().name() == name)
+}
+
+/// `parent` joined with the element's first `<BaseURL>`, if it has one.
+fn base_url(node: Node, parent: &Url) -> Result<Url> {
+    match children(node, "BaseURL")
+        .next()
+        .and_then(|base| base.text())
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+    {
+        Some(text) => parent
+            .join(text)
+            .map_err(|e| anyhow!("Invalid BaseURL {}: {}", text, e)),
+        None => Ok(parent.clone()),
+    }
+}
+
+struct TemplateVars<'a> {
+    id: &'a str,
+    bandwidth: u64,
+}
+
+/// Substitutes `$RepresentationID$`, `$Bandwidth$`, `$Number$`, `$Time$` (with optional
+/// `%0<width>d` formats) and `$$`.
+fn expand_template(template: &str, vars: &TemplateVars, number: u64, time: u64) -> String {
+    let mut out = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('$') {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        let Some(end) = after.find('$') else {
+            out.push_str(&rest[start..]);
+            return out;
+        };
+        let token = &after[..end];
+        let (name, format) = token.split_once('%').unwrap_or((token, ""));
+        let width = format
+            .trim_start_matches('0')
+            .trim_end_matches('d')
+            .parse::<usize>()
+            .unwrap_or(0);
+        match name {
+            "" => out.push('$'),
+            "RepresentationID" => out.push_str(vars.id),
+            "Bandwidth" => out.push_str(&format!("{:0width$}", vars.bandwidth)),
+            "Number" => out.push_str(&format!("{:0width$}", number)),
+            "Time" => out.push_str(&format!("{:0width$}", time)),
+            // Unknown identifiers are left as written
+            _ => {
+                out.push('$');
+                out.push_str(token);
+                out.push('$');
+            }
+        }
+        rest = &after[end + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Segments of a `<SegmentTemplate>`; `templates` runs from the innermost to the outermost.
+fn template_segments(
+    templates: &[Node],
+    base: &Url,
+    vars: &TemplateVars,
+    period_duration: Option<f64>,
+    limits: &PlaylistLimits,
+) -> Result<(Option<InitSection>, Vec<DashSegment>)> {
+    let attribute = |name: &str| templates.iter().find_map(|t| t.attribute(name));
+    let number = |name: &str, default: u64| {
+        attribute(name)
+            .and_then(|value| value.parse().ok())
+            .unwrap_or(default)
+    };
+    let media = attribute("media").ok_or_else(|| anyhow!("SegmentTemplate has no media"))?;
+    let timescale = number("timescale", 1).max(1);
+    let start_number = number("startNumber", 1);
+    let join = |path: String| {
+        base.join(&path)
+            .map_err(|e| anyhow!("Invalid segment URL {}: {}", path, e))
+    };
+    let init = attribute("initialization")
+        .map(|init| join(expand_template(init, vars, start_number, 0)))
+        .transpose()?
+        .map(|url| InitSection {
+            uri: url.to_string(),
+            byte_range: None,
+        });
+
+    let mut segments = Vec::new();
+    let timeline = templates
+        .iter()
+        .find_map(|t| children(*t, "SegmentTimeline").next());
+    if let Some(timeline) = timeline {
+        let end = period_duration.map(|seconds| (seconds * timescale as f64) as u64);
+        let mut time = 0u64;
+        let mut number = start_number;
+        let entries: Vec<Node> = children(timeline, "S").collect();
+        for (i, entry) in entries.iter().enumerate() {
+            let value = |name: &str| entry.attribute(name).and_then(|v| v.parse::<i64>().ok());
+            if let Some(t) = value("t") {
+                time = t.max(0) as u64;
+            }
+            let duration = value("d")
+                .filter(|d| *d > 0)
+                .ok_or_else(|| anyhow!("SegmentTimeline entry without a duration (d)"))?
+                as u64;
+            // r="-1" repeats until the next entry's start or the end of the period
+            let repeat = match value("r").unwrap_or(0) {
+                r if r >= 0 => r as u64,
+                _ => {
+                    let until = entries
+                        .get(i + 1)
+                        .and_then(|next| next.attribute("t"))
+                        .and_then(|t| t.parse().ok())
+                        .or(end)
+                        .ok_or_else(|| {
+                            anyhow!("SegmentTimeline repeats without a known period end")
+                        })?;
+                    until
+                        .saturating_sub(time)
+                        .div_ceil(duration)
+                        .saturating_sub(1)
+                }
+            };
+            for _ in 0..=repeat {
+                limits.check_segments(segments.len() + 1)?;
+                segments.push(DashSegment {
+                    url: join(expand_template(media, vars, number, time))?,
+                    duration: duration as f64 / timescale as f64,
+                    byte_range: None,
+                });
+                time += duration;
+                number += 1;
+            }
+        }
+    } else {
+        let duration = number("duration", 0);
+        if duration == 0 {
+            return Err(anyhow!(
+                "SegmentTemplate has neither a duration nor a timeline"
+            ));
+        }
+        let total = period_duration
+            .ok_or_else(|| anyhow!("SegmentTemplate needs the period or presentation duration"))?;
+        let seconds = duration as f64 / timescale as f64;
+        let count = (total / seconds).ceil() as usize;
+        limits.check_segments(count)?;
+        for i in 0..count as u64 {
+            let remaining = total - i as f64 * seconds;
+            segments.push(DashSegment {
+                url: join(expand_template(media, vars, start_number + i, i * duration))?,
+                duration: seconds.min(remaining),
+                byte_range: None,
+            });
+        }
+    }
+    Ok((init, segments))
+}
+
+/// Segments of a `<SegmentList>` (`<SegmentURL media mediaRange>` entries).
+fn list_segments(list: Node, base: &Url) -> Result<(Option<InitSection>, Vec<DashSegment>)> {
+    let timescale = list
+        .attribute("timescale")
+        .and_then(|t| t.parse::<u64>().ok())
+        .unwrap_or(1)
+        .max(1);
+    let duration = list
+        .attribute("duration")
+        .and_then(|d| d.parse::<u64>().ok())
+        .unwrap_or(0) as f64
+        / timescale as f64;
+    let init = match children(list, "Initialization").next() {
+        Some(init) => Some(InitSection {
+            uri: match init.attribute("sourceURL") {
+                Some(source) => base.join(source)?.to_string(),
+                None => base.to_string(),
+            },
+            byte_range: init.attribute("range").and_then(parse_range),
+        }),
+        None => None,
+    };
+    let segments = children(list, "SegmentURL")
+        .map(|entry| {
+            Ok(DashSegment {
+                url: match entry.attribute("media") {
+                    Some(media) => base.join(media)?,
+                    None => base.clone(),
+                },
+                duration,
+                byte_range: entry.attribute("mediaRange").and_then(parse_range),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok((init, segments))
+}
+
+/// DASH byte range `first-last` (inclusive).
+fn parse_range(value: &str) -> Option<ByteRange> {
+    let (first, last) = value.trim().split_once('-')?;
+    let first: u64 = first.parse().ok()?;
+    let last: u64 = last.parse().ok()?;
+    (last >= first).then_some(ByteRange {
+        offset: first,
+        length: last - first + 1,
+    })
+}
+
+/// ISO 8601 duration as used by MPDs (`PT1H2M3.5S`, `P1DT2H`), in seconds.
+pub fn parse_iso_duration(value: &str) -> Option<f64> {
+    let rest = value.trim().strip_prefix('P')?;
+    let (date, time) = rest.split_once('T').unwrap_or((rest, ""));
+    let mut seconds = 0.0;
+    for (part, units) in [
+        (
+            date,
+            &[
+                ('Y', 365.0 * 86400.0),
+                ('M', 30.0 * 86400.0),
+                ('W', 7.0 * 86400.0),
+                ('D', 86400.0),
+            ][..],
+        ),
+        (time, &[('H', 3600.0), ('M', 60.0), ('S', 1.0)][..]),
+    ] {
+        let mut number = String::new();
+        for c in part.chars() {
+            if c.is_ascii_digit() || c == '.' {
+                number.push(c);
+                continue;
+            }
+            let (_, factor) = units.iter().find(|(unit, _)| *unit == c)?;
+            seconds += number.parse::<f64>().ok()? * factor;
+            number.clear();
+        }
+        if !number.is_empty() {
+            return None;
+        }
+    }
+    Some(seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url() -> Url {
+        Url::parse("https://cdn.example.com/show/manifest.mpd").unwrap()
+    }
+
+    #[test]
+    fn template_with_timeline_and_number_format() {
+        let mpd = r#"<?xml version="1.0"?>
+<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static" mediaPresentationDuration="PT10S">
+  <Period>
+    <AdaptationSet mimeType="video/mp4">
+      <SegmentTemplate timescale="1000" initialization="$RepresentationID$/init.mp4"
+          media="$RepresentationID$/seg-$Number%03d$.m4s" startNumber="5">
+        <SegmentTimeline><S t="0" d="4000" r="1"/><S d="2000"/></SegmentTimeline>
+      </SegmentTemplate>
+      <Representation id="v720" bandwidth="2000000" width="1280" height="720"/>
+      <Representation id="v360" bandwidth="600000" width="640" height="360"/>
+    </AdaptationSet>
+    <AdaptationSet mimeType="audio/mp4" lang="ja">
+      <BaseURL>audio/</BaseURL>
+      <Representation id="a1" bandwidth="128000">
+        <SegmentList duration="5">
+          <Initialization sourceURL="a.mp4" range="0-799"/>
+          <SegmentURL media="a.mp4" mediaRange="800-1799"/>
+          <SegmentURL media="a.mp4" mediaRange="1800-2999"/>
+        </SegmentList>
+      </Representation>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+        let manifest = DashManifest::parse(mpd, &url(), &PlaylistLimits::default()).unwrap();
+        let (video, audio) = manifest.select("360p").unwrap();
+        assert_eq!(video.id, "v360");
+        let urls: Vec<&str> = video.segments.iter().map(|s| s.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "https://cdn.example.com/show/v360/seg-005.m4s",
+                "https://cdn.example.com/show/v360/seg-006.m4s",
+                "https://cdn.example.com/show/v360/seg-007.m4s",
+            ]
+        );
+        assert_eq!(video.segments[2].duration, 2.0);
+        assert_eq!(
+            video.init.as_ref().unwrap().uri,
+            "https://cdn.example.com/show/v360/init.mp4"
+        );
+
+        let audio = audio.unwrap();
+        assert_eq!(audio.language.as_deref(), Some("ja"));
+        assert_eq!(
+            audio.segments[1].byte_range,
+            Some(ByteRange {
+                offset: 1800,
+                length: 1200
+            })
+        );
+        let playlist = audio.to_media_playlist();
+        assert!(playlist.contains(
+            "#EXT-X-MAP:URI=\"https://cdn.example.com/show/audio/a.mp4\",BYTERANGE=\"800@0\""
+        ));
+        assert!(playlist.contains("#EXT-X-BYTERANGE:1000@800\n"));
+    }
+
+    #[test]
+    fn iso_durations() {
+        assert_eq!(parse_iso_duration("PT1H2M3.5S"), Some(3723.5));
+        assert_eq!(parse_iso_duration("P1DT2H"), Some(93600.0));
+        assert_eq!(parse_iso_duration("PT0S"), Some(0.0));
+        assert_eq!(parse_iso_duration("1H"), None);
+    }
+}
@@ -0,0 +1,102 @@
+use reqwest::Url;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Mutex;
+
+/// Coarse classification of a single failed segment attempt.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ErrorKind {
+    Status(u16),
+    Timeout,
+    Connect,
+    Body,
+    Decrypt,
+    /// 2xx response with a `Content-Type` that is not accepted for segments
+    ContentType,
+    Io,
+    Other,
+}
+
+impl ErrorKind {
+    /// Maps a reqwest transport error to an error kind.
+    pub fn from_reqwest(e: &reqwest::Error) -> Self {
+        if let Some(status) = e.status() {
+            ErrorKind::Status(status.as_u16())
+        } else if e.is_timeout() {
+            ErrorKind::Timeout
+        } else if e.is_connect() {
+            ErrorKind::Connect
+        } else if e.is_body() || e.is_decode() {
+            ErrorKind::Body
+        } else {
+            ErrorKind::Other
+        }
+    }
+
+    /// Failure that usually passes once the origin is back (5xx, timeout, refused connection).
+    pub fn is_outage(self) -> bool {
+        matches!(
+            self,
+            ErrorKind::Status(500..=599) | ErrorKind::Timeout | ErrorKind::Connect
+        )
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::Status(code) => write!(f, "HTTP {}", code),
+            ErrorKind::Timeout => write!(f, "timeout"),
+            ErrorKind::Connect => write!(f, "connection"),
+            ErrorKind::Body => write!(f, "body read"),
+            ErrorKind::Decrypt => write!(f, "decryption"),
+            ErrorKind::ContentType => write!(f, "content type"),
+            ErrorKind::Io => write!(f, "file I/O"),
+            ErrorKind::Other => write!(f, "other"),
+        }
+    }
+}
+
+/// Thread-safe tally of segment errors, grouped by kind and host.
+#[derive(Default)]
+pub struct ErrorSummary {
+    counts: Mutex<HashMap<(ErrorKind, String), usize>>,
+}
+
+impl ErrorSummary {
+    /// Records one failed attempt against the host of `url`.
+    pub fn record(&self, kind: ErrorKind, url: &str) {
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_string()))
+            .unwrap_or_else(|| "-".to_string());
+        let mut counts = self.counts.lock().unwrap();
+        *counts.entry((kind, host)).or_insert(0) += 1;
+    }
+
+    /// Renders a compact table (most frequent first), or `None` when no error was recorded.
+    pub fn render_table(&self) -> Option<String> {
+        let counts = self.counts.lock().unwrap();
+        if counts.is_empty() {
+            return None;
+        }
+
+        let mut rows: Vec<(&(ErrorKind, String), &usize)> = counts.iter().collect();
+        rows.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+
+        let total: usize = counts.values().sum();
+        let mut table = format!(
+            "📊 Segment error summary ({} errors, retries included):\n    {:>6}  {:<12}  Host",
+            total, "Count", "Kind"
+        );
+        for ((kind, host), count) in rows {
+            table.push_str(&format!(
+                "\n    {:>6}  {:<12}  {}",
+                count,
+                kind.to_string(),
+                host
+            ));
+        }
+        Some(table)
+    }
+}
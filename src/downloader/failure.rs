@@ -0,0 +1,88 @@
+// Broad failure categories of a job, so callers (the CLI's exit codes) can tell a network
+// problem from a decryption or FFmpeg one without parsing messages.
+
+use anyhow::Error;
+use std::fmt;
+
+use super::cancel::Cancelled;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureKind {
+    Network,
+    /// The playlist or manifest is malformed, unsupported or over the limits
+    Parse,
+    Decrypt,
+    Ffmpeg,
+    Cancelled,
+    Other,
+}
+
+impl FailureKind {
+    /// Stable name used in machine-readable reports
+    pub fn name(self) -> &'static str {
+        match self {
+            FailureKind::Network => "network",
+            FailureKind::Parse => "parse",
+            FailureKind::Decrypt => "decrypt",
+            FailureKind::Ffmpeg => "ffmpeg",
+            FailureKind::Cancelled => "cancelled",
+            FailureKind::Other => "other",
+        }
+    }
+
+    /// Category of `error`: an explicit tag anywhere in the chain wins, any `reqwest` error
+    /// counts as a network failure.
+    pub fn of(error: &Error) -> Self {
+        for cause in error.chain() {
+            if cause.is::<Cancelled>() {
+                return FailureKind::Cancelled;
+            }
+            if let Some(failure) = cause.downcast_ref::<Failure>() {
+                return failure.kind;
+            }
+            if cause.is::<reqwest::Error>() {
+                return FailureKind::Network;
+            }
+        }
+        FailureKind::Other
+    }
+}
+
+/// Error message tagged with its category; displays as the plain message.
+#[derive(Debug)]
+pub struct Failure {
+    kind: FailureKind,
+    message: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Failure {}
+
+fn tagged(kind: FailureKind, message: impl fmt::Display) -> Error {
+    Failure {
+        kind,
+        message: message.to_string(),
+    }
+    .into()
+}
+
+pub fn network(message: impl fmt::Display) -> Error {
+    tagged(FailureKind::Network, message)
+}
+
+pub fn parse(message: impl fmt::Display) -> Error {
+    tagged(FailureKind::Parse, message)
+}
+
+pub fn decrypt(message: impl fmt::Display) -> Error {
+    tagged(FailureKind::Decrypt, message)
+}
+
+pub fn ffmpeg(message: impl fmt::Display) -> Error {
+    tagged(FailureKind::Ffmpeg, message)
+}
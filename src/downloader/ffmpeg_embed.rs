@@ -3,11 +3,14 @@
 // - extracted to user cache dir under a checksumed folder
 // - verifies checksum, executable bit, and optional "ffmpeg -version" probe
 // - extracts only on first-run / when checksum changes
+// - remembers a successful probe for PROBE_TTL, so jobs skip the `-version` run
 
 use std::fs::{self, OpenOptions};
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, SystemTime};
 
 use anyhow::{Result, anyhow};
 use dirs::cache_dir;
@@ -32,11 +35,23 @@ const FFMPEG_FILENAME: &str = "ffmpeg.exe";
 #[cfg(not(target_os = "windows"))]
 const FFMPEG_FILENAME: &str = "ffmpeg";
 
-/// Compute sha256 checksum of the compressed payload.
-fn compressed_checksum() -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(COMPRESSED_FFMPEG);
-    hex::encode(hasher.finalize())
+/// Marker next to the executable; its modification time is the last successful probe
+const PROBE_STAMP_FILENAME: &str = "probe-ok";
+
+/// How long a successful probe is trusted before `ensure()` runs `-version` again
+const PROBE_TTL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// Serializes `ensure()` so parallel jobs don't extract over each other
+static ENSURE_LOCK: Mutex<()> = Mutex::new(());
+
+/// Compute sha256 checksum of the compressed payload (once per process).
+fn compressed_checksum() -> &'static str {
+    static CHECKSUM: OnceLock<String> = OnceLock::new();
+    CHECKSUM.get_or_init(|| {
+        let mut hasher = Sha256::new();
+        hasher.update(COMPRESSED_FFMPEG);
+        hex::encode(hasher.finalize())
+    })
 }
 
 /// Return a platform-scoped cache directory path: <cache_dir>/hls-downloader/embedded-ffmpeg/<checksum>/
@@ -48,6 +63,24 @@ fn ffmpeg_cache_dir() -> Result<PathBuf> {
     Ok(dir)
 }
 
+/// Extractions of other builds' FFmpeg next to the current one (left behind by updates).
+pub fn stale_extractions() -> Result<Vec<PathBuf>> {
+    let current = ffmpeg_cache_dir()?;
+    let Some(root) = current.parent() else {
+        return Ok(Vec::new());
+    };
+    let entries = match fs::read_dir(root) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir() && *path != current)
+        .collect())
+}
+
 /// Ensure an executable bit on unix platforms. No-op on Windows.
 fn set_executable_permissions(path: &Path) -> io::Result<()> {
     #[cfg(unix)]
@@ -84,6 +117,31 @@ fn probe_ffmpeg(exec_path: &Path) -> Result<()> {
     }
 }
 
+/// Whether the stamp in `cache_dir` records a probe younger than `PROBE_TTL`.
+fn probe_is_fresh(cache_dir: &Path) -> bool {
+    fs::metadata(cache_dir.join(PROBE_STAMP_FILENAME))
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|probed_at| SystemTime::now().duration_since(probed_at).ok())
+        .is_some_and(|age| age < PROBE_TTL)
+}
+
+/// Probes `exec_path` and records the success in the stamp file (removed on failure).
+fn probe_and_stamp(exec_path: &Path) -> Result<()> {
+    let stamp = exec_path.with_file_name(PROBE_STAMP_FILENAME);
+    match probe_ffmpeg(exec_path) {
+        Ok(()) => {
+            // Best effort: without a stamp the next job simply probes again
+            let _ = fs::write(&stamp, []);
+            Ok(())
+        }
+        Err(e) => {
+            let _ = fs::remove_file(&stamp);
+            Err(e)
+        }
+    }
+}
+
 /// Extracts the embedded compressed payload into `target_path`.
 fn extract_to(target_path: &Path) -> Result<()> {
     // Create parent directory if missing
@@ -118,13 +176,20 @@ pub struct FFmpegHandle {
 
 impl FFmpegHandle {
     /// Ensure ffmpeg is present in cache and valid. This extracts on first-run or when checksum changes.
+    /// A probe that succeeded within `PROBE_TTL` is trusted without running ffmpeg again.
+    /// Blocks on process spawns and file I/O; async code should use `ensure_async()`.
     pub fn ensure() -> Result<Self> {
+        let _guard = ENSURE_LOCK.lock().unwrap_or_else(|e| e.into_inner());
         let cache_dir = ffmpeg_cache_dir()?;
         let exec_path = cache_dir.join(FFMPEG_FILENAME);
 
+        if exec_path.exists() && probe_is_fresh(&cache_dir) {
+            return Ok(FFmpegHandle { exec_path });
+        }
+
         // if exec exists, do a cheap probe to ensure it's usable
         if exec_path.exists() {
-            if probe_ffmpeg(&exec_path).is_ok() {
+            if probe_and_stamp(&exec_path).is_ok() {
                 return Ok(FFmpegHandle { exec_path });
             }
             // If probe fails, remove and re-extract
@@ -135,7 +200,7 @@ impl FFmpegHandle {
         extract_to(&exec_path)?;
 
         // Verify with probe; if it fails, remove and error out
-        if let Err(e) = probe_ffmpeg(&exec_path) {
+        if let Err(e) = probe_and_stamp(&exec_path) {
             let _ = fs::remove_file(&exec_path);
             return Err(anyhow!("ffmpeg probe after extraction failed: {}", e));
         }
@@ -143,6 +208,21 @@ impl FFmpegHandle {
         Ok(FFmpegHandle { exec_path })
     }
 
+    /// `ensure()` on the blocking thread pool, for callers on the async runtime.
+    pub async fn ensure_async() -> Result<Self> {
+        tokio::task::spawn_blocking(Self::ensure)
+            .await
+            .map_err(|e| anyhow!("FFmpeg setup task failed to join: {}", e))?
+    }
+
+    /// Drops the remembered probe so the next `ensure()` checks (and if needed re-extracts)
+    /// the binary; called when the cached executable fails to start.
+    pub fn forget_probe() {
+        if let Ok(cache_dir) = ffmpeg_cache_dir() {
+            let _ = fs::remove_file(cache_dir.join(PROBE_STAMP_FILENAME));
+        }
+    }
+
     /// Path to the ffmpeg executable
     pub fn path(&self) -> &Path {
         &self.exec_path
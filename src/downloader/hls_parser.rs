@@ -1,108 +1,1261 @@
 use anyhow::{Result, anyhow};
+use flate2::read::GzDecoder;
 use hex;
 use reqwest::{Client, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
 use std::time::Duration;
 use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
 
+use super::failure;
+use super::limits::PlaylistLimits;
+use super::util::{parse_date_time, parse_time_offset};
+use super::{cancel, dash_parser, progressive, signed_url};
+
+/// AES-128 key length, also the AES block / IV length for both key sizes
 pub const KEY_LEN: usize = 16;
+/// Key length of the nonstandard AES-256-CBC variant
+pub const AES256_KEY_LEN: usize = 32;
 pub const MAX_RETRIES: usize = 5;
+/// Seconds an ad break may be shorter than declared and still count as over
+const AD_DURATION_TOLERANCE: f64 = 0.1;
+/// gzip magic bytes; some origins serve compressed playlists without `Content-Encoding`
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
 
 // Stores segment information, including index
+#[derive(Clone)]
 pub struct Segment {
     pub path: String,
+    /// Position used for file names and ordering (the media sequence unless it restarts)
     pub index: usize,
+    /// Declared media sequence number, used to derive the IV
+    pub sequence: u64,
+    pub duration: f64,      // Seconds, from #EXTINF
+    pub key: Option<usize>, // Index into the playlist's keys; None for clear segments
+    /// Sub-range of `path` from `#EXT-X-BYTERANGE`; None for the whole resource
+    pub byte_range: Option<ByteRange>,
+    /// `#EXT-X-MAP` init section (fMP4/CMAF segments); None for MPEG-TS
+    pub init: Option<InitSection>,
+    /// Preceded by `#EXT-X-DISCONTINUITY`: timestamps (and possibly encoding) restart here
+    pub discontinuity: bool,
+    /// Marked with `#EXT-X-GAP`: listed for timing only, the resource is missing
+    pub gap: bool,
+    /// Inside an ad break (`#EXT-X-CUE-OUT`/`#EXT-X-CUE-IN`, or a SCTE-35 `#EXT-X-DATERANGE`)
+    pub ad: bool,
+    /// Wall-clock start (Unix seconds) from `#EXT-X-PROGRAM-DATE-TIME`, carried forward and
+    /// backward by the segment durations; None if the playlist has no such tag
+    pub program_date_time: Option<f64>,
+}
+
+/// Wall-clock window a recording is clipped to (`#EXT-X-PROGRAM-DATE-TIME` based)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ClipWindow {
+    /// Unix seconds
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+}
+
+impl ClipWindow {
+    /// Parses the user's start/end times (see `util::parse_date_time`).
+    pub fn parse(start: Option<&str>, end: Option<&str>) -> Result<Self> {
+        let parse = |value: Option<&str>, what: &str| {
+            value
+                .filter(|v| !v.trim().is_empty())
+                .map(|v| {
+                    parse_date_time(v).ok_or_else(|| {
+                        anyhow!(
+                            "Invalid clip {} time: {} (expected e.g. 2024-05-01T20:00:00Z)",
+                            what,
+                            v
+                        )
+                    })
+                })
+                .transpose()
+        };
+        let window = Self {
+            start: parse(start, "start")?,
+            end: parse(end, "end")?,
+        };
+        if let (Some(start), Some(end)) = (window.start, window.end)
+            && end <= start
+        {
+            return Err(anyhow!("The clip end time must be after its start time"));
+        }
+        Ok(window)
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.start.is_some() || self.end.is_some()
+    }
+
+    /// The segment overlaps the window; segments without a wall-clock time never do.
+    pub fn contains(&self, segment: &Segment) -> bool {
+        let Some(begin) = segment.program_date_time else {
+            return false;
+        };
+        self.start
+            .is_none_or(|start| begin + segment.duration > start)
+            && self.end.is_none_or(|end| begin < end)
+    }
+
+    /// The segment starts at or after the end of the window.
+    pub fn is_past(&self, segment: &Segment) -> bool {
+        segment
+            .program_date_time
+            .zip(self.end)
+            .is_some_and(|(begin, end)| begin >= end)
+    }
+}
+
+/// Part of a playlist to download, as offsets from its first segment (summed `#EXTINF`)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TimeRange {
+    /// Seconds
+    pub start: Option<f64>,
+    pub end: Option<f64>,
+}
+
+impl TimeRange {
+    /// Parses the user's start/end positions (see `util::parse_time_offset`).
+    pub fn parse(start: Option<&str>, end: Option<&str>) -> Result<Self> {
+        let parse = |value: Option<&str>, what: &str| {
+            value
+                .filter(|v| !v.trim().is_empty())
+                .map(|v| {
+                    parse_time_offset(v).ok_or_else(|| {
+                        anyhow!("Invalid {} time: {} (expected e.g. 1:02:30 or 90)", what, v)
+                    })
+                })
+                .transpose()
+        };
+        let range = Self {
+            start: parse(start, "start")?,
+            end: parse(end, "end")?,
+        };
+        if let (Some(start), Some(end)) = (range.start, range.end)
+            && end <= start
+        {
+            return Err(anyhow!("The end time must be after the start time"));
+        }
+        Ok(range)
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.start.is_some() || self.end.is_some()
+    }
+
+    /// Keeps the segments that span the range. Also returns the offset at which the first kept
+    /// segment starts, so the merged file can be trimmed to the exact times.
+    pub fn select(&self, segments: Vec<Segment>) -> (Vec<Segment>, f64) {
+        let mut offset = 0.0;
+        let mut first_offset = None;
+        let mut kept = Vec::new();
+        for segment in segments {
+            let begin = offset;
+            offset += segment.duration;
+            if self.start.is_none_or(|start| offset > start)
+                && self.end.is_none_or(|end| begin < end)
+            {
+                first_offset.get_or_insert(begin);
+                kept.push(segment);
+            }
+        }
+        (kept, first_offset.unwrap_or(0.0))
+    }
+}
+
+/// Length of a sample download: the first segments of the playlist
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SampleLength {
+    Segments(usize),
+    Seconds(f64),
+}
+
+impl SampleLength {
+    /// `3` (segments) or `30s` (seconds, rounded up to whole segments)
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        match value.strip_suffix(['s', 'S']) {
+            Some(seconds) => seconds
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|s| s.is_finite() && *s > 0.0)
+                .map(Self::Seconds),
+            None => value
+                .parse::<usize>()
+                .ok()
+                .filter(|n| *n > 0)
+                .map(Self::Segments),
+        }
+    }
+
+    /// Same form as `parse` accepts
+    pub fn name(&self) -> String {
+        match self {
+            Self::Segments(count) => count.to_string(),
+            Self::Seconds(seconds) => format!("{}s", seconds),
+        }
+    }
+
+    /// The first segments, up to the sample length.
+    pub fn select(&self, segments: Vec<Segment>) -> Vec<Segment> {
+        match *self {
+            Self::Segments(count) => segments.into_iter().take(count).collect(),
+            Self::Seconds(seconds) => {
+                let mut offset = 0.0;
+                segments
+                    .into_iter()
+                    .take_while(|segment| {
+                        let begin = offset;
+                        offset += segment.duration;
+                        begin < seconds
+                    })
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Ad-break signal of a playlist line
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AdMarker {
+    /// A break starts with the next segment; it lasts the given seconds if declared
+    Out(Option<f64>),
+    /// Still inside a break (the playlist window starts in the middle of one)
+    Continue,
+    In,
+}
+
+/// Reads `#EXT-X-CUE-OUT[:<duration>]`, `#EXT-X-CUE-OUT-CONT`, `#EXT-X-CUE-IN` and
+/// `#EXT-X-DATERANGE` tags with `SCTE35-OUT`/`SCTE35-IN` attributes.
+fn ad_marker(line: &str) -> Option<AdMarker> {
+    if line.starts_with("#EXT-X-CUE-OUT-CONT") {
+        Some(AdMarker::Continue)
+    } else if let Some(rest) = line.strip_prefix("#EXT-X-CUE-OUT") {
+        // `:30`, `:DURATION=30` or nothing
+        let value = rest.strip_prefix(':').unwrap_or_default();
+        let attributes = AttributeList::parse(value);
+        let duration = attributes
+            .get("DURATION")
+            .unwrap_or(value)
+            .trim()
+            .parse::<f64>()
+            .ok();
+        Some(AdMarker::Out(duration.filter(|d| *d > 0.0)))
+    } else if line.starts_with("#EXT-X-CUE-IN") {
+        Some(AdMarker::In)
+    } else if let Some(list) = line.strip_prefix("#EXT-X-DATERANGE:") {
+        let attributes = AttributeList::parse(list);
+        if attributes.get("SCTE35-OUT").is_some() {
+            let duration = attributes
+                .get("DURATION")
+                .or_else(|| attributes.get("PLANNED-DURATION"))
+                .and_then(|d| d.trim().parse::<f64>().ok());
+            Some(AdMarker::Out(duration.filter(|d| *d > 0.0)))
+        } else if attributes.get("SCTE35-IN").is_some() {
+            Some(AdMarker::In)
+        } else {
+            None
+        }
+    } else {
+        None
+    }
+}
+
+/// Byte sub-range of a resource (`#EXT-X-BYTERANGE`, or `BYTERANGE=` on `#EXT-X-MAP`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ByteRange {
+    pub offset: u64,
+    pub length: u64,
+}
+
+impl ByteRange {
+    /// Parses `<length>[@<offset>]`; without an offset the range starts at `default_offset`.
+    pub fn parse(value: &str, default_offset: u64) -> Option<Self> {
+        let (length, offset) = match value.trim().split_once('@') {
+            Some((length, offset)) => (length, Some(offset)),
+            None => (value.trim(), None),
+        };
+        let length = length.trim().parse::<u64>().ok().filter(|l| *l > 0)?;
+        let offset = match offset {
+            Some(offset) => offset.trim().parse::<u64>().ok()?,
+            None => default_offset,
+        };
+        Some(ByteRange { offset, length })
+    }
+
+    pub fn end(&self) -> u64 {
+        self.offset + self.length
+    }
+
+    /// `Range:` header value (the end is inclusive)
+    pub fn header_value(&self) -> String {
+        format!("bytes={}-{}", self.offset, self.end() - 1)
+    }
+
+    /// The addressed bytes of a response body: a `206` body is the range itself, while a server
+    /// ignoring `Range:` answers `200` with the whole resource. None if the body is too short.
+    pub fn select<'a>(&self, body: &'a [u8], partial: bool) -> Option<&'a [u8]> {
+        if partial {
+            body.get(..self.length as usize)
+        } else {
+            body.get(self.offset as usize..self.end() as usize)
+        }
+    }
+}
+
+/// `#EXT-X-MAP` media initialization section
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InitSection {
+    pub uri: String,
+    pub byte_range: Option<ByteRange>,
 }
 
 // Stores encryption information
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct EncryptionInfo {
     pub key_url: Url,
     pub method: String,
-    pub key_bytes: Option<[u8; KEY_LEN]>,
     pub iv_bytes: Option<[u8; KEY_LEN]>,
+    /// Every attribute of the tag (`KEYFORMAT`, `KEYFORMATVERSIONS`, ...), as written
+    pub attributes: AttributeList,
+}
+
+impl EncryptionInfo {
+    /// IV of the segment with media sequence number `sequence`: the tag's `IV`, or else the
+    /// sequence number as a 128-bit big-endian integer (RFC 8216 §5.2).
+    pub fn iv_for(&self, sequence: u64) -> [u8; KEY_LEN] {
+        self.iv_bytes.unwrap_or_else(|| {
+            let mut iv = [0u8; KEY_LEN];
+            iv[8..].copy_from_slice(&sequence.to_be_bytes());
+            iv
+        })
+    }
+}
+
+/// Decryption key as served by the key URI; the cipher follows the key length
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SegmentKey {
+    Aes128([u8; KEY_LEN]),
+    /// Nonstandard: some streams serve 32-byte keys and encrypt with AES-256-CBC
+    Aes256([u8; AES256_KEY_LEN]),
+}
+
+impl SegmentKey {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self> {
+        if let Ok(key) = <[u8; KEY_LEN]>::try_from(bytes) {
+            Ok(SegmentKey::Aes128(key))
+        } else if let Ok(key) = <[u8; AES256_KEY_LEN]>::try_from(bytes) {
+            Ok(SegmentKey::Aes256(key))
+        } else {
+            Err(anyhow!(
+                "Key file length error: Expected 16 (AES-128) or 32 (AES-256) bytes, got {}",
+                bytes.len()
+            ))
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            SegmentKey::Aes128(key) => key,
+            SegmentKey::Aes256(key) => key,
+        }
+    }
+}
+
+/// Result of a (possibly conditional) playlist request
+pub enum FetchOutcome {
+    /// The body and the URL it was served from after redirects (the base for relative URIs)
+    Modified {
+        body: String,
+        url: Url,
+    },
+    NotModified,
+}
+
+/// Fetches a playlist while remembering its cache validators (ETag / Last-Modified), so repeated
+/// reloads of the same URL can be answered with `304 Not Modified` instead of the full body.
+#[derive(Default)]
+pub struct PlaylistFetcher {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    limits: PlaylistLimits,
+}
+
+impl PlaylistFetcher {
+    /// A fetcher that enforces the job's `limits` on every response.
+    pub fn new(limits: PlaylistLimits) -> Self {
+        Self {
+            limits,
+            ..Default::default()
+        }
+    }
+
+    pub async fn fetch(
+        &mut self,
+        client: &Client,
+        playlist_url: &Url,
+        send_log: &impl Fn(String),
+    ) -> Result<FetchOutcome> {
+        let mut request = client.get(playlist_url.as_str());
+        if let Some(etag) = &self.etag {
+            request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &self.last_modified {
+            request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+        }
+
+        let response = request.send().await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(FetchOutcome::NotModified);
+        }
+        let mut response = response.error_for_status()?;
+        let url = response.url().clone();
+        // A media file instead of a playlist is handed back before the size limit rejects it
+        let content_type = response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
+        let length = response.content_length();
+        let first = response.chunk().await?.unwrap_or_default();
+        if let Some(extension) = progressive::sniff(content_type.as_deref(), &first, &url) {
+            return Err(progressive::MediaFile {
+                length,
+                validator: progressive::validator(response.headers()),
+                url,
+                extension,
+            }
+            .into());
+        }
+        let limits = self.limits;
+        if let Some(length) = length {
+            limits.check_playlist_bytes(length)?;
+        }
+
+        let header_value = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|v: &reqwest::header::HeaderValue| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+        if let Some(date) = header_value(reqwest::header::DATE) {
+            signed_url::record_server_date(&url, &date);
+        }
+        self.etag = header_value(reqwest::header::ETAG);
+        self.last_modified = header_value(reqwest::header::LAST_MODIFIED);
+
+        // Read in chunks so a response without Content-Length cannot exceed the limit either
+        let mut body = first.to_vec();
+        limits.check_playlist_bytes(body.len() as u64)?;
+        while let Some(chunk) = response.chunk().await? {
+            body.extend_from_slice(&chunk);
+            limits.check_playlist_bytes(body.len() as u64)?;
+        }
+        Ok(FetchOutcome::Modified {
+            body: decode_playlist_body(&body, &limits, send_log)?,
+            url,
+        })
+    }
+}
+
+/// Playlist text from a response body, inflating gzip data the HTTP layer did not decode.
+pub fn decode_playlist_body(
+    body: &[u8],
+    limits: &PlaylistLimits,
+    send_log: &impl Fn(String),
+) -> Result<String> {
+    if !body.starts_with(&GZIP_MAGIC) {
+        return Ok(String::from_utf8_lossy(body).into_owned());
+    }
+    send_log(
+        "⚠️ Warning: Playlist is gzip-compressed without a Content-Encoding header; decompressing it."
+            .to_string(),
+    );
+    let max_bytes = limits.max_playlist_bytes;
+    let mut text = String::new();
+    GzDecoder::new(body)
+        .take(max_bytes.saturating_add(1))
+        .read_to_string(&mut text)
+        .map_err(|e| failure::parse(format!("Failed to decompress gzip playlist: {}", e)))?;
+    limits.check_playlist_bytes(text.len() as u64)?;
+    Ok(text)
+}
+
+/// `#EXT-X-STREAM-INF` entry of a master playlist
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub uri: Url,
+    pub bandwidth: u64,
+    pub resolution: Option<(u32, u32)>,
+    pub codecs: Option<String>,
+    pub audio_group: Option<String>,
+    pub subtitles_group: Option<String>,
+}
+
+impl Variant {
+    pub fn label(&self) -> String {
+        let mut label = match self.resolution {
+            Some((width, height)) => format!("{}x{}", width, height),
+            None => "audio/unknown".to_string(),
+        };
+        label.push_str(&format!(", {} kbps", self.bandwidth / 1000));
+        if let Some(codecs) = &self.codecs {
+            label.push_str(&format!(", {}", codecs));
+        }
+        label
+    }
+}
+
+/// `#EXT-X-I-FRAME-STREAM-INF` entry: a playlist of only the key frames of one variant
+#[derive(Debug, Clone)]
+pub struct IFrameStream {
+    pub uri: Url,
+    pub bandwidth: u64,
+    pub resolution: Option<(u32, u32)>,
+    pub codecs: Option<String>,
+}
+
+impl IFrameStream {
+    pub fn label(&self) -> String {
+        match self.resolution {
+            Some((width, height)) => format!("{}x{} I-frames", width, height),
+            None => "I-frames".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenditionKind {
+    Audio,
+    Subtitles,
+    Other,
+}
+
+/// `#EXT-X-MEDIA` entry (alternative audio, subtitles, ...)
+#[derive(Debug, Clone)]
+pub struct Rendition {
+    pub kind: RenditionKind,
+    pub group_id: String,
+    pub name: String,
+    pub language: Option<String>,
+    pub default: bool,
+    /// The client may pick it on its own (e.g. by the user's language)
+    pub autoselect: bool,
+    /// None when the rendition is muxed into the variant streams
+    pub uri: Option<Url>,
+}
+
+impl Rendition {
+    /// Human-readable label, e.g. `Japanese (default)` or `English – Commentary`.
+    pub fn label(&self) -> String {
+        let language = self
+            .language
+            .as_deref()
+            .map(|code| language_name(code).unwrap_or(code));
+        let mut label = match language {
+            Some(language) if self.name.is_empty() || self.name.eq_ignore_ascii_case(language) => {
+                language.to_string()
+            }
+            Some(language) => format!("{} – {}", language, self.name),
+            None => self.name.clone(),
+        };
+        if self.default {
+            label.push_str(" (default)");
+        }
+        label
+    }
+
+    /// Metadata for the output track the rendition ends up in.
+    pub fn track_metadata(&self) -> TrackMetadata {
+        TrackMetadata {
+            language: self.language.clone(),
+            name: Some(self.name.clone()).filter(|name| !name.is_empty()),
+            default: self.default,
+        }
+    }
+
+    /// Matches a user selection by name or language (case-insensitive).
+    pub fn matches(&self, wanted: &str) -> bool {
+        self.name.eq_ignore_ascii_case(wanted)
+            || self
+                .language
+                .as_deref()
+                .is_some_and(|language| language.eq_ignore_ascii_case(wanted))
+    }
+}
+
+/// `LANGUAGE`/`NAME`/`DEFAULT` of a separate rendition, written to the output track by FFmpeg
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrackMetadata {
+    /// BCP 47 tag as in the playlist (`ja`, `en-US`, ...)
+    pub language: Option<String>,
+    pub name: Option<String>,
+    pub default: bool,
+}
+
+impl TrackMetadata {
+    /// Parses `language=ja,default,name=Japanese` (as given to `--audio-track`); `name=` comes
+    /// last and takes the rest, so names may contain commas.
+    pub fn parse(spec: &str) -> Option<Self> {
+        let mut track = TrackMetadata::default();
+        let mut rest = spec.trim();
+        while !rest.is_empty() {
+            if let Some(name) = rest.strip_prefix("name=") {
+                track.name = Some(name.to_string()).filter(|name| !name.is_empty());
+                break;
+            }
+            let (item, remaining) = rest.split_once(',').unwrap_or((rest, ""));
+            match item.trim() {
+                "" => {}
+                "default" => track.default = true,
+                item => {
+                    let language = item.strip_prefix("language=")?;
+                    track.language = Some(language.to_string()).filter(|l| !l.is_empty());
+                }
+            }
+            rest = remaining.trim_start();
+        }
+        Some(track)
+    }
+
+    /// The form `parse` reads.
+    pub fn to_spec(&self) -> String {
+        let mut items = Vec::new();
+        if let Some(language) = &self.language {
+            items.push(format!("language={}", language));
+        }
+        if self.default {
+            items.push("default".to_string());
+        }
+        if let Some(name) = &self.name {
+            items.push(format!("name={}", name));
+        }
+        items.join(",")
+    }
+}
+
+/// Common languages: primary BCP 47 subtag, ISO 639-2 code (what MP4/MKV store), English name
+const LANGUAGES: [(&str, &str, &str); 32] = [
+    ("ar", "ara", "Arabic"),
+    ("cs", "ces", "Czech"),
+    ("da", "dan", "Danish"),
+    ("de", "deu", "German"),
+    ("el", "ell", "Greek"),
+    ("en", "eng", "English"),
+    ("es", "spa", "Spanish"),
+    ("fa", "fas", "Persian"),
+    ("fi", "fin", "Finnish"),
+    ("fr", "fra", "French"),
+    ("he", "heb", "Hebrew"),
+    ("hi", "hin", "Hindi"),
+    ("hu", "hun", "Hungarian"),
+    ("id", "ind", "Indonesian"),
+    ("it", "ita", "Italian"),
+    ("ja", "jpn", "Japanese"),
+    ("ko", "kor", "Korean"),
+    ("ms", "msa", "Malay"),
+    ("nl", "nld", "Dutch"),
+    ("no", "nor", "Norwegian"),
+    ("pl", "pol", "Polish"),
+    ("pt", "por", "Portuguese"),
+    ("ro", "ron", "Romanian"),
+    ("ru", "rus", "Russian"),
+    ("sv", "swe", "Swedish"),
+    ("ta", "tam", "Tamil"),
+    ("th", "tha", "Thai"),
+    ("tl", "tgl", "Tagalog"),
+    ("tr", "tur", "Turkish"),
+    ("uk", "ukr", "Ukrainian"),
+    ("vi", "vie", "Vietnamese"),
+    ("zh", "zho", "Chinese"),
+];
+
+fn find_language(code: &str) -> Option<&'static (&'static str, &'static str, &'static str)> {
+    let primary = code.split(['-', '_']).next().unwrap_or(code);
+    LANGUAGES.iter().find(|(short, long, _)| {
+        primary.eq_ignore_ascii_case(short) || primary.eq_ignore_ascii_case(long)
+    })
+}
+
+/// English name of a language tag (`ja`, `jpn`, `pt-BR`, ...), if it is a common one.
+pub fn language_name(code: &str) -> Option<&'static str> {
+    find_language(code).map(|(_, _, name)| *name)
+}
+
+/// ISO 639-2 code for a language tag, as expected by FFmpeg's `language` stream metadata.
+pub fn iso639_2(code: &str) -> Option<&'static str> {
+    find_language(code).map(|(_, long, _)| *long)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MasterPlaylist {
+    pub variants: Vec<Variant>,
+    pub renditions: Vec<Rendition>,
+    pub iframe_streams: Vec<IFrameStream>,
+}
+
+impl MasterPlaylist {
+    /// Picks a variant: `best`/`worst` by bandwidth, `720p`/`720` by height, or a 1-based index.
+    pub fn select(&self, quality: &str) -> Option<&Variant> {
+        let quality = quality.trim().to_ascii_lowercase();
+        match quality.as_str() {
+            "best" => self.variants.iter().max_by_key(|v| v.bandwidth),
+            "worst" => self.variants.iter().min_by_key(|v| v.bandwidth),
+            _ => {
+                if let Some(height) = quality.strip_suffix('p') {
+                    let height: u32 = height.parse().ok()?;
+                    return self
+                        .variants
+                        .iter()
+                        .filter(|v| v.resolution.is_some_and(|(_, h)| h == height))
+                        .max_by_key(|v| v.bandwidth);
+                }
+                let number: usize = quality.parse().ok()?;
+                // Small numbers are list positions, larger ones heights
+                if (1..=self.variants.len()).contains(&number) {
+                    self.variants.get(number - 1)
+                } else {
+                    self.variants
+                        .iter()
+                        .filter(|v| v.resolution.is_some_and(|(_, h)| h as usize == number))
+                        .max_by_key(|v| v.bandwidth)
+                }
+            }
+        }
+    }
+
+    /// Picks the variant of the first rung of a quality ladder (`1080p`, `720p`, ...) that
+    /// matches; the best variant if none does.
+    pub fn select_ladder(&self, ladder: &[String]) -> Option<&Variant> {
+        ladder
+            .iter()
+            .find_map(|rung| self.select(rung))
+            .or_else(|| self.select("best"))
+    }
+
+    /// Renditions of `kind` in the variant's group.
+    pub fn renditions_for(&self, variant: &Variant, kind: RenditionKind) -> Vec<&Rendition> {
+        let group = match kind {
+            RenditionKind::Audio => variant.audio_group.as_deref(),
+            RenditionKind::Subtitles => variant.subtitles_group.as_deref(),
+            RenditionKind::Other => None,
+        };
+        let Some(group) = group else {
+            return Vec::new();
+        };
+        self.renditions
+            .iter()
+            .filter(|r| r.kind == kind && r.group_id == group)
+            .collect()
+    }
+
+    /// Separately delivered audio the variant should be paired with by default.
+    pub fn default_audio(&self, variant: &Variant) -> Option<&Rendition> {
+        self.default_rendition(variant, RenditionKind::Audio)
+    }
+
+    /// Subtitle rendition saved when subtitles are wanted but none was picked.
+    pub fn default_subtitles(&self, variant: &Variant) -> Option<&Rendition> {
+        self.default_rendition(variant, RenditionKind::Subtitles)
+    }
+
+    /// I-frame playlist closest to the variant's resolution (the most detailed one on a tie).
+    pub fn iframe_stream_for(&self, variant: &Variant) -> Option<&IFrameStream> {
+        let height = |resolution: Option<(u32, u32)>| resolution.map(|(_, h)| h);
+        self.iframe_streams.iter().min_by_key(|stream| {
+            let distance = match (height(stream.resolution), height(variant.resolution)) {
+                (Some(a), Some(b)) => a.abs_diff(b),
+                _ => u32::MAX,
+            };
+            (distance, std::cmp::Reverse(stream.bandwidth))
+        })
+    }
+
+    /// DEFAULT=YES, else AUTOSELECT=YES, else the first rendition of the group (with a URI).
+    fn default_rendition(&self, variant: &Variant, kind: RenditionKind) -> Option<&Rendition> {
+        let renditions = self.renditions_for(variant, kind);
+        renditions
+            .iter()
+            .find(|r| r.default)
+            .or_else(|| renditions.iter().find(|r| r.autoselect))
+            .or_else(|| renditions.first())
+            .copied()
+            .filter(|r| r.uri.is_some())
+    }
+}
+
+/// Attribute list of a tag (`NAME=value,NAME="quoted, value"`), in playlist order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AttributeList(Vec<(String, String)>);
+
+impl AttributeList {
+    /// Tokenizes an attribute list. Quoted strings may contain commas and `=` (e.g. signed key
+    /// URLs); their quotes are removed. Entries without `=` are skipped.
+    pub fn parse(list: &str) -> Self {
+        let mut attributes = Vec::new();
+        let mut rest = list.trim();
+        while !rest.is_empty() {
+            let Some(separator) = rest.find(['=', ',']) else {
+                break;
+            };
+            if rest[separator..].starts_with(',') {
+                rest = rest[separator + 1..].trim_start();
+                continue;
+            }
+            let name = rest[..separator].trim();
+            let after_name = rest[separator + 1..].trim_start();
+            let (value, after_value) = match after_name.strip_prefix('"') {
+                Some(quoted) => match quoted.find('"') {
+                    Some(end) => (&quoted[..end], &quoted[end + 1..]),
+                    // Unterminated string: take the rest of the line
+                    None => (quoted, ""),
+                },
+                None => {
+                    let end = after_name.find(',').unwrap_or(after_name.len());
+                    (after_name[..end].trim_end(), &after_name[end..])
+                }
+            };
+            if !name.is_empty() {
+                attributes.push((name.to_string(), value.to_string()));
+            }
+            // Anything between a closing quote and the next comma is not part of a value
+            rest = after_value
+                .find(',')
+                .map_or("", |comma| &after_value[comma + 1..])
+                .trim_start();
+        }
+        AttributeList(attributes)
+    }
+
+    /// Value of the first attribute called `name`.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Low-Latency HLS tags of a media playlist
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LowLatencyInfo {
+    /// `CAN-BLOCK-RELOAD=YES` in `#EXT-X-SERVER-CONTROL`: `_HLS_msn` reloads are held by the server
+    pub can_block_reload: bool,
+    /// `PART-TARGET` of `#EXT-X-PART-INF`, in seconds
+    pub part_target: Option<f64>,
+    /// `#EXT-X-PART` tags after the last complete segment (the segment being produced)
+    pub pending_parts: usize,
+    /// URI of the `#EXT-X-PRELOAD-HINT` for the next part
+    pub preload_hint: Option<String>,
+}
+
+impl LowLatencyInfo {
+    pub fn is_low_latency(&self) -> bool {
+        self.can_block_reload || self.part_target.is_some()
+    }
+}
+
+/// Reads the LL-HLS tags (`#EXT-X-SERVER-CONTROL`, `#EXT-X-PART-INF`, `#EXT-X-PART`,
+/// `#EXT-X-PRELOAD-HINT`); partial segments themselves are not downloaded.
+pub fn parse_low_latency(body: &str) -> LowLatencyInfo {
+    let mut info = LowLatencyInfo::default();
+    for line in body.lines().map(str::trim) {
+        if let Some(list) = line.strip_prefix("#EXT-X-SERVER-CONTROL:") {
+            info.can_block_reload =
+                AttributeList::parse(list).get("CAN-BLOCK-RELOAD") == Some("YES");
+        } else if let Some(list) = line.strip_prefix("#EXT-X-PART-INF:") {
+            info.part_target = AttributeList::parse(list)
+                .get("PART-TARGET")
+                .and_then(|value| value.parse::<f64>().ok());
+        } else if line.starts_with("#EXT-X-PART:") {
+            info.pending_parts += 1;
+        } else if let Some(list) = line.strip_prefix("#EXT-X-PRELOAD-HINT:") {
+            info.preload_hint = AttributeList::parse(list).get("URI").map(str::to_string);
+        } else if !line.starts_with('#') && !line.is_empty() {
+            // A complete segment: its parts are no longer pending
+            info.pending_parts = 0;
+        }
+    }
+    info
+}
+
+/// Parses a master playlist; returns None for media playlists (no `#EXT-X-STREAM-INF`).
+pub fn parse_master_playlist(body: &str, playlist_url: &Url) -> Result<Option<MasterPlaylist>> {
+    if !body.contains("#EXT-X-STREAM-INF") {
+        return Ok(None);
+    }
+    let mut master = MasterPlaylist::default();
+    let mut pending: Option<AttributeList> = None;
+
+    for line in body.lines().map(str::trim) {
+        if let Some(list) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+            pending = Some(AttributeList::parse(list));
+        } else if let Some(list) = line.strip_prefix("#EXT-X-I-FRAME-STREAM-INF:") {
+            // Unlike variants, the playlist URI is an attribute
+            let attributes = AttributeList::parse(list);
+            let get = |name: &str| attributes.get(name).map(str::to_string);
+            let Some(uri) = get("URI") else {
+                continue;
+            };
+            master.iframe_streams.push(IFrameStream {
+                uri: playlist_url.join(&uri)?,
+                bandwidth: get("BANDWIDTH").and_then(|b| b.parse().ok()).unwrap_or(0),
+                resolution: get("RESOLUTION").and_then(|r| parse_resolution(&r)),
+                codecs: get("CODECS"),
+            });
+        } else if let Some(list) = line.strip_prefix("#EXT-X-MEDIA:") {
+            let attributes = AttributeList::parse(list);
+            let get = |name: &str| attributes.get(name).map(str::to_string);
+            let kind = match get("TYPE").as_deref() {
+                Some("AUDIO") => RenditionKind::Audio,
+                Some("SUBTITLES") => RenditionKind::Subtitles,
+                _ => RenditionKind::Other,
+            };
+            master.renditions.push(Rendition {
+                kind,
+                group_id: get("GROUP-ID").unwrap_or_default(),
+                name: get("NAME").unwrap_or_default(),
+                language: get("LANGUAGE"),
+                default: get("DEFAULT").as_deref() == Some("YES"),
+                autoselect: get("AUTOSELECT").as_deref() == Some("YES"),
+                uri: get("URI").map(|uri| playlist_url.join(&uri)).transpose()?,
+            });
+        } else if !line.is_empty()
+            && !line.starts_with('#')
+            && let Some(attributes) = pending.take()
+        {
+            let get = |name: &str| attributes.get(name).map(str::to_string);
+            master.variants.push(Variant {
+                uri: playlist_url.join(line)?,
+                bandwidth: get("BANDWIDTH").and_then(|b| b.parse().ok()).unwrap_or(0),
+                resolution: get("RESOLUTION").and_then(|r| parse_resolution(&r)),
+                codecs: get("CODECS"),
+                audio_group: get("AUDIO"),
+                subtitles_group: get("SUBTITLES"),
+            });
+        }
+    }
+
+    if master.variants.is_empty() {
+        return Err(failure::parse(
+            "Master playlist contains no variant streams",
+        ));
+    }
+    Ok(Some(master))
+}
+
+/// `1280x720`
+fn parse_resolution(value: &str) -> Option<(u32, u32)> {
+    let (width, height) = value.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+/// Playlist text with the URL it was finally served from
+pub struct FetchedPlaylist {
+    /// Final URL after redirects; relative segment and key URIs are resolved against it
+    pub url: Url,
+    pub body: String,
+}
+
+/// Downloads a playlist's text, following redirects.
+pub async fn fetch_playlist_text(
+    client: &Client,
+    playlist_url: &Url,
+    limits: &PlaylistLimits,
+    send_log: &impl Fn(String),
+    cancel: &CancellationToken,
+) -> Result<FetchedPlaylist> {
+    let mut fetcher = PlaylistFetcher::new(*limits);
+    match cancel::or_cancel(cancel, fetcher.fetch(client, playlist_url, send_log)).await? {
+        FetchOutcome::Modified { body, url } => {
+            if url != *playlist_url {
+                send_log(format!("-> Playlist redirected to {}", url));
+            }
+            Ok(FetchedPlaylist { url, body })
+        }
+        FetchOutcome::NotModified => Err(anyhow!(
+            "Server answered 304 Not Modified to an initial request"
+        )),
+    }
+}
+
+/// Media playlist ready for download, after resolving a master playlist if needed
+pub struct MediaPlaylist {
+    /// URL the segment URIs are relative to
+    pub url: Url,
+    pub body: String,
+    /// Separate audio rendition to mux in (from the master playlist)
+    pub audio_url: Option<Url>,
+    /// Language/name of that audio rendition
+    pub audio_track: Option<TrackMetadata>,
+    /// Media playlist of the audio rendition when it is not fetched from `audio_url` (DASH)
+    pub audio_body: Option<String>,
+    /// Resolution of the selected variant, as stated by the master playlist
+    pub resolution: Option<(u32, u32)>,
+    /// `BANDWIDTH` of the selected variant
+    pub bandwidth: Option<u64>,
+    /// Subtitle rendition of the variant, used for sidecar files when none was chosen
+    pub subtitles: Option<Rendition>,
+}
+
+/// Turns a fetched (or pasted) playlist into a media playlist: a master playlist is resolved to
+/// the variant picked by `quality` (default `best`) and its default audio rendition, an MPD
+/// manifest to the picked video and audio representations.
+pub async fn resolve_media_playlist(
+    client: &Client,
+    playlist_url: &Url,
+    body: String,
+    quality: Option<&str>,
+    limits: &PlaylistLimits,
+    send_log: &impl Fn(String),
+    cancel: &CancellationToken,
+) -> Result<MediaPlaylist> {
+    if dash_parser::is_mpd(&body) {
+        return dash_parser::resolve_mpd(playlist_url, &body, quality, limits, send_log);
+    }
+    let Some(master) = parse_master_playlist(&body, playlist_url)? else {
+        return Ok(MediaPlaylist {
+            url: playlist_url.clone(),
+            body,
+            audio_url: None,
+            audio_track: None,
+            audio_body: None,
+            resolution: None,
+            bandwidth: None,
+            subtitles: None,
+        });
+    };
+    let quality = quality.unwrap_or("best");
+    let variant = master
+        .select(quality)
+        .ok_or_else(|| anyhow!("No variant matches quality '{}'", quality))?;
+    send_log(format!(
+        "-> Master playlist with {} variants, selected {} ({})",
+        master.variants.len(),
+        variant.label(),
+        quality
+    ));
+    let audio = master.default_audio(variant);
+    if let Some(rendition) = audio {
+        send_log(format!("-> Audio rendition: {}", rendition.label()));
+    }
+    let fetched = fetch_playlist_text(client, &variant.uri, limits, send_log, cancel).await?;
+    Ok(MediaPlaylist {
+        url: fetched.url,
+        body: fetched.body,
+        audio_url: audio.and_then(|rendition| rendition.uri.clone()),
+        audio_track: audio.map(Rendition::track_metadata),
+        audio_body: None,
+        resolution: variant.resolution,
+        bandwidth: Some(variant.bandwidth),
+        subtitles: master.default_subtitles(variant).cloned(),
+    })
 }
 
 /// Downloads and parses the M3U8 file
 pub async fn download_and_parse_m3u3(
+    client: &Client,
     playlist_url: &Url,
+    limits: &PlaylistLimits,
     send_log: &impl Fn(String),
-) -> Result<(Vec<Segment>, Option<EncryptionInfo>)> {
-    let client = Client::new();
-    let response = client
-        .get(playlist_url.as_str())
-        .send()
-        .await?
-        .error_for_status()?;
-    let body = response.text().await?;
+    cancel: &CancellationToken,
+) -> Result<(Vec<Segment>, Vec<EncryptionInfo>)> {
+    let fetched = fetch_playlist_text(client, playlist_url, limits, send_log, cancel).await?;
+    let media = resolve_media_playlist(
+        client,
+        &fetched.url,
+        fetched.body,
+        None,
+        limits,
+        send_log,
+        cancel,
+    )
+    .await?;
+    parse_m3u8(&media.body, &media.url, limits, send_log)
+}
 
+/// Parses M3U8 playlist text; relative URIs are resolved against `playlist_url`.
+///
+/// Returns the segments and every distinct `#EXT-X-KEY`; each segment refers to the key in
+/// effect at its position (`METHOD=NONE` switches back to clear segments).
+pub fn parse_m3u8(
+    body: &str,
+    playlist_url: &Url,
+    limits: &PlaylistLimits,
+    send_log: &impl Fn(String),
+) -> Result<(Vec<Segment>, Vec<EncryptionInfo>)> {
     let mut segments = Vec::new();
-    let mut encryption_info: Option<EncryptionInfo> = None;
+    let mut keys: Vec<EncryptionInfo> = Vec::new();
+    let mut current_key: Option<usize> = None;
     let mut current_segment_index = 0;
+    let mut pending_duration = 0.0;
+    let mut sequence_restarted = false;
+    let mut current_init: Option<InitSection> = None;
+    let mut pending_range: Option<ByteRange> = None;
+    // End of the previous segment's sub-range, where an offset-less #EXT-X-BYTERANGE continues
+    let mut previous_range_end: Option<u64> = None;
+    let mut pending_discontinuity = false;
+    let mut pending_gap = false;
+    // Inside an ad break, and the seconds left of it if the cue declared a duration
+    let mut in_ad = false;
+    let mut ad_remaining: Option<f64> = None;
+    // Wall-clock time of the next segment
+    let mut next_date_time: Option<f64> = None;
 
     for line in body.lines() {
         let line = line.trim();
 
-        if line.starts_with("#EXT-X-MEDIA-SEQUENCE:") {
-            if let Some(seq_str) = line.split(':').nth(1) {
-                if let Ok(seq) = seq_str.parse::<usize>() {
-                    current_segment_index = seq;
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            // #EXTINF:<duration>,[<title>]
+            pending_duration = extinf
+                .split(',')
+                .next()
+                .and_then(|d| d.trim().parse::<f64>().ok())
+                .unwrap_or(0.0);
+        } else if line.starts_with("#EXT-X-MEDIA-SEQUENCE:") {
+            if let Some(seq) = line
+                .split(':')
+                .nth(1)
+                .and_then(|seq_str| seq_str.parse::<usize>().ok())
+            {
+                if !segments.is_empty() && seq < current_segment_index {
+                    send_log(format!(
+                        "⚠️ Warning: #EXT-X-MEDIA-SEQUENCE restarts at {} after segment {}.",
+                        seq,
+                        current_segment_index - 1
+                    ));
+                    sequence_restarted = true;
+                } else {
                     send_log(format!(
                         "-> Detected #EXT-X-MEDIA-SEQUENCE: {}, segment index starts here.",
-                        current_segment_index
+                        seq
                     ));
                 }
+                current_segment_index = seq;
             }
-        } else if line.starts_with("#EXT-X-KEY") {
-            let content = line.trim_start_matches("#EXT-X-KEY:").trim();
-            let key_parts: Vec<&str> = content.split(',').collect();
-
-            let mut key_url: Option<Url> = None;
-            let mut method: Option<String> = None;
+        } else if line == "#EXT-X-DISCONTINUITY" {
+            pending_discontinuity = true;
+        } else if line == "#EXT-X-GAP" {
+            pending_gap = true;
+        } else if let Some(value) = line.strip_prefix("#EXT-X-PROGRAM-DATE-TIME:") {
+            next_date_time = parse_date_time(value);
+            if next_date_time.is_none() {
+                send_log(format!(
+                    "⚠️ Warning: Invalid #EXT-X-PROGRAM-DATE-TIME: {}",
+                    value
+                ));
+            }
+        } else if let Some(marker) = ad_marker(line) {
+            match marker {
+                AdMarker::Out(duration) => {
+                    in_ad = true;
+                    ad_remaining = duration;
+                }
+                AdMarker::Continue => in_ad = true,
+                AdMarker::In => {
+                    in_ad = false;
+                    ad_remaining = None;
+                }
+            }
+        } else if let Some(range) = line.strip_prefix("#EXT-X-BYTERANGE:") {
+            pending_range = ByteRange::parse(range, previous_range_end.unwrap_or(0));
+            if pending_range.is_none() {
+                send_log(format!(
+                    "⚠️ Warning: Invalid #EXT-X-BYTERANGE: {}, downloading the whole segment.",
+                    range
+                ));
+            }
+        } else if let Some(map) = line.strip_prefix("#EXT-X-MAP:") {
+            let attributes = AttributeList::parse(map);
+            current_init = attributes.get("URI").map(|uri| InitSection {
+                uri: uri.to_string(),
+                byte_range: attributes
+                    .get("BYTERANGE")
+                    .and_then(|range| ByteRange::parse(range, 0)),
+            });
+            if current_init.is_none() {
+                send_log("⚠️ Warning: #EXT-X-MAP without URI attribute, ignoring it.".to_string());
+            }
+        } else if let Some(list) = line.strip_prefix("#EXT-X-KEY:") {
+            let attributes = AttributeList::parse(list);
+            let method = attributes.get("METHOD").map(str::to_string);
+            let key_url = attributes
+                .get("URI")
+                .map(|uri| playlist_url.join(uri))
+                .transpose()?;
             let mut iv_bytes: Option<[u8; KEY_LEN]> = None;
-
-            for part in key_parts {
-                let part = part.trim();
-                if part.starts_with("METHOD=") {
-                    method = Some(part.trim_start_matches("METHOD=").to_string());
-                } else if part.starts_with("URI=") {
-                    let uri_str = part.trim_start_matches("URI=").trim_matches('"');
-                    key_url = Some(playlist_url.join(uri_str)?);
-                } else if part.starts_with("IV=") {
-                    let iv_hex = part.trim_start_matches("IV=").trim_start_matches("0x");
-                    if iv_hex.len() == KEY_LEN * 2 {
-                        match hex::decode(iv_hex) {
-                            Ok(bytes) if bytes.len() == KEY_LEN => {
-                                let mut iv = [0u8; KEY_LEN];
-                                iv.copy_from_slice(&bytes);
-                                iv_bytes = Some(iv);
-                                send_log(format!(
-                                    "  Explicit IV found in M3U8: [{} bytes]",
-                                    hex::encode(iv).len() / 2
-                                ));
-                            }
-                            _ => send_log("⚠️ Warning: Failed to parse IV bytes.".to_string()),
+            if let Some(iv) = attributes.get("IV") {
+                let iv_hex = iv.trim_start_matches("0x").trim_start_matches("0X");
+                if iv_hex.len() == KEY_LEN * 2 {
+                    match hex::decode(iv_hex) {
+                        Ok(bytes) if bytes.len() == KEY_LEN => {
+                            let mut iv = [0u8; KEY_LEN];
+                            iv.copy_from_slice(&bytes);
+                            iv_bytes = Some(iv);
+                            send_log(format!(
+                                "  Explicit IV found in M3U8: [{} bytes]",
+                                hex::encode(iv).len() / 2
+                            ));
                         }
-                    } else {
-                        send_log(format!(
-                            "⚠️ Warning: Invalid IV length or format: {}",
-                            iv_hex
-                        ));
+                        _ => send_log("⚠️ Warning: Failed to parse IV bytes.".to_string()),
                     }
+                } else {
+                    send_log(format!(
+                        "⚠️ Warning: Invalid IV length or format: {}",
+                        iv_hex
+                    ));
                 }
             }
+            if let Some(format) = attributes.get("KEYFORMAT")
+                && format != "identity"
+            {
+                send_log(format!(
+                    "⚠️ Warning: #EXT-X-KEY with KEYFORMAT=\"{}\" belongs to a DRM system; its key is probably not usable.",
+                    format
+                ));
+            }
 
-            if let (Some(url), Some(m)) = (key_url, method) {
-                if m != "AES-128" {
-                    return Err(anyhow!(
-                        "Only AES-128 encryption is currently supported, detected {}",
+            if method.as_deref() == Some("NONE") {
+                if current_key.is_some() {
+                    send_log(
+                        "-> #EXT-X-KEY METHOD=NONE: following segments are unencrypted."
+                            .to_string(),
+                    );
+                }
+                current_key = None;
+            } else if let (Some(url), Some(m)) = (key_url, method) {
+                match m.as_str() {
+                    // SAMPLE-AES only for MPEG-TS; decrypted per sample in `sample_aes`
+                    "AES-128" | "SAMPLE-AES" => {}
+                    "AES-256" | "AES-256-CBC" => send_log(format!(
+                        "⚠️ Warning: METHOD={} is not part of the HLS specification; decrypting as AES-256-CBC.",
                         m
-                    ));
+                    )),
+                    _ => {
+                        return Err(failure::parse(format!(
+                            "Only AES-128, SAMPLE-AES (MPEG-TS) and nonstandard AES-256-CBC encryption are supported, detected {}",
+                            m
+                        )));
+                    }
                 }
-                encryption_info = Some(EncryptionInfo {
+                let info = EncryptionInfo {
                     key_url: url,
                     method: m,
-                    key_bytes: None,
                     iv_bytes,
+                    attributes,
+                };
+                // Repeated identical tags (common around discontinuities) share one key download
+                current_key = Some(match keys.iter().position(|k| *k == info) {
+                    Some(existing) => existing,
+                    None => {
+                        limits.check_keys(keys.len() + 1)?;
+                        keys.push(info);
+                        keys.len() - 1
+                    }
                 });
             } else {
                 send_log(
@@ -111,37 +1264,140 @@ pub async fn download_and_parse_m3u3(
                 );
             }
         } else if !line.starts_with('#') && !line.is_empty() {
+            limits.check_segments(segments.len() + 1)?;
             segments.push(Segment {
                 path: line.to_string(),
                 index: current_segment_index,
+                sequence: current_segment_index as u64,
+                duration: pending_duration,
+                key: current_key,
+                byte_range: pending_range,
+                init: current_init.clone(),
+                discontinuity: std::mem::take(&mut pending_discontinuity),
+                gap: std::mem::take(&mut pending_gap),
+                ad: in_ad,
+                program_date_time: next_date_time,
             });
+            next_date_time = next_date_time.map(|time| time + pending_duration);
+            // A break with a declared duration ends without #EXT-X-CUE-IN (rounding tolerated)
+            if let Some(remaining) = ad_remaining.as_mut() {
+                *remaining -= pending_duration;
+                if *remaining < AD_DURATION_TOLERANCE {
+                    in_ad = false;
+                    ad_remaining = None;
+                }
+            }
+            previous_range_end = pending_range.take().map(|range| range.end());
             current_segment_index += 1;
+            pending_duration = 0.0;
         }
     }
 
     if segments.is_empty() {
-        return Err(anyhow!("No media segments (.ts) found in the M3U8 file."));
+        return Err(failure::parse(
+            "No media segments (.ts) found in the M3U8 file.",
+        ));
+    }
+    if segments.iter().any(|s| s.init.is_some()) {
+        send_log("-> #EXT-X-MAP detected: fragmented MP4 (fMP4/CMAF) segments.".to_string());
+    }
+    // Segments before the first #EXT-X-PROGRAM-DATE-TIME are timed backwards from it
+    if let Some(first_dated) = segments.iter().position(|s| s.program_date_time.is_some()) {
+        let mut time = segments[first_dated].program_date_time.unwrap_or_default();
+        for segment in segments[..first_dated].iter_mut().rev() {
+            time -= segment.duration;
+            segment.program_date_time = Some(time);
+        }
+    }
+    if sequence_restarted {
+        // Declared numbers would collide in file names and ordering; IVs keep using them
+        let first = segments[0].index;
+        for (position, segment) in segments.iter_mut().enumerate() {
+            segment.index = first + position;
+        }
+        send_log(
+            "-> Non-monotonic media sequence: segments are numbered by position instead."
+                .to_string(),
+        );
+    }
+
+    Ok((segments, keys))
+}
+
+/// Leaves out the segments inside ad breaks. The segment after each removed break starts a new
+/// run (`discontinuity`), since the timestamps jump over the missing ad.
+pub fn skip_ad_breaks(segments: Vec<Segment>) -> Vec<Segment> {
+    let mut kept: Vec<Segment> = Vec::with_capacity(segments.len());
+    let mut skipped = false;
+    for mut segment in segments {
+        if segment.ad {
+            skipped = true;
+            continue;
+        }
+        segment.discontinuity |= std::mem::take(&mut skipped) && !kept.is_empty();
+        kept.push(segment);
+    }
+    kept
+}
+
+/// Warns when the key length does not match the playlist's METHOD (the key length wins).
+fn warn_key_method_mismatch(key: &SegmentKey, method: &str, send_log: &impl Fn(String)) {
+    match (key, method) {
+        (SegmentKey::Aes256(_), "AES-128") => send_log(
+            "⚠️ Warning: Playlist declares AES-128 but the key is 32 bytes (nonstandard); decrypting as AES-256-CBC."
+                .to_string(),
+        ),
+        (SegmentKey::Aes128(_), "AES-256" | "AES-256-CBC") => send_log(format!(
+            "⚠️ Warning: Playlist declares {} but the key is 16 bytes; decrypting as AES-128.",
+            method
+        )),
+        _ => {}
     }
+}
 
-    Ok((segments, encryption_info))
+/// Whether the playlist may name `file://` keys: only one that is local itself (a `file://`
+/// URL or pasted text), never one a remote server sent.
+pub fn allows_local_keys(playlist_url: &str, pasted: bool) -> bool {
+    pasted || Url::parse(playlist_url).is_ok_and(|url| url.scheme() == "file")
 }
 
-/// Downloads the key file
-pub async fn download_key_file(key_url: &Url, send_log: &impl Fn(String)) -> Result<[u8; KEY_LEN]> {
-    let client = Client::new();
+/// Downloads the key file (or reads a `file://` key URI if `allow_local`, see
+/// `allows_local_keys`); its length decides between AES-128 and AES-256 decryption. `client`
+/// carries the job's headers (key servers usually check the same Referer/Cookie/Authorization
+/// as the playlist).
+pub async fn download_key_file(
+    client: &Client,
+    key_url: &Url,
+    method: &str,
+    allow_local: bool,
+    send_log: &impl Fn(String),
+) -> Result<SegmentKey> {
+    // Offline keys: `file://` URIs are read from disk
+    if key_url.scheme() == "file" {
+        if !allow_local {
+            return Err(failure::decrypt(format!(
+                "Refusing to read local key {} named by a remote playlist",
+                key_url
+            )));
+        }
+        let path = key_url
+            .to_file_path()
+            .map_err(|_| anyhow!("Invalid key file URL: {}", key_url))?;
+        let bytes = tokio::fs::read(&path)
+            .await
+            .map_err(|e| anyhow!("Failed to read key file {}: {}", path.display(), e))?;
+        send_log(format!("-> Key read from local file {}", path.display()));
+        let key = SegmentKey::from_bytes(&bytes)?;
+        warn_key_method_mismatch(&key, method, send_log);
+        return Ok(key);
+    }
+
     for attempt in 0..MAX_RETRIES {
         match client.get(key_url.as_str()).send().await {
             Ok(response) => {
                 if response.status().is_success() {
-                    let key_bytes = response.bytes().await?;
-                    if key_bytes.len() != KEY_LEN {
-                        return Err(anyhow!(
-                            "Key file length error: Expected 16 bytes, got {}",
-                            key_bytes.len()
-                        ));
-                    }
-                    let mut key = [0u8; KEY_LEN];
-                    key.copy_from_slice(&key_bytes);
+                    let key = SegmentKey::from_bytes(&response.bytes().await?)?;
+                    warn_key_method_mismatch(&key, method, send_log);
                     return Ok(key);
                 } else if response.status() == StatusCode::TOO_MANY_REQUESTS
                     && attempt < MAX_RETRIES - 1
@@ -155,10 +1411,10 @@ pub async fn download_key_file(key_url: &Url, send_log: &impl Fn(String)) -> Res
                     sleep(Duration::from_secs(delay)).await;
                     continue;
                 } else {
-                    return Err(anyhow!(
+                    return Err(failure::network(format!(
                         "Key download failed, status code: {}",
                         response.status()
-                    ));
+                    )));
                 }
             }
             Err(e) => {
@@ -173,10 +1429,406 @@ pub async fn download_key_file(key_url: &Url, send_log: &impl Fn(String)) -> Res
                     sleep(Duration::from_secs(delay)).await;
                     continue;
                 } else {
-                    return Err(anyhow!("Key download failed, connection error: {}", e));
+                    return Err(failure::network(format!(
+                        "Key download failed, connection error: {}",
+                        e
+                    )));
                 }
             }
         }
     }
     unreachable!()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(body: &str) -> (Vec<Segment>, Vec<EncryptionInfo>) {
+        let base = Url::parse("https://example.com/live/index.m3u8").unwrap();
+        parse_m3u8(body, &base, &PlaylistLimits::default(), &|_| {}).unwrap()
+    }
+
+    fn segment_keys(segments: &[Segment]) -> Vec<Option<usize>> {
+        segments.iter().map(|s| s.key).collect()
+    }
+
+    #[test]
+    fn method_none_disables_decryption_for_following_segments() {
+        let (segments, keys) = parse(
+            "#EXTM3U\n\
+             #EXTINF:4,\nclear0.ts\n\
+             #EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\"\n\
+             #EXTINF:4,\nenc1.ts\n#EXTINF:4,\nenc2.ts\n\
+             #EXT-X-KEY:METHOD=NONE\n\
+             #EXTINF:4,\nclear3.ts\n",
+        );
+        assert_eq!(keys.len(), 1);
+        assert_eq!(keys[0].key_url.as_str(), "https://example.com/live/key.bin");
+        assert_eq!(segment_keys(&segments), vec![None, Some(0), Some(0), None]);
+    }
+
+    #[test]
+    fn encryption_resumes_after_method_none() {
+        let (segments, keys) = parse(
+            "#EXTM3U\n\
+             #EXT-X-KEY:METHOD=AES-128,URI=\"a.key\"\n\
+             #EXTINF:4,\n0.ts\n\
+             #EXT-X-KEY:METHOD=NONE\n\
+             #EXTINF:4,\n1.ts\n\
+             #EXT-X-KEY:METHOD=AES-128,URI=\"b.key\"\n\
+             #EXTINF:4,\n2.ts\n\
+             #EXT-X-KEY:METHOD=AES-128,URI=\"a.key\"\n\
+             #EXTINF:4,\n3.ts\n",
+        );
+        // The repeated tag for a.key reuses the first entry
+        assert_eq!(keys.len(), 2);
+        assert_eq!(
+            segment_keys(&segments),
+            vec![Some(0), None, Some(1), Some(0)]
+        );
+    }
+
+    #[test]
+    fn iv_defaults_to_the_media_sequence_number() {
+        let body = "#EXTM3U\n#EXT-X-MEDIA-SEQUENCE:258\n\
+                    #EXT-X-KEY:METHOD=AES-128,URI=\"k1\"\n#EXTINF:4,\n0.ts\n#EXTINF:4,\n1.ts\n\
+                    #EXT-X-KEY:METHOD=AES-128,URI=\"k2\",IV=0x000102030405060708090a0b0c0d0e0f\n\
+                    #EXTINF:4,\n2.ts\n";
+        let (segments, keys) = parse(body);
+        assert_eq!(
+            segments.iter().map(|s| s.sequence).collect::<Vec<_>>(),
+            [258, 259, 260]
+        );
+        let mut expected = [0u8; KEY_LEN];
+        expected[14..].copy_from_slice(&[0x01, 0x03]);
+        assert_eq!(keys[0].iv_for(segments[1].sequence), expected);
+        // An explicit IV applies to every segment of its key
+        assert_eq!(keys[1].iv_for(segments[2].sequence)[15], 0x0f);
+        assert_eq!(keys[1].iv_for(0), keys[1].iv_for(segments[2].sequence));
+    }
+
+    #[test]
+    fn restarted_sequence_keeps_declared_numbers_for_ivs() {
+        let body = "#EXTM3U\n#EXT-X-MEDIA-SEQUENCE:10\n#EXT-X-KEY:METHOD=AES-128,URI=\"k\"\n\
+                    #EXTINF:4,\n10.ts\n#EXT-X-MEDIA-SEQUENCE:3\n#EXTINF:4,\n3.ts\n";
+        let (segments, keys) = parse(body);
+        // Positions number the files, while the IV still follows the declared sequence
+        assert_eq!(segments[1].index, 11);
+        assert_eq!(segments[1].sequence, 3);
+        assert_eq!(keys[0].iv_for(segments[1].sequence)[15], 3);
+    }
+
+    #[test]
+    fn byte_range_without_offset_continues_the_previous_one() {
+        let body = "#EXTM3U\n\
+                    #EXTINF:4,\n#EXT-X-BYTERANGE:1000@500\nall.ts\n\
+                    #EXTINF:4,\n#EXT-X-BYTERANGE:700\nall.ts\n\
+                    #EXTINF:4,\n#EXT-X-BYTERANGE:300\nall.ts\n";
+        let (segments, _) = parse(body);
+        let ranges: Vec<_> = segments
+            .iter()
+            .map(|s| s.byte_range.map(|r| (r.offset, r.length)))
+            .collect();
+        assert_eq!(
+            ranges,
+            [Some((500, 1000)), Some((1500, 700)), Some((2200, 300))]
+        );
+        assert_eq!(
+            segments[1].byte_range.unwrap().header_value(),
+            "bytes=1500-2199"
+        );
+        assert_eq!(ByteRange::parse("0@10", 0), None);
+        assert_eq!(
+            ByteRange::parse(" 16 ", 32),
+            Some(ByteRange {
+                offset: 32,
+                length: 16
+            })
+        );
+    }
+
+    #[test]
+    fn only_local_playlists_may_name_local_keys() {
+        assert!(allows_local_keys("file:///media/index.m3u8", false));
+        assert!(allows_local_keys("https://example.com/index.m3u8", true));
+        assert!(!allows_local_keys("https://example.com/index.m3u8", false));
+
+        let dir = tempfile::tempdir().unwrap();
+        let key_path = dir.path().join("key.bin");
+        std::fs::write(&key_path, [9u8; KEY_LEN]).unwrap();
+        let key_url = Url::from_file_path(&key_path).unwrap();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        let client = Client::new();
+        let fetch = |allow_local| {
+            runtime.block_on(download_key_file(
+                &client,
+                &key_url,
+                "AES-128",
+                allow_local,
+                &|_| {},
+            ))
+        };
+        assert!(fetch(false).is_err());
+        assert_eq!(fetch(true).unwrap().as_bytes(), &[9u8; KEY_LEN]);
+    }
+
+    #[test]
+    fn malformed_playlists_are_parse_failures() {
+        let base = Url::parse("https://example.com/live/index.m3u8").unwrap();
+        let limits = PlaylistLimits::default();
+        let empty = parse_m3u8("#EXTM3U\n", &base, &limits, &|_| {})
+            .err()
+            .unwrap();
+        assert_eq!(
+            failure::FailureKind::of(&empty),
+            failure::FailureKind::Parse
+        );
+        let body = "#EXTM3U\n#EXT-X-KEY:METHOD=SAMPLE-AES-CTR,URI=\"k\"\n#EXTINF:4,\n0.ts\n";
+        let method = parse_m3u8(body, &base, &limits, &|_| {}).err().unwrap();
+        assert_eq!(
+            failure::FailureKind::of(&method),
+            failure::FailureKind::Parse
+        );
+    }
+
+    #[test]
+    fn limits_apply_per_call() {
+        let base = Url::parse("https://example.com/live/index.m3u8").unwrap();
+        let body = "#EXTM3U\n#EXTINF:4,\n0.ts\n#EXTINF:4,\n1.ts\n";
+        let tight = PlaylistLimits {
+            max_segments: 1,
+            ..Default::default()
+        };
+        // One job's tight limits don't leak into a parse with other limits
+        assert!(parse_m3u8(body, &base, &tight, &|_| {}).is_err());
+        assert_eq!(parse(body).0.len(), 2);
+    }
+
+    #[test]
+    fn attribute_list_keeps_commas_inside_quotes() {
+        let attributes = AttributeList::parse(
+            "METHOD=AES-128, URI=\"https://k.example/key?sig=a,b&exp=1\",IV=0x0A,KEYFORMAT=\"identity\"",
+        );
+        assert_eq!(attributes.get("METHOD"), Some("AES-128"));
+        assert_eq!(
+            attributes.get("URI"),
+            Some("https://k.example/key?sig=a,b&exp=1")
+        );
+        assert_eq!(attributes.get("IV"), Some("0x0A"));
+        assert_eq!(attributes.get("KEYFORMAT"), Some("identity"));
+    }
+
+    #[test]
+    fn key_uri_with_commas_is_not_split() {
+        let (_, keys) = parse(
+            "#EXTM3U\n\
+             #EXT-X-KEY:METHOD=AES-128,URI=\"key?token=a,b\",IV=0x000102030405060708090a0b0c0d0e0f\n\
+             #EXTINF:4,\n0.ts\n",
+        );
+        assert_eq!(keys.len(), 1);
+        assert_eq!(
+            keys[0].key_url.as_str(),
+            "https://example.com/live/key?token=a,b"
+        );
+        assert_eq!(keys[0].iv_bytes.map(|iv| iv[15]), Some(0x0f));
+    }
+
+    #[test]
+    fn playlist_with_only_method_none_has_no_keys() {
+        let (segments, keys) =
+            parse("#EXTM3U\n#EXT-X-KEY:METHOD=NONE\n#EXTINF:4,\n0.ts\n#EXTINF:4,\n1.ts\n");
+        assert!(keys.is_empty());
+        assert_eq!(segment_keys(&segments), vec![None, None]);
+    }
+
+    #[test]
+    fn iframe_streams_are_matched_to_the_closest_variant() {
+        let base = Url::parse("https://example.com/master.m3u8").unwrap();
+        let master = parse_master_playlist(
+            "#EXTM3U\n\
+             #EXT-X-STREAM-INF:BANDWIDTH=5000000,RESOLUTION=1920x1080\n1080p.m3u8\n\
+             #EXT-X-STREAM-INF:BANDWIDTH=1500000,RESOLUTION=960x540\n540p.m3u8\n\
+             #EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=300000,RESOLUTION=1920x1080,URI=\"iframe_1080.m3u8\"\n\
+             #EXT-X-I-FRAME-STREAM-INF:BANDWIDTH=90000,RESOLUTION=640x360,URI=\"iframe_360.m3u8\"\n",
+            &base,
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(master.variants.len(), 2);
+        assert_eq!(master.iframe_streams.len(), 2);
+        let stream = |quality: &str| {
+            let variant = master.select(quality).unwrap();
+            master.iframe_stream_for(variant).unwrap().uri.to_string()
+        };
+        assert_eq!(stream("1080p"), "https://example.com/iframe_1080.m3u8");
+        assert_eq!(stream("540p"), "https://example.com/iframe_360.m3u8");
+    }
+
+    #[test]
+    fn ad_breaks_are_marked_and_skipped() {
+        let (segments, _) = parse(
+            "#EXTM3U\n\
+             #EXTINF:2,\nmain0.ts\n\
+             #EXT-X-CUE-OUT:4\n\
+             #EXTINF:2,\nad0.ts\n\
+             #EXTINF:2,\nad1.ts\n\
+             #EXTINF:2,\nmain1.ts\n\
+             #EXT-X-DATERANGE:ID=\"b2\",START-DATE=\"2024-01-01T00:00:10Z\",SCTE35-OUT=0xFC30\n\
+             #EXTINF:2,\nad2.ts\n\
+             #EXT-X-DATERANGE:ID=\"b2\",SCTE35-IN=0xFC30\n\
+             #EXTINF:2,\nmain2.ts\n",
+        );
+        let ads: Vec<bool> = segments.iter().map(|s| s.ad).collect();
+        assert_eq!(ads, [false, true, true, false, true, false]);
+
+        let kept = skip_ad_breaks(segments);
+        let paths: Vec<&str> = kept.iter().map(|s| s.path.as_str()).collect();
+        assert_eq!(paths, ["main0.ts", "main1.ts", "main2.ts"]);
+        let discontinuities: Vec<bool> = kept.iter().map(|s| s.discontinuity).collect();
+        assert_eq!(discontinuities, [false, true, true]);
+    }
+
+    #[test]
+    fn program_date_time_is_tracked_and_clipped() {
+        let (segments, _) = parse(
+            "#EXTM3U\n\
+             #EXTINF:4,\ns0.ts\n\
+             #EXT-X-PROGRAM-DATE-TIME:2024-05-01T20:00:04.000+02:00\n\
+             #EXTINF:4,\ns1.ts\n\
+             #EXTINF:4,\ns2.ts\n\
+             #EXTINF:4,\ns3.ts\n",
+        );
+        let base = parse_date_time("2024-05-01T18:00:00Z").unwrap();
+        let times: Vec<f64> = segments
+            .iter()
+            .map(|s| s.program_date_time.unwrap() - base)
+            .collect();
+        assert_eq!(times, [0.0, 4.0, 8.0, 12.0]);
+
+        let window =
+            ClipWindow::parse(Some("2024-05-01 18:00:06Z"), Some("2024-05-01T18:00:12Z")).unwrap();
+        let kept: Vec<&str> = segments
+            .iter()
+            .filter(|s| window.contains(s))
+            .map(|s| s.path.as_str())
+            .collect();
+        assert_eq!(kept, ["s1.ts", "s2.ts"]);
+        assert!(window.is_past(&segments[3]));
+        assert!(
+            ClipWindow::parse(Some("2024-05-01T18:00:12Z"), Some("2024-05-01T18:00:06Z")).is_err()
+        );
+    }
+
+    #[test]
+    fn time_range_and_sample_select_by_extinf_offsets() {
+        let (segments, _) = parse(
+            "#EXTM3U\n\
+             #EXTINF:6,\ns0.ts\n\
+             #EXTINF:6,\ns1.ts\n\
+             #EXTINF:6,\ns2.ts\n\
+             #EXTINF:6,\ns3.ts\n",
+        );
+        let range = TimeRange::parse(Some("0:07.5"), Some("13")).unwrap();
+        assert_eq!(range.start, Some(7.5));
+        let (kept, first_offset) = range.select(segments.clone());
+        let paths: Vec<&str> = kept.iter().map(|s| s.path.as_str()).collect();
+        assert_eq!(paths, ["s1.ts", "s2.ts"]);
+        assert_eq!(first_offset, 6.0);
+
+        // A range ending on a segment boundary doesn't pull in the next segment
+        let (kept, first_offset) = TimeRange::parse(None, Some("12"))
+            .unwrap()
+            .select(segments.clone());
+        assert_eq!(kept.len(), 2);
+        assert_eq!(first_offset, 0.0);
+
+        assert_eq!(parse_time_offset("1:02:03.5"), Some(3723.5));
+        assert!(parse_time_offset("1:75").is_none());
+        assert!(TimeRange::parse(Some("30"), Some("0:20")).is_err());
+
+        let sample = SampleLength::parse("7s").unwrap();
+        assert_eq!(sample, SampleLength::Seconds(7.0));
+        assert_eq!(sample.select(segments.clone()).len(), 2);
+        assert_eq!(SampleLength::parse("3").unwrap().select(segments).len(), 3);
+        assert!(SampleLength::parse("0").is_none());
+    }
+
+    fn gzip(text: &str) -> Vec<u8> {
+        use flate2::{Compression, write::GzEncoder};
+        use std::io::Write;
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn gzip_body_without_content_encoding_is_inflated() {
+        let playlist = "#EXTM3U\n#EXTINF:4,\nseg0.ts\n";
+        let logs = std::cell::RefCell::new(Vec::new());
+        let text = decode_playlist_body(&gzip(playlist), &PlaylistLimits::default(), &|line| {
+            logs.borrow_mut().push(line)
+        })
+        .unwrap();
+        assert_eq!(text, playlist);
+        assert_eq!(logs.borrow().len(), 1);
+        assert!(logs.borrow()[0].contains("gzip"));
+    }
+
+    #[test]
+    fn plain_body_is_returned_unchanged() {
+        let playlist = "#EXTM3U\n#EXTINF:4,\nseg0.ts\n";
+        let logs = std::cell::RefCell::new(Vec::new());
+        let text = decode_playlist_body(playlist.as_bytes(), &PlaylistLimits::default(), &|line| {
+            logs.borrow_mut().push(line)
+        })
+        .unwrap();
+        assert_eq!(text, playlist);
+        assert!(logs.borrow().is_empty());
+    }
+
+    #[test]
+    fn inflated_body_is_held_to_the_size_limit() {
+        let limits = PlaylistLimits {
+            max_playlist_bytes: 16,
+            ..PlaylistLimits::default()
+        };
+        let body = gzip(&"#EXTINF:4,\nseg.ts\n".repeat(100));
+        assert!(decode_playlist_body(&body, &limits, &|_| {}).is_err());
+    }
+
+    #[test]
+    fn track_metadata_specs_round_trip() {
+        let track =
+            TrackMetadata::parse("language=pt-BR, default,name=Português, dublado").unwrap();
+        assert_eq!(track.language.as_deref(), Some("pt-BR"));
+        assert!(track.default);
+        assert_eq!(track.name.as_deref(), Some("Português, dublado"));
+        assert_eq!(TrackMetadata::parse(&track.to_spec()), Some(track));
+        assert_eq!(TrackMetadata::parse(""), Some(TrackMetadata::default()));
+        assert_eq!(TrackMetadata::parse("lang=ja"), None);
+    }
+
+    #[test]
+    fn segment_key_length_picks_the_cipher() {
+        assert!(matches!(
+            SegmentKey::from_bytes(&[1; 16]),
+            Ok(SegmentKey::Aes128(key)) if key == [1; 16]
+        ));
+        assert!(matches!(
+            SegmentKey::from_bytes(&[2; 32]),
+            Ok(SegmentKey::Aes256(key)) if key == [2; 32]
+        ));
+        for len in [0, 15, 17, 24, 33] {
+            let error = SegmentKey::from_bytes(&vec![0; len]).err().unwrap();
+            assert!(error.to_string().contains(&format!("got {}", len)));
+        }
+        assert_eq!(
+            SegmentKey::from_bytes(&[3; 32]).unwrap().as_bytes(),
+            [3; 32]
+        );
+    }
+}
@@ -0,0 +1,102 @@
+// Post-download hooks: a shell command and/or a webhook receive the facts of the finished job as
+// structured data, so scripts don't have to parse the log.
+
+use anyhow::{Result, anyhow};
+use reqwest::{Client, Url};
+use serde::Serialize;
+use std::path::Path;
+use tokio::process::Command;
+
+/// Facts about a finished job, passed to hooks as `HLSDL_*` variables and as JSON
+#[derive(Debug, Clone, Serialize)]
+pub struct JobContext {
+    pub output_path: String,
+    pub playlist_url: String,
+    /// Host the playlist was loaded from
+    pub origin_host: String,
+    pub duration_secs: f64,
+    /// `WIDTHxHEIGHT` of the selected variant, if the master playlist stated it
+    pub resolution: Option<String>,
+    /// Size of the output file
+    pub bytes: u64,
+    pub segments: usize,
+}
+
+impl JobContext {
+    pub fn new(
+        output_path: &Path,
+        playlist_url: &Url,
+        duration_secs: f64,
+        resolution: Option<(u32, u32)>,
+        segments: usize,
+    ) -> Self {
+        JobContext {
+            output_path: output_path.to_string_lossy().into_owned(),
+            playlist_url: playlist_url.to_string(),
+            origin_host: super::stats::host_of(playlist_url),
+            duration_secs,
+            resolution: resolution.map(|(width, height)| format!("{}x{}", width, height)),
+            bytes: std::fs::metadata(output_path).map_or(0, |meta| meta.len()),
+            segments,
+        }
+    }
+
+    /// Environment of the hook command; `HLSDL_JOB_JSON` holds the whole context.
+    pub fn env_vars(&self) -> Vec<(&'static str, String)> {
+        vec![
+            ("HLSDL_OUTPUT", self.output_path.clone()),
+            ("HLSDL_URL", self.playlist_url.clone()),
+            ("HLSDL_HOST", self.origin_host.clone()),
+            ("HLSDL_DURATION", format!("{:.3}", self.duration_secs)),
+            (
+                "HLSDL_RESOLUTION",
+                self.resolution.clone().unwrap_or_default(),
+            ),
+            ("HLSDL_BYTES", self.bytes.to_string()),
+            ("HLSDL_SEGMENTS", self.segments.to_string()),
+            (
+                "HLSDL_JOB_JSON",
+                serde_json::to_string(self).unwrap_or_default(),
+            ),
+        ]
+    }
+}
+
+/// Runs `command` through the platform shell with the job context in its environment.
+pub async fn run_command_hook(command: &str, context: &JobContext) -> Result<()> {
+    #[cfg(windows)]
+    let mut shell = {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    };
+    #[cfg(not(windows))]
+    let mut shell = {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let status = shell
+        .arg(command)
+        .envs(context.env_vars())
+        .stdin(std::process::Stdio::null())
+        .status()
+        .await
+        .map_err(|e| anyhow!("Failed to run hook: {}", e))?;
+    if !status.success() {
+        return Err(anyhow!("Hook exited with {}", status));
+    }
+    Ok(())
+}
+
+/// POSTs the job context as JSON to `url`.
+pub async fn post_webhook(client: &Client, url: &str, context: &JobContext) -> Result<()> {
+    let response = client.post(url).json(context).send().await?;
+    if !response.status().is_success() {
+        return Err(anyhow!(
+            "Webhook answered with status {}",
+            response.status()
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,181 @@
+use anyhow::{Result, anyhow, bail};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, Proxy, Url};
+
+/// Checks a user-entered URL and adds `https://` when the scheme is missing
+/// (`example.com/video.m3u8`, `//cdn.example.com/video.m3u8`). Returns the URL to use.
+pub fn normalize_url(input: &str) -> Result<String> {
+    let input = input.trim();
+    let parsed = Url::parse(input);
+    if let Ok(url) = &parsed
+        && matches!(url.scheme(), "http" | "https")
+    {
+        return Ok(input.to_string());
+    }
+    // `host:port/path` parses with the host as scheme, so host-like input is checked first
+    let candidate = match input.strip_prefix("//") {
+        Some(rest) => format!("https://{}", rest),
+        None if looks_like_host_path(input) => format!("https://{}", input),
+        None if parsed.is_ok() => return Ok(input.to_string()),
+        None => bail!(
+            "Invalid URL: {} (expected e.g. https://example.com/video.m3u8)",
+            input
+        ),
+    };
+    Url::parse(&candidate).map_err(|e| anyhow!("Invalid URL: {}: {}", input, e))?;
+    Ok(candidate)
+}
+
+/// Last labels of file names that are never top-level domains (`index.m3u8`, `video.ts`).
+const FILE_EXTENSIONS: &[&str] = &["m3u8", "m3u", "mpd", "ts", "mp4", "txt", "json", "xml"];
+
+/// `example.com/...`, `localhost:8080/...`: a host name (with a dot, or localhost) up front.
+/// A relative file path such as `index.m3u8` or `videos/a.ts` is not a host.
+fn looks_like_host_path(input: &str) -> bool {
+    if input.contains("://") || input.contains(char::is_whitespace) {
+        return false;
+    }
+    let authority = input.split(['/', '?', '#']).next().unwrap_or_default();
+    let host = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    let host = host.split(':').next().unwrap_or_default();
+    let top_label = host.rsplit('.').next().unwrap_or_default();
+    host.eq_ignore_ascii_case("localhost")
+        || (host.contains('.')
+            && !host.starts_with('.')
+            && host
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+            && !top_label.is_empty()
+            && !FILE_EXTENSIONS
+                .iter()
+                .any(|ext| top_label.eq_ignore_ascii_case(ext)))
+}
+
+/// Parses `Name: value` lines (one header per line, blank lines ignored).
+pub fn parse_header_lines(text: &str) -> Result<Vec<(String, String)>> {
+    let mut headers = Vec::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| anyhow!("Invalid header line (expected `Name: value`): {}", line))?;
+        headers.push((name.trim().to_string(), value.trim().to_string()));
+    }
+    Ok(headers)
+}
+
+/// Formats headers back into `Name: value` lines.
+pub fn format_header_lines(headers: &[(String, String)]) -> String {
+    headers
+        .iter()
+        .map(|(name, value)| format!("{}: {}", name, value))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Builds the HTTP client shared by all requests of a job, sending `headers` on every request.
+/// An explicit `proxy` takes precedence over the `HTTP(S)_PROXY` environment variables.
+pub fn build_client(headers: &[(String, String)], proxy: Option<&str>) -> Result<Client> {
+    let mut header_map = HeaderMap::new();
+    for (name, value) in headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .map_err(|e| anyhow!("Invalid header name '{}': {}", name, e))?;
+        let value = HeaderValue::from_str(value)
+            .map_err(|e| anyhow!("Invalid value for header '{}': {}", name, e))?;
+        header_map.append(name, value);
+    }
+
+    let mut builder = Client::builder().default_headers(header_map);
+    if let Some(proxy) = proxy {
+        builder = builder
+            .proxy(Proxy::all(proxy).map_err(|e| anyhow!("Invalid proxy '{}': {}", proxy, e))?);
+    }
+    builder
+        .build()
+        .map_err(|e| anyhow!("Failed to build HTTP client: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn header_lines_round_trip() {
+        let headers =
+            parse_header_lines("Referer: https://example.com/\n\n  Cookie: a=1; b=2  \n").unwrap();
+        assert_eq!(
+            headers,
+            vec![
+                ("Referer".to_string(), "https://example.com/".to_string()),
+                ("Cookie".to_string(), "a=1; b=2".to_string()),
+            ]
+        );
+        assert_eq!(
+            parse_header_lines(&format_header_lines(&headers)).unwrap(),
+            headers
+        );
+        assert!(parse_header_lines("Referer example.com").is_err());
+    }
+
+    #[test]
+    fn invalid_headers_fail_the_client() {
+        assert!(build_client(&[("X-Token".to_string(), "abc".to_string())], None).is_ok());
+        assert!(build_client(&[("Bad Name".to_string(), "abc".to_string())], None).is_err());
+        assert!(build_client(&[("X-Token".to_string(), "a\nb".to_string())], None).is_err());
+    }
+
+    #[test]
+    fn host_like_input_is_recognised() {
+        let cases = [
+            ("example.com/video.m3u8", true),
+            ("cdn.example.com", true),
+            ("localhost:8080/live.m3u8", true),
+            ("LOCALHOST/x.m3u8", true),
+            ("user@example.com:8443/a.m3u8", true),
+            ("index.m3u8", false),
+            ("videos/segment.ts", false),
+            ("/var/media/index.m3u8", false),
+            ("./index.m3u8", false),
+            (".hidden/index.m3u8", false),
+            ("https://example.com/a.m3u8", false),
+            ("example.com/my video.m3u8", false),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(looks_like_host_path(input), expected, "{}", input);
+        }
+    }
+
+    #[test]
+    fn normalize_url_adds_https_only_to_hosts() {
+        let cases = [
+            (
+                "example.com/video.m3u8",
+                Some("https://example.com/video.m3u8"),
+            ),
+            (
+                "  http://example.com/a.m3u8 ",
+                Some("http://example.com/a.m3u8"),
+            ),
+            (
+                "//cdn.example.com/a.m3u8",
+                Some("https://cdn.example.com/a.m3u8"),
+            ),
+            (
+                "localhost:8080/live.m3u8",
+                Some("https://localhost:8080/live.m3u8"),
+            ),
+            ("file:///tmp/index.m3u8", Some("file:///tmp/index.m3u8")),
+            ("index.m3u8", None),
+            ("videos/index.m3u8", None),
+            ("/tmp/index.m3u8", None),
+        ];
+        for (input, expected) in cases {
+            assert_eq!(normalize_url(input).ok().as_deref(), expected, "{}", input);
+        }
+    }
+}
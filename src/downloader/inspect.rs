@@ -0,0 +1,191 @@
+// Inspect step (dry run): fetches and parses the playlist without downloading any media, and
+// reports what a download would fetch.
+
+use anyhow::{Result, anyhow};
+use egui::Context as EguiContext;
+use reqwest::Url;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use super::hls_parser::{fetch_playlist_text, parse_m3u8, resolve_media_playlist};
+use super::http::build_client;
+use super::stats::format_bytes;
+use super::url_rewrite::UrlRewriter;
+use super::{DownloadConfig, DownloadMessage, signed_url};
+
+/// What the playlist of a job contains
+#[derive(Debug, Clone, Default)]
+pub struct PlaylistInfo {
+    /// Media playlist the segments come from (the selected variant of a master playlist)
+    pub media_url: String,
+    /// `BANDWIDTH` and `RESOLUTION` of the selected variant; None for a media playlist
+    pub bandwidth: Option<u64>,
+    pub resolution: Option<(u32, u32)>,
+    /// No `#EXT-X-ENDLIST`: the playlist is still growing
+    pub live: bool,
+    pub segment_count: usize,
+    /// Segments marked `#EXT-X-GAP` (not fetched)
+    pub gap_count: usize,
+    /// Sum of the `#EXTINF` durations, in seconds
+    pub duration: f64,
+    /// Distinct `#EXT-X-KEY` methods (`AES-128`, `SAMPLE-AES`, ...); empty if unencrypted
+    pub encryption: Vec<String>,
+    pub key_count: usize,
+    /// Total of the `#EXT-X-BYTERANGE` lengths, if every segment has one
+    pub byterange_bytes: Option<u64>,
+    /// Absolute URL of every segment, as a download would request it
+    pub segment_urls: Vec<String>,
+}
+
+impl PlaylistInfo {
+    /// `BANDWIDTH` x duration; the byte ranges are exact when the playlist has them.
+    pub fn estimated_bytes(&self) -> Option<f64> {
+        self.byterange_bytes
+            .map(|bytes| bytes as f64)
+            .or_else(|| Some(self.bandwidth? as f64 * self.duration / 8.0))
+    }
+
+    /// Human-readable report, one fact per line.
+    pub fn summary_lines(&self) -> Vec<String> {
+        let mut lines = vec![format!("Media playlist: {}", self.media_url)];
+        if let Some(bandwidth) = self.bandwidth {
+            let resolution = self
+                .resolution
+                .map_or(String::new(), |(w, h)| format!("{}x{}, ", w, h));
+            lines.push(format!("Variant: {}{} kbps", resolution, bandwidth / 1000));
+        }
+        lines.push(format!(
+            "Segments: {}{}{}",
+            self.segment_count,
+            if self.gap_count > 0 {
+                format!(" ({} marked #EXT-X-GAP)", self.gap_count)
+            } else {
+                String::new()
+            },
+            if self.live { ", live playlist" } else { "" }
+        ));
+        lines.push(format!("Duration: {:.1}s", self.duration));
+        lines.push(if self.encryption.is_empty() {
+            "Encryption: none".to_string()
+        } else {
+            format!(
+                "Encryption: {} ({} key(s))",
+                self.encryption.join(", "),
+                self.key_count
+            )
+        });
+        lines.push(match (self.byterange_bytes, self.estimated_bytes()) {
+            (Some(bytes), _) => format!(
+                "Size: {} (from #EXT-X-BYTERANGE)",
+                format_bytes(bytes as f64)
+            ),
+            (None, Some(bytes)) => {
+                format!("Estimated size: ~{} (from BANDWIDTH)", format_bytes(bytes))
+            }
+            (None, None) => "Estimated size: unknown (no master playlist BANDWIDTH)".to_string(),
+        });
+        lines
+    }
+}
+
+/// Fetches and parses the playlist of `config` and reports its segments, without fetching
+/// keys or media.
+pub async fn run_inspect_job(
+    config: DownloadConfig,
+    cancel: CancellationToken,
+    sender: mpsc::Sender<DownloadMessage>,
+    ctx: EguiContext,
+) -> Result<()> {
+    let send_log = |msg: String| {
+        sender.try_send(DownloadMessage::Log(msg)).ok();
+        ctx.request_repaint();
+    };
+
+    let limits = config.limits;
+    let playlist_url =
+        Url::parse(&config.playlist_url).map_err(|e| anyhow!("Invalid URL: {}", e))?;
+    let client = build_client(&config.headers, config.proxy.as_deref())?;
+    let (base_url, body) = match &config.playlist_text {
+        Some(text) => (playlist_url.clone(), text.clone()),
+        None => {
+            let fetched =
+                fetch_playlist_text(&client, &playlist_url, &limits, &send_log, &cancel).await?;
+            (fetched.url, fetched.body)
+        }
+    };
+    let media = resolve_media_playlist(
+        &client,
+        &base_url,
+        body,
+        config.quality.as_deref(),
+        &limits,
+        &send_log,
+        &cancel,
+    )
+    .await?;
+    let (mut segments, mut keys) = parse_m3u8(&media.body, &media.url, &limits, &send_log)?;
+    // The same query propagation as a download, so the listed URLs are the requested ones
+    if config.propagate_query {
+        let source = if playlist_url.query().is_some() {
+            &playlist_url
+        } else {
+            &media.url
+        };
+        signed_url::propagate_to_playlist(&mut segments, &mut keys, &media.url, source);
+    }
+    UrlRewriter::new(&config.url_rewrites)?.apply_to_segments(&mut segments, &media.url)?;
+
+    let mut encryption: Vec<String> = Vec::new();
+    for key in &keys {
+        if !encryption.contains(&key.method) {
+            encryption.push(key.method.clone());
+        }
+    }
+    let info = PlaylistInfo {
+        media_url: media.url.to_string(),
+        bandwidth: media.bandwidth,
+        resolution: media.resolution,
+        live: !media.body.contains("#EXT-X-ENDLIST"),
+        segment_count: segments.len(),
+        gap_count: segments.iter().filter(|s| s.gap).count(),
+        duration: segments.iter().map(|s| s.duration).sum(),
+        encryption,
+        key_count: keys.len(),
+        byterange_bytes: segments
+            .iter()
+            .map(|s| s.byte_range.map(|range| range.length))
+            .sum::<Option<u64>>()
+            .filter(|_| !segments.is_empty()),
+        segment_urls: segments
+            .iter()
+            .map(|s| {
+                let url = media
+                    .url
+                    .join(&s.path)
+                    .map_or_else(|_| s.path.clone(), |url| url.to_string());
+                match s.byte_range {
+                    Some(range) => format!(
+                        "{} (bytes {}-{})",
+                        url,
+                        range.offset,
+                        range.offset + range.length.saturating_sub(1)
+                    ),
+                    None => url,
+                }
+            })
+            .collect(),
+    };
+    for line in info.summary_lines() {
+        send_log(format!("-> {}", line));
+    }
+    sender.send(DownloadMessage::Progress(1.0)).await.ok();
+    sender
+        .send(DownloadMessage::Inspected {
+            playlist_url: config.playlist_url,
+            info: Box::new(info),
+        })
+        .await
+        .ok();
+    ctx.request_repaint();
+    Ok(())
+}
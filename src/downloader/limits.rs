@@ -0,0 +1,67 @@
+// Caps on what a playlist may make the downloader allocate or fetch. A broken or malicious
+// playlist with millions of lines would otherwise fill memory before anything fails, which
+// matters most for unattended (headless) runs.
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::failure;
+
+pub const DEFAULT_MAX_SEGMENTS: usize = 200_000;
+pub const DEFAULT_MAX_PLAYLIST_BYTES: u64 = 64 * 1024 * 1024;
+pub const DEFAULT_MAX_KEYS: usize = 1_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PlaylistLimits {
+    /// Segments per playlist
+    pub max_segments: usize,
+    /// Size of a playlist response (after gzip decoding)
+    pub max_playlist_bytes: u64,
+    /// Distinct keys to download per job (key rotation)
+    pub max_keys: usize,
+}
+
+impl PlaylistLimits {
+    pub const DEFAULT: PlaylistLimits = PlaylistLimits {
+        max_segments: DEFAULT_MAX_SEGMENTS,
+        max_playlist_bytes: DEFAULT_MAX_PLAYLIST_BYTES,
+        max_keys: DEFAULT_MAX_KEYS,
+    };
+
+    pub fn check_segments(&self, count: usize) -> Result<()> {
+        if count > self.max_segments {
+            return Err(failure::parse(format!(
+                "Playlist has more than {} segments (limit --max-segments)",
+                self.max_segments
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn check_playlist_bytes(&self, bytes: u64) -> Result<()> {
+        if bytes > self.max_playlist_bytes {
+            return Err(failure::parse(format!(
+                "Playlist is larger than {} bytes (limit --max-playlist-bytes)",
+                self.max_playlist_bytes
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn check_keys(&self, count: usize) -> Result<()> {
+        if count > self.max_keys {
+            return Err(failure::parse(format!(
+                "Playlist uses more than {} keys (limit --max-keys)",
+                self.max_keys
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Default for PlaylistLimits {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
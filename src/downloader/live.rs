@@ -0,0 +1,679 @@
+// Live recording: re-fetches a playlist without #EXT-X-ENDLIST and downloads the segments it
+// advertises until the stream ends or the user stops the recording.
+
+use anyhow::{Result, anyhow};
+use egui::Context as EguiContext;
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+
+use super::cancel::{self, Cancelled};
+use super::content_type::ContentTypeRules;
+use super::error_summary::{ErrorKind, ErrorSummary};
+use super::hls_parser::{
+    ClipWindow, FetchOutcome, InitSection, PlaylistFetcher, Segment, parse_low_latency, parse_m3u8,
+    skip_ad_breaks,
+};
+use super::limits::PlaylistLimits;
+use super::pause::PauseGate;
+use super::repair::{manifest_key, manifest_key_info};
+use super::report::SegmentReport;
+use super::segment_io::{SegmentDecryption, download_segments_concurrently};
+use super::signed_url;
+use super::url_rewrite::UrlRewriter;
+use super::workspace::{MANIFEST_FILENAME, SegmentStatus, WorkspaceManifest, segment_file_name};
+use super::{DownloadMessage, KeyOverride, forward_without_progress, spawn_key_downloads};
+
+/// Reload interval for playlists that do not declare #EXT-X-TARGETDURATION
+const DEFAULT_TARGET_DURATION: f64 = 6.0;
+/// Consecutive failed playlist reloads before the recording is finished early
+const MAX_RELOAD_FAILURES: usize = 5;
+/// Patient mode: polling pause after a streak of outage errors, and how many pauses in a row
+/// (about half an hour) are waited out before the recording is finished after all
+const OUTAGE_PAUSE: Duration = Duration::from_secs(3 * 60);
+const MAX_OUTAGE_PAUSES: usize = 10;
+
+/// Where a new recording starts in the first playlist it sees
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum LiveStart {
+    /// The earliest segment still in the playlist window (all of an event playlist's history)
+    #[default]
+    Window,
+    /// The newest segment; earlier ones in the window are left out
+    Edge,
+}
+
+impl LiveStart {
+    pub const ALL: [LiveStart; 2] = [LiveStart::Window, LiveStart::Edge];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            LiveStart::Window => "From the playlist start",
+            LiveStart::Edge => "From the live edge",
+        }
+    }
+
+    /// Name on the command line (`--live-start window|edge`)
+    pub fn name(self) -> &'static str {
+        match self {
+            LiveStart::Window => "window",
+            LiveStart::Edge => "edge",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|start| start.name().eq_ignore_ascii_case(value))
+    }
+}
+
+/// Recording choices of a job (the live fields of `DownloadConfig`)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LiveOptions {
+    pub start: LiveStart,
+    /// Leave out segments inside ad breaks
+    pub skip_ads: bool,
+    /// Wait out origin outages instead of finishing the recording
+    pub patient: bool,
+    /// Only record this wall-clock window; the recording ends once it has passed
+    pub clip: ClipWindow,
+    /// Caps on every reloaded playlist and on the keys of a long recording
+    pub limits: PlaylistLimits,
+}
+
+/// Everything recorded from a live playlist, in media sequence order
+#[derive(Default)]
+pub struct LiveRecording {
+    pub segments: Vec<Segment>,
+    pub paths: Vec<PathBuf>,
+    /// Keys referenced by `segments` (grows when the stream rotates keys)
+    pub keys: Vec<SegmentDecryption>,
+}
+
+/// `#EXT-X-TARGETDURATION` of a media playlist, in seconds
+pub fn target_duration(body: &str) -> Option<f64> {
+    body.lines()
+        .find_map(|line| line.trim().strip_prefix("#EXT-X-TARGETDURATION:"))
+        .and_then(|value| value.trim().parse::<f64>().ok())
+        .filter(|value| *value > 0.0)
+}
+
+/// Media sequence number of the newest segment a playlist advertises, from
+/// `#EXT-X-MEDIA-SEQUENCE` and the `#EXTINF` count; None without segments.
+fn newest_sequence(body: &str) -> Option<u64> {
+    let mut first = 0;
+    let mut count = 0u64;
+    for line in body.lines().map(str::trim) {
+        if let Some(value) = line.strip_prefix("#EXT-X-MEDIA-SEQUENCE:") {
+            first = value.trim().parse().unwrap_or(0);
+        } else if line.starts_with("#EXTINF:") {
+            count += 1;
+        }
+    }
+    count.checked_sub(1).map(|last| first + last)
+}
+
+/// `#EXT-X-DISCONTINUITY-SEQUENCE` of a media playlist (0 when absent)
+fn discontinuity_sequence(body: &str) -> u64 {
+    body.lines()
+        .find_map(|line| line.trim().strip_prefix("#EXT-X-DISCONTINUITY-SEQUENCE:"))
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(0)
+}
+
+/// Whether a reloaded playlist no longer continues the recording: its newest segment is older
+/// than the last recorded one, or its discontinuity sequence went backwards. Both happen when
+/// the encoder restarts and numbers its segments from the beginning again.
+fn sequence_restarted(body: &str, last_sequence: u64, previous_discontinuity: u64) -> bool {
+    newest_sequence(body).is_some_and(|newest| newest < last_sequence)
+        || discontinuity_sequence(body) < previous_discontinuity
+}
+
+/// Playlist URL for a blocking reload, which the server answers once segment `next_sequence` is
+/// complete. With partial segments that is when the first part of the segment after it exists.
+fn blocking_reload_url(playlist_url: &Url, next_sequence: u64, has_parts: bool) -> Url {
+    let mut url = playlist_url.clone();
+    if has_parts {
+        url.query_pairs_mut()
+            .append_pair("_HLS_msn", &(next_sequence + 1).to_string())
+            .append_pair("_HLS_part", "0");
+    } else {
+        url.query_pairs_mut()
+            .append_pair("_HLS_msn", &next_sequence.to_string());
+    }
+    url
+}
+
+/// Segments an earlier recording of the same output left in `workspace_dir` (stopped, or
+/// crashed after its last checkpoint), to be continued by `record_live_stream`. Empty when the
+/// workspace has no manifest yet.
+pub fn load_previous_recording(
+    workspace_dir: &Path,
+    playlist_url: &Url,
+    client: &Client,
+    key_override: &KeyOverride,
+    log: &(impl Fn(String) + Clone + Send + Sync + 'static),
+    cancel: &CancellationToken,
+) -> Result<LiveRecording> {
+    if !workspace_dir.join(MANIFEST_FILENAME).is_file() {
+        log("-> No earlier recording in the workspace, starting a new one.".to_string());
+        return Ok(LiveRecording::default());
+    }
+    let mut manifest = WorkspaceManifest::load(workspace_dir)?;
+    if manifest.config.playlist_url != playlist_url.as_str() {
+        log(format!(
+            "⚠️ Warning: The earlier recording was made from {}; appending anyway.",
+            manifest.config.playlist_url
+        ));
+    }
+    manifest.refresh_statuses(workspace_dir);
+
+    let mut keys = Vec::with_capacity(manifest.keys.len());
+    for enc in &manifest.keys {
+        let info = manifest_key_info(enc)?;
+        match enc.key_hex.as_deref() {
+            Some(key_hex) => keys.push(SegmentDecryption {
+                info,
+                key: manifest_key(key_hex)?,
+            }),
+            // Checkpoints are written before the keys have arrived
+            // Live playlists come from a server, so their keys never are local files
+            None => keys.extend(spawn_key_downloads(
+                client,
+                vec![info],
+                key_override,
+                false,
+                log,
+                log,
+                cancel,
+            )),
+        }
+    }
+
+    let mut recording = LiveRecording {
+        keys,
+        ..Default::default()
+    };
+    for entry in manifest
+        .segments
+        .iter()
+        .filter(|s| s.status == SegmentStatus::Done)
+    {
+        recording.paths.push(workspace_dir.join(&entry.file));
+        recording.segments.push(Segment {
+            path: entry.url.clone(),
+            index: entry.index,
+            sequence: entry.sequence.unwrap_or(entry.index as u64),
+            duration: entry.duration,
+            key: entry.key,
+            byte_range: entry.byte_range,
+            init: entry
+                .init
+                .and_then(|n| manifest.init_sections.get(n))
+                .map(|init| InitSection {
+                    uri: init.url.clone(),
+                    byte_range: init.byte_range,
+                }),
+            discontinuity: entry.discontinuity,
+            gap: false,
+            ad: false,
+            program_date_time: None,
+        });
+    }
+    let skipped = manifest.segments.len() - recording.segments.len();
+    log(format!(
+        "-> Appending to the earlier recording: {} segment(s){}.",
+        recording.segments.len(),
+        if skipped > 0 {
+            format!(", {} incomplete one(s) dropped", skipped)
+        } else {
+            String::new()
+        }
+    ));
+    Ok(recording)
+}
+
+/// Whether a failed reload looks like a passing origin outage (5xx, timeout, connection).
+fn is_outage(error: &anyhow::Error) -> bool {
+    error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<reqwest::Error>())
+        .is_some_and(|e| ErrorKind::from_reqwest(e).is_outage())
+}
+
+/// Records the live playlist `body` (already fetched from `playlist_url`) until `#EXT-X-ENDLIST`
+/// or `stop`; segments are deduplicated by media sequence across reloads.
+///
+/// The recording continues `previous` (see `load_previous_recording`), skipping the media
+/// sequences it already has; a new recording starts at `options.start`. `checkpoint` is called
+/// after every recorded batch.
+///
+/// In patient mode an origin outage pauses polling (`OUTAGE_PAUSE`) instead of finishing the
+/// recording, and segments that failed are tried once more with the next batch.
+#[allow(clippy::too_many_arguments)]
+pub async fn record_live_stream(
+    client: &Client,
+    playlist_url: &Url,
+    body: String,
+    query_source: Option<&Url>,
+    url_rewriter: &UrlRewriter,
+    keys: Vec<SegmentDecryption>,
+    previous: LiveRecording,
+    options: LiveOptions,
+    checkpoint: &(dyn Fn(&LiveRecording) + Sync),
+    max_concurrent_downloads: usize,
+    temp_dir_path: &Path,
+    error_summary: Arc<ErrorSummary>,
+    content_types: Arc<ContentTypeRules>,
+    report: Arc<SegmentReport>,
+    key_override: &KeyOverride,
+    cancel: &CancellationToken,
+    stop: &CancellationToken,
+    sender: &mpsc::Sender<DownloadMessage>,
+    ctx: &EguiContext,
+) -> Result<LiveRecording> {
+    let log = {
+        let sender = sender.clone();
+        let ctx = ctx.clone();
+        move |msg: String| {
+            sender.try_send(DownloadMessage::Log(msg)).ok();
+            ctx.request_repaint();
+        }
+    };
+    let mut recording = previous;
+    recording.keys.extend(keys);
+    let mut fetcher = PlaylistFetcher::new(options.limits);
+    let mut body = body;
+    // Where the last reload was served from (after redirects); relative URIs resolve against it
+    let mut base_url = playlist_url.clone();
+    // False after a `304 Not Modified` reload: the previous body has been handled already
+    let mut fresh = true;
+    let mut last_sequence = recording.segments.last().map(|segment| segment.sequence);
+    let mut recorded_secs: f64 = recording.segments.iter().map(|s| s.duration).sum();
+    // The first segment appended to an earlier recording starts a new run if any went missing
+    let mut resuming = last_sequence.is_some();
+    if last_sequence.is_none()
+        && options.start == LiveStart::Edge
+        && let Some(newest) = newest_sequence(&body)
+        && let Some(before_newest) = newest.checked_sub(1)
+    {
+        // Everything up to the segment before the newest counts as already recorded
+        log(format!(
+            "-> Starting at the live edge (segment {}); earlier segments in the window are skipped.",
+            newest
+        ));
+        last_sequence = Some(before_newest);
+    }
+    if let (Some(last), Some(newest)) = (last_sequence, newest_sequence(&body))
+        && newest < last
+    {
+        return Err(anyhow!(
+            "The live playlist's media sequence ({}) is behind the earlier recording ({}); it cannot be appended",
+            newest,
+            last
+        ));
+    }
+    let mut reload_failures = 0;
+    let mut outage_pauses = 0;
+    let mut clip_passed = false;
+    // Patient mode: failed segments waiting for their second attempt (keys already mapped)
+    let mut retry_later: Vec<Segment> = Vec::new();
+    let mut low_latency_announced = false;
+    let mut discontinuity = discontinuity_sequence(&body);
+    // After an encoder restart the new segment numbers are shifted past the recorded ones, so
+    // their file names and order follow the earlier segments
+    let mut index_offset = 0;
+    let mut next_index = recording
+        .segments
+        .last()
+        .map_or(0, |segment| segment.index + 1);
+    let mut restarted = false;
+
+    loop {
+        if fresh
+            && let Some(last) = last_sequence
+            && sequence_restarted(&body, last, discontinuity)
+        {
+            log(format!(
+                "⚠️ Warning: The live playlist's media sequence restarted (segment {} after {}); recording continues from its new segments.",
+                newest_sequence(&body).unwrap_or(0),
+                last
+            ));
+            last_sequence = None;
+            index_offset = next_index;
+            restarted = true;
+        }
+        if fresh {
+            discontinuity = discontinuity_sequence(&body);
+        }
+        // Only a playlist that advertises segments past the recorded ones is parsed; with
+        // sub-second LL-HLS reloads most polls bring nothing new
+        let has_new = fresh
+            && newest_sequence(&body)
+                .is_some_and(|newest| last_sequence.is_none_or(|last| newest > last));
+        let (mut new_segments, infos) = if has_new {
+            let (mut segments, mut infos) = parse_m3u8(&body, &base_url, &options.limits, &|_| {})?;
+            if let Some(source) = query_source {
+                signed_url::propagate_to_playlist(&mut segments, &mut infos, &base_url, source);
+            }
+            url_rewriter.apply_to_segments(&mut segments, &base_url)?;
+            let new_segments: Vec<Segment> = segments
+                .into_iter()
+                .filter(|s| last_sequence.is_none_or(|last| s.sequence > last))
+                .map(|mut s| {
+                    s.index += index_offset;
+                    s
+                })
+                .collect();
+            (new_segments, infos)
+        } else {
+            (Vec::new(), Vec::new())
+        };
+        let ended = body.contains("#EXT-X-ENDLIST");
+        let target = target_duration(&body).unwrap_or(DEFAULT_TARGET_DURATION);
+        let low_latency = parse_low_latency(&body);
+        if low_latency.is_low_latency() && !low_latency_announced {
+            low_latency_announced = true;
+            log(format!(
+                "⚡ Low-Latency HLS: part target {}, blocking reload {}, {} pending part(s){}. Complete segments are recorded.",
+                low_latency
+                    .part_target
+                    .map_or("unknown".to_string(), |t| format!("{}s", t)),
+                if low_latency.can_block_reload {
+                    "supported"
+                } else {
+                    "not supported"
+                },
+                low_latency.pending_parts,
+                low_latency
+                    .preload_hint
+                    .as_deref()
+                    .map_or(String::new(), |uri| format!(", preload hint {}", uri))
+            ));
+        }
+
+        if let (Some(last), Some(first)) = (last_sequence, new_segments.first_mut())
+            && first.sequence > last + 1
+        {
+            log(format!(
+                "⚠️ Warning: {} live segment(s) left the playlist window before they could be downloaded.",
+                first.sequence - last - 1
+            ));
+            first.discontinuity |= resuming;
+        }
+        resuming &= new_segments.is_empty();
+        if let Some(first) = new_segments.first_mut()
+            && restarted
+        {
+            // Timestamps start over with the restarted encoder
+            first.discontinuity = true;
+            restarted = false;
+        }
+        if let Some(newest) = new_segments.last() {
+            next_index = newest.index + 1;
+        }
+
+        let changed = !new_segments.is_empty();
+        if changed {
+            // Key indices of this reload, mapped into the recording's key list
+            let key_map: Vec<usize> = infos
+                .into_iter()
+                .map(|mut info| {
+                    // Known keys carry the entered IV already
+                    key_override.apply_iv(&mut info);
+                    info
+                })
+                .map(
+                    |info| match recording.keys.iter().position(|known| known.info == info) {
+                        Some(existing) => Ok(existing),
+                        None => {
+                            // Rotating keys add up over a long recording
+                            options.limits.check_keys(recording.keys.len() + 1)?;
+                            recording.keys.extend(spawn_key_downloads(
+                                client,
+                                vec![info],
+                                key_override,
+                                false,
+                                &log,
+                                &log,
+                                cancel,
+                            ));
+                            Ok(recording.keys.len() - 1)
+                        }
+                    },
+                )
+                .collect::<Result<_>>()?;
+            last_sequence = new_segments.last().map(|segment| segment.sequence);
+            if options.skip_ads {
+                new_segments = skip_ad_breaks(new_segments);
+            }
+            if options.clip.is_set() {
+                if new_segments.iter().any(|s| s.program_date_time.is_none()) {
+                    return Err(anyhow!(
+                        "The live playlist has no #EXT-X-PROGRAM-DATE-TIME; it cannot be clipped by time"
+                    ));
+                }
+                clip_passed |= new_segments.iter().any(|s| options.clip.is_past(s));
+                new_segments.retain(|s| options.clip.contains(s));
+            }
+            // #EXT-X-GAP segments have no media to fetch
+            let retried = retry_later.len();
+            let batch: Vec<Segment> = std::mem::take(&mut retry_later)
+                .into_iter()
+                .chain(new_segments.into_iter().filter(|segment| !segment.gap).map(
+                    |mut segment| {
+                        segment.key = segment.key.map(|key| key_map[key]);
+                        segment
+                    },
+                ))
+                .collect();
+
+            let result = if batch.is_empty() {
+                Ok(Vec::new())
+            } else {
+                let (batch_sender, forward) = forward_without_progress(sender);
+                let result = download_segments_concurrently(
+                    client,
+                    &base_url,
+                    batch.clone(),
+                    recording.keys.clone(),
+                    batch.len(),
+                    max_concurrent_downloads,
+                    temp_dir_path.to_path_buf(),
+                    error_summary.clone(),
+                    content_types.clone(),
+                    report.clone(),
+                    cancel,
+                    // Segments leave the live window while waiting, so recordings never pause
+                    &PauseGate::default(),
+                    batch_sender,
+                    ctx.clone(),
+                )
+                .await;
+                forward.await.ok();
+                result
+            };
+            match result {
+                Err(e) if e.is::<Cancelled>() => return Err(e),
+                // Lost segments only leave a gap; the recording goes on
+                Err(e) => log(format!(
+                    "⚠️ Warning: Live segments skipped after download errors: {}",
+                    e
+                )),
+                Ok(_) => {}
+            }
+            for (position, segment) in batch.into_iter().enumerate() {
+                let path = temp_dir_path.join(segment_file_name(segment.index));
+                if path.is_file() {
+                    recorded_secs += segment.duration;
+                    recording.paths.push(path);
+                    recording.segments.push(segment);
+                } else if options.patient && position >= retried {
+                    retry_later.push(segment);
+                }
+            }
+            if !retry_later.is_empty() {
+                log(format!(
+                    "-> {} failed live segment(s) are tried again with the next batch.",
+                    retry_later.len()
+                ));
+            }
+            log(format!(
+                "🔴 Live: {} segment(s) recorded ({:.0}s)",
+                recording.segments.len(),
+                recorded_secs
+            ));
+            checkpoint(&recording);
+        }
+
+        if ended {
+            log("-> #EXT-X-ENDLIST received, the live stream has ended.".to_string());
+            break;
+        }
+        if clip_passed {
+            log("-> The clip end time has been reached.".to_string());
+            break;
+        }
+
+        let reload_url = match last_sequence.filter(|_| low_latency.can_block_reload && changed) {
+            // The server holds the request until the next segment exists; no need to wait
+            Some(last) => {
+                blocking_reload_url(playlist_url, last + 1, low_latency.part_target.is_some())
+            }
+            None => {
+                // RFC 8216 §6.3.4: wait one target duration after a change, half of it otherwise
+                let wait = if changed { target } else { target / 2.0 };
+                tokio::select! {
+                    biased;
+                    _ = cancel.cancelled() => return Err(Cancelled.into()),
+                    _ = stop.cancelled() => {
+                        log("⏹ Recording stopped.".to_string());
+                        break;
+                    }
+                    _ = sleep(Duration::from_secs_f64(wait)) => {}
+                }
+                playlist_url.clone()
+            }
+        };
+
+        let reload = tokio::select! {
+            biased;
+            _ = stop.cancelled() => {
+                log("⏹ Recording stopped.".to_string());
+                break;
+            }
+            result = cancel::or_cancel(cancel, fetcher.fetch(client, &reload_url, &log)) => result,
+        };
+        match reload {
+            Ok(FetchOutcome::Modified { body: text, url }) => {
+                fresh = text != body;
+                body = text;
+                base_url = url;
+                reload_failures = 0;
+                outage_pauses = 0;
+            }
+            Ok(FetchOutcome::NotModified) => {
+                fresh = false;
+                reload_failures = 0;
+                outage_pauses = 0;
+            }
+            Err(e) if e.is::<Cancelled>() => return Err(e),
+            Err(e) => {
+                reload_failures += 1;
+                if reload_failures >= MAX_RELOAD_FAILURES
+                    && options.patient
+                    && outage_pauses < MAX_OUTAGE_PAUSES
+                    && is_outage(&e)
+                {
+                    outage_pauses += 1;
+                    log(format!(
+                        "⏸ Origin unavailable ({}); pausing the recording for {} min ({} of {}).",
+                        e,
+                        OUTAGE_PAUSE.as_secs() / 60,
+                        outage_pauses,
+                        MAX_OUTAGE_PAUSES
+                    ));
+                    tokio::select! {
+                        biased;
+                        _ = cancel.cancelled() => return Err(Cancelled.into()),
+                        _ = stop.cancelled() => {
+                            log("⏹ Recording stopped.".to_string());
+                            break;
+                        }
+                        _ = sleep(OUTAGE_PAUSE) => {}
+                    }
+                    log("-> Resuming the live recording.".to_string());
+                    reload_failures = 0;
+                } else if reload_failures >= MAX_RELOAD_FAILURES {
+                    if recording.paths.is_empty() {
+                        return Err(e);
+                    }
+                    log(format!(
+                        "❌ Playlist reload failed {} times ({}); finishing the recording.",
+                        reload_failures, e
+                    ));
+                    break;
+                }
+                log(format!(
+                    "⚠️ Warning: Playlist reload failed ({}), retrying.",
+                    e
+                ));
+            }
+        }
+    }
+
+    Ok(recording)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WINDOW: &str = "#EXTM3U\n#EXT-X-MEDIA-SEQUENCE:120\n#EXT-X-DISCONTINUITY-SEQUENCE:3\n\
+                          #EXTINF:4,\n120.ts\n#EXTINF:4,\n121.ts\n#EXTINF:4,\n122.ts\n";
+
+    #[test]
+    fn newest_sequence_counts_from_the_media_sequence() {
+        assert_eq!(newest_sequence(WINDOW), Some(122));
+        assert_eq!(newest_sequence("#EXTM3U\n#EXTINF:4,\n0.ts\n"), Some(0));
+        assert_eq!(newest_sequence("#EXTM3U\n#EXT-X-MEDIA-SEQUENCE:7\n"), None);
+    }
+
+    #[test]
+    fn restarts_are_detected_by_either_sequence() {
+        assert_eq!(discontinuity_sequence(WINDOW), 3);
+        assert!(!sequence_restarted(WINDOW, 121, 3));
+        assert!(!sequence_restarted(WINDOW, 122, 2));
+        // Newest segment older than the recorded one
+        assert!(sequence_restarted(WINDOW, 500, 3));
+        // Discontinuity sequence went backwards
+        assert!(sequence_restarted(WINDOW, 121, 4));
+        let restarted = "#EXTM3U\n#EXTINF:4,\n0.ts\n#EXTINF:4,\n1.ts\n";
+        assert!(sequence_restarted(restarted, 122, 3));
+    }
+
+    #[test]
+    fn low_latency_tags_are_read() {
+        let body = "#EXTM3U\n\
+                    #EXT-X-SERVER-CONTROL:CAN-BLOCK-RELOAD=YES,PART-HOLD-BACK=1.0\n\
+                    #EXT-X-PART-INF:PART-TARGET=0.33334\n\
+                    #EXT-X-PART:DURATION=0.33334,URI=\"a.0.mp4\"\n\
+                    #EXTINF:4,\na.mp4\n\
+                    #EXT-X-PART:DURATION=0.33334,URI=\"b.0.mp4\"\n\
+                    #EXT-X-PART:DURATION=0.33334,URI=\"b.1.mp4\"\n\
+                    #EXT-X-PRELOAD-HINT:TYPE=PART,URI=\"b.2.mp4\"\n";
+        let info = parse_low_latency(body);
+        assert!(info.can_block_reload);
+        assert_eq!(info.part_target, Some(0.33334));
+        assert_eq!(info.pending_parts, 2);
+        assert_eq!(info.preload_hint.as_deref(), Some("b.2.mp4"));
+        assert!(info.is_low_latency());
+        assert!(!parse_low_latency(WINDOW).is_low_latency());
+    }
+}
@@ -0,0 +1,202 @@
+// Two-pass EBU R128 loudness normalization (FFmpeg `loudnorm`) of a finished output file.
+
+use anyhow::{Result, anyhow};
+use egui::Context as EguiContext;
+use serde::Deserialize;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use super::DownloadMessage;
+use super::cancel::Cancelled;
+use super::failure;
+use super::segment_io::run_ffmpeg_with_progress;
+use super::util::PathStringLossy;
+
+/// Integrated loudness / true peak / loudness range targets (podcast-style defaults)
+const TARGET_I: f64 = -16.0;
+const TARGET_TP: f64 = -1.5;
+const TARGET_LRA: f64 = 11.0;
+/// loudnorm resamples internally to 192 kHz; write a normal rate back out
+const OUTPUT_SAMPLE_RATE: &str = "48000";
+const AUDIO_BITRATE: &str = "192k";
+
+/// Measurements printed by the first loudnorm pass (`print_format=json`)
+#[derive(Debug, Deserialize)]
+struct LoudnormMeasurement {
+    input_i: String,
+    input_tp: String,
+    input_lra: String,
+    input_thresh: String,
+    target_offset: String,
+}
+
+/// Normalizes the audio of `path` in place.
+///
+/// The result is written to a temporary file next to it and only replaces the original once both
+/// passes succeeded. `on_progress` receives 0.0 to 1.0 across both passes.
+pub fn normalize_loudness(
+    path: &Path,
+    cancel: &CancellationToken,
+    on_progress: impl Fn(f32),
+) -> Result<()> {
+    // Pass 1: measure
+    let filter = format!(
+        "loudnorm=I={}:TP={}:LRA={}:print_format=json",
+        TARGET_I, TARGET_TP, TARGET_LRA
+    );
+    let args: Vec<&OsStr> = vec![
+        "-i".as_ref(),
+        path.as_os_str(),
+        "-map".as_ref(),
+        "0:a:0".as_ref(),
+        "-af".as_ref(),
+        filter.as_ref(),
+        "-f".as_ref(),
+        "null".as_ref(),
+        "-".as_ref(),
+    ];
+    let run = run_ffmpeg_with_progress(&args, cancel, |p| on_progress(p * 0.5))?;
+    if !run.success {
+        return Err(failure::ffmpeg(format!(
+            "Loudness measurement failed: {}",
+            run.stderr
+        )));
+    }
+    let measured = parse_measurement(&run.stderr)?;
+    for value in [&measured.input_i, &measured.input_tp, &measured.input_lra] {
+        if value.parse::<f64>().map_or(true, |v| !v.is_finite()) {
+            return Err(anyhow!(
+                "No usable loudness measurement (silent or missing audio track)"
+            ));
+        }
+    }
+
+    // Pass 2: apply the measured values (linear mode), copying all other streams
+    let filter = format!(
+        "loudnorm=I={}:TP={}:LRA={}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true",
+        TARGET_I,
+        TARGET_TP,
+        TARGET_LRA,
+        measured.input_i,
+        measured.input_tp,
+        measured.input_lra,
+        measured.input_thresh,
+        measured.target_offset
+    );
+    let ext = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let audio_codec = if ext == "webm" { "libopus" } else { "aac" };
+    let temp_path = normalized_temp_path(path, &ext);
+    let mut args: Vec<&OsStr> = vec![
+        "-i".as_ref(),
+        path.as_os_str(),
+        "-map".as_ref(),
+        "0".as_ref(),
+        "-c".as_ref(),
+        "copy".as_ref(),
+        "-c:a".as_ref(),
+        audio_codec.as_ref(),
+        "-b:a".as_ref(),
+        AUDIO_BITRATE.as_ref(),
+        "-ar".as_ref(),
+        OUTPUT_SAMPLE_RATE.as_ref(),
+        "-af".as_ref(),
+        filter.as_ref(),
+    ];
+    if matches!(ext.as_str(), "mp4" | "mov" | "m4a") {
+        args.extend(["-movflags", "+faststart"].map(OsStr::new));
+    }
+    args.extend(["-y".as_ref(), temp_path.as_os_str()]);
+
+    let result = run_ffmpeg_with_progress(&args, cancel, |p| on_progress(0.5 + p * 0.5));
+    match result {
+        Ok(run) if run.success => {
+            std::fs::rename(&temp_path, path)?;
+            Ok(())
+        }
+        other => {
+            let _ = std::fs::remove_file(&temp_path);
+            match other {
+                Ok(run) => Err(failure::ffmpeg(format!(
+                    "Loudness normalization failed: {}",
+                    run.stderr
+                ))),
+                Err(e) => Err(e),
+            }
+        }
+    }
+}
+
+/// Extracts the JSON block loudnorm prints at the end of stderr.
+fn parse_measurement(stderr: &str) -> Result<LoudnormMeasurement> {
+    let start = stderr.rfind('{');
+    let end = stderr.rfind('}');
+    let (Some(start), Some(end)) = (start, end) else {
+        return Err(anyhow!("FFmpeg did not print loudnorm measurements"));
+    };
+    if end < start {
+        return Err(anyhow!("FFmpeg did not print loudnorm measurements"));
+    }
+    serde_json::from_str(&stderr[start..=end])
+        .map_err(|e| anyhow!("Invalid loudnorm measurements: {}", e))
+}
+
+/// `<dir>/<stem>.normalizing.<ext>`, removed again if the second pass fails.
+fn normalized_temp_path(path: &Path, ext: &str) -> PathBuf {
+    let stem = path.to_path_buf().file_string_lossy();
+    path.with_file_name(format!("{}.normalizing.{}", stem, ext))
+}
+
+/// Runs `normalize_loudness` off the async runtime, reporting progress and log lines to the GUI.
+pub async fn run_normalize_step(
+    path: PathBuf,
+    cancel: CancellationToken,
+    sender: mpsc::Sender<DownloadMessage>,
+    ctx: EguiContext,
+) -> Result<()> {
+    sender
+        .send(DownloadMessage::Log(format!(
+            "🔊 Normalizing loudness of {} (two-pass loudnorm)...",
+            path.display()
+        )))
+        .await
+        .ok();
+    ctx.request_repaint();
+
+    let progress_sender = sender.clone();
+    let progress_ctx = ctx.clone();
+    let normalize_path = path.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        normalize_loudness(&normalize_path, &cancel, |p| {
+            progress_sender.try_send(DownloadMessage::Progress(p)).ok();
+            progress_ctx.request_repaint();
+        })
+    })
+    .await
+    .map_err(|e| anyhow!("Normalization blocking task failed to join: {}", e))?;
+
+    if let Err(e) = result {
+        if e.is::<Cancelled>() {
+            return Err(e);
+        }
+        return Err(failure::ffmpeg(format!(
+            "{}. The un-normalized file was kept at {}",
+            e,
+            path.display()
+        )));
+    }
+    sender.send(DownloadMessage::Progress(1.0)).await.ok();
+    sender
+        .send(DownloadMessage::Log(
+            "✅ Loudness normalization finished.".to_string(),
+        ))
+        .await
+        .ok();
+    ctx.request_repaint();
+    Ok(())
+}
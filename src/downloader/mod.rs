@@ -1,23 +1,86 @@
 use anyhow::{Result, anyhow};
 use egui::Context as EguiContext;
 use reqwest::Url;
-use std::path::PathBuf;
-use tempfile::tempdir;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 
 // 導出子模組
+pub mod batch;
+pub mod cancel;
+pub mod captions;
+pub mod capture;
+pub mod content_type;
+pub mod dash_parser;
+pub mod error_summary;
+pub mod failure;
 pub mod ffmpeg_embed;
 pub mod hls_parser;
+pub mod hooks;
+pub mod http;
+pub mod inspect;
+pub mod limits;
+pub mod live;
+pub mod loudnorm;
+pub mod network;
+pub mod pause;
+pub mod player_stream;
+pub mod preview;
+pub mod probe;
+pub mod progressive;
+pub mod remux_pool;
+pub mod repair;
+pub mod report;
+pub mod resources;
+pub mod retry_budget;
+pub mod sample_aes;
 pub mod segment_io;
+pub mod signed_url;
+pub mod stats;
+pub mod subtitles;
+pub mod url_rewrite;
 pub mod util;
+pub mod verify;
+pub mod webvtt;
+pub mod workspace;
 
 // 從子模組引入需要的類型和函數
-use hls_parser::{download_and_parse_m3u3, download_key_file};
-use segment_io::{concatenate_segments, download_segments_concurrently, run_ffmpeg_remux};
-use util::PathStringLossy; // 引入 helper trait
+use content_type::ContentTypeRules;
+use error_summary::ErrorSummary;
+use hls_parser::{
+    ClipWindow, EncryptionInfo, KEY_LEN, SampleLength, Segment, SegmentKey, TimeRange,
+    TrackMetadata, allows_local_keys, download_key_file, fetch_playlist_text, parse_m3u8,
+    resolve_media_playlist, skip_ad_breaks,
+};
+use hooks::JobContext;
+use http::build_client;
+use limits::PlaylistLimits;
+use pause::PauseGate;
+use player_stream::PlayerStream;
+use report::SegmentReport;
+use segment_io::{
+    ExternalAudio, PendingKey, RemuxOptions, ResolvedInit, SegmentDecryption, Trim,
+    concatenate_segments, concatenate_with_discontinuities, download_init_sections,
+    download_segments_concurrently, generate_gap_filler, init_sections, run_ffmpeg_remux,
+};
+use subtitles::SubtitleMode;
+use url_rewrite::UrlRewriter;
+use util::{PathStringLossy, expand_output_subfolder}; // 引入 helper trait
+use workspace::{
+    ConcatIndex, MERGED_TS_FILENAME, ManifestEncryption, ManifestInit, ManifestSegment,
+    SegmentStatus, TEMP_DIR_PREFIX, WorkspaceManifest, discontinuity_starts,
+    interleave_init_sections, segment_file_name, workspace_dir_for,
+};
 
 // --- HLS related structs and constants ---
 pub const DEFAULT_CONCURRENT_DOWNLOADS: u8 = 4;
+/// Segments and merged stream of a separate audio rendition, inside the job's temp dir
+const AUDIO_DIR_NAME: &str = "audio";
+const AUDIO_TS_FILENAME: &str = "audio_merge.ts.tmp";
 
 // --- Egui/MPSC bridge structs and messages ---
 
@@ -26,20 +89,330 @@ pub const DEFAULT_CONCURRENT_DOWNLOADS: u8 = 4;
 pub enum DownloadMessage {
     Log(String),
     Progress(f32), // 0.0 to 1.0 (overall progress)
+    /// Segment errors of one download, classified by kind and host (see `ErrorSummary`); shown
+    /// in the log and kept for the diagnostics bundle
+    ErrorSummary(String),
+    /// Segments downloaded so far, shown as a log line (sub-downloads drop it)
+    SegmentProgress {
+        completed: usize,
+        total: usize,
+    },
     Finished(Result<(), String>),
     OutputPathSelected(String),
+    RemuxInputSelected(String),
+    KeyFileSelected(String),
+    VerifyFileSelected(String),
+    RepairWorkspaceSelected(String),
+    /// A URL was submitted through the local HTTP API (`name` may be empty)
+    ApiJobSubmitted {
+        url: String,
+        name: String,
+    },
+    /// The HTTP API asked to cancel every running download
+    ApiCancelRequested,
+    /// The HTTP API asked to remove the queue job at `position`
+    ApiJobRemoved {
+        position: usize,
+    },
+    /// A segment request failed and will be retried
+    RetryWarning {
+        segment: usize,
+        attempt: usize,
+        reason: String,
+        /// Backoff before the next attempt
+        delay: Duration,
+    },
+    /// Analyze step finished with the codecs of the first segment of `playlist_url`
+    Analyzed {
+        playlist_url: String,
+        info: probe::StreamInfo,
+    },
+    /// Analyze step fetched a playlist for the job `playlist_url`: the entered one first, then
+    /// the selected variant of a master playlist
+    PlaylistFetched {
+        playlist_url: String,
+        url: String,
+        text: String,
+    },
+    /// Analyze step parsed the media playlist of `playlist_url`
+    SegmentsListed {
+        playlist_url: String,
+        segments: Vec<probe::SegmentEntry>,
+    },
+    /// Inspect (dry run) parsed the playlist of `playlist_url`
+    Inspected {
+        playlist_url: String,
+        info: Box<inspect::PlaylistInfo>,
+    },
+    /// One URL of a batch import was analyzed (`row` is its position in the list)
+    BatchAnalyzed {
+        row: usize,
+        result: Result<Box<batch::BatchEntry>, String>,
+    },
+    /// "Save for later" finished capturing the job
+    Captured(Box<capture::CapturedJob>),
+    /// Remux failed; the concatenated TS was kept at `ts_path` so the remux can be retried
+    /// with the job's own `options`
+    RemuxFailed {
+        ts_path: PathBuf,
+        output_path: PathBuf,
+        options: RemuxOptions,
+    },
+    /// The download of `output_path` is done and its remux continues in the background; the
+    /// remux reports its logs and its own `Finished` on `receiver`, and stops on `cancel`
+    OutputStageDetached {
+        output_path: PathBuf,
+        receiver: mpsc::Receiver<DownloadMessage>,
+        cancel: CancellationToken,
+    },
+}
+
+impl DownloadMessage {
+    /// Log line for a `SegmentProgress` update
+    pub fn segment_progress_line(completed: usize, total: usize) -> String {
+        format!(
+            "📦 Segment progress: {}/{} ({:.2}%)",
+            completed,
+            total,
+            completed as f32 / total as f32 * 100.0
+        )
+    }
+}
+
+/// User-facing parameters of a single download job
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadConfig {
+    pub playlist_url: String,
+    pub output_location: String,
+    pub output_filename: String,
+    pub concurrency: usize,
+    pub output_format: String,
+    /// Extra request headers (`Name`, `value`), e.g. Referer or Cookie
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    /// Repair PTS/DTS discontinuities in the merged stream (runs FFmpeg even for TS output)
+    #[serde(default)]
+    pub fix_timestamps: bool,
+    /// Keep segments and a manifest in `<name>.hlsdl/` next to the output for later repair
+    #[serde(default)]
+    pub keep_workspace: bool,
+    /// Playlist content pasted by the user; `playlist_url` is then only the base for relative URIs
+    #[serde(default)]
+    pub playlist_text: Option<String>,
+    /// Two-pass loudness normalization of the finished output (re-encodes audio only)
+    #[serde(default)]
+    pub normalize_audio: bool,
+    /// Save CEA-608/708 closed captions of the video stream as `<name>.cc.srt`
+    #[serde(default)]
+    pub extract_captions: bool,
+    /// Read every chunk back after concatenation and compare it with its segment file
+    #[serde(default)]
+    pub verify_concat: bool,
+    /// Subtitle track (URL, WebVTT playlist or local file) added to the output
+    #[serde(default)]
+    pub subtitle_url: Option<String>,
+    #[serde(default)]
+    pub subtitle_mode: SubtitleMode,
+    /// Language/name of the subtitle rendition, written as track metadata
+    #[serde(default)]
+    pub subtitle_track: Option<TrackMetadata>,
+    /// Serve the segments as one live TS on localhost while downloading (for mpv/VLC)
+    #[serde(default)]
+    pub stream_to_player: bool,
+    /// Variant to pick from a master playlist (`best`, `worst`, `720p`, ...); None = best
+    #[serde(default)]
+    pub quality: Option<String>,
+    /// Separate audio playlist muxed into the output (alternative audio rendition)
+    #[serde(default)]
+    pub audio_url: Option<String>,
+    /// Language/name of that audio rendition, written as track metadata
+    #[serde(default)]
+    pub audio_track: Option<TrackMetadata>,
+    /// Proxy URL for all requests; None = `HTTP(S)_PROXY` from the environment
+    #[serde(default)]
+    pub proxy: Option<String>,
+    /// Keep reloading a live playlist (no `#EXT-X-ENDLIST`) and record until it ends or is stopped
+    #[serde(default)]
+    pub live: bool,
+    /// Live: continue the recording kept in the output's workspace instead of starting over
+    /// (implies `keep_workspace`)
+    #[serde(default)]
+    pub append_recording: bool,
+    /// Live: where a new recording starts in the playlist window
+    #[serde(default)]
+    pub live_start: live::LiveStart,
+    /// Live: wait out origin outages (5xx, timeouts) with polling pauses instead of stopping
+    #[serde(default)]
+    pub patient_live: bool,
+    /// Wall-clock start of the part to download (ISO 8601, matched against
+    /// `#EXT-X-PROGRAM-DATE-TIME`); None = from the beginning
+    #[serde(default)]
+    pub clip_start: Option<String>,
+    /// Wall-clock end of the part to download; a live recording stops once it has passed
+    #[serde(default)]
+    pub clip_end: Option<String>,
+    /// Position in the playlist (`[[hh:]mm:]ss`, summed `#EXTINF`) where the download starts;
+    /// None = from the beginning
+    #[serde(default)]
+    pub time_start: Option<String>,
+    /// Position where the download ends; None = to the end
+    #[serde(default)]
+    pub time_end: Option<String>,
+    /// Cut the output to exactly `time_start`/`time_end` instead of whole segments (re-encodes)
+    #[serde(default)]
+    pub exact_trim: bool,
+    /// Re-encode (VP9/Opus for WebM, H.264/AAC otherwise) when the output format cannot hold
+    /// the stream's codecs, instead of a stream copy that would fail
+    #[serde(default)]
+    pub reencode: bool,
+    /// Only download the first segments/seconds, saved as `<name>.sample.<ext>`, to check
+    /// quality and language before the full download
+    #[serde(default)]
+    pub sample: Option<SampleLength>,
+    /// Segment `Content-Type`s that are accepted (`video/*`, `application/json`, ...), others are
+    /// rejected; empty = any type, with a warning for types outside the defaults
+    #[serde(default)]
+    pub accepted_content_types: Vec<String>,
+    /// Regex find/replace rules applied to segment URLs before they are fetched
+    #[serde(default)]
+    pub url_rewrites: Vec<url_rewrite::RewriteRule>,
+    /// Per-segment timings written after the download (`.json` for JSON, CSV otherwise)
+    #[serde(default)]
+    pub report_path: Option<String>,
+    /// Decryption key entered by the user (hex); replaces the key file download. Read from API
+    /// job posts but never written to the queue, history or watch-later files
+    #[serde(default, skip_serializing)]
+    pub key_hex: Option<String>,
+    /// IV entered by the user (hex); replaces the playlist IV
+    #[serde(default)]
+    pub iv_hex: Option<String>,
+    /// Shell command run after a successful download, with the job context in `HLSDL_*` variables
+    #[serde(default)]
+    pub on_complete: Option<String>,
+    /// URL the job context is POSTed to (JSON) after a successful download
+    #[serde(default)]
+    pub webhook_url: Option<String>,
+    /// Sub-folder of `output_location` created on demand, e.g. `{host}/{yyyy-mm}`
+    #[serde(default)]
+    pub output_subfolder: Option<String>,
+    /// Caps on playlist size, segment count and key downloads
+    #[serde(default)]
+    pub limits: PlaylistLimits,
+    /// Insert black video/silence for `#EXT-X-GAP` segments instead of leaving a jump in time
+    #[serde(default)]
+    pub fill_gaps: bool,
+    /// Leave out segments inside ad breaks (SCTE-35 `#EXT-X-DATERANGE`, `#EXT-X-CUE-OUT`/`-IN`)
+    #[serde(default)]
+    pub skip_ads: bool,
+    /// Append the playlist URL's query parameters (signed tokens) to segment and key URLs
+    #[serde(default)]
+    pub propagate_query: bool,
+    /// Indices (media sequence numbers) of segments left out of the download (e.g. pre-roll ads)
+    #[serde(default)]
+    pub excluded_segments: Vec<usize>,
+    /// Hand the remux and later steps to a remux worker once the segments are merged, so the
+    /// next queued job can start downloading (see `DownloadMessage::OutputStageDetached`)
+    #[serde(default)]
+    pub background_remux: bool,
+}
+
+/// Key and IV entered by the user, validated
+#[derive(Clone, Default)]
+pub struct KeyOverride {
+    pub key: Option<SegmentKey>,
+    pub iv: Option<[u8; KEY_LEN]>,
+}
+
+impl KeyOverride {
+    pub fn from_config(config: &DownloadConfig) -> Result<Self> {
+        let key = match parse_hex(config.key_hex.as_deref(), "key")? {
+            Some(bytes) => Some(SegmentKey::from_bytes(&bytes).map_err(|_| {
+                anyhow!(
+                    "Key must be 16 (AES-128) or 32 (AES-256) bytes, got {}",
+                    bytes.len()
+                )
+            })?),
+            None => None,
+        };
+        let iv = match parse_hex(config.iv_hex.as_deref(), "IV")? {
+            Some(bytes) => Some(
+                <[u8; KEY_LEN]>::try_from(bytes.as_slice())
+                    .map_err(|_| anyhow!("IV must be {} bytes, got {}", KEY_LEN, bytes.len()))?,
+            ),
+            None => None,
+        };
+        Ok(Self { key, iv })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.key.is_none() && self.iv.is_none()
+    }
+
+    /// Replaces the playlist IV of `info` with the entered one.
+    pub fn apply_iv(&self, info: &mut EncryptionInfo) {
+        if let Some(iv) = self.iv {
+            info.iv_bytes = Some(iv);
+        }
+    }
+}
+
+/// Hex input (`0x` prefix and whitespace allowed); None if empty.
+fn parse_hex(value: Option<&str>, what: &str) -> Result<Option<Vec<u8>>> {
+    let digits: String = value
+        .unwrap_or_default()
+        .trim()
+        .trim_start_matches("0x")
+        .trim_start_matches("0X")
+        .split_whitespace()
+        .collect();
+    if digits.is_empty() {
+        return Ok(None);
+    }
+    hex::decode(&digits)
+        .map(Some)
+        .map_err(|e| anyhow!("Invalid {} hex: {}", what, e))
 }
 
 /// Core download logic
+///
+/// `stop` ends a live recording early and still produces the output; `cancel` aborts the job;
+/// `pause` holds the segment downloads of an on-demand stream until it is resumed.
+/// Every message goes to `sender`, which belongs to this job alone: callers open one channel
+/// per task (see `ui::job`) and route by channel, so the messages carry no task id.
 pub async fn run_hls_download_core(
-    playlist_url_str: String,
-    output_location: String,
-    output_filename: String,
-    max_concurrent_downloads: usize,
-    output_format: String,
+    mut config: DownloadConfig,
+    cancel: CancellationToken,
+    stop: CancellationToken,
+    pause: PauseGate,
     sender: mpsc::Sender<DownloadMessage>,
     ctx: EguiContext,
 ) -> Result<()> {
+    let _job = stats::global().start_job();
+    let limits = config.limits;
+    // Appending needs the segments and manifest of the earlier recording
+    config.keep_workspace |= config.append_recording;
+    let mut config_snapshot = config.clone();
+    let key_override = KeyOverride::from_config(&config)?;
+    let local_keys = allows_local_keys(&config.playlist_url, config.playlist_text.is_some());
+    let clip = ClipWindow::parse(config.clip_start.as_deref(), config.clip_end.as_deref())?;
+    let time_range = TimeRange::parse(config.time_start.as_deref(), config.time_end.as_deref())?;
+    let playlist_url_str = config.playlist_url;
+    let output_location = config.output_location;
+    let output_filename = match config.sample {
+        // Kept apart from the full download of the same job
+        Some(_) => sample_filename(&config.output_filename, &config.output_format),
+        None => config.output_filename,
+    };
+    let output_format = config.output_format;
+    let mut remux_options = RemuxOptions {
+        fix_timestamps: config.fix_timestamps,
+        ..Default::default()
+    };
+    let client = build_client(&config.headers, config.proxy.as_deref())?;
+    let content_types = Arc::new(ContentTypeRules::new(&config.accepted_content_types));
+    let url_rewriter = UrlRewriter::new(&config.url_rewrites)?;
+    let report = Arc::new(SegmentReport::default());
+
     // Helper function to send log messages to the GUI
     let send_log = |msg: String| {
         let sender_clone = sender.clone();
@@ -49,6 +422,7 @@ pub async fn run_hls_download_core(
             ctx_clone.request_repaint();
         });
     };
+    let max_concurrent_downloads = resources::clamp_concurrency(config.concurrency, &send_log);
 
     // 1. Parameter Handling
     let playlist_url = Url::parse(&playlist_url_str).map_err(|e| anyhow!("Invalid URL: {}", e))?;
@@ -56,6 +430,18 @@ pub async fn run_hls_download_core(
     let initial_filename_path = PathBuf::from(&output_filename);
     let final_format = output_format.to_lowercase();
     let needs_remuxing = final_format != "ts";
+    if needs_remuxing {
+        // Extract / probe FFmpeg while the segments download instead of before the remux
+        tokio::spawn(ffmpeg_embed::FFmpegHandle::ensure_async());
+    }
+    if config.subtitle_url.is_some()
+        && config.subtitle_mode == SubtitleMode::SoftMux
+        && !needs_remuxing
+    {
+        return Err(anyhow!(
+            "TS output cannot carry a soft subtitle track; choose mkv/mp4 or burn the subtitles in"
+        ));
+    }
 
     let mut corrected_filename_only = initial_filename_path.clone();
 
@@ -79,34 +465,76 @@ pub async fn run_hls_download_core(
         corrected_filename_only = PathBuf::from(new_filename);
     }
 
-    let final_directory = PathBuf::from(output_location);
+    let mut final_directory = PathBuf::from(output_location);
+    if let Some(template) = &config.output_subfolder {
+        final_directory.push(expand_output_subfolder(template, &playlist_url_str)?);
+        tokio::fs::create_dir_all(&final_directory)
+            .await
+            .map_err(|e| anyhow!("Failed to create output folder: {}", e))?;
+        send_log(format!("-> Output folder: {}", final_directory.display()));
+    }
     let final_output_path = final_directory.join(corrected_filename_only);
 
-    send_log("📦 Creating safe temporary directory for segments...".to_string());
-    let temp_dir_handle = tokio::task::spawn_blocking(|| {
-        // tempdir() 是一個同步操作，需要在 blocking thread 中運行
-        tempdir().map_err(|e| anyhow!("Failed to create temporary directory: {}", e))
-    })
-    .await
-    .map_err(|e| anyhow!("Tempdir creation blocking task failed: {}", e))??;
+    // The TempDir guard (if any) must live until the end of the job
+    let (temp_dir_path, temp_dir_handle) = if config.keep_workspace {
+        let workspace_dir = workspace_dir_for(&final_output_path);
+        tokio::fs::create_dir_all(&workspace_dir)
+            .await
+            .map_err(|e| anyhow!("Failed to create workspace directory: {}", e))?;
+        send_log(format!(
+            "📦 Workspace directory set: {} (kept for repair)",
+            workspace_dir.display()
+        ));
+        (workspace_dir, None)
+    } else {
+        send_log("📦 Creating safe temporary directory for segments...".to_string());
+        let temp_dir_handle = tokio::task::spawn_blocking(|| {
+            // tempdir() 是一個同步操作，需要在 blocking thread 中運行
+            tempfile::Builder::new()
+                .prefix(TEMP_DIR_PREFIX)
+                .tempdir()
+                .map_err(|e| anyhow!("Failed to create temporary directory: {}", e))
+        })
+        .await
+        .map_err(|e| anyhow!("Tempdir creation blocking task failed: {}", e))??;
 
-    // 獲取該臨時目錄的路徑
-    let temp_dir_path = temp_dir_handle.path().to_path_buf();
+        // 獲取該臨時目錄的路徑
+        let temp_dir_path = temp_dir_handle.path().to_path_buf();
 
-    send_log(format!(
-        "-> Temporary directory set: {} (Auto-cleanup on exit)",
-        temp_dir_path.display()
-    ));
+        send_log(format!(
+            "-> Temporary directory set: {} (Auto-cleanup on exit)",
+            temp_dir_path.display()
+        ));
+        (temp_dir_path, Some(temp_dir_handle))
+    };
 
-    let temp_ts_filename = "final_merge.ts.tmp".to_string();
+    let temp_ts_filename = MERGED_TS_FILENAME.to_string();
     let temp_ts_path = temp_dir_path.join(&temp_ts_filename);
 
-    send_log(format!("-> Downloading playlist: {}", playlist_url));
+    if config.playlist_text.is_some() {
+        send_log(format!(
+            "-> Using pasted playlist content, base URL: {}",
+            playlist_url
+        ));
+    } else {
+        send_log(format!("-> Downloading playlist: {}", playlist_url));
+    }
     send_log(format!(
         "-> Concurrent downloads: {}",
         max_concurrent_downloads
     ));
     send_log(format!("-> Final output format: {}", final_format));
+    if !config.headers.is_empty() {
+        send_log(format!(
+            "-> Custom request headers: {}",
+            config
+                .headers
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
     if initial_filename_path.file_name() != final_output_path.file_name() {
         send_log(format!(
             "    Note: Output filename adjusted to: {}",
@@ -118,45 +546,600 @@ pub async fn run_hls_download_core(
     }
 
     // 2. Download and Parse M3U8 file
-    let (segments, mut encryption_info) = download_and_parse_m3u3(&playlist_url, &send_log).await?;
-    let key_bytes = match encryption_info.as_mut() {
-        Some(info) => {
+    let (base_url, playlist_body) = match &config.playlist_text {
+        Some(text) => (playlist_url.clone(), text.clone()),
+        None => {
+            match fetch_playlist_text(&client, &playlist_url, &limits, &send_log, &cancel).await {
+                Ok(fetched) => (fetched.url, fetched.body),
+                Err(e) => {
+                    let media = e.downcast::<progressive::MediaFile>()?;
+                    return progressive::run_progressive_download(
+                        &client,
+                        &media,
+                        &final_output_path,
+                        &final_format,
+                        &cancel,
+                        &sender,
+                        &ctx,
+                    )
+                    .await;
+                }
+            }
+        }
+    };
+    let media = resolve_media_playlist(
+        &client,
+        &base_url,
+        playlist_body,
+        config.quality.as_deref(),
+        &limits,
+        &send_log,
+        &cancel,
+    )
+    .await?;
+    if media.url != playlist_url {
+        // The workspace manifest must point at the variant for repairs
+        config_snapshot.playlist_url = media.url.to_string();
+        config_snapshot.playlist_text = None;
+    }
+    // Tokens usually sit on the URL the user entered; after a redirect, on the final one
+    let query_source = config.propagate_query.then(|| {
+        if playlist_url.query().is_some() {
+            playlist_url.clone()
+        } else {
+            media.url.clone()
+        }
+    });
+    let playlist_url = media.url;
+    let resolution = media.resolution;
+    // Sidecar subtitles fall back to the master playlist's subtitle rendition
+    let mut subtitle_track = config.subtitle_track.clone();
+    let subtitle_url = config.subtitle_url.clone().or_else(|| {
+        config.subtitle_mode.sidecar_extension()?;
+        let rendition = media.subtitles.as_ref()?;
+        send_log(format!("-> Subtitles: {}", rendition.label()));
+        subtitle_track = Some(rendition.track_metadata());
+        rendition.uri.as_ref().map(Url::to_string)
+    });
+    // A sample only needs the segments the playlist lists now
+    let live = config.live && config.sample.is_none() && !media.body.contains("#EXT-X-ENDLIST");
+    if live {
+        send_log(
+            "🔴 Live playlist: recording until #EXT-X-ENDLIST or until the recording is stopped."
+                .to_string(),
+        );
+    } else if config.live {
+        send_log(
+            "-> Playlist has #EXT-X-ENDLIST, downloading it as a finished stream.".to_string(),
+        );
+    }
+    if config.append_recording && !live {
+        send_log(
+            "⚠️ Warning: Append only continues live recordings; the workspace is downloaded anew."
+                .to_string(),
+        );
+    }
+    // Metadata only belongs to the rendition it was taken from
+    let audio_track = match &config.audio_url {
+        Some(_) => config.audio_track.clone(),
+        None => media.audio_track.clone(),
+    };
+    // An MPD lists the audio representation's segments itself
+    let audio_body = match &config.audio_url {
+        Some(_) => None,
+        None => media.audio_body,
+    };
+    let audio_url = config
+        .audio_url
+        .clone()
+        .or_else(|| media.audio_url.map(|url| url.to_string()))
+        .filter(|_| {
+            if live {
+                send_log(
+                    "⚠️ Warning: The separate audio rendition is not recorded in live mode."
+                        .to_string(),
+                );
+            }
+            !live
+        });
+    let (mut segments, mut encryption_infos) =
+        parse_m3u8(&media.body, &playlist_url, &limits, &send_log)?;
+    if let Some(source) = &query_source {
+        signed_url::propagate_to_playlist(
+            &mut segments,
+            &mut encryption_infos,
+            &playlist_url,
+            source,
+        );
+        send_log("-> Playlist query parameters are added to segment and key URLs.".to_string());
+    }
+    let rewritten = url_rewriter.apply_to_segments(&mut segments, &playlist_url)?;
+    if !url_rewriter.is_empty() {
+        send_log(format!(
+            "-> URL rewrite rules changed {} of {} segment URL(s).",
+            rewritten,
+            segments.len()
+        ));
+    }
+    // Reported to the completion hooks
+    let mut media_duration = segments.iter().map(|s| s.duration).sum::<f64>();
+    let mut segment_count = segments.len();
+    if time_range.is_set() && live {
+        send_log(
+            "⚠️ Warning: Start/end times only apply to finished playlists; use the wall-clock clip for live recordings."
+                .to_string(),
+        );
+    } else if time_range.is_set() {
+        let before = segments.len();
+        let first_offset;
+        (segments, first_offset) = time_range.select(segments);
+        send_log(format!(
+            "-> Time range: {} of {} segment(s) selected, starting at {:.1}s.",
+            segments.len(),
+            before,
+            first_offset
+        ));
+        if config.exact_trim && !segments.is_empty() {
+            let trim = Trim {
+                start: time_range.start.map_or(0.0, |start| start - first_offset),
+                end: time_range.end.map(|end| end - first_offset),
+            };
             send_log(format!(
-                "-> Encryption detected: {}. Downloading key...",
-                info.method
+                "-> The output is trimmed to {:.3}s-{} of the selected segments (re-encoded).",
+                trim.start,
+                trim.end
+                    .map_or("end".to_string(), |end| format!("{:.3}s", end))
             ));
-            send_log(format!("  Key URI: {}", info.key_url));
-            info.key_bytes = Some(download_key_file(&info.key_url, &send_log).await?);
-            if let Some(key) = info.key_bytes {
-                let key_hex = hex::encode(key);
-                send_log(format!(
-                    "🔑 Key (Hex): {} [{} bytes]",
-                    key_hex,
-                    hex::encode(key).len() / 2
-                ));
+            remux_options.trim = Some(trim);
+        }
+        media_duration = segments.iter().map(|s| s.duration).sum::<f64>();
+        segment_count = segments.len();
+    }
+    if let Some(sample) = config.sample {
+        let before = segments.len();
+        segments = sample.select(segments);
+        media_duration = segments.iter().map(|s| s.duration).sum::<f64>();
+        segment_count = segments.len();
+        send_log(format!(
+            "-> Sample: the first {} of {} segment(s) ({:.1}s).",
+            segment_count, before, media_duration
+        ));
+    }
+    if !config.excluded_segments.is_empty() {
+        let excluded: HashSet<usize> = config.excluded_segments.iter().copied().collect();
+        let before = segments.len();
+        segments.retain(|s| !excluded.contains(&s.index));
+        send_log(format!(
+            "-> {} segment(s) excluded from the download.",
+            before - segments.len()
+        ));
+        media_duration = segments.iter().map(|s| s.duration).sum::<f64>();
+        segment_count = segments.len();
+    }
+    let ad_segments: Vec<f64> = segments
+        .iter()
+        .filter(|s| s.ad)
+        .map(|s| s.duration)
+        .collect();
+    if !ad_segments.is_empty() {
+        if config.skip_ads {
+            segments = skip_ad_breaks(segments);
+            send_log(format!(
+                "-> {} segment(s) ({:.1}s) inside ad breaks are skipped.",
+                ad_segments.len(),
+                ad_segments.iter().sum::<f64>()
+            ));
+            media_duration = segments.iter().map(|s| s.duration).sum::<f64>();
+            segment_count = segments.len();
+        } else {
+            send_log(format!(
+                "-> {} segment(s) ({:.1}s) are marked as ad breaks (SCTE-35/CUE-OUT); \"Skip ad breaks\" leaves them out.",
+                ad_segments.len(),
+                ad_segments.iter().sum::<f64>()
+            ));
+        }
+    }
+    if clip.is_set() && !live {
+        if segments.iter().all(|s| s.program_date_time.is_none()) {
+            return Err(anyhow!(
+                "The playlist has no #EXT-X-PROGRAM-DATE-TIME; it cannot be clipped by time"
+            ));
+        }
+        let before = segments.len();
+        segments.retain(|s| clip.contains(s));
+        send_log(format!(
+            "-> Clip window: {} of {} segment(s) selected by #EXT-X-PROGRAM-DATE-TIME.",
+            segments.len(),
+            before
+        ));
+        media_duration = segments.iter().map(|s| s.duration).sum::<f64>();
+        segment_count = segments.len();
+    }
+    // #EXT-X-GAP segments are listed for timing only; requesting them would just fail
+    let (gap_segments, segments): (Vec<Segment>, Vec<Segment>) =
+        segments.into_iter().partition(|s| s.gap);
+    if segments.is_empty() {
+        return Err(anyhow!(
+            "No segments left to download (all excluded, outside the clip window or time range, or marked #EXT-X-GAP)"
+        ));
+    }
+    if !gap_segments.is_empty() {
+        send_log(format!(
+            "-> {} #EXT-X-GAP segment(s) ({:.1}s) have no media and are skipped{}.",
+            gap_segments.len(),
+            gap_segments.iter().map(|s| s.duration).sum::<f64>(),
+            if config.fill_gaps {
+                "; black/silent filler is inserted"
+            } else {
+                ""
+            }
+        ));
+    }
+    let segment_indices: Vec<usize> = segments.iter().map(|s| s.index).collect();
+    let mut discontinuities = discontinuity_starts(segments.iter().map(|s| s.discontinuity));
+    // A separate audio rendition is downloaded alongside the video and mapped in at remux time
+    let audio_playlist = match &audio_url {
+        Some(url) => {
+            config_snapshot.audio_url = Some(url.clone());
+            config_snapshot.audio_track = audio_track.clone();
+            match load_audio_playlist(
+                &client,
+                url,
+                audio_body.as_deref(),
+                query_source.as_ref(),
+                &url_rewriter,
+                &limits,
+                &cancel,
+            )
+            .await
+            {
+                Ok(playlist) => Some(playlist),
+                Err(e) if e.is::<cancel::Cancelled>() => return Err(e),
+                Err(e) => {
+                    send_log(format!(
+                        "⚠️ Warning: Audio playlist could not be loaded ({}); FFmpeg will read it directly.",
+                        e
+                    ));
+                    remux_options.external_audio = Some(ExternalAudio {
+                        url: url.clone(),
+                        headers: config.headers.clone(),
+                        track: audio_track.clone().unwrap_or_default(),
+                    });
+                    None
+                }
             }
-            info.key_bytes
         }
-        None => {
-            send_log("-> No #EXT-X-KEY tag detected, assuming content is unencrypted.".to_string());
-            None
+        None => None,
+    };
+    signed_url::check_signed_urls(
+        segments
+            .iter()
+            .filter_map(|segment| playlist_url.join(&segment.path).ok()),
+        &playlist_url,
+        &send_log,
+    );
+    // Keys are fetched in the background so segment downloads can start immediately
+    if encryption_infos.is_empty() {
+        send_log("-> No #EXT-X-KEY tag detected, assuming content is unencrypted.".to_string());
+    } else {
+        if encryption_infos.len() > 1 {
+            send_log(format!(
+                "-> Key rotation detected: {} distinct keys in the playlist.",
+                encryption_infos.len()
+            ));
+        }
+        let clear_count = segments.iter().filter(|s| s.key.is_none()).count();
+        if clear_count > 0 {
+            send_log(format!(
+                "-> {} segment(s) are unencrypted and do not wait for a key.",
+                clear_count
+            ));
+        }
+    }
+    let key_log = {
+        let sender = sender.clone();
+        let ctx = ctx.clone();
+        move |msg: String| {
+            sender.try_send(DownloadMessage::Log(msg)).ok();
+            ctx.request_repaint();
+        }
+    };
+    if !key_override.is_empty() {
+        match encryption_infos.len() {
+            0 => send_log(
+                "⚠️ Warning: The playlist declares no encryption; the entered key/IV is not used."
+                    .to_string(),
+            ),
+            1 => {}
+            count if key_override.key.is_some() => send_log(format!(
+                "⚠️ Warning: The playlist uses {} different keys; the entered key replaces all of them.",
+                count
+            )),
+            _ => {}
+        }
+    }
+    let mut keys = spawn_key_downloads(
+        &client,
+        encryption_infos,
+        &key_override,
+        local_keys,
+        &send_log,
+        &key_log,
+        &cancel,
+    );
+
+    // fMP4/CMAF: the init section(s) go in front of the fragments when concatenating
+    let (mut init_urls, mut init_of) = init_sections(&playlist_url, &segments)?;
+    let mut init_paths =
+        download_init_sections(&client, &init_urls, &temp_dir_path, &cancel).await?;
+    if !init_urls.is_empty() {
+        remux_options.fragmented_mp4 = true;
+        if init_urls.len() > 1 {
+            send_log(format!(
+                "⚠️ Warning: {} different #EXT-X-MAP init sections; players may not handle the switch.",
+                init_urls.len()
+            ));
         }
+    }
+
+    // Record the job in the workspace manifest before downloading anything
+    // (a live recording is only known once it has finished)
+    let mut manifest = if config.keep_workspace && !live {
+        let manifest = new_manifest(
+            &config_snapshot,
+            &final_output_path,
+            &playlist_url,
+            &keys,
+            &init_urls,
+            &segments,
+            &init_of,
+        );
+        manifest.save(&temp_dir_path)?;
+        Some(manifest)
+    } else {
+        None
+    };
+
+    // Optional live hand-off to an external player
+    if config.stream_to_player && live {
+        send_log("⚠️ Warning: Stream to player is not available for live recordings.".to_string());
+    }
+    let player_stream = if config.stream_to_player && !live {
+        let segment_paths: Vec<PathBuf> = segments
+            .iter()
+            .map(|segment| temp_dir_path.join(segment_file_name(segment.index)))
+            .collect();
+        let stream = PlayerStream::start(
+            interleave_init_sections(&segment_paths, &init_of, &init_paths),
+            &cancel,
+        )
+        .await?;
+        send_log(format!(
+            "📺 Stream available for players at {} (e.g. `mpv {}`)",
+            stream.url(),
+            stream.url()
+        ));
+        Some(stream)
+    } else {
+        None
     };
 
     // 3. Concurrent Segment Download
     let total_segments = segments.len();
-    let downloaded_segments = download_segments_concurrently(
+    let error_summary = Arc::new(ErrorSummary::default());
+    let video_download = download_segments_concurrently(
+        &client,
         &playlist_url,
         segments,
-        encryption_info,
-        key_bytes,
+        keys.clone(),
         total_segments,
         max_concurrent_downloads,
         temp_dir_path.clone(),
+        error_summary.clone(),
+        content_types.clone(),
+        report.clone(),
+        &cancel,
+        &pause,
         sender.clone(),
         ctx.clone(),
-    )
-    .await?;
+    );
+    let audio_download = async {
+        match audio_playlist {
+            Some((audio_url, audio_segments, audio_infos)) => {
+                let audio_keys = spawn_key_downloads(
+                    &client,
+                    audio_infos,
+                    &key_override,
+                    local_keys,
+                    &send_log,
+                    &key_log,
+                    &cancel,
+                );
+                download_audio_rendition(
+                    &client,
+                    &audio_url,
+                    audio_segments,
+                    audio_keys,
+                    max_concurrent_downloads,
+                    &temp_dir_path,
+                    content_types.clone(),
+                    report.clone(),
+                    &cancel,
+                    &pause,
+                    &sender,
+                    &ctx,
+                )
+                .await
+                .map(Some)
+            }
+            None => Ok(None),
+        }
+    };
+    let (download_result, audio_result) = if live {
+        // Nothing was started yet; the recording downloads in batches instead
+        drop((video_download, audio_download));
+        let previous = if config.append_recording {
+            live::load_previous_recording(
+                &temp_dir_path,
+                &playlist_url,
+                &client,
+                &key_override,
+                &key_log,
+                &cancel,
+            )?
+        } else {
+            live::LiveRecording::default()
+        };
+        // Saved after every batch, so a crashed recording can be appended to later
+        let checkpoint = |recording: &live::LiveRecording| {
+            if !config.keep_workspace {
+                return;
+            }
+            let saved = init_sections(&playlist_url, &recording.segments).and_then(
+                |(init_urls, init_of)| {
+                    new_manifest(
+                        &config_snapshot,
+                        &final_output_path,
+                        &playlist_url,
+                        &recording.keys,
+                        &init_urls,
+                        &recording.segments,
+                        &init_of,
+                    )
+                    .save(&temp_dir_path)
+                },
+            );
+            if let Err(e) = saved {
+                send_log(format!(
+                    "⚠️ Warning: Failed to save the recording checkpoint: {}",
+                    e
+                ));
+            }
+        };
+        let recording = live::record_live_stream(
+            &client,
+            &playlist_url,
+            media.body,
+            query_source.as_ref(),
+            &url_rewriter,
+            keys.clone(),
+            previous,
+            live::LiveOptions {
+                start: config.live_start,
+                skip_ads: config.skip_ads,
+                patient: config.patient_live,
+                clip,
+                limits,
+            },
+            &checkpoint,
+            max_concurrent_downloads,
+            &temp_dir_path,
+            error_summary.clone(),
+            content_types.clone(),
+            report.clone(),
+            &key_override,
+            &cancel,
+            &stop,
+            &sender,
+            &ctx,
+        )
+        .await?;
+        if recording.paths.is_empty() {
+            return Err(anyhow!("No segments were recorded from the live stream"));
+        }
+        keys = recording.keys;
+        media_duration = recording.segments.iter().map(|s| s.duration).sum();
+        segment_count = recording.segments.len();
+        discontinuities = discontinuity_starts(recording.segments.iter().map(|s| s.discontinuity));
+        (init_urls, init_of) = init_sections(&playlist_url, &recording.segments)?;
+        init_paths = download_init_sections(&client, &init_urls, &temp_dir_path, &cancel).await?;
+        if config.keep_workspace {
+            manifest = Some(new_manifest(
+                &config_snapshot,
+                &final_output_path,
+                &playlist_url,
+                &keys,
+                &init_urls,
+                &recording.segments,
+                &init_of,
+            ));
+        }
+        (Ok(recording.paths), Ok(None))
+    } else {
+        tokio::join!(video_download, audio_download)
+    };
+
+    // Print the classified error table once instead of relying on scattered log lines
+    if let Some(table) = error_summary.render_table() {
+        sender.send(DownloadMessage::ErrorSummary(table)).await.ok();
+    }
+    if let Some(report_path) = &config.report_path {
+        match report.write(std::path::Path::new(report_path)) {
+            Ok(rows) => send_log(format!(
+                "📄 Segment report ({} rows) saved to {}",
+                rows, report_path
+            )),
+            Err(e) => send_log(format!(
+                "⚠️ Warning: Failed to write the segment report {}: {}",
+                report_path, e
+            )),
+        }
+    }
+    if let Some(manifest) = manifest.as_mut() {
+        for (entry, decryption) in manifest.keys.iter_mut().zip(&keys) {
+            entry.key_hex = decryption
+                .key
+                .get()
+                .await
+                .ok()
+                .map(|key| hex::encode(key.as_bytes()));
+        }
+        manifest.refresh_statuses(&temp_dir_path);
+        manifest.save(&temp_dir_path)?;
+        if manifest.failed_count() > 0 {
+            send_log(format!(
+                "⚠️ {} segment(s) missing. Use Tools → Repair workspace on {} to re-download only those.",
+                manifest.failed_count(),
+                temp_dir_path.display()
+            ));
+        }
+    }
+    let mut downloaded_segments = download_result?;
+    if config.fill_gaps && !gap_segments.is_empty() && !live {
+        if init_urls.is_empty() {
+            let filler_dir = temp_dir_path.clone();
+            let filler_cancel = cancel.clone();
+            let filler_log = key_log.clone();
+            (downloaded_segments, discontinuities) = tokio::task::spawn_blocking(move || {
+                insert_gap_fillers(
+                    downloaded_segments,
+                    &segment_indices,
+                    &discontinuities,
+                    &gap_segments,
+                    resolution.unwrap_or(GAP_FILLER_RESOLUTION),
+                    &filler_dir,
+                    &filler_cancel,
+                    &filler_log,
+                )
+            })
+            .await
+            .map_err(|e| anyhow!("Gap filler blocking task failed to join: {}", e))??;
+            // MPEG-TS only, so no segment refers to an init section
+            init_of = vec![None; downloaded_segments.len()];
+        } else {
+            send_log(
+                "⚠️ Warning: Gap filler is only generated for MPEG-TS streams, not fMP4."
+                    .to_string(),
+            );
+        }
+    }
+    if let Some(audio_path) = audio_result? {
+        remux_options.external_audio = Some(ExternalAudio {
+            url: audio_path.to_string_lossy().into_owned(),
+            headers: Vec::new(),
+            track: audio_track.clone().unwrap_or_default(),
+        });
+    }
 
     // 4. Concatenate segments to a temporary TS file
     send_log(format!(
@@ -164,18 +1147,85 @@ pub async fn run_hls_download_core(
         temp_ts_path.display()
     ));
 
-    let concat_segments = downloaded_segments.clone();
+    let concat_segments = interleave_init_sections(&downloaded_segments, &init_of, &init_paths);
+    let chunk_count = concat_segments.len();
     let concat_temp_ts_path = temp_ts_path.clone();
+    let verify_concat = config.verify_concat;
+    let concat_cancel = cancel.clone();
+    // fMP4 fragments carry 64-bit decode times and are always joined byte by byte
+    let merge_runs = if init_urls.is_empty() {
+        discontinuities
+    } else {
+        Vec::new()
+    };
 
-    tokio::task::spawn_blocking(move || {
-        concatenate_segments(&concat_segments, &concat_temp_ts_path)
-    })
-    .await
-    .map_err(|e| anyhow!("Concatenation blocking task failed to join: {}", e))??;
+    let chunk_ranges = if merge_runs.is_empty() {
+        tokio::task::spawn_blocking(move || {
+            concatenate_segments(
+                &concat_segments,
+                &concat_temp_ts_path,
+                verify_concat,
+                &concat_cancel,
+            )
+        })
+        .await
+        .map_err(|e| anyhow!("Concatenation blocking task failed to join: {}", e))??
+    } else {
+        send_log(format!(
+            "-> {} #EXT-X-DISCONTINUITY tag(s): joining the runs with FFmpeg's concat demuxer...",
+            merge_runs.len()
+        ));
+        let work_dir = temp_dir_path.clone();
+        tokio::task::spawn_blocking(move || {
+            concatenate_with_discontinuities(
+                &concat_segments,
+                &merge_runs,
+                &concat_temp_ts_path,
+                &work_dir,
+                verify_concat,
+                &concat_cancel,
+            )
+        })
+        .await
+        .map_err(|e| anyhow!("Concatenation blocking task failed to join: {}", e))??;
+        // No byte layout to patch later; a repair rebuilds the merged file
+        Vec::new()
+    };
+    if config.verify_concat {
+        send_log(format!("-> Verified {} concatenated chunks.", chunk_count));
+    }
+    if config.keep_workspace && !chunk_ranges.is_empty() {
+        ConcatIndex::new(
+            &interleave_init_sections(&downloaded_segments, &init_of, &init_paths),
+            &chunk_ranges,
+        )
+        .save(&temp_dir_path)?;
+    }
+
+    // A player still reading needs the segment files; the stream ends with this step
+    if let Some(stream) = &player_stream
+        && stream.has_players()
+    {
+        send_log(
+            "-> Waiting for the player to finish reading the stream (close the player to continue)..."
+                .to_string(),
+        );
+        cancel::or_cancel(&cancel, async {
+            stream.wait_for_players().await;
+            Ok(())
+        })
+        .await?;
+    }
+    drop(player_stream);
 
-    // 5. Clean up temporary segment files
-    send_log("-> Cleaning up temporary segment files...".to_string());
-    for path in downloaded_segments {
+    // 5. Clean up temporary segment files (kept in workspace mode for later repair)
+    let segments_to_remove = if config.keep_workspace {
+        Vec::new()
+    } else {
+        send_log("-> Cleaning up temporary segment files...".to_string());
+        downloaded_segments.into_iter().chain(init_paths).collect()
+    };
+    for path in segments_to_remove {
         if let Err(e) = tokio::fs::remove_file(&path).await {
             send_log(format!(
                 "⚠️ Warning: Failed to delete temporary segment file {}: {}",
@@ -187,20 +1237,183 @@ pub async fn run_hls_download_core(
 
     final_directory.to_string_lossy().into_owned();
 
+    // Closed captions are read from the merged stream while it still has its plain temp name
+    if config.extract_captions {
+        captions::run_captions_step(
+            temp_ts_path.clone(),
+            captions::captions_path(&final_output_path),
+            cancel.clone(),
+            sender.clone(),
+            ctx.clone(),
+        )
+        .await?;
+    }
+
+    // Soft subtitles go into the container during the remux
+    if let Some(source) = &subtitle_url
+        && config.subtitle_mode == SubtitleMode::SoftMux
+        && let Some(track) = subtitles::prepare_soft_track(
+            &client,
+            source,
+            subtitle_track.unwrap_or_default(),
+            &temp_dir_path,
+            &send_log,
+            &cancel,
+        )
+        .await?
+    {
+        remux_options.subtitles.push(track);
+    }
+
+    let stage = OutputStage {
+        client,
+        merged_path: temp_ts_path,
+        output_path: final_output_path,
+        output_directory: final_directory,
+        output_format: final_format,
+        needs_remuxing,
+        remux_options,
+        reencode: config.reencode,
+        keep_workspace: config.keep_workspace,
+        subtitle_url,
+        subtitle_mode: config.subtitle_mode,
+        headers: config.headers.clone(),
+        normalize_audio: config.normalize_audio,
+        on_complete: config.on_complete.clone(),
+        webhook_url: config.webhook_url.clone(),
+        proxy: config.proxy.clone(),
+        temp_dir_path,
+        temp_dir: temp_dir_handle,
+        playlist_url,
+        media_duration,
+        resolution,
+        segment_count,
+    };
+
+    // 6.-9. In the queue, the next job downloads while this one remuxes in the background
+    if config.background_remux {
+        let (stage_sender, receiver) = mpsc::channel(100);
+        sender
+            .send(DownloadMessage::OutputStageDetached {
+                output_path: stage.output_path.clone(),
+                receiver,
+                cancel: cancel.clone(),
+            })
+            .await
+            .ok();
+        send_log("-> Download finished; remuxing continues in the background.".to_string());
+        tokio::spawn(async move {
+            let result = run_output_stage(stage, cancel, stage_sender.clone(), ctx.clone()).await;
+            stage_sender
+                .send(DownloadMessage::Finished(result.map_err(|e| e.to_string())))
+                .await
+                .ok();
+            ctx.request_repaint();
+        });
+        return Ok(());
+    }
+    run_output_stage(stage, cancel, sender, ctx).await
+}
+
+/// Everything the steps after concatenation need from the job
+struct OutputStage {
+    client: reqwest::Client,
+    merged_path: PathBuf,
+    output_path: PathBuf,
+    output_directory: PathBuf,
+    output_format: String,
+    needs_remuxing: bool,
+    remux_options: RemuxOptions,
+    reencode: bool,
+    keep_workspace: bool,
+    subtitle_url: Option<String>,
+    subtitle_mode: SubtitleMode,
+    headers: Vec<(String, String)>,
+    normalize_audio: bool,
+    on_complete: Option<String>,
+    webhook_url: Option<String>,
+    proxy: Option<String>,
+    temp_dir_path: PathBuf,
+    /// Removes the temporary directory once the stage is done
+    temp_dir: Option<tempfile::TempDir>,
+    playlist_url: Url,
+    media_duration: f64,
+    resolution: Option<(u32, u32)>,
+    segment_count: usize,
+}
+
+/// Remux (in a remux worker), subtitles, loudness normalization and completion hooks.
+async fn run_output_stage(
+    stage: OutputStage,
+    cancel: CancellationToken,
+    sender: mpsc::Sender<DownloadMessage>,
+    ctx: EguiContext,
+) -> Result<()> {
+    let send_log = |msg: String| {
+        let sender_clone = sender.clone();
+        let ctx_clone = ctx.clone();
+        tokio::spawn(async move {
+            sender_clone.send(DownloadMessage::Log(msg)).await.ok();
+            ctx_clone.request_repaint();
+        });
+    };
+    let OutputStage {
+        client,
+        merged_path: temp_ts_path,
+        output_path: final_output_path,
+        output_directory: final_directory,
+        output_format: final_format,
+        needs_remuxing,
+        mut remux_options,
+        reencode,
+        keep_workspace,
+        subtitle_url,
+        subtitle_mode,
+        headers,
+        normalize_audio,
+        on_complete,
+        webhook_url,
+        proxy,
+        temp_dir_path,
+        temp_dir: _temp_dir,
+        playlist_url,
+        media_duration,
+        resolution,
+        segment_count,
+    } = stage;
+
     // 6. Check and execute FFmpeg conversion
     if needs_remuxing {
+        remux_options.reencode =
+            probe::needs_reencode(&temp_ts_path, &final_format, reencode, &send_log, &cancel)
+                .await?;
+    }
+    if needs_remuxing || remux_options.alters_streams() {
         send_log(format!("🚀 Remuxing using FFmpeg to {}...", final_format));
+        if remux_options.fix_timestamps {
+            send_log("-> Correcting timestamp discontinuities and wraparounds.".to_string());
+        }
 
+        let _permit = remux_pool::acquire(&send_log, &cancel).await?;
         // 將 `run_ffmpeg_remux` 移入 spawn_blocking
         let ffmpeg_temp_ts_path = temp_ts_path.clone();
         let ffmpeg_final_output_path = final_output_path.clone();
+        let ffmpeg_options = remux_options.clone();
+        let ffmpeg_cancel = cancel.clone();
 
         let ffmpeg_result = tokio::task::spawn_blocking(move || {
-            run_ffmpeg_remux(&ffmpeg_temp_ts_path, &ffmpeg_final_output_path)
+            run_ffmpeg_remux(
+                &ffmpeg_temp_ts_path,
+                &ffmpeg_final_output_path,
+                &ffmpeg_options,
+                &ffmpeg_cancel,
+                |_| {},
+            )
         })
         .await
         .map_err(|e| anyhow!("FFmpeg blocking task failed to join: {}", e))?; // 處理 JoinError
 
+        let mut remux_error = None;
         match ffmpeg_result {
             Ok(()) => {
                 sender.send(DownloadMessage::Progress(1.0)).await.ok();
@@ -210,32 +1423,665 @@ pub async fn run_hls_download_core(
                     final_output_path.display()
                 ));
             }
+            // A cancelled job keeps nothing, not even the output FFmpeg had begun to write
+            Err(e) if e.is::<cancel::Cancelled>() => {
+                tokio::fs::remove_file(&final_output_path).await.ok();
+                return Err(e);
+            }
             Err(e) => {
-                send_log(format!(
-                    "\n⚠️ FFmpeg conversion failed: {}. Please ensure FFmpeg is installed and in your PATH.",
-                    e
-                ));
-                send_log(format!(
-                    "  Original concatenated file (TS format) retained as: {}",
-                    temp_ts_path.display()
-                ));
+                send_log(format!("\n⚠️ FFmpeg conversion failed: {}.", e));
+
+                // The temp dir is removed when the handle drops, so copy the merged stream next
+                // to the intended output to keep the download.
+                let retained_extension = if remux_options.fragmented_mp4 {
+                    "mp4"
+                } else {
+                    "ts"
+                };
+                let stem = final_output_path.file_string_lossy();
+                // With an audio rendition or subtitles kept as separate files, the stream alone
+                // is not the output; it keeps its own name so a retry can still write the output
+                let separate_inputs = remux_options
+                    .local_inputs()
+                    .iter()
+                    .any(|path| path.starts_with(&temp_dir_path));
+                let retained_ts_path = if separate_inputs {
+                    final_directory.join(format!("{}.video.{}", stem, retained_extension))
+                } else {
+                    final_directory.join(format!("{}.{}", stem, retained_extension))
+                };
+                match tokio::fs::copy(&temp_ts_path, &retained_ts_path).await {
+                    Ok(_) if retained_ts_path == final_output_path => {
+                        // Same container as requested (TS, or fMP4 to MP4): keep the unprocessed stream
+                        send_log(format!(
+                            "  Unprocessed stream saved as: {}",
+                            retained_ts_path.display()
+                        ));
+                    }
+                    Ok(_) => {
+                        send_log(format!(
+                            "  Concatenated file (TS format) retained as: {}",
+                            retained_ts_path.display()
+                        ));
+                        match retain_remux_inputs(
+                            &remux_options,
+                            &temp_dir_path,
+                            &final_directory,
+                            &stem,
+                            &send_log,
+                        )
+                        .await
+                        {
+                            Ok(options) => {
+                                sender
+                                    .send(DownloadMessage::RemuxFailed {
+                                        ts_path: retained_ts_path,
+                                        output_path: final_output_path.clone(),
+                                        options,
+                                    })
+                                    .await
+                                    .ok();
+                            }
+                            // Retrying without them would silently drop the audio or subtitles
+                            Err(e) => send_log(format!(
+                                "❌ Failed to retain the audio/subtitle files, so the remux cannot be retried: {}",
+                                e
+                            )),
+                        }
+                    }
+                    Err(copy_err) => send_log(format!(
+                        "❌ Failed to retain concatenated TS file at {}: {}",
+                        retained_ts_path.display(),
+                        copy_err
+                    )),
+                }
+                remux_error = Some(e);
             }
         }
 
-        if let Err(e) = tokio::fs::remove_file(&temp_ts_path).await {
+        if !keep_workspace && let Err(e) = tokio::fs::remove_file(&temp_ts_path).await {
             send_log(format!(
                 "⚠️ Warning: Failed to delete temporary concatenated file {}: {}",
                 temp_ts_path.display(),
                 e
             ));
         }
+
+        if let Some(e) = remux_error {
+            return Err(failure::ffmpeg(format!("FFmpeg remux failed: {}", e)));
+        }
     } else {
-        send_log(format!(
-            "-> Output format is TS, renaming concatenated file to {}...",
-            final_output_path.display()
-        ));
-        tokio::fs::rename(&temp_ts_path, &final_output_path).await?;
+        if keep_workspace {
+            send_log(format!(
+                "-> Output format is TS, copying concatenated file to {}...",
+                final_output_path.display()
+            ));
+            tokio::fs::copy(&temp_ts_path, &final_output_path).await?;
+        } else {
+            send_log(format!(
+                "-> Output format is TS, renaming concatenated file to {}...",
+                final_output_path.display()
+            ));
+            tokio::fs::rename(&temp_ts_path, &final_output_path).await?;
+        }
+    }
+
+    // 7. Optional subtitles burned in or saved next to the video
+    if let Some(subtitle_url) = subtitle_url {
+        subtitles::run_subtitle_step(
+            &client,
+            subtitle_url,
+            headers,
+            subtitle_mode,
+            final_output_path.clone(),
+            temp_dir_path.clone(),
+            cancel.clone(),
+            sender.clone(),
+            ctx.clone(),
+        )
+        .await?;
+    }
+
+    // 8. Optional loudness normalization of the finished file
+    if normalize_audio {
+        loudnorm::run_normalize_step(
+            final_output_path.clone(),
+            cancel,
+            sender.clone(),
+            ctx.clone(),
+        )
+        .await?;
+    }
+
+    // 9. Completion hooks; a failing hook does not fail the finished download
+    if on_complete.is_some() || webhook_url.is_some() {
+        let context = JobContext::new(
+            &final_output_path,
+            &playlist_url,
+            media_duration,
+            resolution,
+            segment_count,
+        );
+        if let Some(command) = &on_complete {
+            send_log(format!("-> Running completion hook: {}", command));
+            if let Err(e) = hooks::run_command_hook(command, &context).await {
+                send_log(format!("⚠️ Warning: Completion hook failed: {}", e));
+            }
+        }
+        if let Some(url) = &webhook_url {
+            // Without the job's headers: cookies for the stream site must not reach the webhook
+            let result = match build_client(&[], proxy.as_deref()) {
+                Ok(webhook_client) => hooks::post_webhook(&webhook_client, url, &context).await,
+                Err(e) => Err(e),
+            };
+            match result {
+                Ok(()) => send_log(format!("-> Job context posted to {}", url)),
+                Err(e) => send_log(format!("⚠️ Warning: Webhook {} failed: {}", url, e)),
+            }
+        }
     }
 
     Ok(())
 }
+
+/// Copies the remux inputs that live in the job's temp dir (audio rendition, soft subtitles)
+/// into `directory` as `<stem>.audio.ts` and `<stem>.subtitles.<ext>`, and returns the options
+/// pointing at the copies, so "Retry remux" still has them once the temp dir is removed.
+async fn retain_remux_inputs(
+    options: &RemuxOptions,
+    temp_dir: &Path,
+    directory: &Path,
+    stem: &str,
+    send_log: &impl Fn(String),
+) -> Result<RemuxOptions> {
+    let mut retained = options.clone();
+    if let Some(audio) = &mut retained.external_audio
+        && Path::new(&audio.url).starts_with(temp_dir)
+    {
+        let path = directory.join(format!("{}.audio.ts", stem));
+        tokio::fs::copy(&audio.url, &path).await?;
+        send_log(format!("  Audio track retained as: {}", path.display()));
+        audio.url = path.to_string_lossy().into_owned();
+    }
+    for (n, subtitle) in retained.subtitles.iter_mut().enumerate() {
+        if !subtitle.path.starts_with(temp_dir) {
+            continue;
+        }
+        let extension = subtitle
+            .path
+            .extension()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .into_owned();
+        let number = if n == 0 {
+            String::new()
+        } else {
+            (n + 1).to_string()
+        };
+        let path = directory.join(format!("{}.subtitles{}.{}", stem, number, extension));
+        tokio::fs::copy(&subtitle.path, &path).await?;
+        send_log(format!("  Subtitles retained as: {}", path.display()));
+        subtitle.path = path;
+    }
+    Ok(retained)
+}
+
+/// Runs only the FFmpeg remux step on an existing TS file (e.g. one retained after a failed remux).
+///
+/// With `remove_input_on_success`, the input and the local audio/subtitle files of `options`
+/// (retained next to it) are deleted once the remux succeeded.
+pub async fn run_remux_job(
+    input_path: PathBuf,
+    output_path: PathBuf,
+    options: RemuxOptions,
+    remove_input_on_success: bool,
+    cancel: CancellationToken,
+    sender: mpsc::Sender<DownloadMessage>,
+    ctx: EguiContext,
+) -> Result<()> {
+    sender
+        .send(DownloadMessage::Log(format!(
+            "🚀 Remuxing {} to {} using FFmpeg...",
+            input_path.display(),
+            output_path.display()
+        )))
+        .await
+        .ok();
+    ctx.request_repaint();
+
+    let log_sender = sender.clone();
+    let _permit = remux_pool::acquire(
+        &|msg| {
+            log_sender.try_send(DownloadMessage::Log(msg)).ok();
+        },
+        &cancel,
+    )
+    .await?;
+    let side_inputs = options.local_inputs();
+    let ffmpeg_input_path = input_path.clone();
+    let ffmpeg_output_path = output_path.clone();
+    let progress_sender = sender.clone();
+    let progress_ctx = ctx.clone();
+    tokio::task::spawn_blocking(move || {
+        run_ffmpeg_remux(
+            &ffmpeg_input_path,
+            &ffmpeg_output_path,
+            &options,
+            &cancel,
+            |p| {
+                // Progress updates are best-effort; dropping one when the channel is full is fine
+                progress_sender.try_send(DownloadMessage::Progress(p)).ok();
+                progress_ctx.request_repaint();
+            },
+        )
+    })
+    .await
+    .map_err(|e| anyhow!("FFmpeg blocking task failed to join: {}", e))??;
+
+    sender.send(DownloadMessage::Progress(1.0)).await.ok();
+    sender
+        .send(DownloadMessage::Log(format!(
+            "✅ FFmpeg conversion successful! File saved as: {}",
+            output_path.display()
+        )))
+        .await
+        .ok();
+
+    if remove_input_on_success {
+        for path in std::iter::once(input_path).chain(side_inputs) {
+            if let Err(e) = tokio::fs::remove_file(&path).await {
+                sender
+                    .send(DownloadMessage::Log(format!(
+                        "⚠️ Warning: Failed to delete retained file {}: {}",
+                        path.display(),
+                        e
+                    )))
+                    .await
+                    .ok();
+            }
+        }
+    }
+    ctx.request_repaint();
+
+    Ok(())
+}
+
+/// `name` with `.sample` before the output extension (`show.mp4` -> `show.sample.mp4`).
+fn sample_filename(name: &str, format: &str) -> String {
+    // The extension is replaced by the format's below anyway
+    format!(
+        "{}.sample.{}",
+        PathBuf::from(name).file_string_lossy(),
+        format.to_lowercase()
+    )
+}
+
+/// Workspace manifest for `segments`; statuses are filled in from the files after downloading.
+fn new_manifest(
+    config: &DownloadConfig,
+    output_path: &std::path::Path,
+    playlist_url: &Url,
+    keys: &[SegmentDecryption],
+    init_urls: &[ResolvedInit],
+    segments: &[Segment],
+    init_of: &[Option<usize>],
+) -> WorkspaceManifest {
+    WorkspaceManifest {
+        config: config.clone(),
+        output_path: output_path.to_path_buf(),
+        keys: keys
+            .iter()
+            .map(|decryption| ManifestEncryption {
+                method: decryption.info.method.clone(),
+                key_url: decryption.info.key_url.to_string(),
+                // Filled in once the background key download has finished
+                key_hex: None,
+                iv_hex: decryption.info.iv_bytes.map(hex::encode),
+            })
+            .collect(),
+        init_sections: init_urls
+            .iter()
+            .map(|(url, byte_range)| ManifestInit {
+                url: url.to_string(),
+                byte_range: *byte_range,
+            })
+            .collect(),
+        segments: segments
+            .iter()
+            .zip(init_of)
+            .map(|(segment, init)| ManifestSegment {
+                index: segment.index,
+                sequence: (segment.sequence != segment.index as u64).then_some(segment.sequence),
+                url: playlist_url
+                    .join(&segment.path)
+                    .map(|u| u.to_string())
+                    .unwrap_or_else(|_| segment.path.clone()),
+                duration: segment.duration,
+                file: segment_file_name(segment.index),
+                status: SegmentStatus::Failed,
+                key: segment.key,
+                init: *init,
+                byte_range: segment.byte_range,
+                discontinuity: segment.discontinuity,
+            })
+            .collect(),
+    }
+}
+
+/// Channel for a sub-download whose progress must not move the job's progress bar: log lines and
+/// warnings are forwarded to `sender`, progress updates are dropped.
+fn forward_without_progress(
+    sender: &mpsc::Sender<DownloadMessage>,
+) -> (mpsc::Sender<DownloadMessage>, tokio::task::JoinHandle<()>) {
+    let (forward_sender, mut receiver) = mpsc::channel(100);
+    let forward = tokio::spawn({
+        let sender = sender.clone();
+        async move {
+            while let Some(message) = receiver.recv().await {
+                match message {
+                    DownloadMessage::Progress(_) | DownloadMessage::SegmentProgress { .. } => {}
+                    other => {
+                        sender.send(other).await.ok();
+                    }
+                }
+            }
+        }
+    });
+    (forward_sender, forward)
+}
+
+fn spawn_key_downloads(
+    client: &reqwest::Client,
+    encryption_infos: Vec<EncryptionInfo>,
+    key_override: &KeyOverride,
+    allow_local: bool,
+    send_log: &impl Fn(String),
+    key_log: &(impl Fn(String) + Clone + Send + Sync + 'static),
+    cancel: &CancellationToken,
+) -> Vec<SegmentDecryption> {
+    encryption_infos
+        .into_iter()
+        .map(|mut info| {
+            key_override.apply_iv(&mut info);
+            if let Some(key) = key_override.key {
+                send_log(format!(
+                    "-> Encryption detected: {}. Using the entered key instead of {}",
+                    info.method, info.key_url
+                ));
+                return SegmentDecryption {
+                    info,
+                    key: PendingKey::ready(key),
+                };
+            }
+            send_log(format!(
+                "-> Encryption detected: {}. Downloading key alongside segments...",
+                info.method
+            ));
+            send_log(format!("  Key URI: {}", info.key_url));
+            let key_client = client.clone();
+            let key_url = info.key_url.clone();
+            let key_method = info.method.clone();
+            let key_log = key_log.clone();
+            let key_cancel = cancel.clone();
+            let key = PendingKey::spawn(async move {
+                let key = cancel::or_cancel(
+                    &key_cancel,
+                    download_key_file(&key_client, &key_url, &key_method, allow_local, &key_log),
+                )
+                .await?;
+                key_log(format!(
+                    "🔑 Key (Hex): {} [{} bytes]",
+                    hex::encode(key.as_bytes()),
+                    key.as_bytes().len()
+                ));
+                Ok(key)
+            });
+            SegmentDecryption { info, key }
+        })
+        .collect()
+}
+
+/// Frame size of gap filler when the master playlist does not state the variant's resolution
+const GAP_FILLER_RESOLUTION: (u32, u32) = (1280, 720);
+
+/// Generates a filler for each run of `#EXT-X-GAP` segments and slots it in front of the first
+/// segment after the run. `indices` are the media indices of `paths`; returns the new paths and
+/// discontinuity positions (a filler and the segment after it restart the timestamps).
+#[allow(clippy::too_many_arguments)]
+fn insert_gap_fillers(
+    paths: Vec<PathBuf>,
+    indices: &[usize],
+    discontinuities: &[usize],
+    gap_segments: &[Segment],
+    resolution: (u32, u32),
+    dir: &Path,
+    cancel: &CancellationToken,
+    send_log: &impl Fn(String),
+) -> Result<(Vec<PathBuf>, Vec<usize>)> {
+    // Consecutive gap segments share one filler: (first index, last index, duration)
+    let mut runs: Vec<(usize, usize, f64)> = Vec::new();
+    for segment in gap_segments {
+        match runs.last_mut() {
+            Some(run) if run.1 + 1 == segment.index => {
+                run.1 = segment.index;
+                run.2 += segment.duration;
+            }
+            _ => runs.push((segment.index, segment.index, segment.duration)),
+        }
+    }
+
+    let mut fillers = Vec::new();
+    for (n, (first, _, duration)) in runs.iter().enumerate() {
+        let path = dir.join(format!("gap_filler_{:03}.ts", n));
+        match generate_gap_filler(&path, *duration, resolution, cancel) {
+            Ok(()) => fillers.push((*first, path)),
+            Err(e) if e.is::<cancel::Cancelled>() => return Err(e),
+            Err(e) => send_log(format!(
+                "⚠️ Warning: Could not generate filler for the gap at segment {}: {}",
+                first, e
+            )),
+        }
+    }
+
+    let mut merged = Vec::with_capacity(paths.len() + fillers.len());
+    let mut starts = Vec::new();
+    let mut fillers = fillers.into_iter().peekable();
+    for (position, (path, index)) in paths.into_iter().zip(indices).enumerate() {
+        let mut after_filler = false;
+        while let Some((_, filler)) = fillers.next_if(|(first, _)| first < index) {
+            starts.push(merged.len());
+            merged.push(filler);
+            after_filler = true;
+        }
+        if after_filler || discontinuities.contains(&position) {
+            starts.push(merged.len());
+        }
+        merged.push(path);
+    }
+    // A gap at the very end
+    for (_, filler) in fillers {
+        starts.push(merged.len());
+        merged.push(filler);
+    }
+    starts.retain(|&start| start > 0);
+    starts.dedup();
+    Ok((merged, starts))
+}
+
+/// Fetches and parses the media playlist of an audio rendition; `body` is used instead of
+/// fetching when the playlist is already known (DASH).
+async fn load_audio_playlist(
+    client: &reqwest::Client,
+    audio_url: &str,
+    body: Option<&str>,
+    query_source: Option<&Url>,
+    url_rewriter: &UrlRewriter,
+    limits: &PlaylistLimits,
+    cancel: &CancellationToken,
+) -> Result<(Url, Vec<Segment>, Vec<EncryptionInfo>)> {
+    let url = Url::parse(audio_url).map_err(|e| anyhow!("Invalid URL: {}", e))?;
+    let (url, body) = match body {
+        Some(body) => (url, body.to_string()),
+        None => {
+            let fetched = fetch_playlist_text(client, &url, limits, &|_| {}, cancel).await?;
+            (fetched.url, fetched.body)
+        }
+    };
+    let (mut segments, mut infos) = parse_m3u8(&body, &url, limits, &|_| {})?;
+    if let Some(source) = query_source {
+        signed_url::propagate_to_playlist(&mut segments, &mut infos, &url, source);
+    }
+    url_rewriter.apply_to_segments(&mut segments, &url)?;
+    segments.retain(|s| !s.gap);
+    Ok((url, segments, infos))
+}
+
+/// Downloads an audio rendition into `<temp_dir>/audio/` and concatenates it to
+/// `<temp_dir>/audio_merge.ts.tmp` (`AUDIO_TS_FILENAME`), which the remux step maps next to
+/// the video.
+#[allow(clippy::too_many_arguments)]
+async fn download_audio_rendition(
+    client: &reqwest::Client,
+    audio_url: &Url,
+    segments: Vec<Segment>,
+    keys: Vec<SegmentDecryption>,
+    max_concurrent_downloads: usize,
+    temp_dir_path: &std::path::Path,
+    content_types: Arc<ContentTypeRules>,
+    report: Arc<SegmentReport>,
+    cancel: &CancellationToken,
+    pause: &PauseGate,
+    sender: &mpsc::Sender<DownloadMessage>,
+    ctx: &EguiContext,
+) -> Result<PathBuf> {
+    let audio_dir = temp_dir_path.join(AUDIO_DIR_NAME);
+    tokio::fs::create_dir_all(&audio_dir).await?;
+    // Audio segments are small; a share of the connections keeps up with the video
+    let concurrency = (max_concurrent_downloads / 4).max(1);
+    sender
+        .send(DownloadMessage::Log(format!(
+            "🔊 Downloading audio rendition: {} segments ({} connection(s))",
+            segments.len(),
+            concurrency
+        )))
+        .await
+        .ok();
+
+    // The progress bar follows the video; only log lines and warnings are forwarded
+    let (audio_sender, forward) = forward_without_progress(sender);
+
+    let (init_urls, init_of) = init_sections(audio_url, &segments)?;
+    let init_paths = download_init_sections(client, &init_urls, &audio_dir, cancel).await?;
+    let total = segments.len();
+    let error_summary = Arc::new(ErrorSummary::default());
+    let result = download_segments_concurrently(
+        client,
+        audio_url,
+        segments,
+        keys,
+        total,
+        concurrency,
+        audio_dir.clone(),
+        error_summary.clone(),
+        content_types,
+        report,
+        cancel,
+        pause,
+        audio_sender,
+        ctx.clone(),
+    )
+    .await;
+    forward.await.ok();
+    if let Some(table) = error_summary.render_table() {
+        sender.send(DownloadMessage::ErrorSummary(table)).await.ok();
+    }
+    let paths = result.map_err(|e| {
+        let message = format!("Audio rendition download failed: {}", e);
+        e.context(message)
+    })?;
+    let paths = interleave_init_sections(&paths, &init_of, &init_paths);
+
+    let audio_path = temp_dir_path.join(AUDIO_TS_FILENAME);
+    let concat_path = audio_path.clone();
+    let concat_cancel = cancel.clone();
+    tokio::task::spawn_blocking(move || {
+        concatenate_segments(&paths, &concat_path, false, &concat_cancel)
+    })
+    .await
+    .map_err(|e| anyhow!("Concatenation blocking task failed to join: {}", e))??;
+    // A repair reads the audio from the origin again, so the segments are not kept
+    tokio::fs::remove_dir_all(&audio_dir).await.ok();
+
+    sender
+        .send(DownloadMessage::Log(
+            "✅ Audio rendition downloaded.".to_string(),
+        ))
+        .await
+        .ok();
+    Ok(audio_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use segment_io::SubtitleTrack;
+
+    #[tokio::test]
+    async fn retained_inputs_outlive_the_temp_dir() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let output_dir = tempfile::tempdir().unwrap();
+        let audio_path = temp_dir.path().join(AUDIO_TS_FILENAME);
+        let subtitle_path = temp_dir.path().join("subtitles.vtt");
+        std::fs::write(&audio_path, b"audio").unwrap();
+        std::fs::write(&subtitle_path, b"WEBVTT").unwrap();
+        let options = RemuxOptions {
+            fix_timestamps: true,
+            external_audio: Some(ExternalAudio {
+                url: audio_path.to_string_lossy().into_owned(),
+                headers: Vec::new(),
+                track: TrackMetadata::default(),
+            }),
+            subtitles: vec![SubtitleTrack {
+                path: subtitle_path,
+                track: TrackMetadata::default(),
+            }],
+            ..Default::default()
+        };
+
+        let retained = retain_remux_inputs(
+            &options,
+            temp_dir.path(),
+            output_dir.path(),
+            "show",
+            &|_| {},
+        )
+        .await
+        .unwrap();
+        drop(temp_dir);
+
+        assert!(retained.fix_timestamps);
+        let local = retained.local_inputs();
+        assert_eq!(
+            local,
+            [
+                output_dir.path().join("show.audio.ts"),
+                output_dir.path().join("show.subtitles.vtt"),
+            ]
+        );
+        assert_eq!(std::fs::read(&local[0]).unwrap(), b"audio");
+        assert_eq!(std::fs::read(&local[1]).unwrap(), b"WEBVTT");
+    }
+
+    #[test]
+    fn remote_audio_is_not_a_local_input() {
+        let options = RemuxOptions {
+            external_audio: Some(ExternalAudio {
+                url: "https://example.com/audio.m3u8".to_string(),
+                headers: Vec::new(),
+                track: TrackMetadata::default(),
+            }),
+            ..Default::default()
+        };
+        assert!(options.local_inputs().is_empty());
+    }
+}
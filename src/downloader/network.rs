@@ -0,0 +1,116 @@
+use anyhow::{Result, anyhow};
+use reqwest::Url;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio::sync::watch;
+use tokio::time::{sleep, timeout};
+
+/// How long a single reachability probe may take
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+/// Interval between probes while offline
+const PROBE_INTERVAL: Duration = Duration::from_secs(3);
+/// Well-known anycast endpoints used to tell "origin down" apart from "network down"
+const PUBLIC_PROBE_ADDRS: [(&str, u16); 2] = [("1.1.1.1", 443), ("8.8.8.8", 53)];
+/// Give up on the job if connectivity does not return within this window
+pub const MAX_OFFLINE_WAIT: Duration = Duration::from_secs(30 * 60);
+
+/// Job-wide connectivity state shared by all segment tasks.
+///
+/// When a request fails at the connection level, tasks ask the monitor whether the origin is
+/// still reachable. If it is not, the whole job pauses (without consuming retries) until a probe
+/// succeeds again, so a Wi-Fi switch doesn't burn through every segment's retry budget at once.
+pub struct ConnectivityMonitor {
+    online: watch::Sender<bool>,
+    probing: AtomicBool,
+    log: Box<dyn Fn(String) + Send + Sync>,
+}
+
+impl ConnectivityMonitor {
+    pub fn new(log: impl Fn(String) + Send + Sync + 'static) -> Self {
+        let (online, _) = watch::channel(true);
+        Self {
+            online,
+            probing: AtomicBool::new(false),
+            log: Box::new(log),
+        }
+    }
+
+    /// Returns `true` if the error should be treated as a network outage (the caller has already
+    /// waited for connectivity to come back and should retry without counting the attempt).
+    pub async fn handle_connection_error(&self, url: &Url) -> Result<bool> {
+        let online = *self.online.borrow();
+        if online && (is_reachable(url).await || internet_reachable().await) {
+            // Either the origin answers or only the origin is failing: regular retry handling
+            return Ok(false);
+        }
+
+        self.mark_offline(url);
+        self.wait_until_online().await?;
+        Ok(true)
+    }
+
+    fn mark_offline(&self, url: &Url) {
+        // Only the first task to notice the outage starts the probe loop
+        if self.probing.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        self.online.send_replace(false);
+        (self.log)("📡 Network connection lost. Pausing downloads until it returns...".to_string());
+
+        let online = self.online.clone();
+        let url = url.clone();
+        tokio::spawn(async move {
+            loop {
+                sleep(PROBE_INTERVAL).await;
+                if online.is_closed() || is_reachable(&url).await {
+                    break;
+                }
+            }
+            online.send_replace(true);
+        });
+    }
+
+    async fn wait_until_online(&self) -> Result<()> {
+        let mut receiver = self.online.subscribe();
+        timeout(MAX_OFFLINE_WAIT, receiver.wait_for(|online| *online))
+            .await
+            .map_err(|_| {
+                anyhow!(
+                    "Network did not recover within {} minutes",
+                    MAX_OFFLINE_WAIT.as_secs() / 60
+                )
+            })?
+            .map_err(|e| anyhow!("Connectivity monitor closed: {}", e))?;
+
+        if self.probing.swap(false, Ordering::SeqCst) {
+            (self.log)("📡 Network connection restored, resuming downloads.".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Probes whether a TCP connection to the URL's host can be established.
+async fn is_reachable(url: &Url) -> bool {
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        return false;
+    };
+    can_connect(host, port).await
+}
+
+/// Probes well-known public endpoints to check for general internet access.
+async fn internet_reachable() -> bool {
+    for (host, port) in PUBLIC_PROBE_ADDRS {
+        if can_connect(host, port).await {
+            return true;
+        }
+    }
+    false
+}
+
+async fn can_connect(host: &str, port: u16) -> bool {
+    matches!(
+        timeout(PROBE_TIMEOUT, TcpStream::connect((host, port))).await,
+        Ok(Ok(_))
+    )
+}
@@ -0,0 +1,49 @@
+// Pausing a download: segment tasks wait at a shared gate before they start, so the segments
+// in flight finish and everything already on disk is kept until the job resumes.
+
+use anyhow::Result;
+use std::sync::Arc;
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+use super::cancel::Cancelled;
+
+/// Job-wide pause switch shared by the GUI and the segment tasks; clones control the same gate.
+#[derive(Debug, Clone)]
+pub struct PauseGate {
+    paused: Arc<watch::Sender<bool>>,
+}
+
+impl Default for PauseGate {
+    fn default() -> Self {
+        let (paused, _) = watch::channel(false);
+        Self {
+            paused: Arc::new(paused),
+        }
+    }
+}
+
+impl PauseGate {
+    pub fn pause(&self) {
+        self.paused.send_replace(true);
+    }
+
+    pub fn resume(&self) {
+        self.paused.send_replace(false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    /// Returns once the gate is open, or `Err(Cancelled)` if the job is cancelled while paused.
+    pub async fn wait(&self, cancel: &CancellationToken) -> Result<()> {
+        let mut receiver = self.paused.subscribe();
+        tokio::select! {
+            biased;
+            _ = cancel.cancelled() => Err(Cancelled.into()),
+            // The sender lives in `self`, so the channel cannot close while waiting
+            _ = receiver.wait_for(|paused| !*paused) => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,150 @@
+// "Stream to player": serves the segments of a running download, in playlist order, as one
+// continuous MPEG-TS over HTTP on 127.0.0.1, so mpv/VLC can play while the download continues.
+
+use anyhow::{Result, anyhow};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+
+pub const STREAM_PATH: &str = "/stream.ts";
+/// How often a player waiting for the next segment checks whether it has arrived
+const SEGMENT_POLL_INTERVAL: Duration = Duration::from_millis(250);
+const MAX_REQUEST_BYTES: usize = 8 * 1024;
+
+/// Running stream endpoint; stops serving when dropped.
+pub struct PlayerStream {
+    url: String,
+    clients: Arc<ClientCount>,
+    shutdown: CancellationToken,
+}
+
+#[derive(Default)]
+struct ClientCount {
+    active: AtomicUsize,
+    changed: Notify,
+}
+
+impl PlayerStream {
+    /// Binds a free localhost port and serves `segment_paths` (complete files appear as the
+    /// download progresses) until the returned handle is dropped or `cancel` fires.
+    pub async fn start(segment_paths: Vec<PathBuf>, cancel: &CancellationToken) -> Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .map_err(|e| anyhow!("Failed to bind the player stream: {}", e))?;
+        let url = format!("http://{}{}", listener.local_addr()?, STREAM_PATH);
+        let clients = Arc::new(ClientCount::default());
+        let shutdown = cancel.child_token();
+        tokio::spawn(serve(
+            listener,
+            Arc::new(segment_paths),
+            clients.clone(),
+            shutdown.clone(),
+        ));
+        Ok(Self {
+            url,
+            clients,
+            shutdown,
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Waits until no player is reading the stream any more.
+    pub async fn wait_for_players(&self) {
+        loop {
+            let changed = self.clients.changed.notified();
+            if self.clients.active.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            changed.await;
+        }
+    }
+
+    pub fn has_players(&self) -> bool {
+        self.clients.active.load(Ordering::SeqCst) > 0
+    }
+}
+
+impl Drop for PlayerStream {
+    fn drop(&mut self) {
+        self.shutdown.cancel();
+    }
+}
+
+async fn serve(
+    listener: TcpListener,
+    segments: Arc<Vec<PathBuf>>,
+    clients: Arc<ClientCount>,
+    shutdown: CancellationToken,
+) {
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => return,
+            accepted = listener.accept() => {
+                if let Ok((stream, _)) = accepted {
+                    let segments = segments.clone();
+                    let clients = clients.clone();
+                    let shutdown = shutdown.clone();
+                    tokio::spawn(async move {
+                        clients.active.fetch_add(1, Ordering::SeqCst);
+                        tokio::select! {
+                            _ = shutdown.cancelled() => {}
+                            _ = handle_connection(stream, &segments) => {}
+                        }
+                        clients.active.fetch_sub(1, Ordering::SeqCst);
+                        clients.changed.notify_waiters();
+                    });
+                }
+            }
+        }
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, segments: &[PathBuf]) {
+    let mut buf = vec![0u8; MAX_REQUEST_BYTES];
+    let mut len = 0;
+    while !buf[..len].windows(4).any(|w| w == b"\r\n\r\n") {
+        match stream.read(&mut buf[len..]).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => len += n,
+        }
+        if len == buf.len() {
+            return;
+        }
+    }
+    let request = String::from_utf8_lossy(&buf[..len]);
+    let mut request_line = request.lines().next().unwrap_or("").split_whitespace();
+    let method = request_line.next().unwrap_or("");
+    let path = request_line.next().unwrap_or("");
+    if !matches!(method, "GET" | "HEAD") || path != STREAM_PATH {
+        let response = "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+        stream.write_all(response.as_bytes()).await.ok();
+        return;
+    }
+
+    // Total length is unknown while downloading, so the body simply ends with the connection
+    let header = "HTTP/1.1 200 OK\r\nContent-Type: video/mp2t\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n";
+    if stream.write_all(header.as_bytes()).await.is_err() || method == "HEAD" {
+        return;
+    }
+    for path in segments {
+        // Segment files only appear once complete (written as `.part` and renamed)
+        let bytes = loop {
+            match tokio::fs::read(path).await {
+                Ok(bytes) => break bytes,
+                Err(_) => tokio::time::sleep(SEGMENT_POLL_INTERVAL).await,
+            }
+        };
+        if stream.write_all(&bytes).await.is_err() {
+            return; // Player closed the connection
+        }
+    }
+    stream.shutdown().await.ok();
+}
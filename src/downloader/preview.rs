@@ -0,0 +1,250 @@
+// Quick preview: downloads a sample of the I-frame playlist (`#EXT-X-I-FRAME-STREAM-INF`, only
+// the key frames of a variant) instead of the whole stream and renders it as a thumbnail strip
+// or a short clip, so the right stream can be checked before the full download.
+
+use anyhow::{Result, anyhow};
+use egui::Context as EguiContext;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use super::content_type::ContentTypeRules;
+use super::error_summary::ErrorSummary;
+use super::hls_parser::{fetch_playlist_text, parse_m3u8, parse_master_playlist};
+use super::http::build_client;
+use super::pause::PauseGate;
+use super::report::SegmentReport;
+use super::segment_io::{
+    concatenate_segments, download_init_sections, download_segments_concurrently,
+    ffmpeg_progress_command, init_sections, run_progress_command,
+};
+use super::util::DEFAULT_OUTPUT_NAME;
+use super::workspace::{TEMP_DIR_PREFIX, interleave_init_sections};
+use super::{DownloadConfig, DownloadMessage, failure};
+
+/// Frames sampled evenly across the I-frame playlist
+const PREVIEW_FRAMES: usize = 12;
+/// Parallel I-frame downloads (each is a small byte range)
+const PREVIEW_CONCURRENCY: usize = 4;
+/// Thumbnails per row of the strip, and their width in pixels
+const STRIP_COLUMNS: usize = 4;
+const STRIP_TILE_WIDTH: u32 = 320;
+/// Seconds each frame is shown in the clip
+const CLIP_FRAME_SECONDS: f64 = 0.5;
+
+/// What the preview is rendered as
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum PreviewKind {
+    /// One JPEG with the frames in a grid
+    #[default]
+    Strip,
+    /// Short MP4 showing the frames one after another
+    Clip,
+}
+
+impl PreviewKind {
+    pub const ALL: [PreviewKind; 2] = [PreviewKind::Strip, PreviewKind::Clip];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            PreviewKind::Strip => "Thumbnail strip",
+            PreviewKind::Clip => "Preview clip",
+        }
+    }
+
+    /// Name on the command line (`--preview strip|clip`)
+    pub fn name(self) -> &'static str {
+        match self {
+            PreviewKind::Strip => "strip",
+            PreviewKind::Clip => "clip",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|kind| kind.name().eq_ignore_ascii_case(value))
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            PreviewKind::Strip => "jpg",
+            PreviewKind::Clip => "mp4",
+        }
+    }
+}
+
+/// `<output dir>/<name>.preview.jpg` (or `.mp4`)
+pub fn preview_path(config: &DownloadConfig, kind: PreviewKind) -> PathBuf {
+    let name = Path::new(&config.output_filename)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .filter(|stem| !stem.is_empty())
+        .unwrap_or_else(|| DEFAULT_OUTPUT_NAME.to_string());
+    Path::new(&config.output_location).join(format!("{}.preview.{}", name, kind.extension()))
+}
+
+/// `count` positions spread evenly over `0..len`, first and last included (all if fewer).
+fn sample_positions(len: usize, count: usize) -> Vec<usize> {
+    if len <= count {
+        return (0..len).collect();
+    }
+    (0..count).map(|i| i * (len - 1) / (count - 1)).collect()
+}
+
+/// Finds the I-frame playlist of the job's variant (`config.quality`, default best), downloads
+/// a sample of its frames and renders the preview next to where the download would go.
+pub async fn run_preview_job(
+    config: DownloadConfig,
+    kind: PreviewKind,
+    cancel: CancellationToken,
+    sender: mpsc::Sender<DownloadMessage>,
+    ctx: EguiContext,
+) -> Result<()> {
+    let send_log = |msg: String| {
+        sender.try_send(DownloadMessage::Log(msg)).ok();
+        ctx.request_repaint();
+    };
+
+    let limits = config.limits;
+    let playlist_url =
+        Url::parse(&config.playlist_url).map_err(|e| anyhow!("Invalid URL: {}", e))?;
+    let client = build_client(&config.headers, config.proxy.as_deref())?;
+    let (base_url, body) = match &config.playlist_text {
+        Some(text) => (playlist_url.clone(), text.clone()),
+        None => {
+            let fetched =
+                fetch_playlist_text(&client, &playlist_url, &limits, &send_log, &cancel).await?;
+            (fetched.url, fetched.body)
+        }
+    };
+
+    // A master playlist points to the I-frame playlists; one can also be entered directly
+    let (iframe_url, iframe_body) = match parse_master_playlist(&body, &base_url)? {
+        Some(master) => {
+            let quality = config.quality.as_deref().unwrap_or("best");
+            let variant = master
+                .select(quality)
+                .ok_or_else(|| anyhow!("No variant matches quality '{}'", quality))?;
+            let stream = master.iframe_stream_for(variant).ok_or_else(|| {
+                anyhow!(
+                    "The master playlist lists no I-frame playlists (#EXT-X-I-FRAME-STREAM-INF)"
+                )
+            })?;
+            send_log(format!(
+                "-> Previewing {} with its {}",
+                variant.label(),
+                stream.label()
+            ));
+            let fetched =
+                fetch_playlist_text(&client, &stream.uri, &limits, &send_log, &cancel).await?;
+            (fetched.url, fetched.body)
+        }
+        None if body.contains("#EXT-X-I-FRAMES-ONLY") => (base_url, body),
+        None => {
+            return Err(anyhow!(
+                "Quick preview needs a master playlist with I-frame playlists; this is a media playlist"
+            ));
+        }
+    };
+
+    let (segments, keys) = parse_m3u8(&iframe_body, &iframe_url, &limits, &send_log)?;
+    if !keys.is_empty() {
+        // Byte ranges inside an AES-128 segment cannot be decrypted on their own
+        return Err(anyhow!("Encrypted I-frame playlists cannot be previewed"));
+    }
+    let segments: Vec<_> = segments.into_iter().filter(|s| !s.gap).collect();
+    if segments.is_empty() {
+        return Err(anyhow!("The I-frame playlist contains no frames"));
+    }
+    let total = segments.len();
+    let positions = sample_positions(total, PREVIEW_FRAMES);
+    let frames: Vec<_> = positions.iter().map(|&i| segments[i].clone()).collect();
+    let frame_count = frames.len();
+    send_log(format!(
+        "-> Downloading {} of {} I-frames...",
+        frame_count, total
+    ));
+
+    let temp_dir = tempfile::Builder::new().prefix(TEMP_DIR_PREFIX).tempdir()?;
+    let (init_urls, init_of) = init_sections(&iframe_url, &frames)?;
+    let init_paths = download_init_sections(&client, &init_urls, temp_dir.path(), &cancel).await?;
+    let frame_paths = download_segments_concurrently(
+        &client,
+        &iframe_url,
+        frames,
+        Vec::new(),
+        frame_count,
+        PREVIEW_CONCURRENCY,
+        temp_dir.path().to_path_buf(),
+        Arc::new(ErrorSummary::default()),
+        Arc::new(ContentTypeRules::new(&config.accepted_content_types)),
+        Arc::new(SegmentReport::default()),
+        &cancel,
+        &PauseGate::default(),
+        sender.clone(),
+        ctx.clone(),
+    )
+    .await?;
+
+    let output = preview_path(&config, kind);
+    send_log(format!("-> Rendering {}...", kind.label().to_lowercase()));
+    let merged = temp_dir.path().join("iframes.ts");
+    let render_output = output.clone();
+    let render_cancel = cancel.clone();
+    tokio::task::spawn_blocking(move || {
+        let paths = interleave_init_sections(&frame_paths, &init_of, &init_paths);
+        concatenate_segments(&paths, &merged, false, &render_cancel)?;
+        render_preview(&merged, &render_output, kind, frame_count, &render_cancel)
+    })
+    .await
+    .map_err(|e| anyhow!("Preview blocking task failed to join: {}", e))??;
+
+    send_log(format!("✅ Preview saved as {}", output.display()));
+    Ok(())
+}
+
+/// Renders the concatenated I-frames with FFmpeg.
+fn render_preview(
+    frames: &Path,
+    output: &Path,
+    kind: PreviewKind,
+    frame_count: usize,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    let mut command = ffmpeg_progress_command()?;
+    command.arg("-i").arg(frames).arg("-an");
+    match kind {
+        PreviewKind::Strip => {
+            let columns = frame_count.clamp(1, STRIP_COLUMNS);
+            let rows = frame_count.div_ceil(columns);
+            command
+                .arg("-vf")
+                .arg(format!(
+                    "scale={}:-2,tile={}x{}",
+                    STRIP_TILE_WIDTH, columns, rows
+                ))
+                .args(["-frames:v", "1", "-q:v", "3"]);
+        }
+        PreviewKind::Clip => {
+            command
+                .arg("-vf")
+                .arg(format!("setpts=N*{}/TB,scale=-2:360", CLIP_FRAME_SECONDS))
+                .args(["-r", "25", "-c:v", "libx264", "-preset", "veryfast"])
+                .args(["-pix_fmt", "yuv420p", "-movflags", "+faststart"]);
+        }
+    }
+    command.arg("-y").arg(output);
+    let run = run_progress_command(command, cancel, |_| {})?;
+    if !run.success {
+        let _ = std::fs::remove_file(output);
+        return Err(failure::ffmpeg(format!(
+            "Preview rendering failed: {}",
+            run.stderr
+        )));
+    }
+    Ok(())
+}
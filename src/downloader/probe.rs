@@ -0,0 +1,418 @@
+// Analyze step: probes the first segment of a playlist to find its codecs before downloading.
+
+use anyhow::{Result, anyhow};
+use egui::Context as EguiContext;
+use reqwest::Url;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use super::content_type::ContentTypeRules;
+use super::error_summary::ErrorSummary;
+use super::hls_parser::{
+    allows_local_keys, download_key_file, fetch_playlist_text, parse_m3u8, resolve_media_playlist,
+};
+use super::http::build_client;
+use super::pause::PauseGate;
+use super::report::SegmentReport;
+use super::segment_io::{
+    PendingKey, SegmentDecryption, download_segments_concurrently, run_ffmpeg_with_progress,
+};
+use super::workspace::TEMP_DIR_PREFIX;
+use super::{DownloadConfig, DownloadMessage, cancel};
+
+/// One row of the segment list shown after Analyze
+#[derive(Debug, Clone)]
+pub struct SegmentEntry {
+    /// Media sequence number, as used by `DownloadConfig::excluded_segments`
+    pub index: usize,
+    pub duration: f64,
+    pub uri: String,
+    pub discontinuity: bool,
+    pub gap: bool,
+    pub ad: bool,
+}
+
+/// Streams found in the probed segment (FFmpeg codec names); the video and audio properties
+/// are those of the first stream of each kind
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StreamInfo {
+    pub video_codecs: Vec<String>,
+    pub audio_codecs: Vec<String>,
+    pub resolution: Option<(u32, u32)>,
+    /// As FFmpeg prints it (`25`, `29.97`)
+    pub frame_rate: Option<String>,
+    pub sample_rate: Option<u32>,
+    /// FFmpeg channel layout (`stereo`, `5.1(side)`, `mono`)
+    pub audio_channels: Option<String>,
+}
+
+impl StreamInfo {
+    /// Reads the `Stream #0:0...: Video: h264 (High) ..., 1920x1080 [SAR 1:1], 25 fps, ...` and
+    /// `Audio: aac (LC) ..., 48000 Hz, stereo, ...` lines of `ffmpeg -i` output.
+    pub fn parse(ffmpeg_stderr: &str) -> Self {
+        let mut info = StreamInfo::default();
+        for line in ffmpeg_stderr.lines() {
+            let line = line.trim();
+            if !line.starts_with("Stream #") {
+                continue;
+            }
+            if let Some(rest) = line.split_once("Video: ").map(|(_, rest)| rest) {
+                let fields = stream_fields(rest);
+                if info.video_codecs.is_empty() {
+                    info.resolution = fields.iter().find_map(|field| parse_resolution(field));
+                    info.frame_rate = fields
+                        .iter()
+                        .find_map(|field| field.strip_suffix(" fps"))
+                        .map(str::to_string);
+                }
+                push_codec(&mut info.video_codecs, &fields);
+            }
+            if let Some(rest) = line.split_once("Audio: ").map(|(_, rest)| rest) {
+                let fields = stream_fields(rest);
+                if info.audio_codecs.is_empty() {
+                    // The channel layout follows the sample rate
+                    let rate = fields.iter().position(|field| field.ends_with(" Hz"));
+                    info.sample_rate = rate
+                        .and_then(|i| fields[i].strip_suffix(" Hz").and_then(|hz| hz.parse().ok()));
+                    info.audio_channels = rate
+                        .and_then(|i| fields.get(i + 1))
+                        .map(|field| field.to_string());
+                }
+                push_codec(&mut info.audio_codecs, &fields);
+            }
+        }
+        info
+    }
+
+    /// Log lines with the properties of the first video and audio stream.
+    pub fn details(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        if let Some(codec) = self.video_codecs.first() {
+            let mut line = format!("Video: {}", codec);
+            if let Some((width, height)) = self.resolution {
+                line.push_str(&format!(", {}x{}", width, height));
+            }
+            if let Some(fps) = &self.frame_rate {
+                line.push_str(&format!(", {} fps", fps));
+            }
+            lines.push(line);
+        }
+        if let Some(codec) = self.audio_codecs.first() {
+            let mut line = format!("Audio: {}", codec);
+            if let Some(rate) = self.sample_rate {
+                line.push_str(&format!(", {} Hz", rate));
+            }
+            if let Some(channels) = &self.audio_channels {
+                line.push_str(&format!(", {}", channels));
+            }
+            lines.push(line);
+        }
+        lines
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.video_codecs.is_empty() && self.audio_codecs.is_empty()
+    }
+
+    /// Why `format` cannot hold these streams with a stream copy, or None if it can.
+    pub fn incompatibility(&self, format: &str) -> Option<String> {
+        let rejected: Vec<&str> = self
+            .video_codecs
+            .iter()
+            .chain(&self.audio_codecs)
+            .map(String::as_str)
+            .filter(|codec| !container_accepts(format, codec))
+            .collect();
+        (!rejected.is_empty()).then(|| {
+            format!(
+                "{} cannot hold {} without re-encoding",
+                format,
+                rejected.join("/")
+            )
+        })
+    }
+
+    pub fn summary(&self) -> String {
+        let list = |codecs: &[String]| {
+            if codecs.is_empty() {
+                "none".to_string()
+            } else {
+                codecs.join(", ")
+            }
+        };
+        format!(
+            "video: {}; audio: {}",
+            list(&self.video_codecs),
+            list(&self.audio_codecs)
+        )
+    }
+}
+
+/// The comma-separated fields of a stream description, leaving commas inside parentheses
+/// (`yuv420p(tv, bt709)`) alone.
+fn stream_fields(description: &str) -> Vec<&str> {
+    let mut fields = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in description.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                fields.push(description[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    fields.push(description[start..].trim());
+    fields
+}
+
+/// `1920x1080 [SAR 1:1 DAR 16:9]` -> (1920, 1080)
+fn parse_resolution(field: &str) -> Option<(u32, u32)> {
+    let size = field.split(' ').next()?;
+    let (width, height) = size.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+fn push_codec(codecs: &mut Vec<String>, fields: &[&str]) {
+    if let Some(codec) = fields
+        .first()
+        .and_then(|field| field.split(' ').next())
+        .filter(|c| !c.is_empty())
+    {
+        codecs.push(codec.to_string());
+    }
+}
+
+/// Codecs each output container accepts with `-c copy`.
+fn container_accepts(format: &str, codec: &str) -> bool {
+    match format {
+        "webm" => matches!(codec, "vp8" | "vp9" | "av1" | "opus" | "vorbis"),
+        "mp4" => matches!(
+            codec,
+            "h264"
+                | "hevc"
+                | "av1"
+                | "vp9"
+                | "mpeg4"
+                | "aac"
+                | "mp3"
+                | "ac3"
+                | "eac3"
+                | "opus"
+                | "flac"
+                | "alac"
+        ),
+        // Matroska takes practically anything; TS output is the source container
+        _ => true,
+    }
+}
+
+/// Codecs of a media file, from FFmpeg's stream list (blocking).
+pub fn probe_streams(path: &Path, cancel: &CancellationToken) -> Result<StreamInfo> {
+    // No output file: FFmpeg prints the stream list and exits with an error
+    let args: Vec<&OsStr> = vec!["-i".as_ref(), path.as_os_str()];
+    let run = run_ffmpeg_with_progress(&args, cancel, |_| {})?;
+    Ok(StreamInfo::parse(&run.stderr))
+}
+
+/// Probes the merged stream before the remux to `format`. Returns whether it must be
+/// re-encoded: only if `reencode` was asked for and the codecs don't fit (or can't be probed).
+pub(super) async fn needs_reencode(
+    merged_path: &Path,
+    format: &str,
+    reencode: bool,
+    send_log: &impl Fn(String),
+    cancel: &CancellationToken,
+) -> Result<bool> {
+    let path = merged_path.to_path_buf();
+    let probe_cancel = cancel.clone();
+    let probed = tokio::task::spawn_blocking(move || probe_streams(&path, &probe_cancel))
+        .await
+        .map_err(|e| anyhow!("FFmpeg blocking task failed to join: {}", e))?;
+    let problem = match probed {
+        Ok(info) if !info.is_empty() => info.incompatibility(format),
+        Err(e) if e.is::<cancel::Cancelled>() => return Err(e),
+        _ => {
+            send_log(
+                "⚠️ Warning: The merged stream could not be probed for its codecs.".to_string(),
+            );
+            return Ok(reencode);
+        }
+    };
+    match problem {
+        Some(problem) if reencode => {
+            send_log(format!("-> {}; re-encoding it.", problem));
+            Ok(true)
+        }
+        Some(problem) => {
+            send_log(format!(
+                "⚠️ Warning: {}; the stream copy will likely fail (choose mkv/mp4, or enable re-encoding).",
+                problem
+            ));
+            Ok(false)
+        }
+        None => Ok(false),
+    }
+}
+
+/// What to expect from `format` before the stream has been analyzed, if it rarely fits HLS.
+pub fn unanalyzed_format_warning(format: &str) -> Option<&'static str> {
+    (format == "webm").then_some(
+        "WebM only holds VP8/VP9/AV1 video and Opus/Vorbis audio, while HLS streams are usually \
+         H.264/AAC; the final stream copy would fail",
+    )
+}
+
+/// Fetches the playlist, downloads (and decrypts) its first segment and reports its codecs.
+pub async fn run_analyze_job(
+    config: DownloadConfig,
+    cancel: CancellationToken,
+    sender: mpsc::Sender<DownloadMessage>,
+    ctx: EguiContext,
+) -> Result<()> {
+    let send_log = |msg: String| {
+        sender.try_send(DownloadMessage::Log(msg)).ok();
+        ctx.request_repaint();
+    };
+
+    let limits = config.limits;
+    let playlist_url =
+        Url::parse(&config.playlist_url).map_err(|e| anyhow!("Invalid URL: {}", e))?;
+    let client = build_client(&config.headers, config.proxy.as_deref())?;
+    let (base_url, body) = match &config.playlist_text {
+        Some(text) => (playlist_url.clone(), text.clone()),
+        None => {
+            let fetched =
+                fetch_playlist_text(&client, &playlist_url, &limits, &send_log, &cancel).await?;
+            (fetched.url, fetched.body)
+        }
+    };
+    // Shown as returned by the server, before anything can fail to parse
+    let send_raw = |url: &Url, text: &str| {
+        sender
+            .try_send(DownloadMessage::PlaylistFetched {
+                playlist_url: config.playlist_url.clone(),
+                url: url.to_string(),
+                text: text.to_string(),
+            })
+            .ok();
+        ctx.request_repaint();
+    };
+    send_raw(&base_url, &body);
+    let media = resolve_media_playlist(
+        &client,
+        &base_url,
+        body,
+        config.quality.as_deref(),
+        &limits,
+        &send_log,
+        &cancel,
+    )
+    .await?;
+    if media.url != base_url {
+        send_raw(&media.url, &media.body);
+    }
+    let (mut segments, keys) = parse_m3u8(&media.body, &media.url, &limits, &send_log)?;
+    // Listed before probing, so segments can be excluded even if the probe fails
+    sender
+        .send(DownloadMessage::SegmentsListed {
+            playlist_url: config.playlist_url.clone(),
+            segments: segments
+                .iter()
+                .map(|s| SegmentEntry {
+                    index: s.index,
+                    duration: s.duration,
+                    uri: s.path.clone(),
+                    discontinuity: s.discontinuity,
+                    gap: s.gap,
+                    ad: s.ad,
+                })
+                .collect(),
+        })
+        .await
+        .ok();
+    ctx.request_repaint();
+    let duration: f64 = segments.iter().map(|s| s.duration).sum();
+    let segment_count = segments.len();
+    let position = segments
+        .iter()
+        .position(|s| !s.gap)
+        .ok_or_else(|| anyhow!("Every segment of the playlist is marked #EXT-X-GAP"))?;
+    let mut first = segments.swap_remove(position);
+    send_log(format!("-> Probing first segment: {}", first.path));
+
+    // Only the key of the probed segment is needed
+    let mut decryption = Vec::new();
+    if let Some(info) = first.key.and_then(|key| keys.get(key)).cloned() {
+        let key = cancel::or_cancel(
+            &cancel,
+            download_key_file(
+                &client,
+                &info.key_url,
+                &info.method,
+                allows_local_keys(&config.playlist_url, config.playlist_text.is_some()),
+                &send_log,
+            ),
+        )
+        .await?;
+        decryption.push(SegmentDecryption {
+            info,
+            key: PendingKey::ready(key),
+        });
+        first.key = Some(0);
+    }
+
+    let temp_dir = tempfile::Builder::new().prefix(TEMP_DIR_PREFIX).tempdir()?;
+    let paths = download_segments_concurrently(
+        &client,
+        &media.url,
+        vec![first],
+        decryption,
+        1,
+        1,
+        temp_dir.path().to_path_buf(),
+        Arc::new(ErrorSummary::default()),
+        Arc::new(ContentTypeRules::new(&config.accepted_content_types)),
+        Arc::new(SegmentReport::default()),
+        &cancel,
+        &PauseGate::default(),
+        sender.clone(),
+        ctx.clone(),
+    )
+    .await?;
+
+    let segment_path = paths[0].clone();
+    let probe_cancel = cancel.clone();
+    let info = tokio::task::spawn_blocking(move || probe_streams(&segment_path, &probe_cancel))
+        .await
+        .map_err(|e| anyhow!("FFmpeg blocking task failed to join: {}", e))??;
+    if info.is_empty() {
+        return Err(anyhow!(
+            "No audio or video streams detected in the first segment"
+        ));
+    }
+    send_log(format!("-> Detected streams ({})", info.summary()));
+    for line in info.details() {
+        send_log(format!("   {}", line));
+    }
+    send_log(format!(
+        "   Duration: {:.1}s in {} segments (from #EXTINF)",
+        duration, segment_count
+    ));
+    sender.send(DownloadMessage::Progress(1.0)).await.ok();
+    sender
+        .send(DownloadMessage::Analyzed {
+            playlist_url: config.playlist_url,
+            info,
+        })
+        .await
+        .ok();
+    ctx.request_repaint();
+    Ok(())
+}
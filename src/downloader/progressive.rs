@@ -0,0 +1,561 @@
+// Progressive download: a URL that points at a media file (`.mp4`, `.ts`, ...) instead of a
+// playlist is fetched in byte ranges into `<output>.<ext>.part`, which a later run resumes.
+// The `.part.json` sidecar records where the bytes came from, so a resume only continues the
+// same file.
+
+use anyhow::{Result, anyhow};
+use egui::Context as EguiContext;
+use reqwest::header::{
+    CONTENT_RANGE, CONTENT_TYPE, ETAG, HeaderMap, IF_RANGE, LAST_MODIFIED, RANGE,
+};
+use reqwest::{Client, StatusCode, Url};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::sync::mpsc;
+use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
+
+use super::hls_parser::MAX_RETRIES;
+use super::segment_io::{RemuxOptions, run_ffmpeg_remux};
+use super::{DownloadMessage, cancel, remux_pool, stats};
+
+/// Bytes requested per range request
+const CHUNK_BYTES: u64 = 8 * 1024 * 1024;
+
+/// The fetched "playlist" is a media file; returned by the playlist fetch so the job can
+/// download it progressively instead
+#[derive(Debug, Clone)]
+pub struct MediaFile {
+    pub url: Url,
+    /// Container of the file (`mp4`, `ts`, `mkv`, ...)
+    pub extension: String,
+    pub length: Option<u64>,
+    /// Strong `ETag` (else `Last-Modified`) of the file, sent as `If-Range` when resuming
+    pub validator: Option<String>,
+}
+
+impl fmt::Display for MediaFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} is a media file ({}), not a playlist",
+            self.url, self.extension
+        )
+    }
+}
+
+impl std::error::Error for MediaFile {}
+
+/// Validator for `If-Range`: a strong `ETag`, else `Last-Modified` (weak ETags are not allowed).
+pub fn validator(headers: &HeaderMap) -> Option<String> {
+    let header = |name| headers.get(name)?.to_str().ok().map(str::to_string);
+    header(ETAG)
+        .filter(|etag| !etag.starts_with("W/"))
+        .or_else(|| header(LAST_MODIFIED))
+}
+
+/// Contents of the `.part.json` sidecar: the file the `.part` bytes were downloaded from
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct PartOrigin {
+    /// URL without its query, which signed URLs change on every run
+    url: String,
+    validator: Option<String>,
+}
+
+impl PartOrigin {
+    fn of(media: &MediaFile) -> Self {
+        let mut url = media.url.clone();
+        url.set_query(None);
+        url.set_fragment(None);
+        Self {
+            url: url.to_string(),
+            validator: media.validator.clone(),
+        }
+    }
+}
+
+/// Container of a response that is a media file rather than a playlist, judged by its
+/// `Content-Type` or, for generic types, by the first bytes of the body.
+pub fn sniff(content_type: Option<&str>, head: &[u8], url: &Url) -> Option<String> {
+    let mime = content_type
+        .and_then(|value| value.split(';').next())
+        .map(|value| value.trim().to_ascii_lowercase())
+        .unwrap_or_default();
+    if mime.contains("mpegurl") || mime.contains("dash+xml") {
+        return None;
+    }
+    let by_type = match mime.as_str() {
+        "video/mp2t" => Some("ts"),
+        "video/mp4" | "video/quicktime" => Some("mp4"),
+        "video/webm" => Some("webm"),
+        "video/x-matroska" => Some("mkv"),
+        "audio/mp4" => Some("m4a"),
+        "audio/mpeg" => Some("mp3"),
+        _ => None,
+    };
+    let by_bytes = if head.first() == Some(&0x47) && head.get(188).is_none_or(|b| *b == 0x47) {
+        Some("ts")
+    } else if head.get(4..8) == Some(b"ftyp") {
+        Some("mp4")
+    } else if head.starts_with(&[0x1a, 0x45, 0xdf, 0xa3]) {
+        // WebM is Matroska too; the URL tells them apart
+        Some(if url.path().ends_with(".webm") {
+            "webm"
+        } else {
+            "mkv"
+        })
+    } else {
+        None
+    };
+    by_type.or(by_bytes).map(str::to_string)
+}
+
+/// Downloads `media` to `output_path`, remuxing it when the requested format differs from the
+/// file's container. An interrupted download leaves a `.part` file that the next run continues.
+#[allow(clippy::too_many_arguments)]
+pub async fn run_progressive_download(
+    client: &Client,
+    media: &MediaFile,
+    output_path: &Path,
+    output_format: &str,
+    cancel: &CancellationToken,
+    sender: &mpsc::Sender<DownloadMessage>,
+    ctx: &EguiContext,
+) -> Result<()> {
+    let send_log = |msg: String| {
+        sender.try_send(DownloadMessage::Log(msg)).ok();
+        ctx.request_repaint();
+    };
+    let part_path = part_path(output_path, &media.extension);
+    send_log(format!(
+        "-> {} is a {} file, not a playlist; downloading it progressively to {}",
+        media.url,
+        media.extension,
+        part_path.display()
+    ));
+
+    download_ranges(client, media, &part_path, cancel, sender, ctx).await?;
+
+    if media.extension == output_format {
+        tokio::fs::rename(&part_path, output_path).await?;
+        tokio::fs::remove_file(origin_path(&part_path)).await.ok();
+    } else {
+        let _permit = remux_pool::acquire(&send_log, cancel).await?;
+        send_log(format!(
+            "🚀 Remuxing {} to {} using FFmpeg...",
+            media.extension, output_format
+        ));
+        let input = part_path.clone();
+        let output = output_path.to_path_buf();
+        let ffmpeg_cancel = cancel.clone();
+        tokio::task::spawn_blocking(move || {
+            run_ffmpeg_remux(
+                &input,
+                &output,
+                &RemuxOptions::default(),
+                &ffmpeg_cancel,
+                |_| {},
+            )
+        })
+        .await
+        .map_err(|e| anyhow!("FFmpeg blocking task failed to join: {}", e))?
+        .map_err(|e| {
+            if e.is::<cancel::Cancelled>() {
+                // The `.part` download stays for the next run; the partial remux does not
+                let _ = std::fs::remove_file(output_path);
+                return e;
+            }
+            anyhow!(
+                "FFmpeg remux failed ({}); the download is kept at {}",
+                e,
+                part_path.display()
+            )
+        })?;
+        tokio::fs::remove_file(&part_path).await.ok();
+        tokio::fs::remove_file(origin_path(&part_path)).await.ok();
+    }
+    sender.send(DownloadMessage::Progress(1.0)).await.ok();
+    send_log(format!("✅ File saved as: {}", output_path.display()));
+    Ok(())
+}
+
+/// `show.mp4` + `ts` -> `show.ts.part`
+fn part_path(output_path: &Path, extension: &str) -> PathBuf {
+    output_path.with_extension(format!("{}.part", extension))
+}
+
+/// `show.ts.part` -> `show.ts.part.json`
+fn origin_path(part_path: &Path) -> PathBuf {
+    let mut name = part_path.as_os_str().to_owned();
+    name.push(".json");
+    PathBuf::from(name)
+}
+
+/// Appends the missing bytes to `part_path` in `CHUNK_BYTES` range requests, retrying each
+/// one; servers without range support are read in one response from the start.
+///
+/// An existing `.part` is only continued if its sidecar names the same URL and validator and
+/// it is not larger than the file; otherwise the download starts over.
+async fn download_ranges(
+    client: &Client,
+    media: &MediaFile,
+    part_path: &Path,
+    cancel: &CancellationToken,
+    sender: &mpsc::Sender<DownloadMessage>,
+    ctx: &EguiContext,
+) -> Result<()> {
+    let origin = PartOrigin::of(media);
+    let mut offset = match tokio::fs::metadata(part_path).await {
+        Ok(metadata) => metadata.len(),
+        Err(_) => 0,
+    };
+    if offset > 0 {
+        let recorded = tokio::fs::read(origin_path(part_path))
+            .await
+            .ok()
+            .and_then(|text| serde_json::from_slice::<PartOrigin>(&text).ok());
+        if recorded.as_ref() == Some(&origin) {
+            sender
+                .send(DownloadMessage::Log(format!(
+                    "-> Resuming at byte {} of the earlier download",
+                    offset
+                )))
+                .await
+                .ok();
+        } else {
+            sender
+                .send(DownloadMessage::Log(format!(
+                    "⚠️ Warning: {} is from another URL or version of the file; downloading from the start.",
+                    part_path.display()
+                )))
+                .await
+                .ok();
+            offset = 0;
+        }
+    }
+    tokio::fs::write(origin_path(part_path), serde_json::to_vec(&origin)?).await?;
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(part_path)
+        .await?;
+    if offset == 0 {
+        file.set_len(0).await?;
+    }
+    let mut total = media.length;
+    let mut attempt = 0;
+    loop {
+        if let Some(total) = total
+            && offset > total
+        {
+            sender
+                .send(DownloadMessage::Log(format!(
+                    "⚠️ Warning: {} has {} bytes, more than the file's {}; downloading from the start.",
+                    part_path.display(),
+                    offset,
+                    total
+                )))
+                .await
+                .ok();
+            file.set_len(0).await?;
+            offset = 0;
+        }
+        if total.is_some_and(|total| offset >= total) {
+            break;
+        }
+        cancel::check(cancel)?;
+        let range = format!("bytes={}-{}", offset, offset + CHUNK_BYTES - 1);
+        let result = cancel::or_cancel(cancel, async {
+            let mut request = client.get(media.url.as_str()).header(RANGE, &range);
+            if let Some(validator) = &origin.validator {
+                // The server sends the whole file instead of the range if it changed
+                request = request.header(IF_RANGE, validator);
+            }
+            Ok(request.send().await?.error_for_status()?)
+        })
+        .await;
+        let mut response = match result {
+            Ok(response) => response,
+            Err(e) if e.is::<cancel::Cancelled>() => return Err(e),
+            Err(e) if attempt + 1 < MAX_RETRIES => {
+                attempt += 1;
+                stats::global().record_retry();
+                sender
+                    .send(DownloadMessage::Log(format!(
+                        "⚠️ Range request failed ({}), retrying ({}/{})...",
+                        e, attempt, MAX_RETRIES
+                    )))
+                    .await
+                    .ok();
+                sleep(Duration::from_secs(2u64.pow(attempt as u32))).await;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        let ranged = response.status() == StatusCode::PARTIAL_CONTENT;
+        if ranged {
+            total = total.or_else(|| {
+                let value = response.headers().get(CONTENT_RANGE)?.to_str().ok()?;
+                value.rsplit_once('/')?.1.parse().ok()
+            });
+        } else {
+            // No range support, or the file changed: the whole file follows, so start over
+            if offset > 0 {
+                sender
+                    .send(DownloadMessage::Log(
+                        "⚠️ Warning: The server sent the whole file (no range support, or the file changed); downloading from the start."
+                            .to_string(),
+                    ))
+                    .await
+                    .ok();
+                file.set_len(0).await?;
+                offset = 0;
+            }
+            total = response.content_length();
+        }
+        if response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("text/html"))
+        {
+            return Err(anyhow!(
+                "The server answered with an HTML page instead of the media file"
+            ));
+        }
+
+        let chunk_start = offset;
+        loop {
+            let chunk = match cancel::or_cancel(cancel, async { Ok(response.chunk().await?) }).await
+            {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => break,
+                Err(e) if e.is::<cancel::Cancelled>() => return Err(e),
+                // Interrupted body: the next request continues where it ended
+                Err(_) if ranged => break,
+                Err(e) => return Err(e),
+            };
+            file.write_all(&chunk).await?;
+            offset += chunk.len() as u64;
+            stats::global().record_download(&media.url, chunk.len() as u64);
+            if let Some(total) = total.filter(|total| *total > 0) {
+                sender
+                    .try_send(DownloadMessage::Progress(offset as f32 / total as f32))
+                    .ok();
+                ctx.request_repaint();
+            }
+        }
+        file.flush().await?;
+        if !ranged || (total.is_none() && offset - chunk_start < CHUNK_BYTES) {
+            // A full response, or a short last range of a file of unknown size
+            break;
+        }
+        if offset == chunk_start {
+            attempt += 1;
+            if attempt >= MAX_RETRIES {
+                return Err(anyhow!("The server sent no data for {}", range));
+            }
+        } else {
+            attempt = 0;
+        }
+    }
+    if let Some(total) = total
+        && offset < total
+    {
+        return Err(anyhow!(
+            "Download ended at byte {} of {}; run it again to resume",
+            offset,
+            total
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    const ETAG_V2: &str = "\"v2\"";
+
+    /// Serves `body` as `/video.ts` with ETag `"v2"`, honouring `Range` and `If-Range`.
+    async fn range_server(body: Vec<u8>) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let body = body.clone();
+                tokio::spawn(async move {
+                    let mut buf = vec![0u8; 4096];
+                    let n = socket.read(&mut buf).await.unwrap_or(0);
+                    let request = String::from_utf8_lossy(&buf[..n]).to_ascii_lowercase();
+                    let header = |name: &str| {
+                        request.lines().find_map(|line| {
+                            line.strip_prefix(name)
+                                .map(|value| value.trim().to_string())
+                        })
+                    };
+                    let range = header("range: bytes=").and_then(|range| {
+                        let (start, end) = range.split_once('-')?;
+                        Some((start.parse::<usize>().ok()?, end.parse::<usize>().ok()?))
+                    });
+                    let current = header("if-range:").is_none_or(|tag| tag == ETAG_V2);
+                    let (status, extra, content) = match range {
+                        Some((start, end)) if current && start < body.len() => {
+                            let end = end.min(body.len() - 1);
+                            (
+                                "206 Partial Content",
+                                format!(
+                                    "Content-Range: bytes {}-{}/{}\r\n",
+                                    start,
+                                    end,
+                                    body.len()
+                                ),
+                                body[start..=end].to_vec(),
+                            )
+                        }
+                        _ => ("200 OK", String::new(), body),
+                    };
+                    let head = format!(
+                        "HTTP/1.1 {}\r\nETag: {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                        status,
+                        ETAG_V2,
+                        extra,
+                        content.len()
+                    );
+                    socket.write_all(head.as_bytes()).await.ok();
+                    socket.write_all(&content).await.ok();
+                });
+            }
+        });
+        Url::parse(&format!("http://{}/video.ts?token=1", addr)).unwrap()
+    }
+
+    fn file_body() -> Vec<u8> {
+        (0..1000u32).map(|i| (i % 251) as u8).collect()
+    }
+
+    /// Runs `download_ranges` for a file seen with `validator` over a `.part` holding
+    /// `existing`, recorded as downloaded from `recorded_url` with `recorded_validator`, and
+    /// returns the finished part.
+    async fn resume(
+        validator: &str,
+        existing: &[u8],
+        recorded_url: Option<&str>,
+        recorded_validator: &str,
+    ) -> Vec<u8> {
+        let body = file_body();
+        let url = range_server(body.clone()).await;
+        let media = MediaFile {
+            url: url.clone(),
+            extension: "ts".to_string(),
+            length: Some(body.len() as u64),
+            validator: Some(validator.to_string()),
+        };
+        let dir = tempfile::tempdir().unwrap();
+        let part = part_path(&dir.path().join("show.mp4"), "ts");
+        std::fs::write(&part, existing).unwrap();
+        if let Some(recorded_url) = recorded_url {
+            let origin = PartOrigin {
+                url: recorded_url.replace("{server}", url.authority()),
+                validator: Some(recorded_validator.to_string()),
+            };
+            std::fs::write(origin_path(&part), serde_json::to_vec(&origin).unwrap()).unwrap();
+        }
+        let (sender, _receiver) = mpsc::channel(100);
+        download_ranges(
+            &Client::new(),
+            &media,
+            &part,
+            &CancellationToken::new(),
+            &sender,
+            &egui::Context::default(),
+        )
+        .await
+        .unwrap();
+        std::fs::read(&part).unwrap()
+    }
+
+    #[tokio::test]
+    async fn matching_part_is_continued() {
+        // A recognizable prefix shows the existing bytes were kept, not downloaded again;
+        // the query of the signed URL differs from the recorded one
+        let part = resume(
+            ETAG_V2,
+            &[0xaa; 100],
+            Some("http://{server}/video.ts"),
+            ETAG_V2,
+        )
+        .await;
+        assert_eq!(part[..100], [0xaa; 100]);
+        assert_eq!(part[100..], file_body()[100..]);
+    }
+
+    #[tokio::test]
+    async fn part_of_another_version_or_url_starts_over() {
+        let part = resume(
+            ETAG_V2,
+            &[0xaa; 100],
+            Some("http://{server}/video.ts"),
+            "\"v1\"",
+        )
+        .await;
+        assert_eq!(part, file_body());
+        let part = resume(
+            ETAG_V2,
+            &[0xaa; 100],
+            Some("http://{server}/other.ts"),
+            ETAG_V2,
+        )
+        .await;
+        assert_eq!(part, file_body());
+        // Parts from before the sidecar existed can't be matched to a file either
+        let part = resume(ETAG_V2, &[0xaa; 100], None, ETAG_V2).await;
+        assert_eq!(part, file_body());
+    }
+
+    #[tokio::test]
+    async fn file_changed_since_the_playlist_fetch_starts_over() {
+        // Recorded and seen as v1, but the server has v2 by the time of the range request
+        let part = resume(
+            "\"v1\"",
+            &[0xaa; 100],
+            Some("http://{server}/video.ts"),
+            "\"v1\"",
+        )
+        .await;
+        assert_eq!(part, file_body());
+    }
+
+    #[tokio::test]
+    async fn oversized_part_starts_over() {
+        let part = resume(
+            ETAG_V2,
+            &[0xaa; 1500],
+            Some("http://{server}/video.ts"),
+            ETAG_V2,
+        )
+        .await;
+        assert_eq!(part, file_body());
+    }
+
+    #[test]
+    fn weak_etags_fall_back_to_last_modified() {
+        let mut headers = HeaderMap::new();
+        headers.insert(ETAG, "W/\"v1\"".parse().unwrap());
+        headers.insert(
+            LAST_MODIFIED,
+            "Wed, 21 Oct 2015 07:28:00 GMT".parse().unwrap(),
+        );
+        assert_eq!(
+            validator(&headers).as_deref(),
+            Some("Wed, 21 Oct 2015 07:28:00 GMT")
+        );
+        headers.insert(ETAG, ETAG_V2.parse().unwrap());
+        assert_eq!(validator(&headers).as_deref(), Some(ETAG_V2));
+    }
+}
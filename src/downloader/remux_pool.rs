@@ -0,0 +1,69 @@
+// Remux worker pool: caps how many CPU-bound FFmpeg remuxes run at once, independently of the
+// network-bound segment downloads, so queued jobs can download while earlier ones remux.
+
+use anyhow::Result;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+
+use super::cancel;
+
+/// Remuxes that run at once unless configured otherwise
+pub const DEFAULT_REMUX_WORKERS: usize = 2;
+
+static LIMIT: AtomicUsize = AtomicUsize::new(DEFAULT_REMUX_WORKERS);
+static ACTIVE: Mutex<usize> = Mutex::new(0);
+static FREED: Notify = Notify::const_new();
+
+/// Sets how many remuxes may run at once (at least one); takes effect for the next acquire.
+pub fn set_workers(workers: usize) {
+    LIMIT.store(workers.max(1), Ordering::Relaxed);
+    // A raised limit lets waiting remuxes start right away
+    FREED.notify_waiters();
+}
+
+/// A running remux; the worker is free again when it drops.
+pub struct RemuxPermit(());
+
+impl Drop for RemuxPermit {
+    fn drop(&mut self) {
+        *ACTIVE.lock().unwrap_or_else(|e| e.into_inner()) -= 1;
+        FREED.notify_waiters();
+    }
+}
+
+fn try_acquire() -> Option<RemuxPermit> {
+    let mut active = ACTIVE.lock().unwrap_or_else(|e| e.into_inner());
+    (*active < LIMIT.load(Ordering::Relaxed)).then(|| {
+        *active += 1;
+        RemuxPermit(())
+    })
+}
+
+/// Waits for a free remux worker, logging once if all are busy.
+pub async fn acquire(
+    send_log: &impl Fn(String),
+    cancel: &CancellationToken,
+) -> Result<RemuxPermit> {
+    let mut logged = false;
+    loop {
+        // Registered before the check, so a release in between is not missed
+        let freed = FREED.notified();
+        if let Some(permit) = try_acquire() {
+            return Ok(permit);
+        }
+        if !logged {
+            send_log(format!(
+                "-> All {} remux worker(s) are busy, waiting for a free one...",
+                LIMIT.load(Ordering::Relaxed)
+            ));
+            logged = true;
+        }
+        cancel::or_cancel(cancel, async {
+            freed.await;
+            Ok(())
+        })
+        .await?;
+    }
+}
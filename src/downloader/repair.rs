@@ -0,0 +1,366 @@
+use anyhow::{Result, anyhow};
+use egui::Context as EguiContext;
+use reqwest::Url;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use super::DownloadMessage;
+use super::cancel;
+use super::captions::{captions_path, run_captions_step};
+use super::content_type::ContentTypeRules;
+use super::error_summary::ErrorSummary;
+use super::hls_parser::{EncryptionInfo, KEY_LEN, Segment, SegmentKey};
+use super::http::build_client;
+use super::loudnorm::run_normalize_step;
+use super::pause::PauseGate;
+use super::probe::needs_reencode;
+use super::remux_pool;
+use super::report::SegmentReport;
+use super::resources::clamp_concurrency;
+use super::segment_io::{
+    ChunkRange, ExternalAudio, PendingKey, RemuxOptions, SegmentDecryption, concatenate_segments,
+    concatenate_with_discontinuities, download_init_sections, download_segments_concurrently,
+    overwrite_chunk, run_ffmpeg_remux,
+};
+use super::stats;
+use super::subtitles::{SubtitleMode, prepare_soft_track, run_subtitle_step};
+use super::workspace::{
+    ConcatIndex, MERGED_TS_FILENAME, ManifestEncryption, SegmentStatus, WorkspaceManifest,
+    init_file_name,
+};
+
+/// Re-downloads only the failed/corrupt segments of a kept workspace, then rebuilds the output.
+pub async fn run_repair_job(
+    workspace_dir: PathBuf,
+    cancel: CancellationToken,
+    sender: mpsc::Sender<DownloadMessage>,
+    ctx: EguiContext,
+) -> Result<()> {
+    let send_log = |msg: String| {
+        sender.try_send(DownloadMessage::Log(msg)).ok();
+        ctx.request_repaint();
+    };
+
+    let _job = stats::global().start_job();
+    let mut manifest = WorkspaceManifest::load(&workspace_dir)?;
+    manifest.refresh_statuses(&workspace_dir);
+    let broken: Vec<Segment> = manifest
+        .segments
+        .iter()
+        .filter(|s| s.status != SegmentStatus::Done)
+        .map(|s| Segment {
+            path: s.url.clone(),
+            index: s.index,
+            sequence: s.sequence.unwrap_or(s.index as u64),
+            duration: s.duration,
+            key: s.key,
+            byte_range: s.byte_range,
+            init: None,
+            discontinuity: s.discontinuity,
+            gap: false,
+            ad: false,
+            program_date_time: None,
+        })
+        .collect();
+    let repaired_files: Vec<String> = manifest
+        .segments
+        .iter()
+        .filter(|s| s.status != SegmentStatus::Done)
+        .map(|s| s.file.clone())
+        .collect();
+
+    send_log(format!(
+        "-> Workspace {}: {} of {} segments need to be re-downloaded.",
+        workspace_dir.display(),
+        broken.len(),
+        manifest.segments.len()
+    ));
+
+    // 1. Re-download the broken segments into the workspace
+    if !broken.is_empty() {
+        let config = &manifest.config;
+        let client = build_client(&config.headers, config.proxy.as_deref())?;
+        let base_url =
+            Url::parse(&config.playlist_url).map_err(|e| anyhow!("Invalid URL: {}", e))?;
+        let mut keys = Vec::with_capacity(manifest.keys.len());
+        for enc in &manifest.keys {
+            let info = manifest_key_info(enc)?;
+            // A key that was never fetched only fails the segments that need it
+            let key = match enc.key_hex.as_deref() {
+                Some(key_hex) => manifest_key(key_hex)?,
+                None => PendingKey::spawn(async {
+                    Err(anyhow!("Workspace manifest has no decryption key"))
+                }),
+            };
+            keys.push(SegmentDecryption { info, key });
+        }
+
+        let total = broken.len();
+        let error_summary = Arc::new(ErrorSummary::default());
+        let result = download_segments_concurrently(
+            &client,
+            &base_url,
+            broken,
+            keys,
+            total,
+            clamp_concurrency(config.concurrency, &send_log),
+            workspace_dir.clone(),
+            error_summary.clone(),
+            Arc::new(ContentTypeRules::new(&config.accepted_content_types)),
+            Arc::new(SegmentReport::default()),
+            &cancel,
+            &PauseGate::default(),
+            sender.clone(),
+            ctx.clone(),
+        )
+        .await;
+
+        if let Some(table) = error_summary.render_table() {
+            sender.send(DownloadMessage::ErrorSummary(table)).await.ok();
+        }
+        manifest.refresh_statuses(&workspace_dir);
+        manifest.save(&workspace_dir)?;
+        result?;
+    }
+
+    if manifest.failed_count() > 0 {
+        return Err(anyhow!(
+            "{} segment(s) are still missing after repair",
+            manifest.failed_count()
+        ));
+    }
+
+    // 2. Patch the repaired chunks into the merged TS, or rebuild it from all segments
+    let merged_path = workspace_dir.join(MERGED_TS_FILENAME);
+    let verify = manifest.config.verify_concat;
+    let patch_plan = if merged_path.is_file() {
+        ConcatIndex::load(&workspace_dir)
+            .ok()
+            .and_then(|index| plan_in_place_patch(&index, &workspace_dir, &repaired_files))
+    } else {
+        None
+    };
+
+    match patch_plan {
+        Some(plan) => {
+            send_log(format!(
+                "-> Patching {} chunk(s) in place in {}...",
+                plan.len(),
+                merged_path.display()
+            ));
+            let patch_path = merged_path.clone();
+            let patch_cancel = cancel.clone();
+            tokio::task::spawn_blocking(move || -> Result<()> {
+                for (segment_path, range) in plan {
+                    cancel::check(&patch_cancel)?;
+                    overwrite_chunk(&patch_path, &segment_path, range, verify)?;
+                }
+                Ok(())
+            })
+            .await
+            .map_err(|e| anyhow!("Patch blocking task failed to join: {}", e))??;
+        }
+        None => {
+            send_log(format!(
+                "-> Concatenating {} segments...",
+                manifest.segments.len()
+            ));
+            // Init sections are tiny; fetch any that went missing from the workspace
+            let missing_init: Vec<usize> = (0..manifest.init_sections.len())
+                .filter(|n| !workspace_dir.join(init_file_name(*n)).is_file())
+                .collect();
+            if !missing_init.is_empty() {
+                let config = &manifest.config;
+                let client = build_client(&config.headers, config.proxy.as_deref())?;
+                let sections = manifest
+                    .init_sections
+                    .iter()
+                    .map(|init| Ok((Url::parse(&init.url)?, init.byte_range)))
+                    .collect::<Result<Vec<_>>>()?;
+                download_init_sections(&client, &sections, &workspace_dir, &cancel).await?;
+            }
+            let segment_paths = manifest.concat_paths(&workspace_dir);
+            let concat_paths = segment_paths.clone();
+            let concat_path = merged_path.clone();
+            let concat_cancel = cancel.clone();
+            let run_starts = manifest.discontinuity_starts();
+            if run_starts.is_empty() {
+                let ranges = tokio::task::spawn_blocking(move || {
+                    concatenate_segments(&concat_paths, &concat_path, verify, &concat_cancel)
+                })
+                .await
+                .map_err(|e| anyhow!("Concatenation blocking task failed to join: {}", e))??;
+                ConcatIndex::new(&segment_paths, &ranges).save(&workspace_dir)?;
+            } else {
+                let work_dir = workspace_dir.clone();
+                tokio::task::spawn_blocking(move || {
+                    concatenate_with_discontinuities(
+                        &concat_paths,
+                        &run_starts,
+                        &concat_path,
+                        &work_dir,
+                        verify,
+                        &concat_cancel,
+                    )
+                })
+                .await
+                .map_err(|e| anyhow!("Concatenation blocking task failed to join: {}", e))??;
+            }
+        }
+    }
+
+    // 3. Produce the final output again
+    let output_path = manifest.output_path.clone();
+    if manifest.config.extract_captions {
+        run_captions_step(
+            merged_path.clone(),
+            captions_path(&output_path),
+            cancel.clone(),
+            sender.clone(),
+            ctx.clone(),
+        )
+        .await?;
+    }
+    let subtitle_client = build_client(&manifest.config.headers, manifest.config.proxy.as_deref())?;
+    let mut options = RemuxOptions {
+        fix_timestamps: manifest.config.fix_timestamps,
+        external_audio: manifest.config.audio_url.clone().map(|url| ExternalAudio {
+            url,
+            headers: manifest.config.headers.clone(),
+            track: manifest.config.audio_track.clone().unwrap_or_default(),
+        }),
+        fragmented_mp4: !manifest.init_sections.is_empty(),
+        subtitles: Vec::new(),
+        // The cut points are relative to segments the manifest no longer places in the playlist
+        trim: None,
+        reencode: false,
+    };
+    if manifest.config.exact_trim && manifest.config.time_start.is_some() {
+        send_log(
+            "⚠️ Warning: The repaired output is not trimmed to the exact start time again."
+                .to_string(),
+        );
+    }
+    if let Some(source) = &manifest.config.subtitle_url
+        && manifest.config.subtitle_mode == SubtitleMode::SoftMux
+        && let Some(track) = prepare_soft_track(
+            &subtitle_client,
+            source,
+            manifest.config.subtitle_track.clone().unwrap_or_default(),
+            &workspace_dir,
+            &send_log,
+            &cancel,
+        )
+        .await?
+    {
+        options.subtitles.push(track);
+    }
+    let is_ts_output = output_path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("ts"));
+    if !is_ts_output {
+        options.reencode = needs_reencode(
+            &merged_path,
+            &manifest.config.output_format,
+            manifest.config.reencode,
+            &send_log,
+            &cancel,
+        )
+        .await?;
+    }
+    if is_ts_output && !options.alters_streams() {
+        tokio::fs::copy(&merged_path, &output_path).await?;
+    } else {
+        send_log(format!(
+            "🚀 Remuxing using FFmpeg to {}...",
+            output_path.display()
+        ));
+        let _permit = remux_pool::acquire(&send_log, &cancel).await?;
+        let ffmpeg_output_path = output_path.clone();
+        let ffmpeg_cancel = cancel.clone();
+        tokio::task::spawn_blocking(move || {
+            run_ffmpeg_remux(
+                &merged_path,
+                &ffmpeg_output_path,
+                &options,
+                &ffmpeg_cancel,
+                |_| {},
+            )
+        })
+        .await
+        .map_err(|e| anyhow!("FFmpeg blocking task failed to join: {}", e))??;
+    }
+
+    if let Some(subtitle_url) = manifest.config.subtitle_url.clone() {
+        run_subtitle_step(
+            &subtitle_client,
+            subtitle_url,
+            manifest.config.headers.clone(),
+            manifest.config.subtitle_mode,
+            output_path.clone(),
+            workspace_dir.clone(),
+            cancel.clone(),
+            sender.clone(),
+            ctx.clone(),
+        )
+        .await?;
+    }
+    if manifest.config.normalize_audio {
+        run_normalize_step(output_path.clone(), cancel, sender.clone(), ctx.clone()).await?;
+    }
+
+    sender.send(DownloadMessage::Progress(1.0)).await.ok();
+    send_log(format!(
+        "✅ Workspace repaired. File saved as: {}",
+        output_path.display()
+    ));
+    Ok(())
+}
+
+/// Maps each repaired segment to its chunk in the merged TS.
+/// Returns `None` when any chunk is unknown or the new segment size differs (full rebuild needed).
+fn plan_in_place_patch(
+    index: &ConcatIndex,
+    workspace_dir: &Path,
+    repaired_files: &[String],
+) -> Option<Vec<(PathBuf, ChunkRange)>> {
+    repaired_files
+        .iter()
+        .map(|file| {
+            let range = index.range_of(file)?;
+            let segment_path = workspace_dir.join(file);
+            let length = std::fs::metadata(&segment_path).ok()?.len();
+            (length == range.length).then_some((segment_path, range))
+        })
+        .collect()
+}
+
+/// The playlist key a manifest entry was recorded from.
+pub(super) fn manifest_key_info(enc: &ManifestEncryption) -> Result<EncryptionInfo> {
+    Ok(EncryptionInfo {
+        key_url: Url::parse(&enc.key_url)?,
+        method: enc.method.clone(),
+        iv_bytes: decode_iv_hex(enc.iv_hex.as_deref())?,
+        attributes: Default::default(),
+    })
+}
+
+/// A key stored in the manifest once it had been fetched.
+pub(super) fn manifest_key(key_hex: &str) -> Result<PendingKey> {
+    Ok(PendingKey::ready(SegmentKey::from_bytes(
+        &hex::decode(key_hex).map_err(|e| anyhow!("Invalid hex in manifest: {}", e))?,
+    )?))
+}
+
+fn decode_iv_hex(value: Option<&str>) -> Result<Option<[u8; KEY_LEN]>> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    let bytes = hex::decode(value).map_err(|e| anyhow!("Invalid hex in manifest: {}", e))?;
+    let array: [u8; KEY_LEN] = bytes
+        .try_into()
+        .map_err(|_| anyhow!("Invalid IV length in manifest"))?;
+    Ok(Some(array))
+}
@@ -0,0 +1,80 @@
+// Per-segment timings of one job, exported as CSV or JSON (e.g. to profile a CDN or back up a
+// complaint to the streaming provider).
+
+use anyhow::Result;
+use serde::Serialize;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// One segment download, successful or not
+#[derive(Debug, Clone, Serialize)]
+pub struct SegmentTiming {
+    pub index: usize,
+    pub url: String,
+    pub host: String,
+    pub bytes: u64,
+    /// Duration of the successful request (headers and body), in milliseconds
+    pub fetch_ms: u64,
+    /// Including failed attempts and retry back-off, in milliseconds
+    pub total_ms: u64,
+    pub retries: usize,
+    /// Final error of a segment that could not be downloaded
+    pub error: Option<String>,
+}
+
+/// Thread-safe collection of the segment timings of a job.
+#[derive(Default)]
+pub struct SegmentReport {
+    rows: Mutex<Vec<SegmentTiming>>,
+}
+
+impl SegmentReport {
+    pub fn record(&self, timing: SegmentTiming) {
+        self.rows.lock().unwrap().push(timing);
+    }
+
+    /// Writes the rows in segment order: JSON for a `.json` path, CSV otherwise. Returns the row count.
+    pub fn write(&self, path: &Path) -> Result<usize> {
+        let mut rows = self.rows.lock().unwrap().clone();
+        rows.sort_by_key(|row| row.index);
+        let is_json = path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("json"));
+        let text = if is_json {
+            serde_json::to_string_pretty(&rows)?
+        } else {
+            render_csv(&rows)
+        };
+        fs::write(path, text)?;
+        Ok(rows.len())
+    }
+}
+
+fn render_csv(rows: &[SegmentTiming]) -> String {
+    let mut csv = String::from("index,url,host,bytes,fetch_ms,total_ms,retries,error\n");
+    for row in rows {
+        let fields = [
+            row.index.to_string(),
+            csv_field(&row.url),
+            csv_field(&row.host),
+            row.bytes.to_string(),
+            row.fetch_ms.to_string(),
+            row.total_ms.to_string(),
+            row.retries.to_string(),
+            csv_field(row.error.as_deref().unwrap_or("")),
+        ];
+        csv.push_str(&fields.join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Quotes a field that contains a separator, quote or line break (RFC 4180).
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,75 @@
+// Guardrails that keep the effective download concurrency within what the machine can handle.
+
+use sysinfo::System;
+
+/// Worst-case memory held by one in-flight segment (response body plus its decrypted copy)
+const ESTIMATED_BYTES_PER_CONNECTION: u64 = 32 * 1024 * 1024;
+/// Share of the currently available memory that segment buffers may use
+const MEMORY_BUDGET_DIVISOR: u64 = 4;
+/// Descriptors kept free for the runtime, GUI, logs and FFmpeg
+const RESERVED_FILE_DESCRIPTORS: u64 = 64;
+/// Each connection holds a socket and a `.part` file open
+const FILE_DESCRIPTORS_PER_CONNECTION: u64 = 2;
+
+/// Clamps the requested concurrency to the available memory and file-descriptor limits,
+/// logging a warning whenever the value is lowered.
+///
+/// The limit is per job: parallel downloads each get their own clamp against the whole
+/// machine, so N parallel jobs may open up to N times as many connections.
+pub fn clamp_concurrency(requested: usize, send_log: &impl Fn(String)) -> usize {
+    let mut effective = requested.max(1);
+
+    if let Some(available) = available_memory_bytes() {
+        let by_memory =
+            (available / MEMORY_BUDGET_DIVISOR / ESTIMATED_BYTES_PER_CONNECTION).max(1) as usize;
+        if by_memory < effective {
+            send_log(format!(
+                "⚠️ Warning: Only {} MiB of memory available, limiting concurrency from {} to {}.",
+                available / (1024 * 1024),
+                effective,
+                by_memory
+            ));
+            effective = by_memory;
+        }
+    }
+
+    if let Some(limit) = file_descriptor_limit() {
+        let by_descriptors = (limit.saturating_sub(RESERVED_FILE_DESCRIPTORS)
+            / FILE_DESCRIPTORS_PER_CONNECTION)
+            .max(1) as usize;
+        if by_descriptors < effective {
+            send_log(format!(
+                "⚠️ Warning: Open file limit is {}, limiting concurrency from {} to {}.",
+                limit, effective, by_descriptors
+            ));
+            effective = by_descriptors;
+        }
+    }
+
+    effective
+}
+
+fn available_memory_bytes() -> Option<u64> {
+    let mut system = System::new();
+    system.refresh_memory();
+    // Some containers report 0 when the value cannot be determined
+    Some(system.available_memory()).filter(|&bytes| bytes > 0)
+}
+
+#[cfg(unix)]
+#[allow(clippy::unnecessary_cast)] // rlim_t is not u64 on every unix
+fn file_descriptor_limit() -> Option<u64> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    // SAFETY: getrlimit only writes into the provided struct
+    let result = unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) };
+    (result == 0 && limit.rlim_cur != libc::RLIM_INFINITY).then_some(limit.rlim_cur as u64)
+}
+
+#[cfg(not(unix))]
+fn file_descriptor_limit() -> Option<u64> {
+    // Windows has no small per-process handle limit comparable to RLIMIT_NOFILE
+    None
+}
@@ -0,0 +1,89 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Lower/upper bounds of the job-wide retry budget
+const MIN_RETRY_BUDGET: usize = 20;
+const MAX_RETRY_BUDGET: usize = 200;
+/// Budget as a share of the segment count (10%)
+const RETRY_BUDGET_DIVISOR: usize = 10;
+
+/// Caps the total number of segment retries in one job, so a flaky origin fails the job fast
+/// instead of retrying every segment up to `MAX_RETRIES` times.
+pub struct RetryBudget {
+    limit: usize,
+    used: AtomicUsize,
+}
+
+impl RetryBudget {
+    /// 10% of the segment count, clamped to 20..=200 retries.
+    pub fn for_segments(segment_count: usize) -> Self {
+        Self {
+            limit: (segment_count / RETRY_BUDGET_DIVISOR).clamp(MIN_RETRY_BUDGET, MAX_RETRY_BUDGET),
+            used: AtomicUsize::new(0),
+        }
+    }
+
+    /// Takes one retry from the budget; returns false once it is used up.
+    pub fn try_consume(&self) -> bool {
+        self.used
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |used| {
+                (used < self.limit).then_some(used + 1)
+            })
+            .is_ok()
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.used.load(Ordering::SeqCst) >= self.limit
+    }
+
+    pub fn used(&self) -> usize {
+        self.used.load(Ordering::SeqCst)
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn budget_scales_with_segments_within_bounds() {
+        assert_eq!(RetryBudget::for_segments(0).limit(), MIN_RETRY_BUDGET);
+        assert_eq!(RetryBudget::for_segments(50).limit(), MIN_RETRY_BUDGET);
+        assert_eq!(RetryBudget::for_segments(500).limit(), 50);
+        assert_eq!(RetryBudget::for_segments(100_000).limit(), MAX_RETRY_BUDGET);
+    }
+
+    #[test]
+    fn consuming_stops_at_the_limit() {
+        let budget = RetryBudget::for_segments(0);
+        for _ in 0..MIN_RETRY_BUDGET {
+            assert!(!budget.is_exhausted());
+            assert!(budget.try_consume());
+        }
+        assert!(budget.is_exhausted());
+        assert!(!budget.try_consume());
+        assert_eq!(budget.used(), MIN_RETRY_BUDGET);
+    }
+
+    #[test]
+    fn parallel_segments_share_one_budget() {
+        let budget = RetryBudget::for_segments(1_000);
+        let granted = std::sync::atomic::AtomicUsize::new(0);
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..50 {
+                        if budget.try_consume() {
+                            granted.fetch_add(1, Ordering::SeqCst);
+                        }
+                    }
+                });
+            }
+        });
+        assert_eq!(granted.load(Ordering::SeqCst), 100);
+        assert_eq!(budget.used(), 100);
+    }
+}
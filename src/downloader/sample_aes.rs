@@ -0,0 +1,528 @@
+// SAMPLE-AES decryption of MPEG-TS segments ("MPEG-2 Stream Encryption Format for HTTP Live
+// Streaming"): only the media samples are encrypted, so the TS/PES framing is kept and the
+// elementary streams are decrypted PES by PES.
+
+use aes::Aes128;
+use aes::cipher::{BlockDecryptMut, KeyIvInit};
+use anyhow::{Result, anyhow};
+use cbc::Decryptor;
+use std::collections::HashMap;
+
+use super::hls_parser::KEY_LEN;
+
+type Aes128CbcDec = Decryptor<Aes128>;
+
+const TS_PACKET_LEN: usize = 188;
+const TS_SYNC_BYTE: u8 = 0x47;
+const PAT_PID: u16 = 0;
+const BLOCK_LEN: usize = 16;
+/// H.264: clear bytes at the start of an encrypted NAL unit (header + 31 bytes)
+const NAL_CLEAR_LEADER: usize = 32;
+/// H.264: slice NAL units up to this size are not encrypted
+const NAL_MIN_ENCRYPTED_LEN: usize = 48;
+/// H.264: clear bytes between two encrypted blocks (1:9 pattern)
+const NAL_CLEAR_STRIDE: usize = 144;
+/// AAC: clear bytes after the ADTS header
+const ADTS_CLEAR_LEADER: usize = 16;
+
+/// Codec of an elementary stream, from its PMT stream type
+#[derive(Clone, Copy, PartialEq)]
+enum Codec {
+    H264,
+    Aac,
+}
+
+/// Codec and clear stream type for the PMT stream types SAMPLE-AES streams use; `Err` names
+/// encrypted codecs that cannot be decrypted here.
+fn classify_stream_type(stream_type: u8) -> Option<std::result::Result<(Codec, u8), &'static str>> {
+    match stream_type {
+        0x1b | 0xdb => Some(Ok((Codec::H264, 0x1b))),
+        0x0f | 0xcf => Some(Ok((Codec::Aac, 0x0f))),
+        0xc1 => Some(Err("AC-3")),
+        0xc2 => Some(Err("E-AC-3")),
+        _ => None,
+    }
+}
+
+/// A decrypted segment and the encrypted codecs that were passed through as they are
+pub struct SampleAesOutput {
+    pub data: Vec<u8>,
+    pub unsupported: Vec<&'static str>,
+}
+
+/// Decrypts the H.264 and AAC samples of a SAMPLE-AES MPEG-TS segment.
+///
+/// The PMT is rewritten to the clear stream types so FFmpeg reads the result like any other TS.
+pub fn decrypt_ts_segment(
+    key: &[u8; KEY_LEN],
+    iv: &[u8; KEY_LEN],
+    data: &[u8],
+) -> Result<SampleAesOutput> {
+    if data.first() != Some(&TS_SYNC_BYTE) {
+        return Err(anyhow!(
+            "SAMPLE-AES is only supported for MPEG-TS segments (fMP4 uses CENC/cbcs)"
+        ));
+    }
+    let mut out = data.to_vec();
+    let packet_count = data.len() / TS_PACKET_LEN;
+
+    // 1. PAT -> PMT PIDs -> elementary streams
+    let packet = |n: usize| &data[n * TS_PACKET_LEN..(n + 1) * TS_PACKET_LEN];
+    let mut pmt_pids = Vec::new();
+    let mut streams: HashMap<u16, Codec> = HashMap::new();
+    let mut unsupported = Vec::new();
+    for n in 0..packet_count {
+        let Some(ts) = TsPacket::parse(packet(n)) else {
+            continue;
+        };
+        if ts.pid == PAT_PID && ts.unit_start {
+            pmt_pids = psi_section(ts.payload)
+                .map(pat_program_pids)
+                .unwrap_or_default();
+        } else if pmt_pids.contains(&ts.pid) && ts.unit_start {
+            let Some(section) = psi_section(ts.payload) else {
+                continue;
+            };
+            for (pid, stream_type, _) in pmt_streams(section) {
+                match classify_stream_type(stream_type) {
+                    Some(Ok((codec, _))) => {
+                        streams.insert(pid, codec);
+                    }
+                    Some(Err(name)) if !unsupported.contains(&name) => unsupported.push(name),
+                    _ => {}
+                }
+            }
+            rewrite_pmt(&mut out[n * TS_PACKET_LEN..(n + 1) * TS_PACKET_LEN]);
+        }
+    }
+
+    // 2. Reassemble each PES from its packets, decrypt it and write it back into the same slots
+    let mut pending: HashMap<u16, Vec<usize>> = HashMap::new();
+    for n in 0..packet_count {
+        let Some(ts) = TsPacket::parse(packet(n)) else {
+            continue;
+        };
+        let Some(&codec) = streams.get(&ts.pid) else {
+            continue;
+        };
+        if ts.payload.is_empty() {
+            continue;
+        }
+        if ts.unit_start
+            && let Some(slots) = pending.remove(&ts.pid)
+        {
+            rewrite_pes(&mut out, &slots, codec, key, iv)?;
+        }
+        pending.entry(ts.pid).or_default().push(n);
+    }
+    for (pid, slots) in pending {
+        rewrite_pes(&mut out, &slots, streams[&pid], key, iv)?;
+    }
+
+    Ok(SampleAesOutput {
+        data: out,
+        unsupported,
+    })
+}
+
+/// Header fields of one 188-byte TS packet
+struct TsPacket<'a> {
+    pid: u16,
+    unit_start: bool,
+    /// Length of the adaptation field including its length byte (0 if absent)
+    adaptation_len: usize,
+    payload: &'a [u8],
+}
+
+impl<'a> TsPacket<'a> {
+    fn parse(packet: &'a [u8]) -> Option<Self> {
+        if packet.len() != TS_PACKET_LEN || packet[0] != TS_SYNC_BYTE {
+            return None;
+        }
+        let control = (packet[3] >> 4) & 0b11;
+        let adaptation_len = if control & 0b10 != 0 {
+            1 + packet[4] as usize
+        } else {
+            0
+        };
+        let payload_start = (4 + adaptation_len).min(TS_PACKET_LEN);
+        Some(Self {
+            pid: u16::from_be_bytes([packet[1] & 0x1f, packet[2]]),
+            unit_start: packet[1] & 0x40 != 0,
+            adaptation_len,
+            payload: if control & 0b01 != 0 {
+                &packet[payload_start..]
+            } else {
+                &[]
+            },
+        })
+    }
+}
+
+/// The PSI section at the start of a payload (after the pointer field), if it is complete
+fn psi_section(payload: &[u8]) -> Option<&[u8]> {
+    let start = 1 + *payload.first()? as usize;
+    let header = payload.get(start..start + 3)?;
+    let section_len = (u16::from_be_bytes([header[1], header[2]]) & 0x0fff) as usize;
+    payload.get(start..start + 3 + section_len)
+}
+
+/// PMT PIDs listed in a PAT section (network PID entries excluded)
+fn pat_program_pids(section: &[u8]) -> Vec<u16> {
+    let entries = section
+        .get(8..section.len().saturating_sub(4))
+        .unwrap_or(&[]);
+    entries
+        .chunks_exact(4)
+        .filter(|entry| u16::from_be_bytes([entry[0], entry[1]]) != 0)
+        .map(|entry| u16::from_be_bytes([entry[2] & 0x1f, entry[3]]))
+        .collect()
+}
+
+/// `(pid, stream_type, offset of the stream_type byte)` of every stream in a PMT section
+fn pmt_streams(section: &[u8]) -> Vec<(u16, u8, usize)> {
+    let mut streams = Vec::new();
+    let Some(info_len) = section.get(10..12) else {
+        return streams;
+    };
+    let mut pos = 12 + (u16::from_be_bytes([info_len[0], info_len[1]]) & 0x0fff) as usize;
+    let end = section.len().saturating_sub(4);
+    while pos + 5 <= end {
+        let pid = u16::from_be_bytes([section[pos + 1] & 0x1f, section[pos + 2]]);
+        let es_info_len =
+            (u16::from_be_bytes([section[pos + 3], section[pos + 4]]) & 0x0fff) as usize;
+        streams.push((pid, section[pos], pos));
+        pos += 5 + es_info_len;
+    }
+    streams
+}
+
+/// Replaces the encrypted stream types of the PMT in `packet` and updates the section CRC.
+fn rewrite_pmt(packet: &mut [u8]) {
+    let Some(ts) = TsPacket::parse(packet) else {
+        return;
+    };
+    let payload_start = 4 + ts.adaptation_len;
+    let section_start = payload_start + 1 + ts.payload[0] as usize;
+    let Some(section) = psi_section(ts.payload) else {
+        // Sections spanning several packets are left as they are
+        return;
+    };
+    let section_len = section.len();
+    let mut changed = false;
+    for (_, stream_type, offset) in pmt_streams(section) {
+        if let Some(Ok((_, clear))) = classify_stream_type(stream_type)
+            && clear != stream_type
+        {
+            packet[section_start + offset] = clear;
+            changed = true;
+        }
+    }
+    if changed {
+        let crc_at = section_start + section_len - 4;
+        let crc = crc32_mpeg2(&packet[section_start..crc_at]);
+        packet[crc_at..crc_at + 4].copy_from_slice(&crc.to_be_bytes());
+    }
+}
+
+/// CRC-32/MPEG-2 of a PSI section
+fn crc32_mpeg2(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= (byte as u32) << 24;
+        for _ in 0..8 {
+            crc = if crc & 0x8000_0000 != 0 {
+                (crc << 1) ^ 0x04c1_1db7
+            } else {
+                crc << 1
+            };
+        }
+    }
+    crc
+}
+
+/// Decrypts the PES carried by the packets `slots` of `out` and writes it back into them.
+///
+/// H.264 units lose their emulation prevention bytes, so the PES can shrink; the missing bytes
+/// become adaptation field stuffing at the end of the PES.
+fn rewrite_pes(
+    out: &mut [u8],
+    slots: &[usize],
+    codec: Codec,
+    key: &[u8; KEY_LEN],
+    iv: &[u8; KEY_LEN],
+) -> Result<()> {
+    let read_packet =
+        |out: &[u8], n: usize| out[n * TS_PACKET_LEN..(n + 1) * TS_PACKET_LEN].to_vec();
+    let mut capacities = Vec::with_capacity(slots.len());
+    let mut pes = Vec::new();
+    for &n in slots {
+        let packet = read_packet(out, n);
+        let ts = TsPacket::parse(&packet).ok_or_else(|| anyhow!("Broken TS packet {}", n))?;
+        capacities.push(ts.payload.len());
+        pes.extend_from_slice(ts.payload);
+    }
+
+    // PES header: start code, stream id, length, flags, header data length
+    if pes.len() < 9 || pes[..3] != [0, 0, 1] {
+        return Ok(());
+    }
+    let header_len = 9 + pes[8] as usize;
+    if header_len > pes.len() {
+        return Ok(());
+    }
+    let es = match codec {
+        Codec::H264 => decrypt_h264(&pes[header_len..], key, iv),
+        Codec::Aac => decrypt_adts(&pes[header_len..], key, iv),
+    };
+    let removed = pes.len() - header_len - es.len();
+    pes.truncate(header_len);
+    pes.extend_from_slice(&es);
+    let declared_len = u16::from_be_bytes([pes[4], pes[5]]) as usize;
+    if declared_len != 0 {
+        pes[4..6].copy_from_slice(&(declared_len.saturating_sub(removed) as u16).to_be_bytes());
+    }
+
+    // Shrink the last packets, keeping at least one payload byte in each
+    let mut deficit = removed;
+    for capacity in capacities.iter_mut().rev() {
+        let cut = deficit.min(capacity.saturating_sub(1));
+        *capacity -= cut;
+        deficit -= cut;
+    }
+    if deficit > 0 {
+        return Err(anyhow!("PES too short to absorb {} removed bytes", removed));
+    }
+
+    let mut pes = &pes[..];
+    for (&n, &capacity) in slots.iter().zip(&capacities) {
+        let (chunk, rest) = pes.split_at(capacity);
+        pes = rest;
+        let rebuilt = rebuild_packet(&read_packet(out, n), chunk);
+        out[n * TS_PACKET_LEN..(n + 1) * TS_PACKET_LEN].copy_from_slice(&rebuilt);
+    }
+    Ok(())
+}
+
+/// `packet` with its payload replaced by `payload`, padding the rest with adaptation stuffing.
+fn rebuild_packet(packet: &[u8], payload: &[u8]) -> Vec<u8> {
+    let had_adaptation = packet[3] & 0x20 != 0;
+    let mut adaptation = if had_adaptation {
+        packet[5..5 + packet[4] as usize].to_vec()
+    } else {
+        Vec::new()
+    };
+    let adaptation_total = if had_adaptation {
+        1 + adaptation.len()
+    } else {
+        0
+    };
+    let stuffing = TS_PACKET_LEN - 4 - adaptation_total - payload.len();
+
+    let mut rebuilt = packet[..4].to_vec();
+    if stuffing > 0 {
+        let content_len = adaptation_total + stuffing - 1;
+        if content_len > 0 && adaptation.is_empty() {
+            // Adaptation field flags: none set
+            adaptation.push(0x00);
+        }
+        adaptation.resize(content_len, 0xff);
+        rebuilt[3] |= 0x20;
+    }
+    if rebuilt[3] & 0x20 != 0 {
+        rebuilt.push(adaptation.len() as u8);
+        rebuilt.extend_from_slice(&adaptation);
+    }
+    rebuilt.extend_from_slice(payload);
+    rebuilt
+}
+
+/// Decrypts the slice NAL units of an H.264 Annex B stream (1 of every 10 blocks after the
+/// 32-byte leader, CBC restarting per NAL unit); the decrypted units carry no extra escaping.
+fn decrypt_h264(es: &[u8], key: &[u8; KEY_LEN], iv: &[u8; KEY_LEN]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(es.len());
+    let starts = nal_unit_starts(es);
+    out.extend_from_slice(&es[..starts.first().copied().unwrap_or(es.len())]);
+    for (i, &start) in starts.iter().enumerate() {
+        let next = starts.get(i + 1).map_or(es.len(), |&next| next - 3);
+        // Zero bytes before the next start code belong to it (4-byte start codes)
+        let mut end = next;
+        while end > start && es[end - 1] == 0 {
+            end -= 1;
+        }
+        let nal = &es[start..end];
+        let nal_type = nal.first().map_or(0, |header| header & 0x1f);
+        if matches!(nal_type, 1 | 5) && nal.len() > NAL_MIN_ENCRYPTED_LEN {
+            let mut unit = remove_emulation_prevention(nal);
+            let mut cipher = Aes128CbcDec::new(key.into(), iv.into());
+            let mut pos = NAL_CLEAR_LEADER;
+            while pos < unit.len() {
+                if unit.len() - pos > BLOCK_LEN {
+                    cipher.decrypt_block_mut((&mut unit[pos..pos + BLOCK_LEN]).into());
+                    pos += BLOCK_LEN;
+                }
+                pos += NAL_CLEAR_STRIDE.min(unit.len() - pos);
+            }
+            out.append(&mut unit);
+        } else {
+            out.extend_from_slice(nal);
+        }
+        // Trailing zeros and the next start code
+        let gap_end = starts.get(i + 1).copied().unwrap_or(es.len());
+        out.extend_from_slice(&es[end..gap_end]);
+    }
+    out
+}
+
+/// Offsets of the first byte after every `00 00 01` start code
+fn nal_unit_starts(es: &[u8]) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut i = 0;
+    while i + 3 <= es.len() {
+        if es[i] == 0 && es[i + 1] == 0 && es[i + 2] == 1 {
+            starts.push(i + 3);
+            i += 3;
+        } else {
+            i += 1;
+        }
+    }
+    starts
+}
+
+/// NAL unit bytes with every `00 00 03` emulation prevention byte removed
+fn remove_emulation_prevention(nal: &[u8]) -> Vec<u8> {
+    let mut unit = Vec::with_capacity(nal.len());
+    let mut zeros = 0;
+    for &byte in nal {
+        if zeros >= 2 && byte == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        unit.push(byte);
+    }
+    unit
+}
+
+/// Decrypts the AAC frames of an ADTS stream (every whole block after the 16-byte leader, CBC
+/// restarting per frame); the length does not change.
+fn decrypt_adts(es: &[u8], key: &[u8; KEY_LEN], iv: &[u8; KEY_LEN]) -> Vec<u8> {
+    let mut out = es.to_vec();
+    let mut pos = 0;
+    while pos + 7 <= out.len() && out[pos] == 0xff && out[pos + 1] & 0xf0 == 0xf0 {
+        let header_len = if out[pos + 1] & 0x01 != 0 { 7 } else { 9 };
+        let frame_len = ((out[pos + 3] as usize & 0x03) << 11)
+            | ((out[pos + 4] as usize) << 3)
+            | (out[pos + 5] as usize >> 5);
+        if frame_len < header_len || pos + frame_len > out.len() {
+            break;
+        }
+        let encrypted_start = pos + header_len + ADTS_CLEAR_LEADER;
+        let frame_end = pos + frame_len;
+        if encrypted_start < frame_end {
+            let blocks = (frame_end - encrypted_start) / BLOCK_LEN;
+            let mut cipher = Aes128CbcDec::new(key.into(), iv.into());
+            for block in out[encrypted_start..encrypted_start + blocks * BLOCK_LEN]
+                .chunks_exact_mut(BLOCK_LEN)
+            {
+                cipher.decrypt_block_mut(block.into());
+            }
+        }
+        pos = frame_end;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // NIST SP 800-38A, F.2.1 CBC-AES128 (first block)
+    const KEY: [u8; 16] = [
+        0x2b, 0x7e, 0x15, 0x16, 0x28, 0xae, 0xd2, 0xa6, 0xab, 0xf7, 0x15, 0x88, 0x09, 0xcf, 0x4f,
+        0x3c,
+    ];
+    const IV: [u8; 16] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f,
+    ];
+    const PLAIN: [u8; 16] = [
+        0x6b, 0xc1, 0xbe, 0xe2, 0x2e, 0x40, 0x9f, 0x96, 0xe9, 0x3d, 0x7e, 0x11, 0x73, 0x93, 0x17,
+        0x2a,
+    ];
+    const CIPHER: [u8; 16] = [
+        0x76, 0x49, 0xab, 0xac, 0x81, 0x19, 0xb2, 0x46, 0xce, 0xe9, 0x8e, 0x9b, 0x12, 0xe9, 0x19,
+        0x7d,
+    ];
+
+    #[test]
+    fn h264_slice_decrypts_one_block_after_the_leader() {
+        // IDR slice: header + 31 clear bytes (with an emulation prevention byte), one
+        // encrypted block, then a 10-byte tail shorter than a block that stays clear
+        let mut leader = vec![0x65, 0x88, 0x00, 0x00, 0x03, 0x01];
+        leader.resize(NAL_CLEAR_LEADER + 1, 0x42);
+        let tail = [0x5a; 10];
+        let mut es = vec![0x00, 0x00, 0x00, 0x01];
+        es.extend_from_slice(&leader);
+        es.extend_from_slice(&CIPHER);
+        es.extend_from_slice(&tail);
+
+        let mut expected = vec![0x00, 0x00, 0x00, 0x01, 0x65, 0x88, 0x00, 0x00, 0x01];
+        expected.resize(4 + NAL_CLEAR_LEADER, 0x42);
+        expected.extend_from_slice(&PLAIN);
+        expected.extend_from_slice(&tail);
+        assert_eq!(decrypt_h264(&es, &KEY, &IV), expected);
+    }
+
+    #[test]
+    fn h264_non_slice_units_pass_through() {
+        let mut es = vec![0x00, 0x00, 0x01, 0x67];
+        es.extend_from_slice(&[0x00, 0x00, 0x03, 0x01]);
+        es.extend_from_slice(&CIPHER);
+        es.extend_from_slice(&[0x11; 40]);
+        // Parameter sets are never encrypted and keep their escaping
+        assert_eq!(decrypt_h264(&es, &KEY, &IV), es);
+    }
+
+    #[test]
+    fn emulation_prevention_bytes_are_removed() {
+        assert_eq!(
+            remove_emulation_prevention(&[0x00, 0x00, 0x03, 0x00, 0x00, 0x03, 0x03]),
+            [0x00, 0x00, 0x00, 0x00, 0x03]
+        );
+        assert_eq!(
+            remove_emulation_prevention(&[0x00, 0x03, 0x01]),
+            [0x00, 0x03, 0x01]
+        );
+    }
+
+    #[test]
+    fn adts_frame_decrypts_whole_blocks_and_keeps_the_tail() {
+        let tail = [0x77; 5];
+        let frame_len = 7 + ADTS_CLEAR_LEADER + BLOCK_LEN + tail.len();
+        let header = [
+            0xff,
+            0xf1,
+            0x50,
+            0x80 | (frame_len >> 11) as u8,
+            (frame_len >> 3) as u8,
+            ((frame_len & 0x07) << 5) as u8 | 0x1f,
+            0xfc,
+        ];
+        let mut frame = header.to_vec();
+        frame.extend_from_slice(&[0x21; ADTS_CLEAR_LEADER]);
+        frame.extend_from_slice(&CIPHER);
+        frame.extend_from_slice(&tail);
+
+        let mut expected = header.to_vec();
+        expected.extend_from_slice(&[0x21; ADTS_CLEAR_LEADER]);
+        expected.extend_from_slice(&PLAIN);
+        expected.extend_from_slice(&tail);
+        assert_eq!(decrypt_adts(&frame, &KEY, &IV), expected);
+    }
+
+    #[test]
+    fn pmt_crc_is_crc32_mpeg2() {
+        assert_eq!(crc32_mpeg2(b"123456789"), 0x0376_e6e7);
+    }
+}
@@ -1,48 +1,148 @@
 use anyhow::{Result, anyhow};
 use egui::Context as EguiContext;
 use futures::FutureExt; // For FutureExt::map on JoinHandle
+use futures::future::{BoxFuture, Shared};
 use futures::stream::{self, StreamExt};
 use reqwest::{Client, StatusCode, Url};
-use std::fs::File;
-use std::io::{self};
+use std::ffi::OsStr;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
 use std::sync::Arc;
-use std::sync::atomic::AtomicUsize;
-use std::time::Duration;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use tokio::io::AsyncWriteExt;
 use tokio::sync::Mutex;
 use tokio::sync::mpsc;
 use tokio::time::sleep;
+use tokio_util::sync::CancellationToken;
 
 // 引入解密和 HLS 相關類型
 use super::DownloadMessage;
-use super::hls_parser::{EncryptionInfo, KEY_LEN, MAX_RETRIES, Segment};
+use super::cancel::{self, Cancelled};
+use super::content_type::ContentTypeRules;
+use super::error_summary::{ErrorKind, ErrorSummary};
+use super::failure::{self, FailureKind};
+use super::hls_parser::{
+    ByteRange, EncryptionInfo, KEY_LEN, MAX_RETRIES, Segment, SegmentKey, TrackMetadata, iso639_2,
+};
+use super::network::ConnectivityMonitor;
+use super::pause::PauseGate;
+use super::report::{SegmentReport, SegmentTiming};
+use super::retry_budget::RetryBudget;
+use super::sample_aes;
+use super::signed_url;
+use super::stats;
+use super::subtitles::text_codec;
+use super::workspace::{init_file_name, segment_file_name};
 use crate::downloader::ffmpeg_embed::FFmpegHandle;
 
 // Decryption imports
-use aes::Aes128;
 use aes::cipher::{BlockDecryptMut, KeyIvInit};
-use block_padding::Pkcs7;
+use aes::{Aes128, Aes256};
+use block_padding::{Pkcs7, UnpadError};
 use cbc::Decryptor;
 
 type Aes128CbcDec = Decryptor<Aes128>;
+type Aes256CbcDec = Decryptor<Aes256>;
+
+/// Bytes of a rejected segment response quoted in the log
+const BODY_PREVIEW_LEN: usize = 80;
+
+/// AES-CBC decryption with PKCS#7 padding, using the cipher that matches the key length.
+fn decrypt_cbc(
+    key: &SegmentKey,
+    iv: &[u8; KEY_LEN],
+    data: &[u8],
+) -> std::result::Result<Vec<u8>, UnpadError> {
+    match key {
+        SegmentKey::Aes128(key) => {
+            Aes128CbcDec::new(key.into(), iv.into()).decrypt_padded_vec_mut::<Pkcs7>(data)
+        }
+        SegmentKey::Aes256(key) => {
+            Aes256CbcDec::new(key.into(), iv.into()).decrypt_padded_vec_mut::<Pkcs7>(data)
+        }
+    }
+}
+
+/// A decryption key that may still be downloading.
+///
+/// The key fetch runs as its own task, so segment downloads can start right away; a segment only
+/// waits for the key when it actually needs to decrypt.
+#[derive(Clone)]
+pub struct PendingKey(
+    Shared<BoxFuture<'static, std::result::Result<SegmentKey, (FailureKind, String)>>>,
+);
+
+impl PendingKey {
+    pub fn spawn(fetch: impl Future<Output = Result<SegmentKey>> + Send + 'static) -> Self {
+        let handle = tokio::spawn(fetch);
+        Self(
+            handle
+                .map(|join_result| match join_result {
+                    Ok(result) => result.map_err(|e| (FailureKind::of(&e), e.to_string())),
+                    Err(e) => Err((
+                        FailureKind::Other,
+                        format!("Key task failed to join: {}", e),
+                    )),
+                })
+                .boxed()
+                .shared(),
+        )
+    }
+
+    pub fn ready(key: SegmentKey) -> Self {
+        Self(futures::future::ready(Ok(key)).boxed().shared())
+    }
+
+    /// The key; a fetch that failed on the network stays a network failure, anything else
+    /// (a bad key file, a missing local key) is a decryption failure.
+    pub async fn get(&self) -> Result<SegmentKey> {
+        self.0.clone().await.map_err(|(kind, e)| match kind {
+            FailureKind::Network => failure::network(format!("Key download failed: {}", e)),
+            _ => failure::decrypt(format!("Key download failed: {}", e)),
+        })
+    }
+}
+
+/// One `#EXT-X-KEY` of the playlist together with its (possibly still downloading) key
+#[derive(Clone)]
+pub struct SegmentDecryption {
+    pub info: EncryptionInfo,
+    pub key: PendingKey,
+}
 
 /// Concurrently downloads all segments and returns their temporary paths, updating progress via MPSC.
+#[allow(clippy::too_many_arguments)]
 pub async fn download_segments_concurrently(
+    client: &Client,
     base_url: &Url,
     segments: Vec<Segment>,
-    encryption_info: Option<EncryptionInfo>,
-    key_bytes: Option<[u8; KEY_LEN]>,
+    keys: Vec<SegmentDecryption>,
     total_segments: usize,
     max_concurrent_downloads: usize,
     temp_dir_path: PathBuf,
+    error_summary: Arc<ErrorSummary>,
+    content_types: Arc<ContentTypeRules>,
+    report: Arc<SegmentReport>,
+    cancel: &CancellationToken,
+    pause: &PauseGate,
     sender: mpsc::Sender<DownloadMessage>,
     ctx: EguiContext,
 ) -> Result<Vec<PathBuf>> {
-    let client = Client::new();
     let completed_counter = Arc::new(AtomicUsize::new(0));
 
+    // Shared connectivity state: pauses all segment tasks while the network is down
+    let connectivity = Arc::new(ConnectivityMonitor::new({
+        let sender = sender.clone();
+        let ctx = ctx.clone();
+        move |msg| {
+            sender.try_send(DownloadMessage::Log(msg)).ok();
+            ctx.request_repaint();
+        }
+    }));
+
     // 使用 tokio::sync::Mutex 解決跨 .await 持有鎖的問題
     let last_progress_log = Arc::new(Mutex::new(String::new()));
 
@@ -65,12 +165,8 @@ pub async fn download_segments_concurrently(
 
                 sender.send(DownloadMessage::Progress(progress)).await.ok();
 
-                let progress_msg = format!(
-                    "📦 Segment progress: {}/{} ({:.2}%)",
-                    current as usize,
-                    total_segments,
-                    (current / total_segments_f) * 100.0
-                );
+                let progress_msg =
+                    DownloadMessage::segment_progress_line(current as usize, total_segments);
 
                 // 實作去重邏輯
                 let mut last_log_guard = last_progress_log_clone.lock().await;
@@ -78,7 +174,10 @@ pub async fn download_segments_concurrently(
                 // 只有當新訊息與上次發送的訊息不同時，才發送並更新紀錄
                 if *last_log_guard != progress_msg {
                     sender
-                        .send(DownloadMessage::Log(progress_msg.clone()))
+                        .send(DownloadMessage::SegmentProgress {
+                            completed: current as usize,
+                            total: total_segments,
+                        })
                         .await
                         .ok();
                     *last_log_guard = progress_msg;
@@ -89,32 +188,86 @@ pub async fn download_segments_concurrently(
         }
     });
 
+    // Retries are shared across all segments of this job
+    let retry_budget = Arc::new(RetryBudget::for_segments(total_segments));
+    // Undecryptable SAMPLE-AES codecs are reported once, by whichever segment finds them first
+    let unsupported_warned = Arc::new(AtomicBool::new(false));
+
     // 2. Concurrent Download Logic
     let results: Vec<std::result::Result<PathBuf, anyhow::Error>> = stream::iter(segments)
         .map(|segment| {
             let client = client.clone();
             let base_url = base_url.clone();
             let completed_counter_clone = completed_counter.clone();
-            let key_bytes_clone = key_bytes.clone();
-            let encryption_info_clone = encryption_info.clone();
+            // Clear segments (no key, or after METHOD=NONE) never wait for a key
+            let segment_key = segment.key.and_then(|key| keys.get(key).cloned());
             let temp_dir_path_clone = temp_dir_path.clone();
+            let error_summary_clone = error_summary.clone();
+            let content_types_clone = content_types.clone();
+            let report_clone = report.clone();
+            let connectivity_clone = connectivity.clone();
+            let cancel_clone = cancel.clone();
+            let pause_clone = pause.clone();
+            let retry_budget_clone = retry_budget.clone();
+            let unsupported_warned_clone = unsupported_warned.clone();
+            let warning_sender = sender.clone();
             let segment_url = base_url.join(&segment.path).unwrap();
             let segment_index = segment.index;
+            let segment_sequence = segment.sequence;
+            let segment_range = segment.byte_range;
 
             tokio::spawn(async move {
-                let temp_filename = format!("temp_segment_{:08}.ts", segment_index);
+                // Fail fast: don't start further segments once the budget is used up
+                if retry_budget_clone.is_exhausted() {
+                    return Err(failure::network(format!(
+                        "Segment {} skipped, job retry budget exhausted",
+                        segment_index
+                    )));
+                }
+                // While the job is paused no new segment starts; those in flight finish
+                pause_clone.wait(&cancel_clone).await?;
+                let temp_filename = segment_file_name(segment_index);
                 let temp_path = temp_dir_path_clone.join(&temp_filename);
 
-                // Download segment
-                download_and_process_segment(
-                    client,
-                    segment_url.as_str(),
-                    &temp_path,
-                    segment_index,
-                    key_bytes_clone,
-                    encryption_info_clone,
+                // Download segment (also abandons retry back-offs and offline waits on cancel)
+                let started = Instant::now();
+                let mut retries = 0;
+                let result = cancel::or_cancel(
+                    &cancel_clone,
+                    download_and_process_segment(
+                        client,
+                        &segment_url,
+                        &temp_path,
+                        segment_index,
+                        segment_sequence,
+                        segment_range,
+                        segment_key,
+                        &error_summary_clone,
+                        &content_types_clone,
+                        &connectivity_clone,
+                        &retry_budget_clone,
+                        &unsupported_warned_clone,
+                        &warning_sender,
+                        &mut retries,
+                    ),
                 )
-                .await?;
+                .await;
+                let (bytes, fetch_time, error) = match &result {
+                    Ok((bytes, fetch_time)) => (*bytes as u64, *fetch_time, None),
+                    Err(e) if e.is::<Cancelled>() => return Err(Cancelled.into()),
+                    Err(e) => (0, Duration::ZERO, Some(e.to_string())),
+                };
+                report_clone.record(SegmentTiming {
+                    index: segment_index,
+                    url: segment_url.to_string(),
+                    host: stats::host_of(&segment_url),
+                    bytes,
+                    fetch_ms: fetch_time.as_millis() as u64,
+                    total_ms: started.elapsed().as_millis() as u64,
+                    retries,
+                    error,
+                });
+                result?;
 
                 // Update segment counter
                 let _ =
@@ -136,6 +289,14 @@ pub async fn download_segments_concurrently(
     // Stop the progress update task
     progress_handle.abort();
 
+    if retry_budget.is_exhausted() && results.iter().any(|res| res.is_err()) {
+        return Err(failure::network(format!(
+            "Job retry budget exhausted ({} of {} retries used); the origin is failing too often",
+            retry_budget.used(),
+            retry_budget.limit()
+        )));
+    }
+
     // 3. Collect and process results
     let mut downloaded_paths = Vec::new();
     for res in results {
@@ -162,129 +323,1150 @@ pub async fn download_segments_concurrently(
     Ok(downloaded_paths)
 }
 
-/// Downloads, decrypts, and saves a single segment to the specified temporary path
+/// Downloads, decrypts, and saves a single segment to the specified temporary path.
+///
+/// Returns the downloaded size and the duration of the successful request; `retries` counts the
+/// attempts that were repeated, also when the segment fails.
+#[allow(clippy::too_many_arguments)]
 async fn download_and_process_segment(
     client: Client,
-    url: &str,
+    url: &Url,
     path: &Path,
     index: usize,
-    key_bytes: Option<[u8; KEY_LEN]>,
-    encryption_info: Option<EncryptionInfo>,
-) -> Result<usize> {
+    sequence: u64,
+    byte_range: Option<ByteRange>,
+    decryption: Option<SegmentDecryption>,
+    error_summary: &ErrorSummary,
+    content_types: &ContentTypeRules,
+    connectivity: &ConnectivityMonitor,
+    retry_budget: &RetryBudget,
+    unsupported_warned: &AtomicBool,
+    warnings: &mpsc::Sender<DownloadMessage>,
+    retries: &mut usize,
+) -> Result<(usize, Duration)> {
     let mut last_error: Option<anyhow::Error> = None;
+    let mut attempt = 0;
 
-    for attempt in 0..MAX_RETRIES {
-        let result = client.get(url).send().await;
+    while attempt < MAX_RETRIES {
+        *retries = attempt;
+        let request_started = Instant::now();
+        // Counted as an active connection until the body has been read (not through
+        // decryption, the file write or a retry backoff)
+        let connection = stats::global().open_connection(url);
+        let mut request = client.get(url.as_str());
+        if let Some(range) = byte_range {
+            request = request.header(reqwest::header::RANGE, range.header_value());
+        }
+        let result = request.send().await;
 
         match result {
             Ok(response) => {
                 let status = response.status();
+                let unexpected_type = response
+                    .headers()
+                    .get(reqwest::header::CONTENT_TYPE)
+                    .and_then(|value| value.to_str().ok())
+                    .filter(|content_type| {
+                        status.is_success() && !content_types.accepts(content_type)
+                    })
+                    .map(str::to_string);
+                let rejected_type = match unexpected_type {
+                    Some(content_type) if !content_types.enforced() => {
+                        if content_types.first_warning() {
+                            warnings
+                                .send(DownloadMessage::Log(format!(
+                                    "⚠️ Warning: Segment {} has Content-Type {}; kept anyway (use --accept-content-type to reject unexpected types).",
+                                    index, content_type
+                                )))
+                                .await
+                                .ok();
+                        }
+                        None
+                    }
+                    other => other,
+                };
+
+                // Typically an error page served with 200; retried like a server error
+                if let Some(content_type) = rejected_type {
+                    error_summary.record(ErrorKind::ContentType, url.as_str());
+                    let preview = response
+                        .bytes()
+                        .await
+                        .map_or(String::new(), |body| body_preview(&body));
+                    drop(connection);
+                    let reason = format!("unexpected Content-Type {}{}", content_type, preview);
+                    if attempt == MAX_RETRIES - 1 {
+                        last_error = Some(failure::network(format!(
+                            "Segment {} rejected, {}",
+                            index, reason
+                        )));
+                        break;
+                    }
+                    if !retry_budget.try_consume() {
+                        return Err(failure::network(format!(
+                            "Segment {} rejected, {} (retry budget exhausted)",
+                            index, reason
+                        )));
+                    }
+                    let actual_delay = (2u64.pow(attempt as u32)).max(3);
+                    stats::global().record_retry();
+                    warnings
+                        .send(DownloadMessage::RetryWarning {
+                            segment: index,
+                            attempt: attempt + 1,
+                            reason: format!("{}, retrying in {}s", reason, actual_delay),
+                            delay: Duration::from_secs(actual_delay),
+                        })
+                        .await
+                        .ok();
+                    sleep(Duration::from_secs(actual_delay)).await;
+                    attempt += 1;
+                    continue;
+                }
 
                 if status.is_success() {
-                    let encrypted_bytes = response.bytes().await?;
+                    let body = response.bytes().await.inspect_err(|e| {
+                        error_summary.record(ErrorKind::from_reqwest(e), url.as_str());
+                    })?;
+                    drop(connection);
+                    let fetch_time = request_started.elapsed();
+                    // Only the addressed bytes of a byte-range segment are kept
+                    let encrypted_bytes = match byte_range {
+                        Some(range) => range
+                            .select(&body, status == StatusCode::PARTIAL_CONTENT)
+                            .ok_or_else(|| {
+                            failure::network(format!(
+                                "Segment {} response ({} bytes) does not cover {}",
+                                index,
+                                body.len(),
+                                range.header_value()
+                            ))
+                        })?,
+                        None => &body[..],
+                    };
                     let segment_size = encrypted_bytes.len();
+                    stats::global().record_download(url, segment_size as u64);
 
                     // --- Decryption Logic ---
-                    let decrypted_bytes = match (key_bytes, encryption_info) {
-                        (Some(key), Some(info)) => {
-                            let iv: [u8; KEY_LEN] = if let Some(explicit_iv) = info.iv_bytes {
-                                explicit_iv
+                    let decrypted_bytes = match &decryption {
+                        Some(decryption) => {
+                            let key = decryption.key.get().await?;
+                            let iv = decryption.info.iv_for(sequence);
+                            if decryption.info.method == "SAMPLE-AES" {
+                                let SegmentKey::Aes128(key) = key else {
+                                    return Err(failure::decrypt(
+                                        "SAMPLE-AES requires a 16-byte key".to_string(),
+                                    ));
+                                };
+                                let output =
+                                    sample_aes::decrypt_ts_segment(&key, &iv, encrypted_bytes)
+                                        .map_err(|e| {
+                                            error_summary.record(ErrorKind::Decrypt, url.as_str());
+                                            failure::decrypt(format!(
+                                                "Segment {} SAMPLE-AES decryption failed: {}",
+                                                index, e
+                                            ))
+                                        })?;
+                                // Codecs do not change between segments; reported once
+                                if !output.unsupported.is_empty()
+                                    && !unsupported_warned.swap(true, Ordering::Relaxed)
+                                {
+                                    warnings
+                                        .send(DownloadMessage::Log(format!(
+                                            "⚠️ Warning: SAMPLE-AES decryption of {} is not supported; those tracks are kept encrypted and will not play.",
+                                            output.unsupported.join(", ")
+                                        )))
+                                        .await
+                                        .ok();
+                                }
+                                output.data
                             } else {
-                                let mut iv = [0u8; KEY_LEN];
-                                let sequence_number = (index as u32).to_be_bytes();
-                                iv[12..].copy_from_slice(&sequence_number);
-                                iv
-                            };
-                            let cipher = Aes128CbcDec::new(&key.into(), &iv.into());
-                            let data = encrypted_bytes.to_vec();
-                            cipher.decrypt_padded_vec_mut::<Pkcs7>(&data).map_err(|e| {
-                                anyhow!("Segment {} decryption failed: {:?}", index, e)
-                            })?
+                                decrypt_cbc(&key, &iv, encrypted_bytes).map_err(|e| {
+                                    error_summary.record(ErrorKind::Decrypt, url.as_str());
+                                    failure::decrypt(format!(
+                                        "Segment {} decryption failed: {:?}",
+                                        index, e
+                                    ))
+                                })?
+                            }
                         }
-                        _ => encrypted_bytes.to_vec(),
+                        None => encrypted_bytes.to_vec(),
                     };
                     // --- Write to file ---
-                    let mut file = tokio::fs::File::create(path).await?;
-                    file.write_all(&decrypted_bytes).await?;
-                    return Ok(segment_size);
+                    // Written to a `.part` file first so a present segment file is always complete
+                    let part_path = path.with_extension("part");
+                    let write_result = async {
+                        let mut file = tokio::fs::File::create(&part_path).await?;
+                        file.write_all(&decrypted_bytes).await?;
+                        file.flush().await?;
+                        tokio::fs::rename(&part_path, path).await
+                    }
+                    .await;
+                    if let Err(e) = write_result {
+                        error_summary.record(ErrorKind::Io, url.as_str());
+                        return Err(e.into());
+                    }
+                    stats::global().record_write(decrypted_bytes.len() as u64);
+                    stats::global().record_segment_done();
+                    return Ok((segment_size, fetch_time));
                 }
 
+                error_summary.record(ErrorKind::Status(status.as_u16()), url.as_str());
+
                 if status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error() {
+                    // The error body is not needed; release the connection before the backoff
+                    drop(response);
+                    drop(connection);
                     if attempt == MAX_RETRIES - 1 {
-                        last_error = Some(anyhow!(
+                        last_error = Some(failure::network(format!(
                             "Segment {} download failed, status code: {}",
-                            index,
-                            status
-                        ));
+                            index, status
+                        )));
                         break;
                     }
+                    if !retry_budget.try_consume() {
+                        return Err(failure::network(format!(
+                            "Segment {} download failed, status code: {} (retry budget exhausted)",
+                            index, status
+                        )));
+                    }
                     let actual_delay = (2u64.pow(attempt as u32)).max(3);
+                    stats::global().record_retry();
+                    warnings
+                        .send(DownloadMessage::RetryWarning {
+                            segment: index,
+                            attempt: attempt + 1,
+                            reason: format!("status {}, retrying in {}s", status, actual_delay),
+                            delay: Duration::from_secs(actual_delay),
+                        })
+                        .await
+                        .ok();
                     sleep(Duration::from_secs(actual_delay)).await;
-                    continue;
+                } else if status == StatusCode::FORBIDDEN
+                    && signed_url::is_expired(
+                        url,
+                        response
+                            .headers()
+                            .get(reqwest::header::DATE)
+                            .and_then(|v| v.to_str().ok()),
+                    )
+                {
+                    return Err(failure::network(format!(
+                        "Segment {} download failed, status code: {} (signed URL expired by server time)",
+                        index, status
+                    )));
                 } else {
-                    return Err(anyhow!(
+                    return Err(failure::network(format!(
                         "Segment {} download failed, status code: {}",
-                        index,
-                        status
-                    ));
+                        index, status
+                    )));
                 }
             }
             Err(e) => {
+                drop(connection);
+                error_summary.record(ErrorKind::from_reqwest(&e), url.as_str());
+
+                // A connection-level failure may mean the machine went offline; in that case
+                // the monitor pauses until connectivity returns and the attempt is not counted.
+                if (e.is_connect() || e.is_timeout())
+                    && connectivity.handle_connection_error(url).await?
+                {
+                    continue;
+                }
+
                 if attempt == MAX_RETRIES - 1 {
-                    last_error = Some(anyhow!(
+                    last_error = Some(failure::network(format!(
                         "Segment {} download failed, connection error: {}",
-                        index,
-                        e
-                    ));
+                        index, e
+                    )));
                     break;
                 }
+                if !retry_budget.try_consume() {
+                    return Err(failure::network(format!(
+                        "Segment {} download failed, connection error: {} (retry budget exhausted)",
+                        index, e
+                    )));
+                }
                 let actual_delay = (2u64.pow(attempt as u32)).max(3);
+                stats::global().record_retry();
+                warnings
+                    .send(DownloadMessage::RetryWarning {
+                        segment: index,
+                        attempt: attempt + 1,
+                        reason: format!("{}, retrying in {}s", e, actual_delay),
+                        delay: Duration::from_secs(actual_delay),
+                    })
+                    .await
+                    .ok();
                 sleep(Duration::from_secs(actual_delay)).await;
-                continue;
             }
         }
+        attempt += 1;
     }
     match last_error {
         Some(e) => Err(e),
-        None => Err(anyhow!(
+        None => Err(failure::network(format!(
             "Segment {} download failed, maximum retries reached ({} times).",
-            index,
-            MAX_RETRIES
-        )),
+            index, MAX_RETRIES
+        ))),
     }
 }
 
-/// Concatenates all temporary downloaded segments in order into a single output file.
-pub fn concatenate_segments(segment_paths: &[PathBuf], output_path: &Path) -> Result<()> {
-    let mut output_file = File::create(output_path)?;
+/// Start of a rejected response body (whitespace collapsed), e.g. the message of an error page
+fn body_preview(body: &[u8]) -> String {
+    let text = String::from_utf8_lossy(&body[..body.len().min(BODY_PREVIEW_LEN)])
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if text.is_empty() {
+        String::new()
+    } else {
+        format!(": {}", text)
+    }
+}
+
+/// Init section with its URL resolved against the playlist
+pub type ResolvedInit = (Url, Option<ByteRange>);
+
+/// Distinct `#EXT-X-MAP` init sections (playlist order) and each segment's index into them.
+pub fn init_sections(
+    base_url: &Url,
+    segments: &[Segment],
+) -> Result<(Vec<ResolvedInit>, Vec<Option<usize>>)> {
+    let mut sections: Vec<ResolvedInit> = Vec::new();
+    let mut init_of = Vec::with_capacity(segments.len());
+    for segment in segments {
+        let init = match &segment.init {
+            Some(init) => {
+                let section = (base_url.join(&init.uri)?, init.byte_range);
+                Some(
+                    match sections.iter().position(|existing| *existing == section) {
+                        Some(existing) => existing,
+                        None => {
+                            sections.push(section);
+                            sections.len() - 1
+                        }
+                    },
+                )
+            }
+            None => None,
+        };
+        init_of.push(init);
+    }
+    Ok((sections, init_of))
+}
+
+/// Downloads the init sections into `dir` (as `init_file_name(n)`) and returns their paths.
+pub async fn download_init_sections(
+    client: &Client,
+    sections: &[ResolvedInit],
+    dir: &Path,
+    cancel: &CancellationToken,
+) -> Result<Vec<PathBuf>> {
+    let mut paths = Vec::with_capacity(sections.len());
+    for (n, (url, byte_range)) in sections.iter().enumerate() {
+        let bytes = cancel::or_cancel(cancel, async {
+            let mut request = client.get(url.as_str());
+            if let Some(range) = byte_range {
+                request = request.header(reqwest::header::RANGE, range.header_value());
+            }
+            let response = request.send().await?.error_for_status()?;
+            let partial = response.status() == StatusCode::PARTIAL_CONTENT;
+            let body = response.bytes().await?;
+            match byte_range {
+                Some(range) => range
+                    .select(&body, partial)
+                    .map(|bytes| bytes.to_vec())
+                    .ok_or_else(|| anyhow!("response does not cover {}", range.header_value())),
+                None => Ok(body.to_vec()),
+            }
+        })
+        .await
+        .map_err(|e| {
+            let message = format!("Init section download failed ({}): {}", url, e);
+            e.context(message)
+        })?;
+        stats::global().record_download(url, bytes.len() as u64);
+        let path = dir.join(init_file_name(n));
+        tokio::fs::write(&path, &bytes).await?;
+        stats::global().record_write(bytes.len() as u64);
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+/// Byte range occupied by one segment inside the concatenated TS
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkRange {
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// Concatenates the segments and returns the byte range of each one in the output.
+///
+/// With `verify`, every chunk is read back and compared against its segment file.
+pub fn concatenate_segments(
+    segment_paths: &[PathBuf],
+    output_path: &Path,
+    verify: bool,
+    cancel: &CancellationToken,
+) -> Result<Vec<ChunkRange>> {
+    let mut output_file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(output_path)?;
+    let mut ranges = Vec::with_capacity(segment_paths.len());
+    let mut offset = 0u64;
     for path in segment_paths {
+        cancel::check(cancel)?;
         let mut segment_file = File::open(path)?;
-        io::copy(&mut segment_file, &mut output_file)?;
+        let length = io::copy(&mut segment_file, &mut output_file)?;
+        stats::global().record_write(length);
+        ranges.push(ChunkRange { offset, length });
+        offset += length;
+    }
+    output_file.flush()?;
+
+    if verify {
+        for (path, range) in segment_paths.iter().zip(&ranges) {
+            cancel::check(cancel)?;
+            verify_chunk(&mut output_file, path, *range)?;
+        }
+    }
+    Ok(ranges)
+}
+
+/// Joins MPEG-TS segments whose timestamps restart at `#EXT-X-DISCONTINUITY` tags.
+///
+/// Each continuous run is concatenated into a part file in `work_dir`, then FFmpeg's concat
+/// demuxer joins the parts into `output_path` with continuous timestamps (a plain byte copy
+/// would jump back in time at every run and desync audio). `run_starts` lists the positions in
+/// `segment_paths` that begin a new run.
+pub fn concatenate_with_discontinuities(
+    segment_paths: &[PathBuf],
+    run_starts: &[usize],
+    output_path: &Path,
+    work_dir: &Path,
+    verify: bool,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    let mut bounds = vec![0];
+    bounds.extend(
+        run_starts
+            .iter()
+            .copied()
+            .filter(|&start| start > 0 && start < segment_paths.len()),
+    );
+    bounds.push(segment_paths.len());
+    bounds.dedup();
+
+    let mut part_paths = Vec::with_capacity(bounds.len() - 1);
+    let mut list = String::from("ffconcat version 1.0\n");
+    for (n, run) in bounds.windows(2).enumerate() {
+        let part_path = work_dir.join(format!("discontinuity_{:03}.ts", n));
+        concatenate_segments(&segment_paths[run[0]..run[1]], &part_path, verify, cancel)?;
+        // Single quotes are closed, escaped and reopened in concat list paths
+        list.push_str(&format!(
+            "file '{}'\n",
+            part_path.to_string_lossy().replace('\'', "'\\''")
+        ));
+        part_paths.push(part_path);
+    }
+    let list_path = work_dir.join("discontinuity_concat.txt");
+    fs::write(&list_path, list)?;
+
+    let args: Vec<&OsStr> = vec![
+        "-f".as_ref(),
+        "concat".as_ref(),
+        "-safe".as_ref(),
+        "0".as_ref(),
+        "-i".as_ref(),
+        list_path.as_os_str(),
+        "-map".as_ref(),
+        "0".as_ref(),
+        "-c".as_ref(),
+        "copy".as_ref(),
+        "-f".as_ref(),
+        "mpegts".as_ref(),
+        "-y".as_ref(),
+        output_path.as_os_str(),
+    ];
+    let result = run_ffmpeg_with_progress(&args, cancel, |_| {});
+    for path in part_paths.iter().chain([&list_path]) {
+        fs::remove_file(path).ok();
+    }
+    let run = result?;
+    if run.success {
+        Ok(())
+    } else {
+        Err(failure::ffmpeg(format!(
+            "FFmpeg concat failed: {}",
+            run.stderr
+        )))
+    }
+}
+
+/// Writes `duration` seconds of black video and silence (H.264/AAC in MPEG-TS), standing in for
+/// `#EXT-X-GAP` segments.
+pub fn generate_gap_filler(
+    output_path: &Path,
+    duration: f64,
+    (width, height): (u32, u32),
+    cancel: &CancellationToken,
+) -> Result<()> {
+    let video = format!("color=c=black:s={}x{}:r=25", width, height);
+    let duration = format!("{:.3}", duration);
+    let args: Vec<&OsStr> = [
+        "-f",
+        "lavfi",
+        "-i",
+        video.as_str(),
+        "-f",
+        "lavfi",
+        "-i",
+        "anullsrc=r=48000:cl=stereo",
+        "-t",
+        duration.as_str(),
+        "-c:v",
+        "libx264",
+        "-pix_fmt",
+        "yuv420p",
+        "-c:a",
+        "aac",
+        "-f",
+        "mpegts",
+        "-y",
+    ]
+    .into_iter()
+    .map(OsStr::new)
+    .chain([output_path.as_os_str()])
+    .collect();
+    let run = run_ffmpeg_with_progress(&args, cancel, |_| {})?;
+    if run.success {
+        Ok(())
+    } else {
+        Err(failure::ffmpeg(format!(
+            "FFmpeg filler generation failed: {}",
+            run.stderr
+        )))
+    }
+}
+
+/// Overwrites one segment's chunk in an existing concatenated TS without rebuilding the file.
+/// The new segment must have exactly the recorded length.
+pub fn overwrite_chunk(
+    output_path: &Path,
+    segment_path: &Path,
+    range: ChunkRange,
+    verify: bool,
+) -> Result<()> {
+    let data = fs::read(segment_path)?;
+    if data.len() as u64 != range.length {
+        return Err(anyhow!(
+            "{} is {} bytes but its chunk is {} bytes",
+            segment_path.display(),
+            data.len(),
+            range.length
+        ));
+    }
+    let mut output_file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(output_path)?;
+    output_file.seek(SeekFrom::Start(range.offset))?;
+    output_file.write_all(&data)?;
+    output_file.flush()?;
+
+    if verify {
+        verify_chunk(&mut output_file, segment_path, range)?;
+    }
+    Ok(())
+}
+
+/// Reads a chunk back from the concatenated file and compares it with the segment file.
+fn verify_chunk(output_file: &mut File, segment_path: &Path, range: ChunkRange) -> Result<()> {
+    let expected = fs::read(segment_path)?;
+    let mut actual = vec![0u8; range.length as usize];
+    output_file.seek(SeekFrom::Start(range.offset))?;
+    output_file.read_exact(&mut actual)?;
+    if expected != actual {
+        return Err(anyhow!(
+            "Concatenated chunk at offset {} does not match {}",
+            range.offset,
+            segment_path.display()
+        ));
     }
     Ok(())
 }
 
+/// Options for the FFmpeg remux step
+#[derive(Debug, Clone, Default)]
+pub struct RemuxOptions {
+    /// Regenerate/repair PTS and DTS across discontinuities and 33-bit wraparounds
+    pub fix_timestamps: bool,
+    /// Separate audio rendition muxed in as the output's audio track
+    pub external_audio: Option<ExternalAudio>,
+    /// The merged input is fragmented MP4 (init section + fragments) rather than MPEG-TS
+    pub fragmented_mp4: bool,
+    /// Text tracks muxed in as subtitle streams (mov_text in MP4, SRT in MKV)
+    pub subtitles: Vec<SubtitleTrack>,
+    /// Cut the merged stream to these times (re-encodes for frame accuracy)
+    pub trim: Option<Trim>,
+    /// Re-encode instead of copying, for codecs the output container cannot hold
+    pub reencode: bool,
+}
+
+/// Cut points within the merged stream, in seconds from its start
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trim {
+    pub start: f64,
+    pub end: Option<f64>,
+}
+
+impl RemuxOptions {
+    /// Whether the output differs from a plain copy of the merged TS
+    pub fn alters_streams(&self) -> bool {
+        self.fix_timestamps
+            || self.external_audio.is_some()
+            || self.fragmented_mp4
+            || !self.subtitles.is_empty()
+            || self.trim.is_some()
+            || self.reencode
+    }
+
+    /// Local files read next to the merged stream: a downloaded audio rendition and the
+    /// subtitle tracks (an audio playlist FFmpeg reads over HTTP is not one)
+    pub fn local_inputs(&self) -> Vec<PathBuf> {
+        let audio = self
+            .external_audio
+            .iter()
+            .filter(|audio| !audio.url.contains("://"))
+            .map(|audio| PathBuf::from(&audio.url));
+        let subtitles = self.subtitles.iter().map(|subtitle| subtitle.path.clone());
+        audio.chain(subtitles).collect()
+    }
+}
+
+/// Local WebVTT/SRT file and the metadata of the subtitle stream it becomes
+#[derive(Debug, Clone)]
+pub struct SubtitleTrack {
+    pub path: PathBuf,
+    pub track: TrackMetadata,
+}
+
+/// Audio playlist read directly by FFmpeg, with the job's request headers
+#[derive(Debug, Clone)]
+pub struct ExternalAudio {
+    pub url: String,
+    pub headers: Vec<(String, String)>,
+    /// Language/name from `#EXT-X-MEDIA`, written to the output's audio track
+    pub track: TrackMetadata,
+}
+
+/// FFmpeg output options carrying `track` to the output stream `stream` (`a:0`, `s:1`, ...).
+fn metadata_args(stream: &str, track: &TrackMetadata) -> Vec<String> {
+    let mut args = Vec::new();
+    if let Some(language) = &track.language {
+        // MP4 and MKV store ISO 639-2 codes; unknown tags are passed on unchanged
+        let code = iso639_2(language).unwrap_or(language);
+        args.extend([
+            format!("-metadata:s:{}", stream),
+            format!("language={}", code),
+        ]);
+    }
+    if let Some(name) = &track.name {
+        args.extend([format!("-metadata:s:{}", stream), format!("title={}", name)]);
+    }
+    if track.default {
+        args.extend([format!("-disposition:{}", stream), "default".to_string()]);
+    }
+    args
+}
+
+/// Encoders for a re-encoded output: VP9/Opus for WebM, H.264/AAC otherwise.
+fn encoder_args(output_ext: &str) -> &'static [&'static str] {
+    if output_ext == "webm" {
+        &[
+            "-c:v",
+            "libvpx-vp9",
+            "-crf",
+            "32",
+            "-b:v",
+            "0",
+            "-c:a",
+            "libopus",
+        ]
+    } else {
+        &[
+            "-c:v", "libx264", "-preset", "veryfast", "-crf", "18", "-c:a", "aac",
+        ]
+    }
+}
+
 /// Uses FFmpeg to remux the temporary TS file to the desired output format.
-pub fn run_ffmpeg_remux(input_path: &Path, output_path: &Path) -> Result<()> {
-    let ff = FFmpegHandle::ensure()?;
-    let ff_path = ff.path();
-    let output = Command::new(ff_path)
-        .arg("-i")
-        .arg(input_path)
-        .arg("-c")
-        .arg("copy")
-        .arg("-movflags")
-        .arg("+faststart")
-        .arg("-y")
-        .arg(output_path)
-        .output()?;
-
-    if output.status.success() {
+///
+/// `on_progress` receives values from 0.0 to 1.0, derived from FFmpeg's `-progress` output and
+/// the input duration reported on stderr.
+pub fn run_ffmpeg_remux(
+    input_path: &Path,
+    output_path: &Path,
+    options: &RemuxOptions,
+    cancel: &CancellationToken,
+    on_progress: impl Fn(f32),
+) -> Result<()> {
+    let mut args: Vec<&OsStr> = Vec::new();
+    if options.fix_timestamps {
+        // Input side: generate missing PTS, ignore broken DTS and unwrap 33-bit overflows
+        args.extend(["-fflags", "+genpts+igndts"].map(OsStr::new));
+        if !options.fragmented_mp4 {
+            // MPEG-TS only; fMP4 timestamps are 64-bit
+            args.extend(["-correct_ts_overflow", "1"].map(OsStr::new));
+        }
+    }
+    if options.fragmented_mp4 {
+        // The merged file keeps its `.ts.tmp` name; don't let probing guess
+        args.extend(["-f", "mp4"].map(OsStr::new));
+    }
+    let output_ext = output_path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    args.extend(["-i".as_ref(), input_path.as_os_str()]);
+    let header_arg: String = options
+        .external_audio
+        .iter()
+        .flat_map(|audio| &audio.headers)
+        .map(|(name, value)| format!("{}: {}\r\n", name, value))
+        .collect();
+    if let Some(audio) = &options.external_audio {
+        if !header_arg.is_empty() {
+            args.extend(["-headers", header_arg.as_str()].map(OsStr::new));
+        }
+        args.extend(["-i", audio.url.as_str()].map(OsStr::new));
+    }
+    for subtitle in &options.subtitles {
+        args.extend(["-i".as_ref(), subtitle.path.as_os_str()]);
+    }
+
+    // Output side: stream selection and per-stream metadata
+    let mut output_args: Vec<String> = Vec::new();
+    if let Some(audio) = &options.external_audio {
+        output_args.extend(["-map", "0:v", "-map", "1:a"].map(String::from));
+        output_args.extend(metadata_args("a:0", &audio.track));
+    } else if !options.subtitles.is_empty() {
+        // Explicit maps for the subtitles disable the default selection for the main input too
+        output_args.extend(["-map", "0:v?", "-map", "0:a?"].map(String::from));
+    }
+    if !options.subtitles.is_empty() {
+        let codec = text_codec(&output_ext).ok_or_else(|| {
+            anyhow!(
+                "The {} container cannot carry text subtitles; use mkv or mp4, or burn them in",
+                output_ext
+            )
+        })?;
+        let first_input = 1 + usize::from(options.external_audio.is_some());
+        for (n, subtitle) in options.subtitles.iter().enumerate() {
+            output_args.extend(["-map".to_string(), format!("{}:0", first_input + n)]);
+            output_args.extend(metadata_args(&format!("s:{}", n), &subtitle.track));
+        }
+        output_args.extend(["-c:s".to_string(), codec.to_string()]);
+    }
+    if let Some(trim) = &options.trim {
+        // Output-side seeking decodes up to the cut, so the first and last frames are exact
+        output_args.extend(["-ss".to_string(), format!("{:.3}", trim.start)]);
+        if let Some(end) = trim.end {
+            output_args.extend(["-to".to_string(), format!("{:.3}", end)]);
+        }
+    }
+    // A trim is re-encoded too, as a stream copy could only cut at keyframes
+    if options.trim.is_some() || options.reencode {
+        args.extend(encoder_args(&output_ext).iter().map(OsStr::new));
+    } else {
+        args.extend(["-c", "copy"].map(OsStr::new));
+    }
+    args.extend(output_args.iter().map(OsStr::new));
+    if options.fix_timestamps {
+        // Output side: shift to start at zero and drop the default mux delay/preload
+        args.extend(
+            [
+                "-avoid_negative_ts",
+                "make_zero",
+                "-muxdelay",
+                "0",
+                "-muxpreload",
+                "0",
+            ]
+            .map(OsStr::new),
+        );
+    }
+    if matches!(output_ext.as_str(), "mp4" | "mov" | "m4a") {
+        args.extend(["-movflags", "+faststart"].map(OsStr::new));
+    }
+    args.extend(["-y".as_ref(), output_path.as_os_str()]);
+    let run = run_ffmpeg_with_progress(&args, cancel, on_progress)?;
+
+    if run.success {
         Ok(())
     } else {
-        Err(anyhow!(
+        Err(failure::ffmpeg(format!(
             "FFmpeg execution failed: {}",
-            String::from_utf8_lossy(&output.stderr)
-        ))
+            run.stderr
+        )))
+    }
+}
+
+/// Result of an FFmpeg invocation made through `run_ffmpeg_with_progress`.
+pub struct FfmpegRun {
+    pub success: bool,
+    pub stderr: String,
+    /// Input duration as reported by FFmpeg, if any
+    pub duration_ms: Option<u64>,
+}
+
+/// Runs the embedded FFmpeg with `-progress pipe:1`, reporting progress (0.0 to 1.0) and
+/// collecting stderr.
+pub fn run_ffmpeg_with_progress(
+    args: &[&OsStr],
+    cancel: &CancellationToken,
+    on_progress: impl Fn(f32),
+) -> Result<FfmpegRun> {
+    let mut command = ffmpeg_progress_command()?;
+    command.args(args);
+    let result = run_progress_command(command, cancel, on_progress);
+    if result
+        .as_ref()
+        .is_err_and(|e| e.downcast_ref::<io::Error>().is_some())
+    {
+        // The cached binary did not start: have the next job probe it again
+        FFmpegHandle::forget_probe();
+    }
+    result
+}
+
+/// FFmpeg command preconfigured for `run_progress_command` (progress on stdout, quiet stderr).
+pub fn ffmpeg_progress_command() -> Result<Command> {
+    let ff = FFmpegHandle::ensure()?;
+    let mut command = Command::new(ff.path());
+    command
+        .arg("-hide_banner")
+        .arg("-nostats")
+        .arg("-progress")
+        .arg("pipe:1");
+    Ok(command)
+}
+
+/// How often a running process is checked for cancellation / exit
+const PROCESS_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Runs an FFmpeg-style process, reporting `-progress` output and killing it when cancelled.
+pub fn run_progress_command(
+    mut command: Command,
+    cancel: &CancellationToken,
+    on_progress: impl Fn(f32),
+) -> Result<FfmpegRun> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // stderr is drained on its own thread (it carries the input duration and error details)
+    let duration_ms = Arc::new(AtomicU64::new(0));
+    let stderr = child.stderr.take().expect("ffmpeg stderr is piped");
+    let stderr_handle = std::thread::spawn({
+        let duration_ms = duration_ms.clone();
+        move || {
+            let mut collected = String::new();
+            for line in BufReader::new(stderr).lines().map_while(|l| l.ok()) {
+                if let Some(ms) = parse_ffmpeg_duration_ms(&line) {
+                    // Only the first Duration line belongs to the input
+                    let _ = duration_ms.compare_exchange(0, ms, Ordering::SeqCst, Ordering::SeqCst);
+                }
+                collected.push_str(&line);
+                collected.push('\n');
+            }
+            collected
+        }
+    });
+
+    let stdout = child.stdout.take().expect("ffmpeg stdout is piped");
+
+    // Killing the process on cancel also ends the blocking reads below
+    let child = Arc::new(std::sync::Mutex::new(child));
+    let exited = Arc::new(AtomicBool::new(false));
+    let watcher_handle = std::thread::spawn({
+        let child = child.clone();
+        let exited = exited.clone();
+        let cancel = cancel.clone();
+        move || {
+            while !exited.load(Ordering::SeqCst) {
+                if cancel.is_cancelled() {
+                    let _ = child.lock().unwrap().kill();
+                    return;
+                }
+                std::thread::sleep(PROCESS_POLL_INTERVAL);
+            }
+        }
+    });
+
+    for line in BufReader::new(stdout).lines().map_while(|l| l.ok()) {
+        // `out_time_ms` is actually in microseconds (FFmpeg quirk)
+        if let Some(value) = line.strip_prefix("out_time_ms=") {
+            let total_ms = duration_ms.load(Ordering::SeqCst);
+            if let (Ok(out_us), true) = (value.trim().parse::<u64>(), total_ms > 0) {
+                on_progress(((out_us / 1000) as f32 / total_ms as f32).min(1.0));
+            }
+        } else if line.trim() == "progress=end" {
+            on_progress(1.0);
+        }
+    }
+
+    // Poll instead of wait() so the watcher can still take the lock to kill the process
+    let status = loop {
+        if let Some(status) = child.lock().unwrap().try_wait()? {
+            break status;
+        }
+        std::thread::sleep(PROCESS_POLL_INTERVAL);
+    };
+    exited.store(true, Ordering::SeqCst);
+    let _ = watcher_handle.join();
+    let stderr_output = stderr_handle.join().unwrap_or_default();
+    let duration_ms = duration_ms.load(Ordering::SeqCst);
+
+    if cancel.is_cancelled() {
+        return Err(Cancelled.into());
+    }
+
+    Ok(FfmpegRun {
+        success: status.success(),
+        stderr: stderr_output,
+        duration_ms: (duration_ms > 0).then_some(duration_ms),
+    })
+}
+
+/// Parses `Duration: HH:MM:SS.xx` from an FFmpeg stderr line into milliseconds.
+fn parse_ffmpeg_duration_ms(line: &str) -> Option<u64> {
+    let rest = line.trim().strip_prefix("Duration:")?.trim();
+    let timestamp = rest.split(',').next()?.trim();
+    let mut parts = timestamp.split(':');
+    let hours: f64 = parts.next()?.parse().ok()?;
+    let minutes: f64 = parts.next()?.parse().ok()?;
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    Some(((hours * 3600.0 + minutes * 60.0 + seconds) * 1000.0) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::downloader::hls_parser::{download_key_file, parse_m3u8};
+    use crate::downloader::limits::PlaylistLimits;
+    use aes::cipher::BlockEncryptMut;
+    use cbc::Encryptor;
+    use std::collections::HashMap;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    const KEY: [u8; KEY_LEN] = [7u8; KEY_LEN];
+
+    /// Serves `files` by request path over plain HTTP on localhost.
+    async fn static_server(files: HashMap<&'static str, Vec<u8>>) -> Url {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let files = Arc::new(files);
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let files = files.clone();
+                tokio::spawn(async move {
+                    let mut buf = vec![0u8; 4096];
+                    let n = socket.read(&mut buf).await.unwrap_or(0);
+                    let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                    let path = request.split_whitespace().nth(1).unwrap_or("/").to_string();
+                    let (status, body) = match files.get(path.as_str()) {
+                        Some(body) => ("200 OK", body.clone()),
+                        None => ("404 Not Found", Vec::new()),
+                    };
+                    let header = format!(
+                        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                        status,
+                        body.len()
+                    );
+                    socket.write_all(header.as_bytes()).await.ok();
+                    socket.write_all(&body).await.ok();
+                });
+            }
+        });
+        Url::parse(&format!("http://{}/index.m3u8", addr)).unwrap()
+    }
+
+    /// Two TS packets whose payload identifies the segment
+    fn ts_payload(marker: u8) -> Vec<u8> {
+        let mut packet = vec![marker; 188 * 2];
+        packet[0] = 0x47;
+        packet[188] = 0x47;
+        packet
+    }
+
+    /// AES-128-CBC with the implicit IV (media sequence number), as a packager would write it
+    fn encrypt(data: &[u8], sequence: usize) -> Vec<u8> {
+        let mut iv = [0u8; KEY_LEN];
+        iv[12..].copy_from_slice(&(sequence as u32).to_be_bytes());
+        Encryptor::<Aes128>::new(&KEY.into(), &iv.into()).encrypt_padded_vec_mut::<Pkcs7>(data)
+    }
+
+    #[tokio::test]
+    async fn mixed_encrypted_and_clear_segments_are_decrypted_per_segment() {
+        let playlist = "#EXTM3U\n\
+                        #EXTINF:4,\n0.ts\n\
+                        #EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\"\n\
+                        #EXTINF:4,\n1.ts\n\
+                        #EXT-X-KEY:METHOD=NONE\n\
+                        #EXTINF:4,\n2.ts\n\
+                        #EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\"\n\
+                        #EXTINF:4,\n3.ts\n";
+        let base_url = static_server(HashMap::from([
+            ("/0.ts", ts_payload(0)),
+            ("/1.ts", encrypt(&ts_payload(1), 1)),
+            ("/2.ts", ts_payload(2)),
+            ("/3.ts", encrypt(&ts_payload(3), 3)),
+        ]))
+        .await;
+        let (segments, infos) =
+            parse_m3u8(playlist, &base_url, &PlaylistLimits::default(), &|_| {}).unwrap();
+        assert_eq!(infos.len(), 1);
+        let keys = infos
+            .into_iter()
+            .map(|info| SegmentDecryption {
+                info,
+                key: PendingKey::ready(SegmentKey::Aes128(KEY)),
+            })
+            .collect();
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (sender, _receiver) = mpsc::channel(100);
+        let paths = download_segments_concurrently(
+            &Client::new(),
+            &base_url,
+            segments,
+            keys,
+            4,
+            2,
+            temp_dir.path().to_path_buf(),
+            Arc::new(ErrorSummary::default()),
+            Arc::new(ContentTypeRules::default()),
+            Arc::new(SegmentReport::default()),
+            &CancellationToken::new(),
+            &PauseGate::default(),
+            sender,
+            egui::Context::default(),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(paths.len(), 4);
+        for (marker, path) in paths.iter().enumerate() {
+            assert_eq!(
+                fs::read(path).unwrap(),
+                ts_payload(marker as u8),
+                "segment {} content",
+                marker
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn clear_segments_do_not_wait_for_the_key() {
+        let playlist = "#EXTM3U\n\
+                        #EXT-X-KEY:METHOD=AES-128,URI=\"key.bin\"\n\
+                        #EXT-X-KEY:METHOD=NONE\n\
+                        #EXTINF:4,\n0.ts\n";
+        let base_url = static_server(HashMap::from([("/0.ts", ts_payload(0))])).await;
+        let (segments, infos) =
+            parse_m3u8(playlist, &base_url, &PlaylistLimits::default(), &|_| {}).unwrap();
+        // A key that never arrives
+        let keys = infos
+            .into_iter()
+            .map(|info| SegmentDecryption {
+                info,
+                key: PendingKey::spawn(futures::future::pending()),
+            })
+            .collect();
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (sender, _receiver) = mpsc::channel(100);
+        let result = tokio::time::timeout(
+            Duration::from_secs(5),
+            download_segments_concurrently(
+                &Client::new(),
+                &base_url,
+                segments,
+                keys,
+                1,
+                1,
+                temp_dir.path().to_path_buf(),
+                Arc::new(ErrorSummary::default()),
+                Arc::new(ContentTypeRules::default()),
+                Arc::new(SegmentReport::default()),
+                &CancellationToken::new(),
+                &PauseGate::default(),
+                sender,
+                egui::Context::default(),
+            ),
+        )
+        .await
+        .expect("clear segment waited for the key");
+        assert_eq!(fs::read(&result.unwrap()[0]).unwrap(), ts_payload(0));
+    }
+
+    #[tokio::test]
+    async fn key_failures_are_classified_by_cause() {
+        let mut files = HashMap::new();
+        files.insert("/short.bin", vec![1u8; 3]);
+        let base = static_server(files).await;
+        let client = Client::new();
+        let fetch = |path: &str| {
+            let client = client.clone();
+            let url = base.join(path).unwrap();
+            PendingKey::spawn(async move {
+                download_key_file(&client, &url, "AES-128", false, &|_| {}).await
+            })
+        };
+
+        // A key server answering 404 is a network problem, not a decryption one
+        let missing = fetch("missing.bin").get().await.unwrap_err();
+        assert_eq!(FailureKind::of(&missing), FailureKind::Network);
+        let short = fetch("short.bin").get().await.unwrap_err();
+        assert_eq!(FailureKind::of(&short), FailureKind::Decrypt);
+    }
+
+    // NIST SP 800-38A, F.2.5 CBC-AES256.Encrypt
+    const NIST_AES256_KEY: &str =
+        "603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4";
+    const NIST_IV: &str = "000102030405060708090a0b0c0d0e0f";
+    const NIST_PLAIN: &str = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51\
+                              30c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710";
+    const NIST_AES256_CIPHER: &str = "f58c4c04d6e5f1ba779eabfb5f7bfbd69cfc4e967edb808d679f777bc6702c7d\
+                                      39f23369a9d9bacfa530e26304231461b2eb05e2c39be9fcda6c19078c6a9d1b";
+
+    #[test]
+    fn aes256_cbc_matches_the_nist_vectors() {
+        let key = SegmentKey::from_bytes(&hex::decode(NIST_AES256_KEY).unwrap()).unwrap();
+        let iv: [u8; KEY_LEN] = hex::decode(NIST_IV).unwrap().try_into().unwrap();
+        let plain = hex::decode(NIST_PLAIN).unwrap();
+
+        // Segments carry PKCS#7 padding: the NIST blocks followed by one full padding block
+        let SegmentKey::Aes256(key_bytes) = &key else {
+            panic!("32-byte key is not AES-256");
+        };
+        let encrypted = Encryptor::<Aes256>::new(key_bytes.into(), (&iv).into())
+            .encrypt_padded_vec_mut::<Pkcs7>(&plain);
+        assert_eq!(encrypted.len(), plain.len() + 16);
+        assert_eq!(hex::encode(&encrypted[..plain.len()]), NIST_AES256_CIPHER);
+
+        assert_eq!(decrypt_cbc(&key, &iv, &encrypted).unwrap(), plain);
+        // Without the padding block the last block does not unpad
+        assert!(decrypt_cbc(&key, &iv, &encrypted[..plain.len()]).is_err());
     }
 }
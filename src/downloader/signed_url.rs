@@ -0,0 +1,336 @@
+// Expiry detection for signed segment URLs, judged by the server's clock (from its `Date`
+// header) rather than the local one, which may be skewed. Offsets are kept per host, so one
+// job's server cannot skew the expiry checks of another job's.
+
+use reqwest::Url;
+
+use super::hls_parser::{EncryptionInfo, Segment};
+use super::util::days_from_civil;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Local clock offsets below this are not worth a warning
+const SKEW_WARN_THRESHOLD_SECS: i64 = 30;
+/// Warn when signed URLs expire within this time (server clock)
+const EXPIRY_WARN_WINDOW_SECS: i64 = 10 * 60;
+
+/// Server time minus local time per host, from the most recent `Date` header each host sent
+static SERVER_OFFSETS: Mutex<Option<HashMap<String, i64>>> = Mutex::new(None);
+
+fn local_now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+/// Server time minus `local_now` according to an HTTP `Date` header.
+fn offset_from_date(date_header: &str, local_now: i64) -> Option<i64> {
+    let server_time = httpdate::parse_http_date(date_header).ok()?;
+    let since_epoch = server_time.duration_since(UNIX_EPOCH).ok()?;
+    Some(since_epoch.as_secs() as i64 - local_now)
+}
+
+/// Remembers the offset between the `Date` header `url`'s server sent and the local clock.
+pub fn record_server_date(url: &Url, date_header: &str) {
+    let (Some(host), Some(offset)) = (url.host_str(), offset_from_date(date_header, local_now()))
+    else {
+        return;
+    };
+    SERVER_OFFSETS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .get_or_insert_with(HashMap::new)
+        .insert(host.to_string(), offset);
+}
+
+/// Server time minus local time for `url`'s host, if it has sent a `Date` header.
+pub fn server_offset(url: &Url) -> Option<i64> {
+    let host = url.host_str()?;
+    SERVER_OFFSETS
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .as_ref()?
+        .get(host)
+        .copied()
+}
+
+/// Expiry (Unix seconds) encoded in common signed-URL schemes:
+/// `Expires`/`expires`/`exp` (CloudFront, generic), Akamai `hdnts`/`__token__` (`exp=...`),
+/// and `X-Amz-Date` + `X-Amz-Expires` (S3 presigned).
+pub fn expiry_of(url: &Url) -> Option<i64> {
+    let mut amz_date = None;
+    let mut amz_expires = None;
+    for (name, value) in url.query_pairs() {
+        match name.as_ref() {
+            "Expires" | "expires" | "exp" | "expiry" => {
+                if let Ok(secs) = value.parse::<i64>() {
+                    return Some(secs);
+                }
+            }
+            "hdnts" | "__token__" => {
+                let exp = value
+                    .split('~')
+                    .find_map(|field| field.strip_prefix("exp="))
+                    .and_then(|secs| secs.parse::<i64>().ok());
+                if exp.is_some() {
+                    return exp;
+                }
+            }
+            "X-Amz-Date" => amz_date = parse_amz_date(&value),
+            "X-Amz-Expires" => amz_expires = value.parse::<i64>().ok(),
+            _ => {}
+        }
+    }
+    Some(amz_date? + amz_expires?)
+}
+
+/// `YYYYMMDDTHHMMSSZ` to Unix seconds.
+fn parse_amz_date(value: &str) -> Option<i64> {
+    if value.len() != 16 || !value.ends_with('Z') || value.as_bytes()[8] != b'T' {
+        return None;
+    }
+    let field = |range: std::ops::Range<usize>| value.get(range)?.parse::<i64>().ok();
+    let (year, month, day) = (field(0..4)?, field(4..6)?, field(6..8)?);
+    let (hour, minute, second) = (field(9..11)?, field(11..13)?, field(13..15)?);
+    Some(days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 + second)
+}
+
+/// Adds the query parameters of `source` that `url` does not set itself, as `source` spells
+/// them (re-encoding could break a signature). Only URLs on `source`'s host get them, so a CDN
+/// token is not handed to a third-party key server.
+pub fn propagate_query(url: &mut Url, source: &Url) {
+    if !matches!(url.scheme(), "http" | "https") || url.host_str() != source.host_str() {
+        return;
+    }
+    let Some(source_query) = source.query() else {
+        return;
+    };
+    let name_of = |pair: &str| {
+        pair.split_once('=')
+            .map_or(pair, |(name, _)| name)
+            .to_string()
+    };
+    let existing: Vec<String> = url
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .map(name_of)
+        .collect();
+    let missing: Vec<&str> = source_query
+        .split('&')
+        .filter(|pair| !pair.is_empty() && !existing.contains(&name_of(pair)))
+        .collect();
+    if missing.is_empty() {
+        return;
+    }
+    let query = match url.query().filter(|query| !query.is_empty()) {
+        Some(query) => format!("{}&{}", query, missing.join("&")),
+        None => missing.join("&"),
+    };
+    url.set_query(Some(&query));
+}
+
+/// Gives every segment, init section and key URL the query parameters of `source` (e.g. a
+/// `?token=` the CDN expects on each request). Segment and init paths become absolute URLs.
+pub fn propagate_to_playlist(
+    segments: &mut [Segment],
+    keys: &mut [EncryptionInfo],
+    base_url: &Url,
+    source: &Url,
+) {
+    let with_query = |path: &str| {
+        base_url.join(path).map_or_else(
+            |_| path.to_string(),
+            |mut url| {
+                propagate_query(&mut url, source);
+                url.to_string()
+            },
+        )
+    };
+    for segment in segments {
+        segment.path = with_query(&segment.path);
+        if let Some(init) = &mut segment.init {
+            init.uri = with_query(&init.uri);
+        }
+    }
+    for key in keys {
+        propagate_query(&mut key.key_url, source);
+    }
+}
+
+/// True if the URL carries an expiry that has passed by the server's clock. `date_header` is
+/// the `Date` of the response that rejected the URL; without one the host's last known offset
+/// is used (local clock when there is none).
+pub fn is_expired(url: &Url, date_header: Option<&str>) -> bool {
+    let now = local_now();
+    let offset = date_header
+        .and_then(|date| offset_from_date(date, now))
+        .or_else(|| server_offset(url))
+        .unwrap_or(0);
+    expiry_of(url).is_some_and(|expiry| expiry <= now + offset)
+}
+
+/// Logs warnings about signed segment URLs: expiry soon or already past (by server time), and
+/// local clock skew large enough to make expiry checks or signatures misleading. The clock of
+/// the host serving the earliest-expiring URL is used, else that of `playlist_url`'s host.
+pub fn check_signed_urls(
+    urls: impl IntoIterator<Item = Url>,
+    playlist_url: &Url,
+    send_log: &impl Fn(String),
+) {
+    let Some((earliest, url)) = urls
+        .into_iter()
+        .filter_map(|url| Some((expiry_of(&url)?, url)))
+        .min_by_key(|(expiry, _)| *expiry)
+    else {
+        return;
+    };
+    let offset = server_offset(&url).or_else(|| server_offset(playlist_url));
+    let remaining = earliest - (local_now() + offset.unwrap_or(0));
+
+    if let Some(offset) = offset
+        && offset.abs() >= SKEW_WARN_THRESHOLD_SECS
+    {
+        send_log(format!(
+            "⚠️ Warning: Local clock is {}s {} the server (per its Date header); signed URL expiry is judged by server time.",
+            offset.abs(),
+            if offset < 0 { "ahead of" } else { "behind" }
+        ));
+        let local_remaining = earliest - local_now();
+        if local_remaining <= 0 && remaining > 0 {
+            send_log(
+                "  The URLs look expired by the local clock but are still valid on the server."
+                    .to_string(),
+            );
+        }
+    }
+
+    if remaining <= 0 {
+        send_log(format!(
+            "⚠️ Warning: Signed segment URLs expired {}s ago (server time); expect 403 errors. Reload the playlist to get fresh URLs.",
+            -remaining
+        ));
+    } else if remaining < EXPIRY_WARN_WINDOW_SECS {
+        send_log(format!(
+            "⚠️ Warning: Signed segment URLs expire in {}m {}s (server time); a long download may hit 403 errors.",
+            remaining / 60,
+            remaining % 60
+        ));
+    } else {
+        send_log(format!(
+            "-> Signed segment URLs detected, valid for another {}m (server time{}).",
+            remaining / 60,
+            if offset.is_some() {
+                ""
+            } else {
+                " unknown, using local clock"
+            }
+        ));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(s: &str) -> Url {
+        Url::parse(s).unwrap()
+    }
+
+    #[test]
+    fn expiry_of_reads_common_schemes() {
+        assert_eq!(
+            expiry_of(&url("https://cdn.test/a.ts?Expires=1700000000&Signature=x")),
+            Some(1_700_000_000)
+        );
+        assert_eq!(
+            expiry_of(&url(
+                "https://cdn.test/a.ts?hdnts=st=1~exp=1700000600~acl=*"
+            )),
+            Some(1_700_000_600)
+        );
+        // 2023-11-14T22:13:20Z plus one hour
+        assert_eq!(
+            expiry_of(&url(
+                "https://s3.test/a.ts?X-Amz-Date=20231114T221320Z&X-Amz-Expires=3600"
+            )),
+            Some(1_700_000_000 + 3600)
+        );
+        assert_eq!(
+            expiry_of(&url("https://s3.test/a.ts?X-Amz-Expires=3600")),
+            None
+        );
+        assert_eq!(expiry_of(&url("https://cdn.test/a.ts")), None);
+    }
+
+    #[test]
+    fn amz_dates_must_be_well_formed() {
+        assert_eq!(parse_amz_date("19700101T000000Z"), Some(0));
+        assert_eq!(parse_amz_date("20240229T120000Z"), Some(1_709_208_000));
+        assert_eq!(parse_amz_date("20240229 120000Z"), None);
+        assert_eq!(parse_amz_date("20240229T120000"), None);
+    }
+
+    #[test]
+    fn offset_is_server_minus_local() {
+        // Sun, 06 Nov 1994 08:49:37 GMT
+        let date = "Sun, 06 Nov 1994 08:49:37 GMT";
+        assert_eq!(offset_from_date(date, 784_111_777), Some(0));
+        assert_eq!(offset_from_date(date, 784_111_777 + 90), Some(-90));
+        assert_eq!(offset_from_date(date, 784_111_777 - 45), Some(45));
+        assert_eq!(offset_from_date("yesterday", 0), None);
+    }
+
+    #[test]
+    fn expiry_is_judged_by_the_rejecting_server() {
+        let signed = url("https://expiry.test/a.ts?Expires=784111777");
+        // The server says it is one second past the expiry, whatever the local clock says
+        assert!(is_expired(&signed, Some("Sun, 06 Nov 1994 08:49:38 GMT")));
+        let later = url(&format!(
+            "https://expiry.test/a.ts?Expires={}",
+            local_now() + 3600
+        ));
+        assert!(!is_expired(&later, Some("garbage")));
+        assert!(!is_expired(&url("https://expiry.test/a.ts"), None));
+    }
+
+    #[test]
+    fn query_is_propagated_verbatim_to_the_same_host() {
+        let source = url("https://cdn.test/index.m3u8?token=a+b%2Fc%3D&exp=1&sig=x");
+        let mut segment = url("https://cdn.test/seg/0.ts?exp=2");
+        propagate_query(&mut segment, &source);
+        assert_eq!(
+            segment.as_str(),
+            "https://cdn.test/seg/0.ts?exp=2&token=a+b%2Fc%3D&sig=x"
+        );
+
+        let mut bare = url("https://cdn.test/seg/1.ts");
+        propagate_query(&mut bare, &source);
+        assert_eq!(bare.query(), Some("token=a+b%2Fc%3D&exp=1&sig=x"));
+    }
+
+    #[test]
+    fn query_is_not_sent_to_other_hosts() {
+        let source = url("https://cdn.test/index.m3u8?token=secret");
+        for target in [
+            "https://keys.example/key.bin",
+            "https://other.cdn.test/0.ts",
+            "data:text/plain,key",
+        ] {
+            let mut target = url(target);
+            let before = target.clone();
+            propagate_query(&mut target, &source);
+            assert_eq!(target, before);
+        }
+    }
+
+    #[test]
+    fn offsets_are_kept_per_host() {
+        let one = url("https://offset-one.test/index.m3u8");
+        let two = url("https://offset-two.test/index.m3u8");
+        record_server_date(&one, "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert!(server_offset(&one).is_some_and(|offset| offset < 0));
+        assert_eq!(server_offset(&two), None);
+    }
+}
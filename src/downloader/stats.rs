@@ -0,0 +1,316 @@
+// Process-wide transfer counters shared by every running job (dashboard, metrics).
+
+use reqwest::Url;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+use tokio::runtime::Handle;
+
+static GLOBAL_STATS: LazyLock<GlobalStats> = LazyLock::new(GlobalStats::default);
+
+/// Returns the counters shared by all jobs.
+pub fn global() -> &'static GlobalStats {
+    &GLOBAL_STATS
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HostCounters {
+    pub active_connections: usize,
+    pub bytes_downloaded: u64,
+}
+
+/// Load of the tokio runtime a snapshot was taken on
+#[derive(Debug, Clone, Default)]
+pub struct RuntimeLoad {
+    pub workers: usize,
+    pub alive_tasks: usize,
+    /// Tasks waiting in the shared queue for a free worker
+    pub global_queue_depth: usize,
+    /// Time all workers together spent running tasks since the runtime started
+    pub busy: Duration,
+}
+
+impl RuntimeLoad {
+    fn of(handle: &Handle) -> Self {
+        let metrics = handle.metrics();
+        let workers = metrics.num_workers();
+        #[cfg(target_has_atomic = "64")]
+        let busy = (0..workers)
+            .map(|worker| metrics.worker_total_busy_duration(worker))
+            .sum();
+        #[cfg(not(target_has_atomic = "64"))]
+        let busy = Duration::ZERO;
+        Self {
+            workers,
+            alive_tasks: metrics.num_alive_tasks(),
+            global_queue_depth: metrics.global_queue_depth(),
+            busy,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct GlobalStats {
+    active_connections: AtomicUsize,
+    active_jobs: AtomicUsize,
+    segments_downloaded: AtomicU64,
+    segment_retries: AtomicU64,
+    bytes_downloaded: AtomicU64,
+    bytes_written: AtomicU64,
+    hosts: Mutex<HashMap<String, HostCounters>>,
+}
+
+impl GlobalStats {
+    /// Marks a request to `url` as in flight until the returned guard is dropped.
+    pub fn open_connection(&'static self, url: &Url) -> ConnectionGuard {
+        let host = host_of(url);
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+        self.with_host(&host, |counters| counters.active_connections += 1);
+        ConnectionGuard { stats: self, host }
+    }
+
+    pub fn record_download(&self, url: &Url, bytes: u64) {
+        self.bytes_downloaded.fetch_add(bytes, Ordering::Relaxed);
+        self.with_host(&host_of(url), |counters| counters.bytes_downloaded += bytes);
+    }
+
+    pub fn record_write(&self, bytes: u64) {
+        self.bytes_written.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn record_segment_done(&self) {
+        self.segments_downloaded.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_retry(&self) {
+        self.segment_retries.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a download job as active until the returned guard is dropped.
+    pub fn start_job(&'static self) -> JobGuard {
+        self.active_jobs.fetch_add(1, Ordering::Relaxed);
+        JobGuard { stats: self }
+    }
+
+    /// Copies the counters; runtime load is included when called inside a tokio runtime.
+    pub fn snapshot(&self) -> StatsSnapshot {
+        let mut hosts: Vec<(String, HostCounters)> = self
+            .hosts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(host, counters)| (host.clone(), counters.clone()))
+            .collect();
+        hosts.sort_by(|a, b| a.0.cmp(&b.0));
+        StatsSnapshot {
+            taken_at: Instant::now(),
+            active_connections: self.active_connections.load(Ordering::Relaxed),
+            active_jobs: self.active_jobs.load(Ordering::Relaxed),
+            segments_downloaded: self.segments_downloaded.load(Ordering::Relaxed),
+            segment_retries: self.segment_retries.load(Ordering::Relaxed),
+            bytes_downloaded: self.bytes_downloaded.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+            hosts,
+            runtime: Handle::try_current()
+                .ok()
+                .map(|handle| RuntimeLoad::of(&handle)),
+        }
+    }
+
+    fn with_host(&self, host: &str, update: impl FnOnce(&mut HostCounters)) {
+        let mut hosts = self.hosts.lock().unwrap();
+        update(hosts.entry(host.to_string()).or_default());
+    }
+}
+
+/// Decrements the active connection counters when dropped.
+pub struct ConnectionGuard {
+    stats: &'static GlobalStats,
+    host: String,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.stats
+            .active_connections
+            .fetch_sub(1, Ordering::Relaxed);
+        self.stats.with_host(&self.host, |counters| {
+            counters.active_connections = counters.active_connections.saturating_sub(1)
+        });
+    }
+}
+
+/// Decrements the active job counter when dropped.
+pub struct JobGuard {
+    stats: &'static GlobalStats,
+}
+
+impl Drop for JobGuard {
+    fn drop(&mut self) {
+        self.stats.active_jobs.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Point-in-time copy of the counters; rates are derived by comparing two snapshots.
+#[derive(Debug, Clone)]
+pub struct StatsSnapshot {
+    pub taken_at: Instant,
+    pub active_connections: usize,
+    pub active_jobs: usize,
+    pub segments_downloaded: u64,
+    pub segment_retries: u64,
+    pub bytes_downloaded: u64,
+    pub bytes_written: u64,
+    pub hosts: Vec<(String, HostCounters)>,
+    pub runtime: Option<RuntimeLoad>,
+}
+
+/// Per-second rates between two snapshots
+#[derive(Debug, Clone, Default)]
+pub struct StatsRates {
+    pub download_bytes_per_sec: f64,
+    pub write_bytes_per_sec: f64,
+    pub host_bytes_per_sec: HashMap<String, f64>,
+    /// Share of the interval the runtime's workers were busy (0.0 to 1.0)
+    pub runtime_busy: Option<f64>,
+}
+
+impl StatsSnapshot {
+    pub fn rates_since(&self, previous: &StatsSnapshot) -> StatsRates {
+        let secs = self
+            .taken_at
+            .duration_since(previous.taken_at)
+            .as_secs_f64();
+        if secs <= 0.0 {
+            return StatsRates::default();
+        }
+        let rate = |now: u64, before: u64| now.saturating_sub(before) as f64 / secs;
+        let host_bytes_per_sec = self
+            .hosts
+            .iter()
+            .map(|(host, counters)| {
+                let before = previous
+                    .hosts
+                    .iter()
+                    .find(|(h, _)| h == host)
+                    .map_or(0, |(_, c)| c.bytes_downloaded);
+                (host.clone(), rate(counters.bytes_downloaded, before))
+            })
+            .collect();
+        let runtime_busy = match (&self.runtime, &previous.runtime) {
+            (Some(now), Some(before)) if now.workers > 0 => Some(
+                (now.busy.saturating_sub(before.busy).as_secs_f64() / (secs * now.workers as f64))
+                    .min(1.0),
+            ),
+            _ => None,
+        };
+        StatsRates {
+            download_bytes_per_sec: rate(self.bytes_downloaded, previous.bytes_downloaded),
+            write_bytes_per_sec: rate(self.bytes_written, previous.bytes_written),
+            host_bytes_per_sec,
+            runtime_busy,
+        }
+    }
+
+    /// Renders the counters in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: String| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{} {}", name, value);
+        };
+        metric(
+            "hlsdl_segments_downloaded_total",
+            "counter",
+            "Segments downloaded and written to disk.",
+            self.segments_downloaded.to_string(),
+        );
+        metric(
+            "hlsdl_downloaded_bytes_total",
+            "counter",
+            "Bytes received for media segments.",
+            self.bytes_downloaded.to_string(),
+        );
+        metric(
+            "hlsdl_written_bytes_total",
+            "counter",
+            "Bytes written to disk (segments and concatenation).",
+            self.bytes_written.to_string(),
+        );
+        metric(
+            "hlsdl_segment_retries_total",
+            "counter",
+            "Segment requests that were retried.",
+            self.segment_retries.to_string(),
+        );
+        metric(
+            "hlsdl_active_jobs",
+            "gauge",
+            "Download jobs currently running.",
+            self.active_jobs.to_string(),
+        );
+        metric(
+            "hlsdl_active_connections",
+            "gauge",
+            "Segment requests currently in flight.",
+            self.active_connections.to_string(),
+        );
+        if let Some(runtime) = &self.runtime {
+            metric(
+                "hlsdl_runtime_workers",
+                "gauge",
+                "Worker threads of the async runtime.",
+                runtime.workers.to_string(),
+            );
+            metric(
+                "hlsdl_runtime_alive_tasks",
+                "gauge",
+                "Tasks alive on the async runtime.",
+                runtime.alive_tasks.to_string(),
+            );
+            metric(
+                "hlsdl_runtime_global_queue_depth",
+                "gauge",
+                "Tasks waiting in the runtime's shared queue.",
+                runtime.global_queue_depth.to_string(),
+            );
+            metric(
+                "hlsdl_runtime_busy_seconds_total",
+                "counter",
+                "Time the runtime's workers spent running tasks.",
+                format!("{:.3}", runtime.busy.as_secs_f64()),
+            );
+        }
+
+        out.push_str("# HELP hlsdl_host_downloaded_bytes_total Bytes received per host.\n");
+        out.push_str("# TYPE hlsdl_host_downloaded_bytes_total counter\n");
+        for (host, counters) in &self.hosts {
+            let _ = writeln!(
+                out,
+                "hlsdl_host_downloaded_bytes_total{{host=\"{}\"}} {}",
+                host.replace('\\', "\\\\").replace('"', "\\\""),
+                counters.bytes_downloaded
+            );
+        }
+        out
+    }
+}
+
+/// Human-readable byte count (KiB/MiB/GiB)
+pub fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+pub fn host_of(url: &Url) -> String {
+    url.host_str().unwrap_or("unknown").to_string()
+}
@@ -0,0 +1,379 @@
+// Subtitle track handling: soft-muxing a text track, burning it into the video or saving it
+// as a sidecar file next to it.
+
+use anyhow::{Result, anyhow};
+use egui::Context as EguiContext;
+use futures::{StreamExt, TryStreamExt};
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use super::DownloadMessage;
+use super::cancel::{self, Cancelled};
+use super::failure;
+use super::hls_parser::{TrackMetadata, parse_m3u8};
+use super::limits::PlaylistLimits;
+use super::segment_io::{SubtitleTrack, ffmpeg_progress_command, run_progress_command};
+use super::util::PathStringLossy;
+use super::webvtt::{self, Cue, Stitcher};
+
+/// File name of the converted subtitle track inside the job's temp dir / workspace
+const SUBTITLE_FILENAME: &str = "subtitles.srt";
+/// File name of the stitched WebVTT track handed to the remux
+const SOFT_TRACK_FILENAME: &str = "subtitles.vtt";
+/// Subtitle playlist segments downloaded at once
+const SEGMENT_CONCURRENCY: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SubtitleMode {
+    /// Added as a selectable subtitle track while remuxing (no re-encode)
+    #[default]
+    SoftMux,
+    /// Rendered into the video frames (re-encodes video)
+    BurnIn,
+    /// Saved as `<name>.vtt` next to the video (no FFmpeg)
+    SidecarVtt,
+    /// Saved as `<name>.srt` next to the video (no FFmpeg)
+    SidecarSrt,
+}
+
+impl SubtitleMode {
+    pub const ALL: [SubtitleMode; 4] = [
+        SubtitleMode::SoftMux,
+        SubtitleMode::BurnIn,
+        SubtitleMode::SidecarVtt,
+        SubtitleMode::SidecarSrt,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            SubtitleMode::SoftMux => "Soft (selectable track)",
+            SubtitleMode::BurnIn => "Burn into video",
+            SubtitleMode::SidecarVtt => "Sidecar .vtt file",
+            SubtitleMode::SidecarSrt => "Sidecar .srt file",
+        }
+    }
+
+    /// Extension of the file written next to the video; None when the video itself changes
+    pub fn sidecar_extension(self) -> Option<&'static str> {
+        match self {
+            SubtitleMode::SidecarVtt => Some("vtt"),
+            SubtitleMode::SidecarSrt => Some("srt"),
+            SubtitleMode::SoftMux | SubtitleMode::BurnIn => None,
+        }
+    }
+}
+
+/// Loads the subtitle source (local file, `.vtt`/`.srt` URL or a WebVTT media playlist, whose
+/// segments are stitched together) and writes it to `path`, as SRT for `.srt` and WebVTT otherwise.
+pub async fn save_subtitles(
+    client: &Client,
+    source: &str,
+    path: &Path,
+    send_log: &impl Fn(String),
+    cancel: &CancellationToken,
+) -> Result<()> {
+    let cues = load_cues(client, source, send_log, cancel).await?;
+    if cues.is_empty() {
+        return Err(anyhow!("The subtitle source contains no cues"));
+    }
+    let text = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("srt") => webvtt::to_srt(&cues),
+        _ => webvtt::to_vtt(&cues),
+    };
+    tokio::fs::write(path, text).await?;
+    Ok(())
+}
+
+/// Downloads the soft subtitle track into `work_dir` so the remux can mux it in. A track that
+/// cannot be loaded is logged and left out; the video is still produced.
+pub async fn prepare_soft_track(
+    client: &Client,
+    source: &str,
+    track: TrackMetadata,
+    work_dir: &Path,
+    send_log: &impl Fn(String),
+    cancel: &CancellationToken,
+) -> Result<Option<SubtitleTrack>> {
+    send_log(format!("💬 Downloading subtitles from {}...", source));
+    let path = work_dir.join(SOFT_TRACK_FILENAME);
+    match save_subtitles(client, source, &path, send_log, cancel).await {
+        Ok(()) => Ok(Some(SubtitleTrack { path, track })),
+        Err(e) if e.is::<Cancelled>() => Err(e),
+        Err(e) => {
+            send_log(format!(
+                "⚠️ Warning: Subtitles could not be loaded ({}); the video is saved without them.",
+                e
+            ));
+            Ok(None)
+        }
+    }
+}
+
+/// Codec for a text subtitle stream in a container with extension `ext`; None if it has none.
+pub fn text_codec(ext: &str) -> Option<&'static str> {
+    match ext {
+        "mp4" | "mov" | "m4a" => Some("mov_text"),
+        "mkv" => Some("srt"),
+        "webm" => Some("webvtt"),
+        _ => None,
+    }
+}
+
+async fn load_cues(
+    client: &Client,
+    source: &str,
+    send_log: &impl Fn(String),
+    cancel: &CancellationToken,
+) -> Result<Vec<Cue>> {
+    let url = match std::fs::canonicalize(source) {
+        Ok(path) => Url::from_file_path(path).map_err(|_| anyhow!("Invalid subtitle path"))?,
+        Err(_) => Url::parse(source).map_err(|e| anyhow!("Invalid subtitle URL: {}", e))?,
+    };
+    let (url, body) = fetch_text(client, &url, cancel).await?;
+    let mut stitcher = Stitcher::default();
+    if !body.trim_start().starts_with("#EXTM3U") {
+        stitcher.add(webvtt::parse(&body), 0.0);
+        return Ok(stitcher.finish());
+    }
+
+    let (segments, _) = parse_m3u8(&body, &url, &PlaylistLimits::default(), send_log)?;
+    send_log(format!(
+        "-> Subtitle playlist with {} segments",
+        segments.len()
+    ));
+    let mut starts = Vec::with_capacity(segments.len());
+    let mut position = 0.0;
+    for segment in &segments {
+        starts.push(position);
+        position += segment.duration;
+    }
+    let segment_urls = segments
+        .iter()
+        .map(|segment| url.join(&segment.path))
+        .collect::<Result<Vec<_>, _>>()?;
+    let texts: Vec<String> = futures::stream::iter(segment_urls)
+        .map(|segment_url| {
+            let (client, cancel) = (client.clone(), cancel.clone());
+            async move {
+                fetch_text(&client, &segment_url, &cancel)
+                    .await
+                    .map(|(_, body)| body)
+            }
+        })
+        .buffered(SEGMENT_CONCURRENCY)
+        .try_collect()
+        .await?;
+    for (text, start) in texts.iter().zip(starts) {
+        stitcher.add(webvtt::parse(text), start);
+    }
+    Ok(stitcher.finish())
+}
+
+/// Body of `url` and the URL it was finally served from (after redirects).
+async fn fetch_text(
+    client: &Client,
+    url: &Url,
+    cancel: &CancellationToken,
+) -> Result<(Url, String)> {
+    if url.scheme() == "file" {
+        let path = url
+            .to_file_path()
+            .map_err(|_| anyhow!("Invalid subtitle path {}", url))?;
+        return Ok((url.clone(), tokio::fs::read_to_string(path).await?));
+    }
+    cancel::or_cancel(cancel, async {
+        let response = client
+            .get(url.clone())
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| failure::network(format!("Subtitle download failed: {}", e)))?;
+        let url = response.url().clone();
+        Ok((url, response.text().await?))
+    })
+    .await
+}
+
+/// Converts the subtitle source (local file, `.vtt`/`.srt` URL or a WebVTT media playlist) into
+/// an SRT file in `dest_dir`.
+pub fn fetch_subtitles(
+    source: &str,
+    headers: &[(String, String)],
+    dest_dir: &Path,
+    cancel: &CancellationToken,
+) -> Result<PathBuf> {
+    let output = dest_dir.join(SUBTITLE_FILENAME);
+    let header_arg: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}: {}\r\n", name, value))
+        .collect();
+
+    let mut command = ffmpeg_progress_command()?;
+    if !header_arg.is_empty() && !Path::new(source).exists() {
+        command.arg("-headers").arg(header_arg);
+    }
+    command
+        .args(["-i", source, "-map", "0:s:0", "-c:s", "srt", "-y"])
+        .arg(&output);
+    let run = run_progress_command(command, cancel, |_| {})?;
+    if !run.success {
+        return Err(failure::ffmpeg(format!(
+            "Failed to load subtitles: {}",
+            run.stderr
+        )));
+    }
+    Ok(output)
+}
+
+/// Renders `subtitles` into the frames of `video` in place (via a temporary file that replaces
+/// the original only on success).
+pub fn burn_in_subtitles(
+    video: &Path,
+    subtitles: &Path,
+    cancel: &CancellationToken,
+    on_progress: impl Fn(f32),
+) -> Result<()> {
+    let ext = video
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let temp_path = video.with_file_name(format!(
+        "{}.subtitles.{}",
+        video.to_path_buf().file_string_lossy(),
+        ext
+    ));
+
+    // Run from the subtitle's directory so the filter argument is a plain file name
+    // (avoids filtergraph escaping of drive letters and backslashes); the video paths are made
+    // absolute first so relative ones still resolve from there
+    let video_input = std::path::absolute(video)?;
+    let temp_path = std::path::absolute(temp_path)?;
+    let subtitles = std::path::absolute(subtitles)?;
+    let mut command = ffmpeg_progress_command()?;
+    command.arg("-i").arg(&video_input);
+    let dir = subtitles
+        .parent()
+        .ok_or_else(|| anyhow!("Invalid subtitle path"))?;
+    let name = subtitles
+        .file_name()
+        .ok_or_else(|| anyhow!("Invalid subtitle path"))?
+        .to_string_lossy()
+        .into_owned();
+    command.current_dir(dir);
+    command.args(["-vf", &format!("subtitles={}", name)]);
+    command.args(["-map", "0:v:0", "-map", "0:a?", "-c:a", "copy"]);
+    if ext == "webm" {
+        command.args(["-c:v", "libvpx-vp9", "-b:v", "0", "-crf", "32"]);
+    } else {
+        command.args(["-c:v", "libx264", "-preset", "veryfast", "-crf", "20"]);
+    }
+    if matches!(ext.as_str(), "mp4" | "mov" | "m4a") {
+        command.args(["-movflags", "+faststart"]);
+    }
+    command.arg("-y").arg(&temp_path);
+
+    match run_progress_command(command, cancel, on_progress) {
+        Ok(run) if run.success => {
+            std::fs::rename(&temp_path, video)?;
+            Ok(())
+        }
+        other => {
+            let _ = std::fs::remove_file(&temp_path);
+            match other {
+                Ok(run) => Err(failure::ffmpeg(format!(
+                    "Adding subtitles failed: {}",
+                    run.stderr
+                ))),
+                Err(e) => Err(e),
+            }
+        }
+    }
+}
+
+/// Saves the subtitle track next to `output` or burns it in (via `work_dir`) off the async
+/// runtime, reporting progress and log lines to the GUI. Soft tracks are muxed in during the
+/// remux instead (see `prepare_soft_track`).
+#[allow(clippy::too_many_arguments)]
+pub async fn run_subtitle_step(
+    client: &Client,
+    source: String,
+    headers: Vec<(String, String)>,
+    mode: SubtitleMode,
+    output: PathBuf,
+    work_dir: PathBuf,
+    cancel: CancellationToken,
+    sender: mpsc::Sender<DownloadMessage>,
+    ctx: EguiContext,
+) -> Result<()> {
+    if mode == SubtitleMode::SoftMux {
+        return Ok(());
+    }
+    if let Some(extension) = mode.sidecar_extension() {
+        sender
+            .send(DownloadMessage::Log(format!(
+                "💬 Saving subtitles from {}...",
+                source
+            )))
+            .await
+            .ok();
+        ctx.request_repaint();
+        let send_log = |msg: String| {
+            sender.try_send(DownloadMessage::Log(msg)).ok();
+            ctx.request_repaint();
+        };
+        let path = output.with_extension(extension);
+        save_subtitles(client, &source, &path, &send_log, &cancel)
+            .await
+            .map_err(|e| {
+                if e.is::<Cancelled>() {
+                    return e;
+                }
+                anyhow!("{}. The video was saved without subtitles", e)
+            })?;
+        send_log(format!("✅ Subtitles saved as {}.", path.display()));
+        return Ok(());
+    }
+    sender
+        .send(DownloadMessage::Log(format!(
+            "💬 Burning subtitles from {} into the video (re-encoding)...",
+            source
+        )))
+        .await
+        .ok();
+    ctx.request_repaint();
+
+    let progress_sender = sender.clone();
+    let progress_ctx = ctx.clone();
+    let apply_output = output.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        let subtitles = fetch_subtitles(&source, &headers, &work_dir, &cancel)?;
+        burn_in_subtitles(&apply_output, &subtitles, &cancel, |p| {
+            progress_sender.try_send(DownloadMessage::Progress(p)).ok();
+            progress_ctx.request_repaint();
+        })
+    })
+    .await
+    .map_err(|e| anyhow!("Subtitle blocking task failed to join: {}", e))?;
+
+    if let Err(e) = result {
+        if e.is::<Cancelled>() {
+            return Err(e);
+        }
+        return Err(failure::ffmpeg(format!(
+            "{}. The file without subtitles was kept at {}",
+            e,
+            output.display()
+        )));
+    }
+    sender.send(DownloadMessage::Progress(1.0)).await.ok();
+    sender
+        .send(DownloadMessage::Log("✅ Subtitles added.".to_string()))
+        .await
+        .ok();
+    ctx.request_repaint();
+    Ok(())
+}
@@ -0,0 +1,163 @@
+// Segment URL rewrite rules: regex find/replace applied to the segment and init section URLs of
+// a job before they are fetched, e.g. to swap a dead CDN hostname or strip a broken parameter.
+
+use anyhow::{Result, anyhow};
+use regex::Regex;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use super::hls_parser::Segment;
+
+/// Separates pattern and replacement in the text form of a rule
+const RULE_SEPARATOR: &str = "=>";
+
+/// One find/replace rule of a job
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RewriteRule {
+    /// Regular expression matched against the absolute URL
+    pub pattern: String,
+    /// Replaces every match; `$1` or `${name}` insert capture groups
+    #[serde(default)]
+    pub replacement: String,
+}
+
+impl RewriteRule {
+    /// Parses `pattern => replacement`; without a replacement the matches are deleted.
+    pub fn parse(line: &str) -> Result<Self> {
+        let (pattern, replacement) = line
+            .split_once(RULE_SEPARATOR)
+            .ok_or_else(|| anyhow!("Rewrite rule needs `pattern => replacement`: {}", line))?;
+        let rule = Self {
+            pattern: pattern.trim().to_string(),
+            replacement: replacement.trim().to_string(),
+        };
+        Regex::new(&rule.pattern)
+            .map_err(|e| anyhow!("Invalid rewrite pattern {}: {}", rule.pattern, e))?;
+        Ok(rule)
+    }
+
+    /// Rules of a text with one per line; blank lines and `#` comments are skipped.
+    pub fn parse_lines(text: &str) -> Result<Vec<Self>> {
+        text.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(Self::parse)
+            .collect()
+    }
+}
+
+impl fmt::Display for RewriteRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}",
+            self.pattern, RULE_SEPARATOR, self.replacement
+        )
+    }
+}
+
+/// The compiled rules of a job, applied in order
+#[derive(Debug, Clone, Default)]
+pub struct UrlRewriter {
+    rules: Vec<(Regex, String)>,
+}
+
+impl UrlRewriter {
+    pub fn new(rules: &[RewriteRule]) -> Result<Self> {
+        let rules = rules
+            .iter()
+            .map(|rule| {
+                Regex::new(&rule.pattern)
+                    .map(|regex| (regex, rule.replacement.clone()))
+                    .map_err(|e| anyhow!("Invalid rewrite pattern {}: {}", rule.pattern, e))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// `url` after every rule.
+    pub fn rewrite(&self, url: &str) -> String {
+        self.rules
+            .iter()
+            .fold(url.to_string(), |url, (regex, replacement)| {
+                regex.replace_all(&url, replacement.as_str()).into_owned()
+            })
+    }
+
+    /// Rewrites the segment and init section URLs of a playlist (made absolute against
+    /// `base_url`) and returns how many segments changed.
+    pub fn apply_to_segments(&self, segments: &mut [Segment], base_url: &Url) -> Result<usize> {
+        if self.is_empty() {
+            return Ok(0);
+        }
+        let rewrite = |path: &str| -> Result<String> {
+            let Ok(url) = base_url.join(path) else {
+                return Ok(path.to_string());
+            };
+            let rewritten = self.rewrite(url.as_str());
+            Url::parse(&rewritten)
+                .map_err(|e| anyhow!("Rewritten segment URL {} is invalid: {}", rewritten, e))?;
+            Ok(rewritten)
+        };
+        let mut changed = 0;
+        for segment in segments {
+            let path = rewrite(&segment.path)?;
+            if path != segment.path {
+                changed += 1;
+            }
+            segment.path = path;
+            if let Some(init) = &mut segment.init {
+                init.uri = rewrite(&init.uri)?;
+            }
+        }
+        Ok(changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::downloader::hls_parser::parse_m3u8;
+    use crate::downloader::limits::PlaylistLimits;
+
+    #[test]
+    fn parses_rules_and_rewrites_in_order() {
+        let rules = RewriteRule::parse_lines(
+            "# swap the dead edge\n\
+             ^https://edge1\\.example\\.com/ => https://edge2.example.com/\n\
+             \n\
+             [?&]broken=[^&]* =>\n",
+        )
+        .unwrap();
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[1].replacement, "");
+        let rewriter = UrlRewriter::new(&rules).unwrap();
+        assert_eq!(
+            rewriter.rewrite("https://edge1.example.com/v/seg1.ts?broken=1"),
+            "https://edge2.example.com/v/seg1.ts"
+        );
+        assert!(RewriteRule::parse("no separator").is_err());
+        assert!(RewriteRule::parse("([a- => x").is_err());
+    }
+
+    #[test]
+    fn rewrites_relative_segment_paths_with_capture_groups() {
+        let rules = [RewriteRule::parse(r"/(\d+)p/ => /hd/$1/").unwrap()];
+        let rewriter = UrlRewriter::new(&rules).unwrap();
+        let base = Url::parse("https://cdn.example.com/show/index.m3u8").unwrap();
+        let playlist =
+            "#EXTM3U\n#EXT-X-TARGETDURATION:4\n#EXTINF:4,\n720p/seg0.ts\n#EXT-X-ENDLIST\n";
+        let (mut segments, _) =
+            parse_m3u8(playlist, &base, &PlaylistLimits::default(), &|_| {}).unwrap();
+        assert_eq!(rewriter.apply_to_segments(&mut segments, &base).unwrap(), 1);
+        assert_eq!(
+            segments[0].path,
+            "https://cdn.example.com/show/hd/720/seg0.ts"
+        );
+    }
+}
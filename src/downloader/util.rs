@@ -1,4 +1,10 @@
-use std::path::PathBuf;
+use anyhow::{Result, bail};
+use reqwest::Url;
+use std::path::{Component, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Output file name used when none is given
+pub const DEFAULT_OUTPUT_NAME: &str = "output";
 
 // Helper Trait
 pub trait PathStringLossy {
@@ -13,3 +19,200 @@ impl PathStringLossy for PathBuf {
             .to_string()
     }
 }
+
+/// Expands `{name}` (playlist file stem), `{host}` (dots as `_`), `{date}` (UTC, YYYY-MM-DD),
+/// `{yyyy-mm}`, `{yyyy}` and `{mm}`.
+pub fn expand_output_template(template: &str, playlist_url: &str) -> String {
+    if !template.contains('{') {
+        return template.to_string();
+    }
+    let url = Url::parse(playlist_url).ok();
+    let host = url
+        .as_ref()
+        .and_then(|url| url.host_str())
+        .unwrap_or("stream")
+        // Dots would be taken for the file extension
+        .replace('.', "_");
+    let name = url
+        .as_ref()
+        .and_then(|url| url.path_segments()?.rfind(|s| !s.is_empty()))
+        .map(|file| file.split('.').next().unwrap_or(file).to_string())
+        .unwrap_or_else(|| DEFAULT_OUTPUT_NAME.to_string());
+    let today = today_utc();
+    template
+        .replace("{name}", &name)
+        .replace("{host}", &host)
+        .replace("{date}", &today)
+        .replace("{yyyy-mm}", &today[..7])
+        .replace("{yyyy}", &today[..4])
+        .replace("{mm}", &today[5..7])
+}
+
+/// Expands an output subfolder template into a path relative to the output directory.
+/// Absolute paths and `..` are refused: pushing them would leave the output directory.
+pub fn expand_output_subfolder(template: &str, playlist_url: &str) -> Result<PathBuf> {
+    let folder = PathBuf::from(expand_output_template(template, playlist_url));
+    for component in folder.components() {
+        match component {
+            Component::Normal(_) | Component::CurDir => {}
+            Component::ParentDir => bail!("Output subfolder must not contain '..': {}", template),
+            Component::RootDir | Component::Prefix(_) => {
+                bail!("Output subfolder must be a relative path: {}", template)
+            }
+        }
+    }
+    Ok(folder)
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date.
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// ISO 8601 date-time as used by `#EXT-X-PROGRAM-DATE-TIME`
+/// (`2024-05-01T20:00:00.000Z`, `...+02:00`) to Unix seconds. A space may replace the `T`;
+/// without a zone the time is taken as UTC.
+pub fn parse_date_time(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let (date, time) = value.split_once(['T', 't', ' '])?;
+    let mut date_fields = date.splitn(3, '-').map(|f| f.parse::<i64>().ok());
+    let (year, month, day) = (
+        date_fields.next()??,
+        date_fields.next()??,
+        date_fields.next()??,
+    );
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Zone: `Z`, `+hh:mm`, `+hhmm` or `+hh` (and `-`)
+    let (clock, offset) = if let Some(clock) = time.strip_suffix(['Z', 'z']) {
+        (clock, 0)
+    } else if let Some(sign_at) = time.rfind(['+', '-']) {
+        let (clock, zone) = time.split_at(sign_at);
+        let sign = if zone.starts_with('-') { -1 } else { 1 };
+        let digits: String = zone[1..].chars().filter(|c| *c != ':').collect();
+        let hours = digits.get(0..2)?.parse::<i64>().ok()?;
+        let minutes = match digits.get(2..) {
+            Some("") | None => 0,
+            Some(minutes) => minutes.parse::<i64>().ok()?,
+        };
+        (clock, sign * (hours * 3600 + minutes * 60))
+    } else {
+        (time, 0)
+    };
+    let mut clock_fields = clock.splitn(3, ':');
+    let hour = clock_fields.next()?.parse::<i64>().ok()?;
+    let minute = clock_fields.next()?.parse::<i64>().ok()?;
+    let second = clock_fields
+        .next()
+        .map_or(Some(0.0), |s| s.parse::<f64>().ok())?;
+    if hour > 23 || minute > 59 || !(0.0..61.0).contains(&second) {
+        return None;
+    }
+    let whole = days_from_civil(year, month, day) * 86_400 + hour * 3600 + minute * 60 - offset;
+    Some(whole as f64 + second)
+}
+
+/// Position in a recording as `[[hh:]mm:]ss[.fff]` (`90`, `1:30`, `01:02:03.5`) to seconds.
+pub fn parse_time_offset(value: &str) -> Option<f64> {
+    let fields: Vec<&str> = value.trim().split(':').collect();
+    if fields.len() > 3 {
+        return None;
+    }
+    let (seconds, whole) = fields.split_last()?;
+    let seconds = seconds
+        .parse::<f64>()
+        .ok()
+        .filter(|s| s.is_finite() && *s >= 0.0)?;
+    if !whole.is_empty() && seconds >= 60.0 {
+        return None;
+    }
+    let mut total = 0.0;
+    for (n, field) in whole.iter().enumerate() {
+        let value = field.parse::<u64>().ok()?;
+        // Minutes after hours stay below 60
+        if n > 0 && value >= 60 {
+            return None;
+        }
+        total = total * 60.0 + value as f64;
+    }
+    Some(total * 60.0 + seconds)
+}
+
+/// Current UTC date as YYYY-MM-DD.
+pub fn today_utc() -> String {
+    date_utc(SystemTime::now())
+}
+
+/// UTC date of `time` as YYYY-MM-DD (these strings sort chronologically).
+pub fn date_utc(time: SystemTime) -> String {
+    let days = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs() / 86_400) as i64;
+    // Civil-from-days (proleptic Gregorian calendar)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn dates_are_utc_calendar_days() {
+        let at = |secs: u64| date_utc(UNIX_EPOCH + Duration::from_secs(secs));
+        assert_eq!(at(0), "1970-01-01");
+        assert_eq!(at(86_399), "1970-01-01");
+        assert_eq!(at(951_782_400), "2000-02-29");
+        assert_eq!(at(1_709_251_199), "2024-02-29");
+        assert_eq!(at(1_735_689_600), "2025-01-01");
+        let today = today_utc();
+        assert_eq!(today.len(), 10);
+        assert_eq!(days_from_civil(2025, 1, 1), 1_735_689_600 / 86_400);
+    }
+
+    #[test]
+    fn template_placeholders_are_expanded() {
+        let url = "https://cdn.example.com/shows/episode.m3u8?token=1";
+        let today = today_utc();
+        assert_eq!(
+            expand_output_template("{host}/{name}", url),
+            "cdn_example_com/episode"
+        );
+        assert_eq!(
+            expand_output_template("{yyyy}/{mm}/{date}", url),
+            format!("{}/{}/{}", &today[..4], &today[5..7], today)
+        );
+        assert_eq!(expand_output_template("{name}", "not a url"), "output");
+        assert_eq!(expand_output_template("plain", url), "plain");
+    }
+
+    #[test]
+    fn subfolders_stay_inside_the_output_directory() {
+        let url = "https://example.com/a/index.m3u8";
+        assert_eq!(
+            expand_output_subfolder("{host}/{name}", url).unwrap(),
+            PathBuf::from("example_com/index")
+        );
+        assert!(expand_output_subfolder("./recordings", url).is_ok());
+        assert!(expand_output_subfolder("/tmp/{name}", url).is_err());
+        assert!(expand_output_subfolder("../{name}", url).is_err());
+        assert!(expand_output_subfolder("shows/../../{name}", url).is_err());
+    }
+}
@@ -0,0 +1,136 @@
+use anyhow::{Result, anyhow};
+use egui::Context as EguiContext;
+use reqwest::Url;
+use std::ffi::OsStr;
+use std::path::PathBuf;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use super::hls_parser::download_and_parse_m3u3;
+use super::http::build_client;
+use super::segment_io::run_ffmpeg_with_progress;
+use super::{DownloadConfig, DownloadMessage};
+
+/// Allowed difference between playlist and file duration before the file is flagged
+const MIN_DURATION_TOLERANCE_SECS: f64 = 2.0;
+const DURATION_TOLERANCE_RATIO: f64 = 0.01;
+/// Number of decoder error lines echoed to the log
+const MAX_REPORTED_DECODE_ERRORS: usize = 5;
+
+/// Compares an existing output file against its playlist and decodes it to look for errors.
+/// The playlist (`config.playlist_url`) is fetched with the job's headers, proxy and limits.
+pub async fn run_verify_job(
+    config: DownloadConfig,
+    file_path: PathBuf,
+    cancel: CancellationToken,
+    sender: mpsc::Sender<DownloadMessage>,
+    ctx: EguiContext,
+) -> Result<()> {
+    let send_log = |msg: String| {
+        sender.try_send(DownloadMessage::Log(msg)).ok();
+        ctx.request_repaint();
+    };
+
+    let playlist_url =
+        Url::parse(&config.playlist_url).map_err(|e| anyhow!("Invalid URL: {}", e))?;
+    if !file_path.is_file() {
+        return Err(anyhow!("File does not exist: {}", file_path.display()));
+    }
+
+    // 1. Expected duration from the playlist
+    send_log(format!("-> Downloading playlist: {}", playlist_url));
+    let client = build_client(&config.headers, config.proxy.as_deref())?;
+    let (segments, _) =
+        download_and_parse_m3u3(&client, &playlist_url, &config.limits, &send_log, &cancel).await?;
+    let segment_count = segments.len();
+    let expected_secs: f64 = segments.iter().map(|s| s.duration).sum();
+    send_log(format!(
+        "-> Playlist: {} segments, {:.1}s total",
+        segment_count, expected_secs
+    ));
+
+    // 2. Full decode of the file, collecting errors and the container duration
+    send_log(format!(
+        "-> Decoding {} to check for errors...",
+        file_path.display()
+    ));
+    let progress_sender = sender.clone();
+    let progress_ctx = ctx.clone();
+    let run = tokio::task::spawn_blocking(move || {
+        let args: Vec<&OsStr> = vec![
+            "-i".as_ref(),
+            file_path.as_os_str(),
+            "-map".as_ref(),
+            "0".as_ref(),
+            "-f".as_ref(),
+            "null".as_ref(),
+            "-".as_ref(),
+        ];
+        run_ffmpeg_with_progress(&args, &cancel, |p| {
+            progress_sender.try_send(DownloadMessage::Progress(p)).ok();
+            progress_ctx.request_repaint();
+        })
+    })
+    .await
+    .map_err(|e| anyhow!("FFmpeg blocking task failed to join: {}", e))??;
+
+    let decode_errors: Vec<&str> = run
+        .stderr
+        .lines()
+        .filter(|line| {
+            let lower = line.to_lowercase();
+            lower.contains("error") || lower.contains("corrupt") || lower.contains("invalid")
+        })
+        .collect();
+
+    // 3. Report
+    let actual_secs = run.duration_ms.map(|ms| ms as f64 / 1000.0);
+    let tolerance = (expected_secs * DURATION_TOLERANCE_RATIO).max(MIN_DURATION_TOLERANCE_SECS);
+    let mut complete = run.success && decode_errors.is_empty();
+
+    match actual_secs {
+        Some(actual) => {
+            let average_segment = expected_secs / segment_count as f64;
+            let covered_segments = if average_segment > 0.0 {
+                (actual / average_segment).round() as usize
+            } else {
+                0
+            };
+            send_log(format!(
+                "-> File: {:.1}s (difference {:+.1}s), covering ≈ {} of {} segments",
+                actual,
+                actual - expected_secs,
+                covered_segments.min(segment_count),
+                segment_count
+            ));
+            if (actual - expected_secs).abs() > tolerance {
+                complete = false;
+            }
+        }
+        None => {
+            send_log("⚠️ Warning: FFmpeg did not report a duration for the file.".to_string());
+            complete = false;
+        }
+    }
+
+    if !decode_errors.is_empty() {
+        send_log(format!(
+            "⚠️ {} decoder error line(s) reported:",
+            decode_errors.len()
+        ));
+        for line in decode_errors.iter().take(MAX_REPORTED_DECODE_ERRORS) {
+            send_log(format!("    {}", line));
+        }
+    }
+
+    sender.send(DownloadMessage::Progress(1.0)).await.ok();
+    if complete {
+        send_log("✅ Output looks complete: duration matches and no decode errors.".to_string());
+    } else if actual_secs.is_some_and(|actual| actual + tolerance < expected_secs) {
+        send_log("⚠️ Output is likely incomplete (shorter than the playlist).".to_string());
+    } else {
+        send_log("⚠️ Output may be damaged; consider re-downloading.".to_string());
+    }
+
+    Ok(())
+}
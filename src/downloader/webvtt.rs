@@ -0,0 +1,320 @@
+// WebVTT handling without FFmpeg: parses the segments of a subtitle rendition, stitches them
+// onto the video timeline and writes the result as a `.vtt` or `.srt` file.
+
+use std::collections::HashSet;
+
+/// MPEG-TS clock rate of `X-TIMESTAMP-MAP=MPEGTS:` values
+const MPEGTS_CLOCK: f64 = 90_000.0;
+/// The 33-bit MPEG-TS timestamp wraps around after this many ticks (~26.5 hours)
+const MPEGTS_WRAP: u64 = 1 << 33;
+/// Cue times closer than this are treated as equal (one millisecond, the format's precision)
+const EPSILON: f64 = 0.0015;
+
+/// One subtitle cue; times in seconds
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    pub start: f64,
+    pub end: f64,
+    /// Position/alignment settings after the timing (`line:90% align:center`); VTT only
+    pub settings: String,
+    pub text: String,
+}
+
+/// `X-TIMESTAMP-MAP` of a segment: cue time `local` is MPEG-TS time `mpegts`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimestampMap {
+    pub mpegts: u64,
+    pub local: f64,
+}
+
+/// Parsed subtitle file (WebVTT, or SRT, which has the same cue layout)
+#[derive(Debug, Default)]
+pub struct SubtitleFile {
+    pub timestamp_map: Option<TimestampMap>,
+    pub cues: Vec<Cue>,
+}
+
+/// Parses WebVTT or SRT text; blocks that are not cues (NOTE, STYLE, REGION) are skipped.
+pub fn parse(text: &str) -> SubtitleFile {
+    let text = text.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+    let mut file = SubtitleFile::default();
+    for block in text.split("\n\n") {
+        let lines: Vec<&str> = block.lines().filter(|line| !line.is_empty()).collect();
+        let Some(first) = lines.first() else {
+            continue;
+        };
+        if first.starts_with("WEBVTT") {
+            file.timestamp_map = lines.iter().find_map(|line| {
+                line.strip_prefix("X-TIMESTAMP-MAP=")
+                    .and_then(parse_timestamp_map)
+            });
+            continue;
+        }
+        if ["NOTE", "STYLE", "REGION"]
+            .iter()
+            .any(|keyword| first.starts_with(keyword))
+        {
+            continue;
+        }
+        // An optional identifier (or SRT counter) precedes the timing line
+        let Some(timing) = lines.iter().take(2).position(|line| line.contains("-->")) else {
+            continue;
+        };
+        let Some((start, rest)) = lines[timing].split_once("-->") else {
+            continue;
+        };
+        let rest = rest.trim();
+        let (end, settings) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+        let (Some(start), Some(end)) = (parse_timestamp(start.trim()), parse_timestamp(end)) else {
+            continue;
+        };
+        file.cues.push(Cue {
+            start,
+            end,
+            settings: settings.trim().to_string(),
+            text: lines[timing + 1..].join("\n"),
+        });
+    }
+    file
+}
+
+/// `MPEGTS:900000,LOCAL:00:00:00.000` (either order)
+fn parse_timestamp_map(value: &str) -> Option<TimestampMap> {
+    let mut mpegts = None;
+    let mut local = None;
+    for part in value.split(',') {
+        match part.trim().split_once(':') {
+            Some(("MPEGTS", ticks)) => mpegts = ticks.trim().parse().ok(),
+            Some(("LOCAL", time)) => local = parse_timestamp(time.trim()),
+            _ => {}
+        }
+    }
+    Some(TimestampMap {
+        mpegts: mpegts?,
+        local: local?,
+    })
+}
+
+/// `hh:mm:ss.mmm` or `mm:ss.mmm`; SRT's decimal comma is accepted too.
+fn parse_timestamp(value: &str) -> Option<f64> {
+    let value = value.replace(',', ".");
+    let parts: Vec<&str> = value.split(':').collect();
+    let (hours, minutes, seconds) = match parts.as_slice() {
+        [h, m, s] => (h.parse::<u64>().ok()?, m.parse::<u64>().ok()?, *s),
+        [m, s] => (0, m.parse::<u64>().ok()?, *s),
+        _ => return None,
+    };
+    let seconds: f64 = seconds.parse().ok()?;
+    Some((hours * 3600 + minutes * 60) as f64 + seconds)
+}
+
+/// Joins the segments of a subtitle playlist into one cue list on the video timeline.
+///
+/// With `X-TIMESTAMP-MAP`, segment times are mapped through the MPEG-TS clock and made relative
+/// to the first segment's mapping (where the video starts too). Without it, cue times are taken
+/// as the stream's own unless they lie before the segment, in which case they are relative to it.
+#[derive(Debug, Default)]
+pub struct Stitcher {
+    /// Timeline position of the first mapped segment
+    origin: Option<f64>,
+    /// Last MPEG-TS value, with earlier wrap-arounds added
+    last_mpegts: u64,
+    cues: Vec<Cue>,
+}
+
+impl Stitcher {
+    /// Adds the segment that starts `segment_start` seconds into the playlist.
+    pub fn add(&mut self, file: SubtitleFile, segment_start: f64) {
+        let shift = match file.timestamp_map {
+            Some(map) => {
+                let mut mpegts = map.mpegts;
+                while mpegts + MPEGTS_WRAP / 2 < self.last_mpegts {
+                    mpegts += MPEGTS_WRAP;
+                }
+                self.last_mpegts = mpegts;
+                let position = mpegts as f64 / MPEGTS_CLOCK - map.local;
+                position - *self.origin.get_or_insert(position)
+            }
+            None => match file.cues.first() {
+                Some(cue) if cue.start + 1.0 < segment_start => segment_start,
+                _ => 0.0,
+            },
+        };
+        self.cues
+            .extend(file.cues.into_iter().filter_map(|mut cue| {
+                cue.start = (cue.start + shift).max(0.0);
+                cue.end += shift;
+                (cue.end > cue.start).then_some(cue)
+            }));
+    }
+
+    /// Cues in time order; the copies repeated in neighbouring segments are dropped and cues
+    /// split at a segment boundary are joined again.
+    pub fn finish(mut self) -> Vec<Cue> {
+        self.cues
+            .sort_by(|a, b| a.start.total_cmp(&b.start).then(a.end.total_cmp(&b.end)));
+        let mut seen = HashSet::new();
+        let mut cues: Vec<Cue> = Vec::with_capacity(self.cues.len());
+        for cue in self.cues {
+            let key = (millis(cue.start), millis(cue.end), cue.text.clone());
+            if !seen.insert(key) {
+                continue;
+            }
+            if let Some(open) = cues
+                .iter_mut()
+                .rev()
+                .take(4)
+                .find(|open| open.text == cue.text && (open.end - cue.start).abs() < EPSILON)
+            {
+                open.end = cue.end;
+                continue;
+            }
+            cues.push(cue);
+        }
+        cues
+    }
+}
+
+fn millis(seconds: f64) -> u64 {
+    (seconds * 1000.0).round() as u64
+}
+
+/// `00:01:02.345` (VTT) or `00:01:02,345` (SRT)
+fn format_timestamp(seconds: f64, separator: char) -> String {
+    let total = millis(seconds);
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        total / 3_600_000,
+        total / 60_000 % 60,
+        total / 1000 % 60,
+        separator,
+        total % 1000
+    )
+}
+
+pub fn to_vtt(cues: &[Cue]) -> String {
+    let mut out = String::from("WEBVTT\n");
+    for cue in cues {
+        out.push('\n');
+        out.push_str(&format_timestamp(cue.start, '.'));
+        out.push_str(" --> ");
+        out.push_str(&format_timestamp(cue.end, '.'));
+        if !cue.settings.is_empty() {
+            out.push(' ');
+            out.push_str(&cue.settings);
+        }
+        out.push('\n');
+        out.push_str(&cue.text);
+        out.push('\n');
+    }
+    out
+}
+
+/// SRT keeps `<b>`, `<i>` and `<u>`; voice/class/timestamp tags and cue settings are dropped.
+pub fn to_srt(cues: &[Cue]) -> String {
+    let mut out = String::new();
+    for (n, cue) in cues.iter().enumerate() {
+        out.push_str(&format!(
+            "{}\n{} --> {}\n{}\n\n",
+            n + 1,
+            format_timestamp(cue.start, ','),
+            format_timestamp(cue.end, ','),
+            srt_text(&cue.text)
+        ));
+    }
+    out
+}
+
+fn srt_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(open) = rest.find('<') {
+        out.push_str(&rest[..open]);
+        let Some(close) = rest[open..].find('>') else {
+            out.push_str(&rest[open..]);
+            rest = "";
+            break;
+        };
+        let tag = &rest[open..open + close + 1];
+        let name = tag
+            .trim_matches(|c| c == '<' || c == '>' || c == '/')
+            .split(['.', ' '])
+            .next()
+            .unwrap_or("");
+        // Without classes: `<i.loud>` becomes `<i>`
+        if matches!(name, "b" | "i" | "u") {
+            out.push_str(if tag.starts_with("</") { "</" } else { "<" });
+            out.push_str(name);
+            out.push('>');
+        }
+        rest = &rest[open + close + 1..];
+    }
+    out.push_str(rest);
+    out.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", "\u{a0}")
+        .replace("&lrm;", "\u{200e}")
+        .replace("&rlm;", "\u{200f}")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(map: &str, cues: &str) -> SubtitleFile {
+        parse(&format!("WEBVTT\n{}\n\n{}", map, cues))
+    }
+
+    #[test]
+    fn timestamp_map_offsets_are_relative_to_the_first_segment() {
+        let mut stitcher = Stitcher::default();
+        stitcher.add(
+            segment(
+                "X-TIMESTAMP-MAP=MPEGTS:900000,LOCAL:00:00:00.000",
+                "1\n00:00:01.000 --> 00:00:02.500\nHello",
+            ),
+            0.0,
+        );
+        stitcher.add(
+            segment(
+                "X-TIMESTAMP-MAP=LOCAL:00:00:00.000,MPEGTS:1440000",
+                "00:00:00.500 --> 00:00:01.000 line:90%\nWorld",
+            ),
+            6.0,
+        );
+        let cues = stitcher.finish();
+        assert_eq!(cues.len(), 2);
+        assert_eq!((cues[0].start, cues[0].end), (1.0, 2.5));
+        assert_eq!((cues[1].start, cues[1].end), (6.5, 7.0));
+        assert_eq!(cues[1].settings, "line:90%");
+    }
+
+    #[test]
+    fn cues_repeated_across_segments_are_merged() {
+        let mut stitcher = Stitcher::default();
+        stitcher.add(parse("WEBVTT\n\n00:00:05.000 --> 00:00:06.000\nSpan"), 0.0);
+        stitcher.add(
+            parse("WEBVTT\n\n00:00:05.000 --> 00:00:06.000\nSpan\n\n00:00:06.000 --> 00:00:07.000\nSpan"),
+            6.0,
+        );
+        let cues = stitcher.finish();
+        assert_eq!(cues.len(), 1);
+        assert_eq!((cues[0].start, cues[0].end), (5.0, 7.0));
+    }
+
+    #[test]
+    fn srt_output_keeps_basic_styling_only() {
+        let cues = vec![Cue {
+            start: 3661.5,
+            end: 3662.0,
+            settings: "align:start".to_string(),
+            text: "<v Anna><i.loud>Hi</i> &amp; bye</v>".to_string(),
+        }];
+        assert_eq!(
+            to_srt(&cues),
+            "1\n01:01:01,500 --> 01:01:02,000\n<i>Hi</i> & bye\n\n"
+        );
+        assert!(to_vtt(&cues).contains("01:01:01.500 --> 01:01:02.000 align:start\n"));
+    }
+}
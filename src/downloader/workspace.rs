@@ -0,0 +1,294 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use super::DownloadConfig;
+use super::hls_parser::ByteRange;
+use super::segment_io::ChunkRange;
+use super::util::PathStringLossy;
+
+pub const MANIFEST_FILENAME: &str = "manifest.json";
+/// Prefix of the temporary segment directories, so leftovers of crashed jobs can be found
+pub const TEMP_DIR_PREFIX: &str = "hlsdl-";
+/// Extension of kept workspace directories (see `workspace_dir_for`)
+pub const WORKSPACE_EXTENSION: &str = "hlsdl";
+pub const MERGED_TS_FILENAME: &str = "final_merge.ts.tmp";
+/// Sidecar recording where each segment lives inside the merged TS
+pub const CONCAT_INDEX_FILENAME: &str = "final_merge.index.json";
+
+/// MPEG-TS packet sync byte, used for a cheap corruption check
+const TS_SYNC_BYTE: u8 = 0x47;
+
+/// File name of a downloaded segment inside the temp dir / workspace.
+pub fn segment_file_name(index: usize) -> String {
+    format!("temp_segment_{:08}.ts", index)
+}
+
+/// File name of the `n`-th distinct `#EXT-X-MAP` init section.
+pub fn init_file_name(n: usize) -> String {
+    format!("init_section_{:02}.mp4", n)
+}
+
+/// Concatenation order: each init section goes in front of the first segment after a map change.
+/// `init_of` holds every segment's index into `init_paths`.
+pub fn interleave_init_sections(
+    segment_paths: &[PathBuf],
+    init_of: &[Option<usize>],
+    init_paths: &[PathBuf],
+) -> Vec<PathBuf> {
+    let mut paths = Vec::with_capacity(segment_paths.len() + init_paths.len());
+    let mut current = None;
+    for (path, init) in segment_paths.iter().zip(init_of) {
+        if *init != current
+            && let Some(init_path) = init.and_then(|n| init_paths.get(n))
+        {
+            paths.push(init_path.clone());
+        }
+        current = *init;
+        paths.push(path.clone());
+    }
+    paths
+}
+
+/// Positions of the segments that start a new run after `#EXT-X-DISCONTINUITY` (never 0).
+pub fn discontinuity_starts(flags: impl IntoIterator<Item = bool>) -> Vec<usize> {
+    flags
+        .into_iter()
+        .enumerate()
+        .filter(|&(position, flag)| flag && position > 0)
+        .map(|(position, _)| position)
+        .collect()
+}
+
+/// Directory used when a job keeps its workspace: `<output dir>/<output stem>.hlsdl/`
+pub fn workspace_dir_for(output_path: &Path) -> PathBuf {
+    let stem = output_path.to_path_buf().file_string_lossy();
+    output_path.with_file_name(format!("{}.{}", stem, WORKSPACE_EXTENSION))
+}
+
+/// Cheap validity check of a downloaded segment file (non-empty, starts with a TS sync byte or,
+/// for fMP4 fragments, an ISO BMFF box header such as `moof`/`styp`).
+pub fn segment_file_is_valid(path: &Path) -> bool {
+    let Ok(mut file) = fs::File::open(path) else {
+        return false;
+    };
+    let mut head = [0u8; 8];
+    let Ok(read) = file.read(&mut head) else {
+        return false;
+    };
+    (read >= 1 && head[0] == TS_SYNC_BYTE)
+        || (read == head.len() && head[4..].iter().all(u8::is_ascii_alphanumeric))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SegmentStatus {
+    Done,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestSegment {
+    pub index: usize,
+    /// Declared media sequence (IV source) when it differs from `index`
+    #[serde(default)]
+    pub sequence: Option<u64>,
+    pub url: String,
+    pub duration: f64,
+    pub file: String,
+    pub status: SegmentStatus,
+    /// Index into the manifest's `keys`; None for clear segments
+    #[serde(default)]
+    pub key: Option<usize>,
+    /// Index into the manifest's `init_sections`; None for MPEG-TS segments
+    #[serde(default)]
+    pub init: Option<usize>,
+    /// `#EXT-X-BYTERANGE` sub-range of `url`
+    #[serde(default)]
+    pub byte_range: Option<ByteRange>,
+    /// Preceded by `#EXT-X-DISCONTINUITY`
+    #[serde(default)]
+    pub discontinuity: bool,
+}
+
+/// An `#EXT-X-MAP` init section, stored in the workspace as `init_file_name(n)`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestInit {
+    pub url: String,
+    #[serde(default)]
+    pub byte_range: Option<ByteRange>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestEncryption {
+    pub method: String,
+    pub key_url: String,
+    pub key_hex: Option<String>,
+    pub iv_hex: Option<String>,
+}
+
+/// Describes a kept workspace so that failed segments can be repaired later.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkspaceManifest {
+    pub config: DownloadConfig,
+    pub output_path: PathBuf,
+    /// Every distinct `#EXT-X-KEY` of the playlist, referenced by `ManifestSegment::key`
+    #[serde(default)]
+    pub keys: Vec<ManifestEncryption>,
+    /// Distinct `#EXT-X-MAP` init sections, referenced by `ManifestSegment::init`
+    #[serde(default)]
+    pub init_sections: Vec<ManifestInit>,
+    pub segments: Vec<ManifestSegment>,
+}
+
+impl WorkspaceManifest {
+    pub fn load(workspace_dir: &Path) -> Result<Self> {
+        let path = workspace_dir.join(MANIFEST_FILENAME);
+        let text = fs::read_to_string(&path)
+            .map_err(|e| anyhow!("Failed to read manifest {}: {}", path.display(), e))?;
+        let mut value: serde_json::Value = serde_json::from_str(&text)
+            .map_err(|e| anyhow!("Invalid workspace manifest: {}", e))?;
+        migrate_single_key(&mut value);
+        serde_json::from_value(value).map_err(|e| anyhow!("Invalid workspace manifest: {}", e))
+    }
+
+    pub fn save(&self, workspace_dir: &Path) -> Result<()> {
+        fs::create_dir_all(workspace_dir)?;
+        let path = workspace_dir.join(MANIFEST_FILENAME);
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    /// Re-derives every segment's status from the files present in the workspace.
+    pub fn refresh_statuses(&mut self, workspace_dir: &Path) {
+        for segment in &mut self.segments {
+            segment.status = if segment_file_is_valid(&workspace_dir.join(&segment.file)) {
+                SegmentStatus::Done
+            } else {
+                SegmentStatus::Failed
+            };
+        }
+    }
+
+    pub fn failed_count(&self) -> usize {
+        self.segments
+            .iter()
+            .filter(|s| s.status != SegmentStatus::Done)
+            .count()
+    }
+
+    /// Segment file paths in playlist order.
+    pub fn segment_paths(&self, workspace_dir: &Path) -> Vec<PathBuf> {
+        self.segments
+            .iter()
+            .map(|s| workspace_dir.join(&s.file))
+            .collect()
+    }
+
+    /// Files to concatenate: the segments with their init sections in front.
+    /// Positions in `concat_paths` where timestamps restart (`#EXT-X-DISCONTINUITY`); empty for
+    /// fMP4, whose fragments are always joined byte by byte.
+    pub fn discontinuity_starts(&self) -> Vec<usize> {
+        if !self.init_sections.is_empty() {
+            return Vec::new();
+        }
+        discontinuity_starts(self.segments.iter().map(|s| s.discontinuity))
+    }
+
+    pub fn concat_paths(&self, workspace_dir: &Path) -> Vec<PathBuf> {
+        let init_paths: Vec<PathBuf> = (0..self.init_sections.len())
+            .map(|n| workspace_dir.join(init_file_name(n)))
+            .collect();
+        let init_of: Vec<Option<usize>> = self.segments.iter().map(|s| s.init).collect();
+        interleave_init_sections(&self.segment_paths(workspace_dir), &init_of, &init_paths)
+    }
+}
+
+/// Converts a manifest from before key rotation support (one `encryption` object plus a per-segment
+/// `encrypted` flag) into the `keys` list / per-segment key index layout.
+fn migrate_single_key(value: &mut serde_json::Value) {
+    let Some(object) = value.as_object_mut() else {
+        return;
+    };
+    let Some(encryption) = object.remove("encryption") else {
+        return;
+    };
+    let has_key = !encryption.is_null();
+    if has_key {
+        object.insert(
+            "keys".to_string(),
+            serde_json::Value::Array(vec![encryption]),
+        );
+    }
+    if let Some(segments) = object.get_mut("segments").and_then(|s| s.as_array_mut()) {
+        for segment in segments.iter_mut().filter_map(|s| s.as_object_mut()) {
+            let encrypted = segment
+                .remove("encrypted")
+                .and_then(|e| e.as_bool())
+                .unwrap_or(true);
+            if has_key && encrypted {
+                segment.insert("key".to_string(), 0.into());
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConcatIndexEntry {
+    pub file: String,
+    pub offset: u64,
+    pub length: u64,
+}
+
+/// Byte offset/length of every segment in the merged TS, in playlist order.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConcatIndex {
+    pub entries: Vec<ConcatIndexEntry>,
+}
+
+impl ConcatIndex {
+    pub fn new(segment_paths: &[PathBuf], ranges: &[ChunkRange]) -> Self {
+        let entries = segment_paths
+            .iter()
+            .zip(ranges)
+            .map(|(path, range)| ConcatIndexEntry {
+                file: path
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .into_owned(),
+                offset: range.offset,
+                length: range.length,
+            })
+            .collect();
+        Self { entries }
+    }
+
+    pub fn load(workspace_dir: &Path) -> Result<Self> {
+        let path = workspace_dir.join(CONCAT_INDEX_FILENAME);
+        let text = fs::read_to_string(&path)
+            .map_err(|e| anyhow!("Failed to read concat index {}: {}", path.display(), e))?;
+        serde_json::from_str(&text).map_err(|e| anyhow!("Invalid concat index: {}", e))
+    }
+
+    pub fn save(&self, workspace_dir: &Path) -> Result<()> {
+        let path = workspace_dir.join(CONCAT_INDEX_FILENAME);
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(&tmp_path, &path)?;
+        Ok(())
+    }
+
+    pub fn range_of(&self, file: &str) -> Option<ChunkRange> {
+        self.entries
+            .iter()
+            .find(|entry| entry.file == file)
+            .map(|entry| ChunkRange {
+                offset: entry.offset,
+                length: entry.length,
+            })
+    }
+}
@@ -0,0 +1,600 @@
+// Headless (command-line) mode: runs one download job without opening a window,
+// logging to stderr.
+
+use anyhow::{Result, anyhow};
+use reqwest::Url;
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::cli::{self, CliCommand, CliOptions};
+use crate::completions;
+use crate::diagnostics::JobDiagnostics;
+use crate::downloader::cancel::{self, Cancelled};
+use crate::downloader::dash_parser;
+use crate::downloader::failure::FailureKind;
+use crate::downloader::hls_parser::{
+    Rendition, RenditionKind, Variant, decode_playlist_body, fetch_playlist_text,
+    parse_master_playlist,
+};
+use crate::downloader::http::build_client;
+use crate::downloader::inspect::run_inspect_job;
+use crate::downloader::pause::PauseGate;
+use crate::downloader::preview::{PreviewKind, run_preview_job};
+use crate::downloader::progressive;
+use crate::downloader::{DownloadConfig, DownloadMessage, run_hls_download_core};
+use crate::power::BackgroundPriority;
+
+/// Environment variable holding the default User-Agent for all requests
+const USER_AGENT_ENV: &str = "HLSDL_UA";
+/// Environment variable holding the decryption key (hex), kept out of the process list
+const KEY_ENV: &str = "HLSDL_KEY";
+/// Proxy variables honored by the HTTP client, in lookup order per scheme
+const PROXY_ENV: [&str; 4] = ["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"];
+const HTTP_PROXY_ENV: [&str; 4] = ["HTTP_PROXY", "http_proxy", "ALL_PROXY", "all_proxy"];
+
+/// Stable exit codes, so wrapping scripts can branch on the failure type
+const EXIT_SUCCESS: i32 = 0;
+const EXIT_FAILURE: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_NETWORK: i32 = 3;
+const EXIT_DECRYPT: i32 = 4;
+const EXIT_FFMPEG: i32 = 5;
+const EXIT_PARSE: i32 = 6;
+const EXIT_CANCELLED: i32 = 130;
+
+/// Entry point for a run with command-line arguments.
+pub fn run(args: Vec<String>) -> i32 {
+    let error_json = cli::flag_value(&args, "--error-json").map(PathBuf::from);
+    let (mut config, options) = match cli::parse_args(args) {
+        Ok(CliCommand::Help) => {
+            print!("{}", cli::USAGE);
+            return EXIT_SUCCESS;
+        }
+        Ok(CliCommand::Version) => {
+            println!("{} {}", cli::BIN_NAME, env!("CARGO_PKG_VERSION"));
+            return EXIT_SUCCESS;
+        }
+        Ok(CliCommand::Completions(shell)) => match completions::script(&shell) {
+            Ok(script) => {
+                print!("{}", script);
+                return EXIT_SUCCESS;
+            }
+            Err(e) => {
+                eprintln!("❌ {}", e);
+                return report_failure(EXIT_USAGE, "usage", &e.to_string(), error_json.as_deref());
+            }
+        },
+        Ok(CliCommand::Download(config, options)) => (*config, options),
+        Err(e) => {
+            eprintln!("❌ {}\n\n{}", e, cli::USAGE);
+            return report_failure(EXIT_USAGE, "usage", &e.to_string(), error_json.as_deref());
+        }
+    };
+    apply_environment(&mut config);
+    // No next job to overlap with: a single run waits for its remux (and reports its failure)
+    config.background_remux = false;
+    if options.playlist_from_stdin {
+        match read_stdin_playlist(&config) {
+            Ok(text) => config.playlist_text = Some(text),
+            Err(e) => {
+                eprintln!("❌ {}", e);
+                return report_failure(EXIT_USAGE, "usage", &e.to_string(), error_json.as_deref());
+            }
+        }
+    }
+
+    // Entered before the runtime starts, so its threads (and FFmpeg) inherit the priority
+    let _background = options
+        .background_priority
+        .then(|| {
+            BackgroundPriority::enter()
+                .map_err(|e| eprintln!("⚠️ Warning: Could not lower the process priority: {}", e))
+                .ok()
+        })
+        .flatten();
+    let runtime = match options.runtime.build() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("❌ Failed to create tokio runtime: {}", e);
+            return EXIT_FAILURE;
+        }
+    };
+    if options.scheme_added
+        && let Err(e) = confirm_normalized_url(&config, &options)
+    {
+        eprintln!("❌ {}", e);
+        return report_failure(EXIT_USAGE, "usage", &e.to_string(), error_json.as_deref());
+    }
+    let mut diagnostics = JobDiagnostics::default();
+    let mut logs = Vec::new();
+    let result = runtime.block_on(async {
+        let cancel = CancellationToken::new();
+        let stop = CancellationToken::new();
+        watch_interrupts(config.live, cancel.clone(), stop.clone());
+        // A preview picks the I-frame playlist from the master playlist itself; an inspection
+        // reports the variant's BANDWIDTH, which only the master playlist states
+        if options.preview.is_none() && !options.inspect {
+            // Nothing is recorded yet, so stopping a live recording here cancels it
+            tokio::select! {
+                biased;
+                _ = stop.cancelled() => return Err(Cancelled.into()),
+                result = resolve_master(&mut config, &options, &cancel) => result?,
+            }
+        }
+        diagnostics.config = Some(config.clone());
+        let (preview, inspect) = (options.preview, options.inspect);
+        run_job(
+            config,
+            preview,
+            inspect,
+            cancel,
+            stop,
+            &mut diagnostics,
+            &mut logs,
+        )
+        .await
+    });
+    // A prompt interrupted by Ctrl+C leaves its blocking stdin read behind
+    runtime.shutdown_background();
+    let outcome = match &result {
+        Ok(()) => "✅ Task completed successfully!".to_string(),
+        Err(e) if FailureKind::of(e) == FailureKind::Cancelled => "❌ Task cancelled.".to_string(),
+        Err(e) => format!("❌ Task failed: {}", e),
+    };
+    eprintln!("{}", outcome);
+    logs.push(outcome);
+    if let Some(path) = &options.diagnostics {
+        match diagnostics.write_bundle(&logs, path) {
+            Ok(()) => eprintln!("-> Diagnostics saved to {}", path.display()),
+            Err(e) => eprintln!("⚠️ Warning: Failed to save diagnostics: {}", e),
+        }
+    }
+    match result {
+        Ok(()) => EXIT_SUCCESS,
+        Err(e) => {
+            let kind = FailureKind::of(&e);
+            report_failure(
+                exit_code(kind),
+                kind.name(),
+                &e.to_string(),
+                options.error_json.as_deref(),
+            )
+        }
+    }
+}
+
+/// Resolves a master playlist to one variant plus audio/subtitle renditions before the job
+/// starts, asking on the terminal unless `--quality` or `--yes` was given.
+async fn resolve_master(
+    config: &mut DownloadConfig,
+    options: &CliOptions,
+    cancel: &CancellationToken,
+) -> Result<()> {
+    let playlist_url =
+        Url::parse(&config.playlist_url).map_err(|e| anyhow!("Invalid URL: {}", e))?;
+    let (playlist_url, body) = match &config.playlist_text {
+        Some(text) => (playlist_url, text.clone()),
+        None => {
+            let client = build_client(&config.headers, config.proxy.as_deref())?;
+            let fetched = fetch_playlist_text(
+                &client,
+                &playlist_url,
+                &config.limits,
+                &|msg| eprintln!("{}", msg),
+                cancel,
+            )
+            .await;
+            match fetched {
+                Ok(fetched) => (fetched.url, fetched.body),
+                // No variants to pick; the job downloads the file itself
+                Err(e) if e.is::<progressive::MediaFile>() => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+    };
+    let Some(master) = parse_master_playlist(&body, &playlist_url)? else {
+        return Ok(());
+    };
+
+    let interactive =
+        config.quality.is_none() && !options.assume_yes && std::io::stdin().is_terminal();
+    let variant = if interactive {
+        let labels: Vec<String> = master.variants.iter().map(Variant::label).collect();
+        let best = master
+            .variants
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, v)| v.bandwidth)
+            .map_or(0, |(i, _)| i);
+        &master.variants[prompt_choice_or_cancel(cancel, "Variant", labels, best).await?]
+    } else {
+        let quality = config.quality.as_deref().unwrap_or("best");
+        master
+            .select(quality)
+            .ok_or_else(|| anyhow!("No variant matches --quality {}", quality))?
+    };
+    eprintln!("-> Variant: {}", variant.label());
+
+    if config.audio_url.is_none() {
+        let audio = master.renditions_for(variant, RenditionKind::Audio);
+        let chosen = match &options.audio {
+            Some(wanted) => Some(find_rendition(&audio, wanted, "--audio")?),
+            None if interactive && audio.len() > 1 => {
+                let labels: Vec<String> = audio.iter().map(|r| r.label()).collect();
+                let default = audio.iter().position(|r| r.default).unwrap_or(0);
+                Some(audio[prompt_choice_or_cancel(cancel, "Audio", labels, default).await?])
+            }
+            None => master.default_audio(variant),
+        };
+        if let Some(rendition) = chosen {
+            eprintln!("-> Audio: {}", rendition.label());
+            config.audio_url = rendition.uri.as_ref().map(Url::to_string);
+            config.audio_track = Some(rendition.track_metadata());
+        }
+    }
+
+    if config.subtitle_url.is_none() {
+        let subs = master.renditions_for(variant, RenditionKind::Subtitles);
+        let chosen = match &options.subs {
+            Some(wanted) => Some(find_rendition(&subs, wanted, "--subs")?),
+            None if interactive && !subs.is_empty() => {
+                let labels: Vec<String> = std::iter::once("None".to_string())
+                    .chain(subs.iter().map(|r| r.label()))
+                    .collect();
+                prompt_choice_or_cancel(cancel, "Subtitles", labels, 0)
+                    .await?
+                    .checked_sub(1)
+                    .map(|i| subs[i])
+            }
+            None if config.subtitle_mode.sidecar_extension().is_some() => {
+                master.default_subtitles(variant)
+            }
+            None => None,
+        };
+        if let Some(rendition) = chosen {
+            eprintln!("-> Subtitles: {}", rendition.label());
+            config.subtitle_url = rendition.uri.as_ref().map(Url::to_string);
+            config.subtitle_track = Some(rendition.track_metadata());
+        }
+    }
+
+    // The job now downloads the media playlist directly
+    config.playlist_url = variant.uri.to_string();
+    config.playlist_text = None;
+    config.quality = None;
+    Ok(())
+}
+
+/// Playlist piped in on stdin (`cat index.m3u8 | hls-downloader --base-url ...`), e.g. from a
+/// script that fetched it through a login flow the downloader can't perform.
+fn read_stdin_playlist(config: &DownloadConfig) -> Result<String> {
+    let stdin = std::io::stdin();
+    if stdin.is_terminal() {
+        return Err(anyhow!(
+            "Expected the playlist on stdin (e.g. `cat index.m3u8 | {} --base-url <URL>`)",
+            cli::BIN_NAME
+        ));
+    }
+    let mut body = Vec::new();
+    stdin
+        .lock()
+        .take(config.limits.max_playlist_bytes.saturating_add(1))
+        .read_to_end(&mut body)
+        .map_err(|e| anyhow!("Failed to read the playlist from stdin: {}", e))?;
+    config.limits.check_playlist_bytes(body.len() as u64)?;
+    let text = decode_playlist_body(&body, &config.limits, &|msg| eprintln!("{}", msg))?;
+    if !text
+        .trim_start_matches(['\u{feff}', ' ', '\n', '\r'])
+        .starts_with("#EXTM3U")
+        && !dash_parser::is_mpd(&text)
+    {
+        return Err(anyhow!(
+            "The input on stdin is not an M3U8 playlist (no #EXTM3U) or MPD manifest"
+        ));
+    }
+    eprintln!(
+        "-> Playlist read from stdin ({} bytes), base URL: {}",
+        body.len(),
+        config.playlist_url
+    );
+    Ok(text)
+}
+
+/// Shows the URL with the added scheme and, on a terminal without `--yes`, asks before using it.
+fn confirm_normalized_url(config: &DownloadConfig, options: &CliOptions) -> Result<()> {
+    eprintln!(
+        "-> No scheme in PLAYLIST_URL, using {}",
+        config.playlist_url
+    );
+    if options.assume_yes || !std::io::stdin().is_terminal() {
+        return Ok(());
+    }
+    let labels = [format!("Use {}", config.playlist_url), "Abort".to_string()];
+    match prompt_choice("Continue with this URL?", &labels, 0)? {
+        0 => Ok(()),
+        _ => Err(anyhow!("Aborted, check PLAYLIST_URL")),
+    }
+}
+
+fn find_rendition<'a>(
+    renditions: &[&'a Rendition],
+    wanted: &str,
+    flag: &str,
+) -> Result<&'a Rendition> {
+    renditions
+        .iter()
+        .find(|r| r.matches(wanted))
+        .copied()
+        .ok_or_else(|| {
+            let available: Vec<String> = renditions.iter().map(|r| r.label()).collect();
+            anyhow!(
+                "No rendition matches {} {} (available: {})",
+                flag,
+                wanted,
+                available.join(", ")
+            )
+        })
+}
+
+/// Numbered options list on stderr; Enter or end of input picks `default`.
+fn prompt_choice(title: &str, options: &[String], default: usize) -> Result<usize> {
+    eprintln!("{}:", title);
+    for (i, option) in options.iter().enumerate() {
+        let marker = if i == default { '*' } else { ' ' };
+        eprintln!(" {}{:>2}) {}", marker, i + 1, option);
+    }
+    loop {
+        eprint!("Select [1-{}, Enter = {}]: ", options.len(), default + 1);
+        std::io::stderr().flush()?;
+        let mut line = String::new();
+        if std::io::stdin().read_line(&mut line)? == 0 {
+            return Ok(default);
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            return Ok(default);
+        }
+        match line.parse::<usize>() {
+            Ok(n) if (1..=options.len()).contains(&n) => return Ok(n - 1),
+            _ => eprintln!("Invalid choice: {}", line),
+        }
+    }
+}
+
+/// `prompt_choice` on the blocking thread pool, returning `Cancelled` on Ctrl+C.
+async fn prompt_choice_or_cancel(
+    cancel: &CancellationToken,
+    title: &'static str,
+    options: Vec<String>,
+    default: usize,
+) -> Result<usize> {
+    let prompt = tokio::task::spawn_blocking(move || prompt_choice(title, &options, default));
+    cancel::or_cancel(cancel, async {
+        prompt
+            .await
+            .map_err(|e| anyhow!("Prompt task failed to join: {}", e))?
+    })
+    .await
+}
+
+/// Applies `HLSDL_UA` and `HLSDL_KEY` and reports the proxy the client will pick up from the
+/// environment (reqwest reads `HTTP(S)_PROXY`/`ALL_PROXY`/`NO_PROXY` itself).
+fn apply_environment(config: &mut DownloadConfig) {
+    if config.key_hex.is_none()
+        && let Ok(key) = std::env::var(KEY_ENV)
+        && !key.trim().is_empty()
+    {
+        config.key_hex = Some(key.trim().to_string());
+    }
+    if let Ok(user_agent) = std::env::var(USER_AGENT_ENV)
+        && !user_agent.trim().is_empty()
+        && !config
+            .headers
+            .iter()
+            .any(|(name, _)| name.eq_ignore_ascii_case("user-agent"))
+    {
+        config
+            .headers
+            .push(("User-Agent".to_string(), user_agent.trim().to_string()));
+    }
+
+    let candidates = if config.playlist_url.starts_with("http://") {
+        HTTP_PROXY_ENV
+    } else {
+        PROXY_ENV
+    };
+    if let Some((name, proxy)) = candidates.iter().find_map(|name| {
+        std::env::var(name)
+            .ok()
+            .filter(|value| !value.is_empty())
+            .map(|value| (name, value))
+    }) {
+        eprintln!("-> Using proxy from {}: {}", name, proxy);
+        if let Some(no_proxy) = ["NO_PROXY", "no_proxy"]
+            .iter()
+            .find_map(|name| std::env::var(name).ok().filter(|v| !v.is_empty()))
+        {
+            eprintln!("-> Proxy exceptions (NO_PROXY): {}", no_proxy);
+        }
+    }
+}
+
+/// Ctrl+C cancels the job; the pipeline then stops and reports Cancelled.
+/// A live recording is stopped (and still saved) first; a second Ctrl+C cancels it.
+fn watch_interrupts(live: bool, cancel: CancellationToken, stop: CancellationToken) {
+    tokio::spawn(async move {
+        if live && tokio::signal::ctrl_c().await.is_ok() {
+            eprintln!("\n-> Interrupted, finishing the live recording (Ctrl+C again to cancel)...");
+            stop.cancel();
+        }
+        if tokio::signal::ctrl_c().await.is_ok() {
+            eprintln!("\n-> Interrupted, cancelling...");
+            cancel.cancel();
+        }
+    });
+}
+
+/// Runs the job (or its preview or inspection) and prints its messages; the printed lines and
+/// error summaries are also collected for `--diagnostics`.
+async fn run_job(
+    config: DownloadConfig,
+    preview: Option<PreviewKind>,
+    inspect: bool,
+    cancel: CancellationToken,
+    stop: CancellationToken,
+    diagnostics: &mut JobDiagnostics,
+    logs: &mut Vec<String>,
+) -> Result<()> {
+    let (sender, mut receiver) = mpsc::channel(100);
+    // No window: repaint requests go to a context nobody draws
+    let ctx = egui::Context::default();
+
+    let job = match preview {
+        Some(kind) => tokio::spawn(run_preview_job(config, kind, cancel, sender, ctx)),
+        None if inspect => tokio::spawn(run_inspect_job(config, cancel, sender, ctx)),
+        None => tokio::spawn(run_hls_download_core(
+            config,
+            cancel,
+            stop,
+            PauseGate::default(),
+            sender,
+            ctx,
+        )),
+    };
+
+    // Progress is printed in 10% steps to keep logs readable when redirected.
+    // The channel closes once the job has dropped its sender.
+    let mut last_step = None;
+    let mut print = |line: String| {
+        eprintln!("{}", line);
+        logs.push(line);
+    };
+    while let Some(message) = receiver.recv().await {
+        match message {
+            DownloadMessage::Log(line) => print(line),
+            DownloadMessage::SegmentProgress { completed, total } => {
+                print(DownloadMessage::segment_progress_line(completed, total))
+            }
+            DownloadMessage::ErrorSummary(table) => {
+                print(table.clone());
+                diagnostics.error_summaries.push(table);
+            }
+            DownloadMessage::Progress(p) => {
+                let step = (p * 10.0).floor() as u32;
+                if last_step != Some(step) {
+                    eprintln!("[{:>3}%]", step * 10);
+                    last_step = Some(step);
+                }
+            }
+            DownloadMessage::RetryWarning {
+                segment,
+                attempt,
+                reason,
+                ..
+            } => print(format!(
+                "⚠️ Segment {} (attempt {}): {}",
+                segment, attempt, reason
+            )),
+            // Only the URLs go to stdout, for piping into other tools
+            DownloadMessage::Inspected { info, .. } => {
+                for url in &info.segment_urls {
+                    println!("{}", url);
+                }
+            }
+            DownloadMessage::RemuxFailed { ts_path, .. } => print(format!(
+                "-> Remux can be retried with the GUI's Remux tool on {}",
+                ts_path.display()
+            )),
+            _ => {}
+        }
+    }
+    job.await
+        .map_err(|e| anyhow!("Download task failed to join: {}", e))?
+}
+
+/// Exit code for a failure category (documented in `cli::USAGE`)
+fn exit_code(kind: FailureKind) -> i32 {
+    match kind {
+        FailureKind::Network => EXIT_NETWORK,
+        FailureKind::Decrypt => EXIT_DECRYPT,
+        FailureKind::Ffmpeg => EXIT_FFMPEG,
+        FailureKind::Parse => EXIT_PARSE,
+        FailureKind::Cancelled => EXIT_CANCELLED,
+        FailureKind::Other => EXIT_FAILURE,
+    }
+}
+
+/// Reports a failure on stderr and, if requested, as JSON; returns the exit code.
+fn report_failure(code: i32, kind: &str, message: &str, error_json: Option<&Path>) -> i32 {
+    if let Some(path) = error_json {
+        let report = serde_json::json!({
+            "exit_code": code,
+            "kind": kind,
+            "message": message,
+        });
+        if let Err(e) = std::fs::write(path, format!("{:#}\n", report)) {
+            eprintln!(
+                "⚠️ Warning: Failed to write error report {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MASTER: &str = "#EXTM3U\n\
+        #EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID=\"aud\",NAME=\"English\",LANGUAGE=\"en\",DEFAULT=YES,URI=\"audio/en.m3u8\"\n\
+        #EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360,AUDIO=\"aud\"\n\
+        low/index.m3u8\n\
+        #EXT-X-STREAM-INF:BANDWIDTH=2400000,RESOLUTION=1280x720,AUDIO=\"aud\"\n\
+        high/index.m3u8\n";
+
+    fn config(playlist_text: Option<&str>, quality: &str) -> DownloadConfig {
+        serde_json::from_value(serde_json::json!({
+            "playlist_url": "https://example.com/master.m3u8",
+            "output_location": ".",
+            "output_filename": "video",
+            "concurrency": 4,
+            "output_format": "mp4",
+            "playlist_text": playlist_text,
+            "quality": quality,
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn master_playlist_resolves_to_the_chosen_variant() {
+        let mut config = config(Some(MASTER), "best");
+        let options = CliOptions::default();
+        resolve_master(&mut config, &options, &CancellationToken::new())
+            .await
+            .unwrap();
+        assert_eq!(config.playlist_url, "https://example.com/high/index.m3u8");
+        assert_eq!(
+            config.audio_url.as_deref(),
+            Some("https://example.com/audio/en.m3u8")
+        );
+        assert!(config.playlist_text.is_none());
+        assert!(config.quality.is_none());
+    }
+
+    #[tokio::test]
+    async fn cancelled_job_stops_resolving() {
+        // TEST-NET address: the fetch would hang, so only the cancellation can end it
+        let mut config = config(None, "best");
+        config.playlist_url = "https://203.0.113.1/master.m3u8".to_string();
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let error = resolve_master(&mut config, &CliOptions::default(), &cancel)
+            .await
+            .unwrap_err();
+        assert_eq!(FailureKind::of(&error), FailureKind::Cancelled);
+    }
+}
@@ -0,0 +1,140 @@
+use anyhow::{Result, anyhow};
+use dirs::config_dir;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::downloader::DownloadConfig;
+
+/// Oldest entries are dropped beyond this many
+const MAX_HISTORY_ENTRIES: usize = 5000;
+
+/// Free-text note and tags attached to a queued job, kept with its history entry
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct JobNotes {
+    pub note: String,
+    pub tags: Vec<String>,
+}
+
+impl JobNotes {
+    /// Note plus comma-separated tags as typed by the user (empty tags and repeats dropped).
+    pub fn new(note: &str, tags: &str) -> Self {
+        let mut parsed: Vec<String> = Vec::new();
+        for tag in tags.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            if !parsed.iter().any(|known| known.eq_ignore_ascii_case(tag)) {
+                parsed.push(tag.to_string());
+            }
+        }
+        Self {
+            note: note.trim().to_string(),
+            tags: parsed,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.note.is_empty() && self.tags.is_empty()
+    }
+
+    /// Tags as they are edited: `episode 12, needs subs`
+    pub fn tags_text(&self) -> String {
+        self.tags.join(", ")
+    }
+
+    /// One-line summary for lists: `[tag] [tag] note`
+    pub fn summary(&self) -> String {
+        self.tags
+            .iter()
+            .map(|tag| format!("[{}]", tag))
+            .chain((!self.note.is_empty()).then(|| self.note.replace('\n', " ")))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// A finished download
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub config: DownloadConfig,
+    /// UTC date (YYYY-MM-DD)
+    pub finished_on: String,
+    /// None after success
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub notes: JobNotes,
+}
+
+impl HistoryEntry {
+    /// Output name, or the playlist URL if the job had none
+    pub fn name(&self) -> &str {
+        if self.config.output_filename.is_empty() {
+            &self.config.playlist_url
+        } else {
+            &self.config.output_filename
+        }
+    }
+
+    /// Every word of `query` appears (case-insensitively) in the name, URL, date, note or tags;
+    /// `#word` only matches a tag.
+    pub fn matches(&self, query: &str) -> bool {
+        let fields = [
+            self.name(),
+            self.config.playlist_url.as_str(),
+            self.finished_on.as_str(),
+            self.notes.note.as_str(),
+        ]
+        .map(str::to_lowercase);
+        let tags: Vec<String> = self.notes.tags.iter().map(|t| t.to_lowercase()).collect();
+        query
+            .split_whitespace()
+            .map(str::to_lowercase)
+            .all(|word| match word.strip_prefix('#') {
+                Some(tag) => tags.iter().any(|t| t.contains(tag)),
+                None => {
+                    fields.iter().any(|field| field.contains(&word))
+                        || tags.iter().any(|t| t.contains(&word))
+                }
+            })
+    }
+}
+
+/// Finished downloads with their notes, persisted between runs
+/// (`<config_dir>/hls-downloader/history.json`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct History {
+    pub entries: Vec<HistoryEntry>,
+}
+
+fn history_path() -> Result<PathBuf> {
+    let base = config_dir().ok_or_else(|| anyhow!("Could not determine config directory"))?;
+    Ok(base.join("hls-downloader").join("history.json"))
+}
+
+impl History {
+    pub fn load() -> Self {
+        history_path()
+            .ok()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    /// Writes the history to disk, creating the config directory if needed.
+    pub fn save(&self) -> Result<()> {
+        let path = history_path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn record(&mut self, entry: HistoryEntry) {
+        self.entries.push(entry);
+        if self.entries.len() > MAX_HISTORY_ENTRIES {
+            let excess = self.entries.len() - MAX_HISTORY_ENTRIES;
+            self.entries.drain(..excess);
+        }
+    }
+}
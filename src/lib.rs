@@ -0,0 +1,4 @@
+// Library target: the download engine, shared by the app binary and the integration tests
+// in `tests/`.
+
+pub mod downloader;
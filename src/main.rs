@@ -1,12 +1,66 @@
 use anyhow::Result;
 use eframe::{App, Frame, NativeOptions, egui, run_native};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::runtime::Runtime;
 use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
 
-mod downloader;
-use downloader::{DEFAULT_CONCURRENT_DOWNLOADS, DownloadMessage, run_hls_download_core};
+mod api;
+mod cli;
+mod completions;
+mod config_file;
+mod curl_import;
+mod diagnostics;
+mod headless;
+mod history;
+mod power;
+mod queue;
+mod retention;
+mod runtime;
+mod settings;
+mod ui;
+mod watch_later;
+use api::{ApiConfig, ApiQueueJob, ApiServer, ApiStatus};
+use downloader::capture::run_capture_job;
+use downloader::hls_parser::{ClipWindow, SampleLength, TimeRange};
+use downloader::http::{normalize_url, parse_header_lines};
+use downloader::inspect::{PlaylistInfo, run_inspect_job};
+use downloader::live::LiveStart;
+use downloader::pause::PauseGate;
+use downloader::preview::{PreviewKind, run_preview_job};
+use downloader::probe::{StreamInfo, run_analyze_job};
+use downloader::repair::run_repair_job;
+use downloader::segment_io::RemuxOptions;
+use downloader::stats::{self, StatsRates, StatsSnapshot, format_bytes};
+use downloader::subtitles::SubtitleMode;
+use downloader::url_rewrite::RewriteRule;
+use downloader::util::PathStringLossy;
+use downloader::verify::run_verify_job;
+use downloader::{
+    DEFAULT_CONCURRENT_DOWNLOADS, DownloadConfig, DownloadMessage, KeyOverride,
+    run_hls_download_core, run_remux_job,
+};
+use history::{History, HistoryEntry, JobNotes};
+use hls_downloader::downloader;
+use power::{BackgroundPriority, SleepInhibitor};
+use queue::JobQueue;
+use retention::CleanupReport;
+use settings::AppSettings;
+use ui::history_panel::NotesEdit;
+use ui::job::{JobModel, RemuxRetry};
+use ui::queue_panel::{BatchRow, batch_default_variant};
+use ui::raw_playlist::RawPlaylist;
+use ui::segment_editor::SegmentRow;
+use watch_later::WatchLater;
+
+/// Output containers offered in the format dropdown
+const OUTPUT_FORMATS: [&str; 4] = ["mp4", "mkv", "webm", "ts"];
+/// Redraw interval while the HTTP API runs, so its status and submitted jobs stay current
+const API_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Log lines served at the API's `/status`
+const API_LOG_LINES: usize = 8;
 
 // ------------------------------------------------------------------------
 // 0. Egui Application Structure (App)
@@ -18,12 +72,114 @@ struct HlsDownloaderApp {
     output_filename: String,
     output_location: String,
     concurrent_downloads: u8,
-    output_format: String, // Output format field
+    output_format: String,   // Output format field
+    request_headers: String, // One `Name: value` per line
+    // Segment URL rewrite rules, one `pattern => replacement` per line
+    url_rewrites: String,
+    // Pasted playlist content instead of fetching the URL (which then serves as base URL)
+    use_pasted_playlist: bool,
+    pasted_playlist: String,
+    // Host whose header profile was last applied, so edits are not overwritten on every keystroke
+    applied_profile_host: Option<String>,
+    fix_timestamps: bool,
+    fill_gaps: bool,
+    skip_ads: bool,
+    reencode: bool,
+    propagate_query: bool,
+    keep_workspace: bool,
+    verify_concat: bool,
+    save_report: bool,
+    normalize_audio: bool,
+    extract_captions: bool,
+    // Optional subtitle track (URL, WebVTT playlist or local file) and how to add it
+    subtitle_url: String,
+    subtitle_mode: SubtitleMode,
+    // Optional decryption key/IV (hex) that replace the playlist's key file and IV
+    key_hex: String,
+    iv_hex: String,
+    // Completion hooks: shell command and webhook URL (empty = none)
+    on_complete: String,
+    webhook_url: String,
+    stream_to_player: bool,
+    live: bool,
+    append_recording: bool,
+    live_start: LiveStart,
+    patient_live: bool,
+    clip_start: String,
+    clip_end: String,
+    time_start: String,
+    time_end: String,
+    exact_trim: bool,
+    // Codecs found by the Analyze step, with the playlist URL they belong to
+    stream_info: Option<(String, StreamInfo)>,
+    // Segments listed by the Analyze step (with the playlist URL) and the "Segments" editor window
+    segment_rows: Option<(String, Vec<SegmentRow>)>,
+    segment_editor_open: bool,
+    segment_range: (usize, usize),
+    // Playlist text fetched by the Analyze step, with the playlist URL it belongs to
+    raw_playlists: Option<(String, Vec<RawPlaylist>)>,
+    // Dry-run report of the last Inspect, with the playlist URL it belongs to
+    inspection: Option<(String, PlaylistInfo)>,
+    // What "Quick preview" renders
+    preview_kind: PreviewKind,
+    // Length of a "Download sample" (segments, or seconds with an `s`)
+    sample_length: String,
+
+    // Download queue (persisted), whether it is being worked through, and the startup prompt
+    queue: JobQueue,
+    queue_active: bool,
+    // Queue job running as the window's own task (others run in `job.parallel`)
+    queue_job: Option<u64>,
+    queue_resume_prompt: bool,
+    // Jobs captured with "Save for later" (persisted), run on demand
+    watch_later: WatchLater,
+    // Finished downloads (persisted), the history search and the open note editor
+    history: History,
+    history_search: String,
+    notes_editor: Option<NotesEdit>,
+    // Job waiting for the user's choice because its format can't hold the codecs
+    format_confirm: Option<(DownloadConfig, JobNotes)>,
+    // Note and tags given to the next download or queued job
+    job_note: String,
+    job_tags: String,
+
+    // "Paste curl command" importer window
+    curl_import_open: bool,
+    curl_import_text: String,
+    // "Batch import" window: one URL per line, analyzed before the jobs are queued
+    batch_open: bool,
+    batch_text: String,
+    batch_rows: Vec<BatchRow>,
+    // New quality profile being entered in Settings
+    quality_profile_name: String,
+    quality_profile_ladder: String,
+
+    // Persisted preferences
+    settings: AppSettings,
+    // Held while a download is running (when enabled in settings)
+    sleep_inhibitor: Option<SleepInhibitor>,
+    // Result of the last "Clear caches" click, shown next to the button
+    cache_cleanup: Option<CleanupReport>,
+    background_priority: Option<BackgroundPriority>,
+    // Local HTTP API, running while enabled in settings
+    api_server: Option<ApiServer>,
 
-    // Interface state
-    is_downloading: bool,
-    progress: f32, // 0.0 to 1.0
-    logs: Vec<String>,
+    // Running task: progress, log, warnings and its message channel
+    job: JobModel,
+
+    // "Remux existing file" tool
+    remux_input: String,
+    remux_format: String,
+    remux_fix_timestamps: bool,
+    // "Verify download" tool
+    verify_url: String,
+    verify_file: String,
+    // "Repair workspace" tool
+    repair_workspace: String,
+
+    // Dashboard: last counter snapshot and the rates derived from it
+    stats_snapshot: StatsSnapshot,
+    stats_rates: StatsRates,
 
     // Toki Runtime and Channel (MPSC)
     runtime: Arc<Runtime>,
@@ -31,32 +187,117 @@ struct HlsDownloaderApp {
     sender: mpsc::Sender<DownloadMessage>,
     // Persistent Receiver for GUI commands (Polled by update)
     gui_receiver: mpsc::Receiver<DownloadMessage>,
-    // Temporary receiver for the active download task (recreated on each start)
-    download_receiver: Option<mpsc::Receiver<DownloadMessage>>,
 }
 
 impl Default for HlsDownloaderApp {
     fn default() -> Self {
-        let runtime = Arc::new(Runtime::new().expect("Failed to create tokio runtime"));
+        let settings = AppSettings::load();
+        downloader::remux_pool::set_workers(settings.remux_workers);
+        let mut logs = vec!["Application started.".to_string()];
+        let runtime = settings.runtime.build().unwrap_or_else(|e| {
+            logs.push(format!(
+                "⚠️ Warning: Runtime tuning could not be applied ({}); using defaults.",
+                e
+            ));
+            Runtime::new().expect("Failed to create tokio runtime")
+        });
+        let runtime = Arc::new(runtime);
         // 創建一個常駐的通道，用於處理 UI 相關的非下載任務（例如檔案對話框）
         let (sender, gui_receiver) = mpsc::channel(10);
 
-        Self {
+        let mut app = Self {
             m3u8_url: "".to_string(),
             output_filename: "".to_string(),
             output_location: "".to_string(),
-            concurrent_downloads: DEFAULT_CONCURRENT_DOWNLOADS as u8,
+            concurrent_downloads: DEFAULT_CONCURRENT_DOWNLOADS,
             output_format: "mp4".to_string(),
+            request_headers: "".to_string(),
+            url_rewrites: String::new(),
+            use_pasted_playlist: false,
+            pasted_playlist: "".to_string(),
+            applied_profile_host: None,
+            fix_timestamps: false,
+            fill_gaps: false,
+            skip_ads: false,
+            reencode: false,
+            propagate_query: false,
+            keep_workspace: false,
+            verify_concat: false,
+            save_report: false,
+            normalize_audio: false,
+            extract_captions: false,
+            subtitle_url: "".to_string(),
+            subtitle_mode: SubtitleMode::default(),
+            key_hex: "".to_string(),
+            iv_hex: "".to_string(),
+            on_complete: "".to_string(),
+            webhook_url: "".to_string(),
+            stream_to_player: false,
+            live: false,
+            append_recording: false,
+            live_start: LiveStart::default(),
+            patient_live: false,
+            clip_start: String::new(),
+            clip_end: String::new(),
+            time_start: String::new(),
+            time_end: String::new(),
+            exact_trim: false,
+            stream_info: None,
+            segment_rows: None,
+            segment_editor_open: false,
+            segment_range: (0, 0),
+            raw_playlists: None,
+            inspection: None,
+            preview_kind: PreviewKind::default(),
+            sample_length: "3".to_string(),
 
-            is_downloading: false,
-            progress: 0.0,
-            logs: vec!["Application started.".to_string()],
+            queue: JobQueue::load(),
+            queue_active: false,
+            queue_job: None,
+            queue_resume_prompt: false,
+            watch_later: WatchLater::load(),
+            history: History::load(),
+            history_search: String::new(),
+            notes_editor: None,
+            format_confirm: None,
+            job_note: String::new(),
+            job_tags: String::new(),
+
+            curl_import_open: false,
+            curl_import_text: "".to_string(),
+            batch_open: false,
+            batch_text: String::new(),
+            batch_rows: Vec::new(),
+            quality_profile_name: String::new(),
+            quality_profile_ladder: String::new(),
+
+            settings,
+            sleep_inhibitor: None,
+            cache_cleanup: None,
+            background_priority: None,
+            api_server: None,
+
+            job: JobModel::new(logs),
+
+            remux_input: "".to_string(),
+            remux_format: "mp4".to_string(),
+            remux_fix_timestamps: false,
+            verify_url: "".to_string(),
+            verify_file: "".to_string(),
+            repair_workspace: "".to_string(),
+
+            stats_snapshot: stats::global().snapshot(),
+            stats_rates: StatsRates::default(),
 
             runtime,
-            sender,                  // 常駐 Sender
-            gui_receiver,            // 常駐 Receiver
-            download_receiver: None, // 暫時的下載 Receiver
-        }
+            sender,       // 常駐 Sender
+            gui_receiver, // 常駐 Receiver
+        };
+        app.update_api_server();
+        app.apply_retention();
+        // Offer to continue where the last session left off
+        app.queue_resume_prompt = app.queue.pending_count() > 0;
+        app
     }
 }
 
@@ -66,213 +307,121 @@ impl App for HlsDownloaderApp {
 
         // 1. Poll the PERSISTENT GUI Receiver (處理檔案選擇結果)
         while let Ok(msg) = self.gui_receiver.try_recv() {
-            if let DownloadMessage::OutputPathSelected(path) = msg {
-                self.output_location = path;
+            match msg {
+                DownloadMessage::OutputPathSelected(path) => self.output_location = path,
+                DownloadMessage::RemuxInputSelected(path) => self.remux_input = path,
+                DownloadMessage::KeyFileSelected(path) => self.load_key_file(&path),
+                DownloadMessage::VerifyFileSelected(path) => self.verify_file = path,
+                DownloadMessage::RepairWorkspaceSelected(path) => self.repair_workspace = path,
+                DownloadMessage::ApiJobSubmitted { url, name } => self.enqueue_api_job(url, name),
+                DownloadMessage::ApiCancelRequested => {
+                    self.job
+                        .logs
+                        .push("-> Cancel requested through the HTTP API.".to_string());
+                    self.job.cancel_all();
+                }
+                DownloadMessage::ApiJobRemoved { position } => self.remove_api_job(position),
+                _ => {}
+            }
+            if !self.settings.low_power_mode {
                 ctx.request_repaint();
             }
         }
 
-        // 2. Poll the TEMPORARY Download Receiver (處理下載進度、日誌和結束)
-        if let Some(receiver) = self.download_receiver.as_mut() {
-            let mut finished = false;
-            let mut message_count = 0; // 訊息計數器
-
-            // The Egui thread must use try_recv(), it cannot block.
-            while let Ok(msg) = receiver.try_recv() {
-                match msg {
-                    DownloadMessage::Log(s) => self.logs.push(s),
-                    DownloadMessage::Progress(p) => self.progress = p,
-                    DownloadMessage::Finished(res) => {
-                        self.is_downloading = false;
-                        finished = true; // Set the flag
-
-                        match res {
-                            Ok(_) => self
-                                .logs
-                                .push("✅ Download task completed successfully!".to_string()),
-                            Err(e) => self.logs.push(format!("❌ Task failed: {}", e)),
-                        }
-                    }
-                    // ⚠️ 注意: OutputPathSelected 已經被 persistent gui_receiver 處理，這裡不需要。
-                    DownloadMessage::OutputPathSelected(_) => { /* Ignore, handled by gui_receiver */
-                    }
-                }
+        // 2. Poll the running task (處理下載進度、日誌和結束)
+        let mut message_count = 0; // 訊息計數器
+        // The Egui thread must use try_recv(), it cannot block.
+        while let Some(msg) = self.job.try_recv() {
+            if let Some(msg) = self.job.apply(msg) {
+                self.route_task_message(msg);
+            }
 
-                // Request repaint to update the interface
+            // Request repaint to update the interface (low-power mode redraws on a timer)
+            if !self.settings.low_power_mode {
                 ctx.request_repaint();
-
-                // 讓出控制權的邏輯 (解決 Hyprland 假死問題)
-                message_count += 1;
-                if message_count >= 10 {
-                    // 處理 10 條訊息後
-                    std::thread::sleep(Duration::from_millis(1));
-                    message_count = 0; // 重置計數
-                }
             }
 
-            // Handle outside the mutable borrow scope
-            if finished {
-                // 使用新的欄位名稱
-                self.download_receiver = None;
+            // 讓出控制權的邏輯 (解決 Hyprland 假死問題)
+            message_count += 1;
+            if message_count >= 10 {
+                // 處理 10 條訊息後
+                std::thread::sleep(Duration::from_millis(1));
+                message_count = 0; // 重置計數
             }
         }
+        // 3. Poll the remuxes of earlier queued jobs that continue in the background
+        if self.job.poll_background() && !self.settings.low_power_mode {
+            ctx.request_repaint();
+        }
+        let (received, finished) = self.job.poll_parallel();
+        if !finished.is_empty() {
+            self.update_sleep_inhibitor();
+        }
+        for finished in finished {
+            if let Some(id) = finished.queue_id {
+                self.finish_queue_job(id, &finished.result);
+            }
+            self.record_history(finished.download, &finished.result);
+        }
+        if received && !self.settings.low_power_mode {
+            ctx.request_repaint();
+        }
+        // 4. Keep the API's status fresh and pick up jobs submitted through it while idle
+        if self.api_server.is_some() {
+            self.publish_api_status();
+            ctx.request_repaint_after(API_POLL_INTERVAL);
+        }
         // ---------------------------------------
 
+        if self.settings.low_power_mode && self.job.busy() {
+            // Background tasks do not wake the window in low-power mode; poll at the set rate
+            ctx.request_repaint_after(Duration::from_millis(self.settings.low_power_refresh_ms));
+        }
+
+        self.run_queue(ctx);
+        if self.queue_resume_prompt {
+            self.show_queue_resume_prompt(ctx);
+        }
+
+        self.refresh_stats();
+        if self.stats_snapshot.active_connections > 0
+            || self.stats_rates.download_bytes_per_sec > 0.0
+        {
+            // Keep the dashboard rates moving (and settle back to zero) without user input
+            ctx.request_repaint_after(Duration::from_secs(1));
+        }
+        self.show_curl_import_window(ctx);
+        self.show_batch_import_window(ctx);
+        self.show_segment_editor_window(ctx);
+        self.show_notes_editor_window(ctx);
+        self.show_format_confirm_window(ctx);
+
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.heading("HLS Downloader");
             ui.separator();
 
-            // Input Block
-            ui.add_enabled_ui(!self.is_downloading, |ui| {
-                // 使用 Grid 確保標籤和輸入框垂直對齊
-                egui::Grid::new("input_grid")
-                    .num_columns(2) // 兩欄: 標籤 和 Widget
-                    .spacing([20.0, 10.0]) // [水平間距, 垂直間距]
-                    .striped(true) // 增加條紋背景以提高可讀性
-                    .show(ui, |ui| {
-                        // --- M3U8 URL ---
-                        ui.label("M3U8 URL:"); // 第一欄: 標籤
-                        ui.text_edit_singleline(&mut self.m3u8_url); // 第二欄: 輸入框
-                        ui.end_row();
-
-                        // --- Output Filename (標籤與輸入框平行) ---
-                        ui.label("Output Filename:"); // 第一欄: 標籤
-                        ui.text_edit_singleline(&mut self.output_filename);
-                        ui.end_row();
-
-                        ui.label("Output Location:"); // 第一欄: 標籤
-                        ui.horizontal(|ui| {
-                            // 第二欄: 輸入框 + 按鈕
-                            ui.add(egui::TextEdit::singleline(&mut self.output_location));
-
-                            // "Browse" 按鈕和 rfd 邏輯
-                            if ui.button("Browse...").clicked() {
-                                let current_location = self.output_location.clone();
-                                // 使用 self.sender (現在已在結構體中定義)
-                                let sender_clone = self.sender.clone();
-
-                                // 由於 rfd::FileDialog::save_file() 是阻塞的，必須在 blocking thread 中運行
-                                self.runtime.handle().clone().spawn_blocking(move || {
-                                    if let Some(path) = rfd::FileDialog::new()
-                                        .set_directory(&current_location)
-                                        .pick_folder()
-                                    {
-                                        let full_path = path.to_string_lossy().into_owned();
-                                        // 使用 blocking_send 傳回結果給 GUI
-                                        let _ = sender_clone.blocking_send(
-                                            DownloadMessage::OutputPathSelected(full_path),
-                                        );
-                                    }
-                                });
-                            }
-
-                            // 只有當 output_location 非空時才啟用
-                            ui.add_enabled(
-                                !self.output_location.is_empty(),
-                                |ui: &mut egui::Ui| {
-                                    // 創建一個 scope，並返回 scope 的 Response
-                                    ui.scope(|ui| {
-                                        let button_response = ui.button("Open Folder");
-
-                                        if button_response.clicked() {
-                                            let path_to_open = self.output_location.clone();
-                                            // opener 函式庫用於在作業系統中開啟檔案或資料夾
-                                            if let Err(e) = opener::open(&path_to_open) {
-                                                // 錯誤處理
-                                                eprintln!(
-                                                    "Failed to open directory {}: {}",
-                                                    path_to_open, e
-                                                );
-                                            }
-                                        }
-
-                                        // 返回按鈕的 Response，這成為 scope 的返回值
-                                        button_response
-                                    })
-                                    .response
-                                    // .response 是 Scope 結構體的屬性，包含其內部 Widget 的整體響應。
-                                },
-                            );
-                        });
-                        ui.end_row();
-
-                        // --- Concurrent Downloads & Output Format (放在同一行，但屬於 Grid 的單元格) ---
-                        // 這裡我們需要將兩個控制項擠入 Grid 的第二個單元格
-                        ui.label("Concurrent Downloads / Format:"); // 佔用第一欄的標籤
-
-                        ui.horizontal(|ui| {
-                            // 1. Concurrent Downloads
-                            ui.add(
-                                egui::DragValue::new(&mut self.concurrent_downloads)
-                                    .speed(1.0)
-                                    .clamp_range(1..=16)
-                                    .prefix("x "),
-                            );
-
-                            ui.separator(); // 視覺分隔符
-
-                            // Output Format (Dropdown)
-                            let formats = ["mp4", "mkv", "webm", "ts"];
-                            ui.label("Format:"); // 在水平佈局中再次加入標籤
-
-                            egui::ComboBox::from_label("")
-                                .selected_text(&self.output_format)
-                                .width(70.0)
-                                .show_ui(ui, |ui| {
-                                    for format in formats {
-                                        ui.selectable_value(
-                                            &mut self.output_format,
-                                            format.to_string(),
-                                            format,
-                                        );
-                                    }
-                                });
-                        });
-                        ui.end_row();
-                    });
-            });
-
-            // 2. Button and Progress Bar
-            ui.add_space(10.0);
-            let download_btn =
-                ui.add_enabled(!self.is_downloading, egui::Button::new("🚀 Start Download"));
+            self.show_input_panel(ui);
+            self.show_settings_panel(ui);
+            self.show_tools_panel(ui, ctx);
+            self.show_queue_panel(ui, ctx);
 
-            if download_btn.clicked() {
-                // Clear state and start the task
-                self.start_download_task(ctx.clone());
-            }
+            // Dashboard Block
+            egui::CollapsingHeader::new("Dashboard").show(ui, |ui| {
+                self.show_dashboard(ui);
+            });
 
-            ui.add_space(10.0);
-            ui.add(egui::ProgressBar::new(self.progress).show_percentage());
-
-            // 3. Log Output Block
-            ui.add_space(15.0);
-            ui.label("Log Output:");
-            egui::ScrollArea::vertical()
-                .stick_to_bottom(true)
-                .max_height(250.0)
-                .show(ui, |ui| {
-                    // Display latest logs at the bottom
-                    for log in self.logs.iter() {
-                        let text = egui::RichText::new(log);
-                        // Color based on log content (simplified)
-                        let colored_text = if log.starts_with("❌") {
-                            text.color(egui::Color32::RED)
-                        } else if log.starts_with("✅")
-                            || log.starts_with("📦")
-                            || log.starts_with("🔑")
-                        {
-                            text.color(egui::Color32::GREEN)
-                        } else if log.starts_with("⚠️") {
-                            text.color(egui::Color32::YELLOW)
-                        } else {
-                            text.color(egui::Color32::WHITE)
-                        };
-                        ui.label(colored_text);
-                    }
-                });
+            self.show_actions(ui, ctx);
+            self.show_raw_playlist_panel(ui);
+            self.show_inspection_panel(ui);
+            self.show_log_panel(ui, ctx);
         });
     }
+
+    /// Closing the window cancels the running tasks, so FFmpeg is killed and temp dirs are
+    /// removed instead of the work continuing unseen until the runtime shuts down.
+    fn on_exit(&mut self, _gl: Option<&eframe::glow::Context>) {
+        self.job.cancel_all();
+    }
 }
 
 // ------------------------------------------------------------------------
@@ -280,53 +429,737 @@ impl App for HlsDownloaderApp {
 // ------------------------------------------------------------------------
 
 impl HlsDownloaderApp {
+    /// Task messages that change app state outside the job model.
+    fn route_task_message(&mut self, msg: DownloadMessage) {
+        match msg {
+            DownloadMessage::Finished(res) => {
+                self.background_priority = None;
+
+                if let Some(id) = self.queue_job.take() {
+                    self.finish_queue_job(id, &res);
+                }
+                if let Some(download) = self.job.download.take() {
+                    self.record_history(download, &res);
+                }
+                self.job.finish(&res);
+                // Parallel downloads may still keep the system awake
+                self.update_sleep_inhibitor();
+                // The output location is known now, so kept workspaces can be found
+                self.apply_retention();
+            }
+            DownloadMessage::Analyzed { playlist_url, info } => {
+                if let Some(problem) = info.incompatibility(&self.output_format)
+                    && let Some(format) = OUTPUT_FORMATS
+                        .into_iter()
+                        .find(|format| info.incompatibility(format).is_none())
+                {
+                    self.job.logs.push(format!(
+                        "-> {}; switching the output format to {}.",
+                        problem, format
+                    ));
+                    self.output_format = format.to_string();
+                }
+                self.stream_info = Some((playlist_url, info));
+            }
+            DownloadMessage::Captured(mut job) => {
+                job.captured_on = downloader::util::today_utc();
+                self.watch_later.jobs.push(*job);
+                if let Err(e) = self.watch_later.save() {
+                    self.job.logs.push(format!(
+                        "⚠️ Warning: Failed to save watch later list: {}",
+                        e
+                    ));
+                }
+                self.job.logs.push(format!(
+                    "-> Saved for later ({} in the list).",
+                    self.watch_later.jobs.len()
+                ));
+            }
+            DownloadMessage::PlaylistFetched {
+                playlist_url,
+                url,
+                text,
+            } => {
+                let playlist = RawPlaylist::new(url, &text);
+                match &mut self.raw_playlists {
+                    Some((current, playlists)) if *current == playlist_url => {
+                        playlists.push(playlist)
+                    }
+                    _ => self.raw_playlists = Some((playlist_url, vec![playlist])),
+                }
+            }
+            DownloadMessage::Inspected { playlist_url, info } => {
+                self.inspection = Some((playlist_url, *info));
+            }
+            DownloadMessage::SegmentsListed {
+                playlist_url,
+                segments,
+            } => {
+                // Exclusions survive re-analyzing the same, unchanged playlist
+                let previous = self
+                    .segment_rows
+                    .take()
+                    .filter(|(url, rows)| *url == playlist_url && rows.len() == segments.len());
+                let rows = segments
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, entry)| SegmentRow {
+                        entry,
+                        excluded: previous.as_ref().is_some_and(|(_, rows)| rows[i].excluded),
+                    })
+                    .collect();
+                self.segment_rows = Some((playlist_url, rows));
+            }
+            DownloadMessage::BatchAnalyzed { row, result } => {
+                let variant = result
+                    .as_ref()
+                    .ok()
+                    .map(|entry| batch_default_variant(&self.settings, entry));
+                if let Some(batch_row) = self.batch_rows.get_mut(row) {
+                    if let Some(variant) = variant {
+                        batch_row.variant = variant;
+                    }
+                    batch_row.analysis = Some(result.map(|entry| *entry));
+                }
+            }
+            // File dialog results arrive on gui_receiver; log and progress were applied by the job model
+            _ => {}
+        }
+    }
+
+    fn save_settings(&mut self) {
+        if let Err(e) = self.settings.save() {
+            self.job
+                .logs
+                .push(format!("⚠️ Warning: Failed to save settings: {}", e));
+        }
+    }
+
+    /// Starts, restarts or stops the local HTTP API according to the settings.
+    fn update_api_server(&mut self) {
+        let wanted = match self.settings.api_enabled.then(|| {
+            ApiConfig::new(
+                self.settings.api_port,
+                &self.settings.api_bind_addresses,
+                &self.settings.api_tokens,
+            )
+        }) {
+            Some(Ok(config)) => Some(config),
+            Some(Err(e)) => {
+                self.job.logs.push(format!("⚠️ Warning: {}", e));
+                return;
+            }
+            None => None,
+        };
+        if self.api_server.as_ref().map(ApiServer::config) == wanted.as_ref() {
+            return;
+        }
+        // Drop the old server first so the port is free when restarting
+        self.api_server = None;
+        if let Some(config) = wanted {
+            if config.tokens.is_empty()
+                && config
+                    .bind_addresses
+                    .iter()
+                    .any(|address| !address.is_loopback())
+            {
+                self.job.logs.push(
+                    "⚠️ Warning: The API has no tokens, so it only answers this computer."
+                        .to_string(),
+                );
+            }
+            let urls = config.urls().join(", ");
+            match ApiServer::start(&self.runtime, config, self.sender.clone()) {
+                Ok(server) => {
+                    self.job
+                        .logs
+                        .push(format!("-> Local HTTP API listening on {}", urls));
+                    self.api_server = Some(server);
+                }
+                Err(e) => self.job.logs.push(format!("⚠️ Warning: {}", e)),
+            }
+        }
+    }
+
+    /// Status served at the API's `/status` (and shown by its submit page).
+    fn publish_api_status(&self) {
+        let Some(server) = &self.api_server else {
+            return;
+        };
+        let name = |config: &DownloadConfig| {
+            if config.output_filename.is_empty() {
+                config.playlist_url.clone()
+            } else {
+                config.output_filename.clone()
+            }
+        };
+        server.publish(ApiStatus {
+            running: self.job.running,
+            progress: self.job.progress,
+            current: self.job.download.as_ref().map(|(config, _)| name(config)),
+            queue: self
+                .queue
+                .jobs
+                .iter()
+                .map(|job| ApiQueueJob {
+                    name: name(&job.config),
+                    state: format!("{:?}", job.state),
+                })
+                .collect(),
+            log: self
+                .job
+                .logs
+                .iter()
+                .rev()
+                .take(API_LOG_LINES)
+                .rev()
+                .cloned()
+                .collect(),
+        });
+    }
+
+    /// Acquires or releases the sleep lock according to the job state and settings.
+    fn update_sleep_inhibitor(&mut self) {
+        let wanted = self.job.busy() && self.settings.prevent_sleep;
+        if !wanted {
+            self.sleep_inhibitor = None;
+        } else if self.sleep_inhibitor.is_none() {
+            match SleepInhibitor::acquire() {
+                Ok(guard) => self.sleep_inhibitor = Some(guard),
+                Err(e) => self
+                    .job
+                    .logs
+                    .push(format!("⚠️ Warning: Could not inhibit system sleep: {}", e)),
+            }
+        }
+    }
+
+    /// Enters or leaves background priority according to the job state and settings.
+    fn update_background_priority(&mut self) {
+        let wanted = self.job.running && self.settings.background_priority;
+        if !wanted {
+            self.background_priority = None;
+        } else if self.background_priority.is_none() {
+            match BackgroundPriority::enter() {
+                Ok(guard) => self.background_priority = Some(guard),
+                Err(e) => self.job.logs.push(format!(
+                    "⚠️ Warning: Could not lower the process priority: {}",
+                    e
+                )),
+            }
+        }
+    }
+
     fn start_download_task(&mut self, ctx: egui::Context) {
-        // Parameter check
+        if let Some(config) = self.validated_config() {
+            // Asked before an hour-long download ends in a failing stream copy
+            if !config.reencode && self.format_warning(&config.output_format).is_some() {
+                self.format_confirm = Some((config, self.current_notes()));
+                return;
+            }
+            self.launch_download(ctx, config, self.current_notes());
+        }
+    }
+
+    /// Downloads only the first segments of the job in the form, as `<name>.sample.<ext>`.
+    fn start_sample_task(&mut self, ctx: egui::Context) {
+        let Some(sample) = SampleLength::parse(&self.sample_length) else {
+            self.job.logs.push(format!(
+                "⚠️ Invalid sample length: {} (e.g. 3 segments or 30s)",
+                self.sample_length.trim()
+            ));
+            return;
+        };
+        if let Some(mut config) = self.validated_config() {
+            config.sample = Some(sample);
+            self.launch_download(ctx, config, self.current_notes());
+        }
+    }
+
+    /// Adds the job described by the input fields to the end of the queue.
+    fn enqueue_current_job(&mut self) {
+        if let Some(config) = self.validated_config() {
+            self.job.logs.push(format!(
+                "-> Queued {} ({} pending).",
+                config.playlist_url,
+                self.queue.pending_count() + 1
+            ));
+            self.queue.push(config, self.current_notes());
+            self.save_queue();
+        }
+    }
+
+    /// Captures the job described by the input fields for the Watch later list.
+    fn start_capture_task(&mut self, ctx: egui::Context) {
+        let Some(config) = self.validated_config() else {
+            return;
+        };
+        let ctx = self.task_context(ctx);
+        let cancel = self.begin_task("Capturing playlist and key for later...");
+        let capture_sender = self.open_task_channel();
+
+        self.spawn_task(ctx.clone(), capture_sender.clone(), async move {
+            run_capture_job(config, cancel, capture_sender, ctx).await
+        });
+    }
+
+    /// Deletes old data according to the retention settings (at startup and after each task).
+    fn apply_retention(&mut self) {
+        let report = retention::apply_policy(
+            &self.settings.retention,
+            Path::new(&self.output_location),
+            &mut self.history,
+            &mut self.watch_later,
+        );
+        if report.history_removed > 0 {
+            self.save_history();
+        }
+        if report.watch_later_removed > 0 {
+            self.save_watch_later();
+        }
+        if !report.is_empty() {
+            self.job.logs.push(format!(
+                "🧹 Retention: removed {} folder(s) ({}), {} history and {} watch-later entr{}.",
+                report.removed,
+                format_bytes(report.bytes as f64),
+                report.history_removed,
+                report.watch_later_removed,
+                if report.watch_later_removed == 1 {
+                    "y"
+                } else {
+                    "ies"
+                }
+            ));
+        }
+    }
+
+    /// Parameter check; logs the problem and returns None if the inputs are incomplete.
+    fn validated_config(&mut self) -> Option<DownloadConfig> {
         let url_str = self.m3u8_url.trim();
         if url_str.is_empty() || url_str.starts_with("Enter M3U8 URL...") {
-            self.logs
-                .push("⚠️ Please enter a valid M3U8 URL.".to_string());
-            return;
+            let message = if self.use_pasted_playlist {
+                "⚠️ Please enter the base URL the pasted playlist was loaded from."
+            } else {
+                "⚠️ Please enter a valid M3U8 URL."
+            };
+            self.job.logs.push(message.to_string());
+            return None;
+        }
+        // A URL without scheme is completed in the field; the next click confirms it
+        match normalize_url(url_str) {
+            Ok(url) if url != url_str => {
+                self.job.logs.push(format!(
+                    "-> No scheme in the URL, completed it to {}. Check it and click again to start.",
+                    url
+                ));
+                self.m3u8_url = url;
+                return None;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                self.job.logs.push(format!("⚠️ {}", e));
+                return None;
+            }
         }
 
-        // Set initial state
-        self.is_downloading = true;
-        self.progress = 0.0;
-        self.logs.clear();
-        self.logs.push("Preparing to start download...".to_string());
+        match self.current_config() {
+            Ok(config) => Some(config),
+            Err(e) => {
+                self.job.logs.push(format!("⚠️ {}", e));
+                None
+            }
+        }
+    }
 
-        let url = url_str.to_string();
-        let filename = self.output_filename.clone();
-        let location = self.output_location.clone();
-        let concurrency = self.concurrent_downloads as usize;
-        let format = self.output_format.clone();
+    /// Removes a finished queue job, or pauses it if it failed.
+    fn finish_queue_job(&mut self, id: u64, result: &Result<(), String>) {
+        self.queue.finish(id, result.is_ok());
+        if let Err(e) = self.queue.save() {
+            self.job
+                .logs
+                .push(format!("⚠️ Warning: Failed to save queue: {}", e));
+        }
+        if result.is_err() {
+            self.job
+                .logs
+                .push("-> The failed job was paused in the queue.".to_string());
+        }
+    }
 
-        // 創建一個新的 MPSC 通道，專門用於這個下載任務的狀態更新
-        let (download_sender, download_receiver) = mpsc::channel(100);
-        self.download_receiver = Some(download_receiver); // 儲存這個臨時 Receiver
+    fn record_history(
+        &mut self,
+        (config, notes): (DownloadConfig, JobNotes),
+        result: &Result<(), String>,
+    ) {
+        self.history.record(HistoryEntry {
+            config,
+            finished_on: downloader::util::today_utc(),
+            error: result.as_ref().err().cloned(),
+            notes,
+        });
+        self.save_history();
+    }
 
-        let runtime_handle = self.runtime.handle().clone();
+    /// Runs a download as the window's task, or next to it if a task is running already.
+    fn launch_download(&mut self, ctx: egui::Context, config: DownloadConfig, notes: JobNotes) {
+        if self.job.running {
+            self.launch_parallel_download(ctx, None, config, notes);
+            return;
+        }
+        let ctx = self.task_context(ctx);
+        let cancel = self.begin_task("Preparing to start download...");
+        self.job.download = Some((config.clone(), notes));
+        self.job.diagnostics.config = Some(config.clone());
+        let download_sender = self.open_task_channel();
+        let stop = CancellationToken::new();
+        self.job.live_stop = config.live.then(|| stop.clone());
+        let pause = PauseGate::default();
+        self.job.pause = (!config.live).then(|| pause.clone());
 
         // Start the background task, moving all core logic here
-        runtime_handle.spawn(async move {
-            let result = run_hls_download_core(
-                url,
-                location,
-                filename,
-                concurrency,
-                format,
-                download_sender.clone(), // 使用下載專用的 Sender
-                ctx.clone(),
+        self.spawn_task(ctx.clone(), download_sender.clone(), async move {
+            run_hls_download_core(
+                config,
+                cancel,
+                stop,
+                pause,
+                download_sender, // 使用下載專用的 Sender
+                ctx,
+            )
+            .await
+        });
+    }
+
+    /// Starts a download next to the running task; it reports through its own channel and log
+    /// tab in the job model's parallel downloads.
+    fn launch_parallel_download(
+        &mut self,
+        ctx: egui::Context,
+        queue_id: Option<u64>,
+        config: DownloadConfig,
+        notes: JobNotes,
+    ) {
+        let ctx = self.task_context(ctx);
+        let name = if config.output_filename.is_empty() {
+            config.playlist_url.clone()
+        } else {
+            config.output_filename.clone()
+        };
+        self.job
+            .logs
+            .push(format!("-> Starting download in parallel: {}", name));
+        let stop = CancellationToken::new();
+        let live_stop = config.live.then(|| stop.clone());
+        let pause = PauseGate::default();
+        let cancel = CancellationToken::new();
+        let sender = self.job.open_parallel(
+            queue_id,
+            name,
+            (config.clone(), notes),
+            live_stop,
+            (!config.live).then(|| pause.clone()),
+            cancel.clone(),
+        );
+        self.update_sleep_inhibitor();
+        self.spawn_task(ctx.clone(), sender.clone(), async move {
+            run_hls_download_core(config, cancel, stop, pause, sender, ctx).await
+        });
+    }
+
+    /// Builds the job parameters from the input fields.
+    fn current_config(&self) -> Result<DownloadConfig> {
+        let config = DownloadConfig {
+            playlist_url: self.m3u8_url.trim().to_string(),
+            output_location: self.output_location.clone(),
+            output_filename: self.output_filename.clone(),
+            concurrency: self.concurrent_downloads as usize,
+            output_format: self.output_format.clone(),
+            headers: parse_header_lines(&self.request_headers)?,
+            fix_timestamps: self.fix_timestamps,
+            fill_gaps: self.fill_gaps,
+            skip_ads: self.skip_ads,
+            reencode: self.reencode,
+            propagate_query: self.propagate_query,
+            keep_workspace: self.keep_workspace,
+            verify_concat: self.verify_concat,
+            normalize_audio: self.normalize_audio,
+            extract_captions: self.extract_captions,
+            playlist_text: self.pasted_playlist_text()?,
+            subtitle_url: Some(self.subtitle_url.trim().to_string()).filter(|url| !url.is_empty()),
+            subtitle_mode: self.subtitle_mode,
+            subtitle_track: None,
+            stream_to_player: self.stream_to_player,
+            quality: None,
+            audio_url: None,
+            audio_track: None,
+            proxy: None,
+            live: self.live,
+            append_recording: self.live && self.append_recording,
+            live_start: self.live_start,
+            patient_live: self.patient_live,
+            clip_start: Some(self.clip_start.trim().to_string()).filter(|t| !t.is_empty()),
+            clip_end: Some(self.clip_end.trim().to_string()).filter(|t| !t.is_empty()),
+            time_start: Some(self.time_start.trim().to_string()).filter(|t| !t.is_empty()),
+            time_end: Some(self.time_end.trim().to_string()).filter(|t| !t.is_empty()),
+            exact_trim: self.exact_trim,
+            sample: None,
+            accepted_content_types: Vec::new(),
+            url_rewrites: RewriteRule::parse_lines(&self.url_rewrites)?,
+            report_path: self.save_report.then(|| {
+                PathBuf::from(&self.output_location)
+                    .join(format!("{}.segments.csv", self.output_filename))
+                    .to_string_lossy()
+                    .into_owned()
+            }),
+            key_hex: Some(self.key_hex.trim().to_string()).filter(|key| !key.is_empty()),
+            iv_hex: Some(self.iv_hex.trim().to_string()).filter(|iv| !iv.is_empty()),
+            on_complete: Some(self.on_complete.trim().to_string())
+                .filter(|command| !command.is_empty()),
+            webhook_url: Some(self.webhook_url.trim().to_string()).filter(|url| !url.is_empty()),
+            output_subfolder: Some(self.settings.output_subfolder.trim().to_string())
+                .filter(|template| !template.is_empty()),
+            limits: Default::default(),
+            excluded_segments: self
+                .analyzed_segments()
+                .map(|rows| {
+                    rows.iter()
+                        .filter(|row| row.excluded)
+                        .map(|row| row.entry.index)
+                        .collect()
+                })
+                .unwrap_or_default(),
+            background_remux: false,
+        };
+        // Reported before the job starts instead of failing it
+        KeyOverride::from_config(&config)?;
+        ClipWindow::parse(config.clip_start.as_deref(), config.clip_end.as_deref())?;
+        TimeRange::parse(config.time_start.as_deref(), config.time_end.as_deref())?;
+        Ok(config)
+    }
+
+    /// The pasted playlist, if that source is selected (an empty paste is an error).
+    fn pasted_playlist_text(&self) -> Result<Option<String>> {
+        if !self.use_pasted_playlist {
+            return Ok(None);
+        }
+        if self.pasted_playlist.trim().is_empty() {
+            anyhow::bail!("Paste the playlist content or switch the source back to URL.");
+        }
+        Ok(Some(self.pasted_playlist.clone()))
+    }
+
+    /// Re-runs only the remux step using the TS file retained after a failed remux, with the
+    /// remux options of the job that failed.
+    fn start_remux_retry_task(&mut self, ctx: egui::Context) {
+        let ctx = self.task_context(ctx);
+        let Some(RemuxRetry {
+            ts_path,
+            output_path,
+            options,
+        }) = self.job.remux_retry.take()
+        else {
+            return;
+        };
+
+        let cancel = self.begin_task("Retrying remux from retained TS file...");
+        let remux_sender = self.open_task_channel();
+
+        self.spawn_task(ctx.clone(), remux_sender.clone(), async move {
+            run_remux_job(
+                ts_path,
+                output_path,
+                options,
+                true,
+                cancel,
+                remux_sender,
+                ctx,
             )
-            .await;
+            .await
+        });
+    }
+
+    /// Runs the standalone remux tool on a user-selected file.
+    fn start_remux_file_task(&mut self, ctx: egui::Context) {
+        let ctx = self.task_context(ctx);
+        let input_path = PathBuf::from(self.remux_input.trim());
+        if !input_path.is_file() {
+            self.job.logs.push(format!(
+                "⚠️ Input file does not exist: {}",
+                input_path.display()
+            ));
+            return;
+        }
+
+        // Write next to the input; avoid overwriting it when the container doesn't change
+        let mut output_path = input_path.with_extension(&self.remux_format);
+        if output_path == input_path {
+            output_path = input_path.with_file_name(format!(
+                "{}_remux.{}",
+                input_path.file_string_lossy(),
+                self.remux_format
+            ));
+        }
+
+        let options = RemuxOptions {
+            fix_timestamps: self.remux_fix_timestamps,
+            ..Default::default()
+        };
+
+        let cancel = self.begin_task("Starting remux of existing file...");
+        let remux_sender = self.open_task_channel();
+
+        self.spawn_task(ctx.clone(), remux_sender.clone(), async move {
+            run_remux_job(
+                input_path,
+                output_path,
+                options,
+                false,
+                cancel,
+                remux_sender,
+                ctx,
+            )
+            .await
+        });
+    }
+
+    /// Checks an existing output file against its playlist.
+    fn start_verify_task(&mut self, ctx: egui::Context) {
+        let ctx = self.task_context(ctx);
+        // Fetched like a download of it: same headers, proxy and limits
+        let config = match self.current_config() {
+            Ok(config) => DownloadConfig {
+                playlist_url: self.verify_url.trim().to_string(),
+                playlist_text: None,
+                ..config
+            },
+            Err(e) => {
+                self.job.logs.push(format!("⚠️ {}", e));
+                return;
+            }
+        };
+        let file_path = PathBuf::from(self.verify_file.trim());
+
+        let cancel = self.begin_task("Verifying existing download...");
+        let verify_sender = self.open_task_channel();
+
+        self.spawn_task(ctx.clone(), verify_sender.clone(), async move {
+            run_verify_job(config, file_path, cancel, verify_sender, ctx).await
+        });
+    }
+
+    /// Probes the first segment of the playlist to recommend output formats.
+    fn start_analyze_task(&mut self, ctx: egui::Context) {
+        let ctx = self.task_context(ctx);
+        let config = match self.current_config() {
+            Ok(config) => config,
+            Err(e) => {
+                self.job.logs.push(format!("⚠️ {}", e));
+                return;
+            }
+        };
+
+        // Filled again from this run's fetches
+        self.raw_playlists = None;
+        let cancel = self.begin_task("Analyzing stream...");
+        let analyze_sender = self.open_task_channel();
+
+        self.spawn_task(ctx.clone(), analyze_sender.clone(), async move {
+            run_analyze_job(config, cancel, analyze_sender, ctx).await
+        });
+    }
+
+    /// Parses the entered playlist without downloading media (dry run).
+    fn start_inspect_task(&mut self, ctx: egui::Context) {
+        let ctx = self.task_context(ctx);
+        let config = match self.current_config() {
+            Ok(config) => config,
+            Err(e) => {
+                self.job.logs.push(format!("⚠️ {}", e));
+                return;
+            }
+        };
+
+        self.inspection = None;
+        let cancel = self.begin_task("Inspecting playlist...");
+        let inspect_sender = self.open_task_channel();
+
+        self.spawn_task(ctx.clone(), inspect_sender.clone(), async move {
+            run_inspect_job(config, cancel, inspect_sender, ctx).await
+        });
+    }
+
+    /// Renders a preview from the I-frame playlist of the entered master playlist.
+    fn start_preview_task(&mut self, ctx: egui::Context) {
+        let ctx = self.task_context(ctx);
+        let config = match self.current_config() {
+            Ok(config) => config,
+            Err(e) => {
+                self.job.logs.push(format!("⚠️ {}", e));
+                return;
+            }
+        };
+        let kind = self.preview_kind;
+
+        let cancel = self.begin_task("Rendering quick preview...");
+        let preview_sender = self.open_task_channel();
+
+        self.spawn_task(ctx.clone(), preview_sender.clone(), async move {
+            run_preview_job(config, kind, cancel, preview_sender, ctx).await
+        });
+    }
+
+    /// Re-downloads failed segments of a kept workspace and rebuilds its output.
+    fn start_repair_task(&mut self, ctx: egui::Context) {
+        let ctx = self.task_context(ctx);
+        let workspace_dir = PathBuf::from(self.repair_workspace.trim());
+
+        let cancel = self.begin_task("Repairing workspace...");
+        let repair_sender = self.open_task_channel();
+
+        self.spawn_task(ctx.clone(), repair_sender.clone(), async move {
+            run_repair_job(workspace_dir, cancel, repair_sender, ctx).await
+        });
+    }
+
+    /// Context handed to background tasks: in low-power mode their repaint requests go to a
+    /// context nobody draws, and `update` polls on a timer instead.
+    fn task_context(&self, ctx: egui::Context) -> egui::Context {
+        if self.settings.low_power_mode {
+            egui::Context::default()
+        } else {
+            ctx
+        }
+    }
+
+    /// Resets the interface state for a new background task and returns its cancellation token.
+    fn begin_task(&mut self, first_log: &str) -> CancellationToken {
+        let cancel = self.job.begin(first_log);
+        self.update_sleep_inhibitor();
+        self.update_background_priority();
+        cancel
+    }
+
+    /// Creates the per-task channel; `update` polls its receiver through the job model.
+    fn open_task_channel(&mut self) -> mpsc::Sender<DownloadMessage> {
+        self.job.open_channel()
+    }
+
+    /// Spawns a task on the runtime and reports its result as `DownloadMessage::Finished`.
+    fn spawn_task(
+        &self,
+        ctx: egui::Context,
+        sender: mpsc::Sender<DownloadMessage>,
+        task: impl std::future::Future<Output = Result<()>> + Send + 'static,
+    ) {
+        self.runtime.handle().spawn(async move {
+            let result = task.await;
 
             // Send the final finished message regardless of success or failure
             let final_message = match result {
                 Ok(_) => DownloadMessage::Finished(Ok(())),
                 Err(e) => DownloadMessage::Finished(Err(e.to_string())),
             };
-            // 使用下載專用的 Sender
-            download_sender.send(final_message).await.ok();
+            sender.send(final_message).await.ok();
             ctx.request_repaint();
         });
     }
@@ -341,6 +1174,12 @@ impl HlsDownloaderApp {
 const CJK_FONT_DATA: &[u8] = include_bytes!("./assets/fonts/NotoSansCJKtc-Regular.otf");
 
 fn main() -> Result<(), eframe::Error> {
+    // Any argument selects headless mode; without arguments the GUI starts
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(headless::run(args));
+    }
+
     let options = NativeOptions {
         viewport: egui::ViewportBuilder::default().with_inner_size([500.0, 650.0]),
         ..Default::default()
@@ -0,0 +1,266 @@
+// Platform sleep inhibitor, held while download jobs are running.
+// - Linux: `systemd-inhibit` (logind) child process holding a sleep/idle lock
+// - macOS: `caffeinate -i` child process (IOKit power assertion)
+// - Windows: SetThreadExecutionState(ES_CONTINUOUS | ES_SYSTEM_REQUIRED)
+// The lock is released when the guard is dropped.
+// `BackgroundPriority` lowers CPU and disk priority while downloading:
+// - Linux: nice 10 and I/O class best-effort/7 for every thread (`ionice -c2 -n7`)
+// - macOS: PRIO_DARWIN_BG (background QoS: throttled CPU, disk and network)
+// - Windows: PROCESS_MODE_BACKGROUND_BEGIN (low CPU, I/O and memory priority)
+// `suspend_system` puts the machine to sleep (used after an unattended queue has finished).
+
+use anyhow::Result;
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use anyhow::anyhow;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::process::Child;
+#[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
+use std::process::{Command, Stdio};
+
+#[cfg(target_os = "windows")]
+mod win {
+    pub const ES_CONTINUOUS: u32 = 0x8000_0000;
+    pub const ES_SYSTEM_REQUIRED: u32 = 0x0000_0001;
+
+    pub const PROCESS_MODE_BACKGROUND_BEGIN: u32 = 0x0010_0000;
+    pub const PROCESS_MODE_BACKGROUND_END: u32 = 0x0020_0000;
+
+    #[link(name = "kernel32")]
+    unsafe extern "system" {
+        pub fn SetThreadExecutionState(es_flags: u32) -> u32;
+        pub fn GetCurrentProcess() -> isize;
+        pub fn SetPriorityClass(process: isize, priority_class: u32) -> i32;
+    }
+}
+
+/// RAII guard keeping the system from going to sleep.
+pub struct SleepInhibitor {
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    child: Child,
+}
+
+impl SleepInhibitor {
+    /// Acquires a platform sleep lock.
+    #[cfg(target_os = "linux")]
+    pub fn acquire() -> Result<Self> {
+        let child = Command::new("systemd-inhibit")
+            .args([
+                "--what=sleep:idle",
+                "--who=hls-downloader",
+                "--why=HLS download in progress",
+                "--mode=block",
+                "sleep",
+                "infinity",
+            ])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| anyhow!("Failed to start systemd-inhibit: {}", e))?;
+        Ok(Self { child })
+    }
+
+    /// Acquires a platform sleep lock.
+    #[cfg(target_os = "macos")]
+    pub fn acquire() -> Result<Self> {
+        let child = Command::new("caffeinate")
+            .arg("-i")
+            .arg("-w")
+            .arg(std::process::id().to_string())
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| anyhow!("Failed to start caffeinate: {}", e))?;
+        Ok(Self { child })
+    }
+
+    /// Acquires a platform sleep lock.
+    #[cfg(target_os = "windows")]
+    pub fn acquire() -> Result<Self> {
+        // SAFETY: plain Win32 call without pointers; the state is reset on drop.
+        let previous =
+            unsafe { win::SetThreadExecutionState(win::ES_CONTINUOUS | win::ES_SYSTEM_REQUIRED) };
+        if previous == 0 {
+            return Err(anyhow::anyhow!("SetThreadExecutionState failed"));
+        }
+        Ok(Self {})
+    }
+
+    /// Acquires a platform sleep lock.
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+    pub fn acquire() -> Result<Self> {
+        Err(anyhow::anyhow!(
+            "Sleep inhibition is not supported on this platform"
+        ))
+    }
+}
+
+impl Drop for SleepInhibitor {
+    fn drop(&mut self) {
+        #[cfg(any(target_os = "linux", target_os = "macos"))]
+        {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+
+        #[cfg(target_os = "windows")]
+        unsafe {
+            win::SetThreadExecutionState(win::ES_CONTINUOUS);
+        }
+    }
+}
+
+/// Nice value used in background mode (Linux)
+#[cfg(target_os = "linux")]
+const BACKGROUND_NICE: libc::c_int = 10;
+
+/// RAII guard running the process at background CPU/I/O priority.
+///
+/// On Linux an unprivileged process cannot raise its priority again, so dropping the guard
+/// only restores it where the system allows (otherwise it lasts until the process exits).
+pub struct BackgroundPriority {
+    #[cfg(target_os = "linux")]
+    previous_nice: libc::c_int,
+}
+
+impl BackgroundPriority {
+    /// Lowers the priority of the whole process (threads started later inherit it).
+    #[cfg(target_os = "linux")]
+    pub fn enter() -> Result<Self> {
+        // SAFETY: getpriority has no pointer arguments; -1 is also a valid nice value, which
+        // only means the previous value may be restored as -1.
+        let previous_nice = unsafe { libc::getpriority(libc::PRIO_PROCESS, 0) };
+        // Linux applies both settings per thread, so every existing thread is visited
+        let threads = std::fs::read_dir("/proc/self/task")
+            .map_err(|e| anyhow!("Failed to list threads: {}", e))?;
+        let mut lowered = 0;
+        for tid in threads
+            .flatten()
+            .filter_map(|entry| entry.file_name().to_str()?.parse::<libc::id_t>().ok())
+        {
+            // SAFETY: plain syscalls on thread ids of this process, without pointers
+            unsafe {
+                if libc::setpriority(libc::PRIO_PROCESS, tid, BACKGROUND_NICE) == 0 {
+                    lowered += 1;
+                }
+                set_io_priority(tid, IOPRIO_LOWEST_BEST_EFFORT);
+            }
+        }
+        if lowered == 0 {
+            return Err(anyhow!("setpriority failed"));
+        }
+        Ok(Self { previous_nice })
+    }
+
+    /// Lowers the priority of the whole process (threads started later inherit it).
+    #[cfg(target_os = "macos")]
+    pub fn enter() -> Result<Self> {
+        // SAFETY: plain call without pointers; undone on drop.
+        if unsafe { libc::setpriority(libc::PRIO_DARWIN_PROCESS, 0, libc::PRIO_DARWIN_BG) } != 0 {
+            return Err(anyhow!("setpriority(PRIO_DARWIN_BG) failed"));
+        }
+        Ok(Self {})
+    }
+
+    /// Lowers the priority of the whole process (threads started later inherit it).
+    #[cfg(target_os = "windows")]
+    pub fn enter() -> Result<Self> {
+        // SAFETY: GetCurrentProcess returns a pseudo handle that needs no closing.
+        let ok = unsafe {
+            win::SetPriorityClass(win::GetCurrentProcess(), win::PROCESS_MODE_BACKGROUND_BEGIN)
+        };
+        if ok == 0 {
+            return Err(anyhow::anyhow!("SetPriorityClass failed"));
+        }
+        Ok(Self {})
+    }
+
+    /// Lowers the priority of the whole process (threads started later inherit it).
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+    pub fn enter() -> Result<Self> {
+        Err(anyhow::anyhow!(
+            "Background priority is not supported on this platform"
+        ))
+    }
+}
+
+impl Drop for BackgroundPriority {
+    fn drop(&mut self) {
+        #[cfg(target_os = "linux")]
+        if let Ok(threads) = std::fs::read_dir("/proc/self/task") {
+            for tid in threads
+                .flatten()
+                .filter_map(|entry| entry.file_name().to_str()?.parse::<libc::id_t>().ok())
+            {
+                // SAFETY: see `enter`; failures (missing CAP_SYS_NICE) are expected and ignored
+                unsafe {
+                    libc::setpriority(libc::PRIO_PROCESS, tid, self.previous_nice);
+                    set_io_priority(tid, IOPRIO_DEFAULT);
+                }
+            }
+        }
+
+        #[cfg(target_os = "macos")]
+        unsafe {
+            libc::setpriority(libc::PRIO_DARWIN_PROCESS, 0, 0);
+        }
+
+        #[cfg(target_os = "windows")]
+        unsafe {
+            win::SetPriorityClass(win::GetCurrentProcess(), win::PROCESS_MODE_BACKGROUND_END);
+        }
+    }
+}
+
+/// `ioprio_set` values: class in the top bits, level below (see ioprio_set(2))
+#[cfg(target_os = "linux")]
+const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+/// Best-effort class, lowest level; the idle class could starve writes on a busy disk
+#[cfg(target_os = "linux")]
+const IOPRIO_LOWEST_BEST_EFFORT: libc::c_int = (2 << IOPRIO_CLASS_SHIFT) | 7;
+/// Class "none": I/O priority follows the nice value again
+#[cfg(target_os = "linux")]
+const IOPRIO_DEFAULT: libc::c_int = 0;
+
+/// Sets the I/O priority of thread `tid` (best effort; errors are ignored).
+#[cfg(target_os = "linux")]
+unsafe fn set_io_priority(tid: libc::id_t, priority: libc::c_int) {
+    const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+    // SAFETY: integer-only syscall
+    unsafe {
+        libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, tid, priority);
+    }
+}
+
+/// Suspends the system (sleep to RAM) via the platform's standard tool.
+pub fn suspend_system() -> Result<()> {
+    #[cfg(target_os = "linux")]
+    let (program, args) = ("systemctl", ["suspend"].as_slice());
+    #[cfg(target_os = "macos")]
+    let (program, args) = ("pmset", ["sleepnow"].as_slice());
+    #[cfg(target_os = "windows")]
+    let (program, args) = (
+        "rundll32.exe",
+        ["powrprof.dll,SetSuspendState", "0,1,0"].as_slice(),
+    );
+
+    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
+    {
+        let status = Command::new(program)
+            .args(args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map_err(|e| anyhow::anyhow!("Failed to run {}: {}", program, e))?;
+        if !status.success() {
+            return Err(anyhow::anyhow!("{} exited with {}", program, status));
+        }
+        Ok(())
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", target_os = "windows")))]
+    Err(anyhow::anyhow!("Suspend is not supported on this platform"))
+}
@@ -0,0 +1,156 @@
+use anyhow::{Result, anyhow};
+use dirs::config_dir;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::downloader::DownloadConfig;
+use crate::history::JobNotes;
+
+/// What to do once the last queued job has finished (for unattended runs)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FinishAction {
+    #[default]
+    Nothing,
+    /// Request the user's attention (flashes the taskbar entry / bounces the dock icon)
+    Notify,
+    Quit,
+    Suspend,
+}
+
+impl FinishAction {
+    pub const ALL: [FinishAction; 4] = [
+        FinishAction::Nothing,
+        FinishAction::Notify,
+        FinishAction::Quit,
+        FinishAction::Suspend,
+    ];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            FinishAction::Nothing => "Do nothing",
+            FinishAction::Notify => "Notify",
+            FinishAction::Quit => "Quit the app",
+            FinishAction::Suspend => "Suspend the system",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum QueueState {
+    Pending,
+    /// Started by the queue; still listed so an interrupted job is resumed after a restart
+    Running,
+    /// Skipped until resumed by the user (also set when a job fails)
+    Paused,
+}
+
+/// One download of the queue: its parameters, state and notes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadTask {
+    /// Tells running jobs apart and routes their progress to the row; assigned anew each
+    /// session
+    #[serde(skip)]
+    pub id: u64,
+    /// Playlist URL, output and download settings
+    pub config: DownloadConfig,
+    pub state: QueueState,
+    #[serde(default)]
+    pub notes: JobNotes,
+}
+
+/// Downloads waiting to run in order, persisted between runs
+/// (`<config_dir>/hls-downloader/queue.json`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct JobQueue {
+    pub jobs: Vec<DownloadTask>,
+    #[serde(skip)]
+    next_id: u64,
+}
+
+fn queue_path() -> Result<PathBuf> {
+    let base = config_dir().ok_or_else(|| anyhow!("Could not determine config directory"))?;
+    Ok(base.join("hls-downloader").join("queue.json"))
+}
+
+impl JobQueue {
+    /// Loads the queue saved by the last session; jobs that were running are pending again.
+    pub fn load() -> Self {
+        let mut queue: Self = queue_path()
+            .ok()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default();
+        for (id, job) in queue.jobs.iter_mut().enumerate() {
+            job.id = id as u64;
+            if job.state == QueueState::Running {
+                job.state = QueueState::Pending;
+            }
+        }
+        queue.next_id = queue.jobs.len() as u64;
+        queue
+    }
+
+    /// Writes the queue to disk, creating the config directory if needed.
+    pub fn save(&self) -> Result<()> {
+        let path = queue_path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn push(&mut self, config: DownloadConfig, notes: JobNotes) {
+        self.next_id += 1;
+        self.jobs.push(DownloadTask {
+            id: self.next_id,
+            config,
+            state: QueueState::Pending,
+            notes,
+        });
+    }
+
+    /// Marks the first pending job as running and returns its id, parameters and notes.
+    pub fn start_next(&mut self) -> Option<(u64, DownloadConfig, JobNotes)> {
+        let job = self
+            .jobs
+            .iter_mut()
+            .find(|job| job.state == QueueState::Pending)?;
+        job.state = QueueState::Running;
+        Some((job.id, job.config.clone(), job.notes.clone()))
+    }
+
+    /// Removes a running job after success, or pauses it after a failure.
+    pub fn finish(&mut self, id: u64, success: bool) {
+        let Some(position) = self
+            .jobs
+            .iter()
+            .position(|job| job.id == id && job.state == QueueState::Running)
+        else {
+            return;
+        };
+        if success {
+            self.jobs.remove(position);
+        } else {
+            self.jobs[position].state = QueueState::Paused;
+        }
+    }
+
+    /// Moves a job one place up (`-1`) or down (`1`) in the run order.
+    pub fn move_job(&mut self, index: usize, offset: isize) {
+        if let Some(target) = index.checked_add_signed(offset)
+            && index < self.jobs.len()
+            && target < self.jobs.len()
+        {
+            self.jobs.swap(index, target);
+        }
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.jobs
+            .iter()
+            .filter(|job| job.state == QueueState::Pending)
+            .count()
+    }
+}
@@ -0,0 +1,258 @@
+// Retention: removes data the app leaves behind once it is older than the configured age, and
+// backs the "Clear caches" button.
+// - History entries (by the date the download finished)
+// - Watch-later entries (the saved job list; captured session URLs expire anyway)
+// - Segment directories of jobs that never finished (`hlsdl-*` in the system temp dir)
+// - Kept workspaces (`*.hlsdl/`) below the output location
+// - Embedded-FFmpeg extractions of other builds
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use crate::downloader::ffmpeg_embed;
+use crate::downloader::util::date_utc;
+use crate::downloader::workspace::{MANIFEST_FILENAME, TEMP_DIR_PREFIX, WORKSPACE_EXTENSION};
+use crate::history::History;
+use crate::watch_later::WatchLater;
+
+/// Temp dirs changed more recently than this may belong to a running job (even of another
+/// instance) and are never removed
+const IN_USE_GRACE: Duration = Duration::from_secs(60 * 60);
+/// Sub-folder levels searched for workspaces (sub-folder templates like `{host}/{yyyy-mm}`)
+const WORKSPACE_SEARCH_DEPTH: usize = 3;
+
+/// What is deleted automatically, and after how many days
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetentionPolicy {
+    /// Age in days after which data is removed; 0 = keep everything
+    pub max_age_days: u32,
+    pub history: bool,
+    pub watch_later: bool,
+    pub temp_dirs: bool,
+    pub workspaces: bool,
+    pub ffmpeg_builds: bool,
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        Self {
+            max_age_days: 0,
+            history: false,
+            watch_later: false,
+            temp_dirs: true,
+            workspaces: false,
+            ffmpeg_builds: true,
+        }
+    }
+}
+
+/// What a cleanup removed
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CleanupReport {
+    /// Directories deleted
+    pub removed: usize,
+    pub bytes: u64,
+    pub history_removed: usize,
+    pub watch_later_removed: usize,
+}
+
+impl CleanupReport {
+    pub fn is_empty(&self) -> bool {
+        self.removed == 0 && self.history_removed == 0 && self.watch_later_removed == 0
+    }
+}
+
+/// Applies `policy` (at startup and after each task); does nothing while `max_age_days` is 0.
+pub fn apply_policy(
+    policy: &RetentionPolicy,
+    output_location: &Path,
+    history: &mut History,
+    watch_later: &mut WatchLater,
+) -> CleanupReport {
+    let mut report = CleanupReport::default();
+    if policy.max_age_days == 0 {
+        return report;
+    }
+    let max_age = Duration::from_secs(u64::from(policy.max_age_days) * 86_400);
+    let cutoff = date_utc(SystemTime::now() - max_age);
+    if policy.history {
+        let before = history.entries.len();
+        // Dates are YYYY-MM-DD, so they compare as text; entries without one are kept
+        history
+            .entries
+            .retain(|entry| entry.finished_on.is_empty() || entry.finished_on >= cutoff);
+        report.history_removed = before - history.entries.len();
+    }
+    if policy.watch_later {
+        let before = watch_later.jobs.len();
+        // Entries without a capture date predate it and are kept
+        watch_later
+            .jobs
+            .retain(|job| job.captured_on.is_empty() || job.captured_on >= cutoff);
+        report.watch_later_removed = before - watch_later.jobs.len();
+    }
+    if policy.temp_dirs {
+        remove_all(&mut report, orphaned_temp_dirs(max_age));
+    }
+    if policy.workspaces {
+        let mut workspaces = Vec::new();
+        find_workspaces(output_location, WORKSPACE_SEARCH_DEPTH, &mut workspaces);
+        workspaces.retain(|dir| is_older_than(&dir.join(MANIFEST_FILENAME), max_age));
+        remove_all(&mut report, workspaces);
+    }
+    if policy.ffmpeg_builds {
+        remove_all(&mut report, stale_ffmpeg_builds(max_age));
+    }
+    report
+}
+
+/// "Clear caches": every leftover temp dir not in use and every other FFmpeg build.
+pub fn clear_caches() -> CleanupReport {
+    let mut report = CleanupReport::default();
+    remove_all(&mut report, orphaned_temp_dirs(IN_USE_GRACE));
+    remove_all(&mut report, stale_ffmpeg_builds(Duration::ZERO));
+    report
+}
+
+fn orphaned_temp_dirs(min_age: Duration) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(std::env::temp_dir()) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .filter(|entry| {
+            entry
+                .file_name()
+                .to_string_lossy()
+                .starts_with(TEMP_DIR_PREFIX)
+        })
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir() && is_older_than(path, min_age.max(IN_USE_GRACE)))
+        .collect()
+}
+
+fn stale_ffmpeg_builds(min_age: Duration) -> Vec<PathBuf> {
+    ffmpeg_embed::stale_extractions()
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|path| is_older_than(path, min_age))
+        .collect()
+}
+
+/// Collects `*.hlsdl` directories with a manifest below `dir`.
+fn find_workspaces(dir: &Path, depth: usize, found: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        if !path.is_dir() {
+            continue;
+        }
+        if path
+            .extension()
+            .is_some_and(|ext| ext == WORKSPACE_EXTENSION)
+            && path.join(MANIFEST_FILENAME).is_file()
+        {
+            found.push(path);
+        } else if depth > 0 {
+            find_workspaces(&path, depth - 1, found);
+        }
+    }
+}
+
+/// Last modification at least `age` ago (false if unknown).
+fn is_older_than(path: &Path, age: Duration) -> bool {
+    fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .is_some_and(|elapsed| elapsed >= age)
+}
+
+fn remove_all(report: &mut CleanupReport, dirs: Vec<PathBuf>) {
+    for dir in dirs {
+        let bytes = dir_size(&dir);
+        if fs::remove_dir_all(&dir).is_ok() {
+            report.removed += 1;
+            report.bytes += bytes;
+        }
+    }
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(kind) if kind.is_dir() => dir_size(&entry.path()),
+            _ => entry.metadata().map_or(0, |meta| meta.len()),
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::history::HistoryEntry;
+
+    fn entry(finished_on: &str) -> HistoryEntry {
+        serde_json::from_value(serde_json::json!({
+            "config": {
+                "playlist_url": "https://example.com/index.m3u8",
+                "output_location": ".",
+                "output_filename": "video",
+                "concurrency": 4,
+                "output_format": "mp4",
+            },
+            "finished_on": finished_on,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn history_is_pruned_by_age() {
+        let today = date_utc(SystemTime::now());
+        let mut history = History {
+            entries: vec![entry("2001-01-01"), entry(&today), entry("")],
+        };
+        let policy = RetentionPolicy {
+            max_age_days: 30,
+            history: true,
+            temp_dirs: false,
+            ffmpeg_builds: false,
+            ..Default::default()
+        };
+        let nowhere = Path::new("/nonexistent/hls-downloader-retention-test");
+        let report = apply_policy(&policy, nowhere, &mut history, &mut WatchLater::default());
+        assert_eq!(report.history_removed, 1);
+        let kept: Vec<_> = history
+            .entries
+            .iter()
+            .map(|e| e.finished_on.as_str())
+            .collect();
+        assert_eq!(kept, [today.as_str(), ""]);
+
+        // Without the history option (or at age 0) nothing is touched
+        let mut history = History {
+            entries: vec![entry("2001-01-01")],
+        };
+        for policy in [
+            RetentionPolicy {
+                history: false,
+                ..policy.clone()
+            },
+            RetentionPolicy {
+                max_age_days: 0,
+                ..policy
+            },
+        ] {
+            apply_policy(&policy, nowhere, &mut history, &mut WatchLater::default());
+            assert_eq!(history.entries.len(), 1);
+        }
+    }
+}
@@ -0,0 +1,53 @@
+// Construction of the tokio runtime from the user's tuning settings (instead of
+// `Runtime::new()` defaults), for machines where decryption and disk IO compete for few cores.
+
+use serde::{Deserialize, Serialize};
+use std::io;
+use tokio::runtime::{Builder, Runtime};
+
+/// Upper limits offered in the settings
+pub const MAX_WORKER_THREADS: usize = 64;
+pub const MAX_BLOCKING_THREADS: usize = 1024;
+const DEFAULT_THREAD_NAME: &str = "hlsdl-worker";
+
+/// Sizing of the async runtime; takes effect at the next start
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RuntimeTuning {
+    /// Async worker threads (segment requests, playlist reloads); 0 = one per CPU core
+    pub worker_threads: usize,
+    /// Upper bound of the blocking pool (decryption, file IO, FFmpeg); 0 = tokio's default (512)
+    pub max_blocking_threads: usize,
+    /// Name of the runtime's threads, as shown by debuggers and `top -H`
+    pub thread_name: String,
+}
+
+impl Default for RuntimeTuning {
+    fn default() -> Self {
+        Self {
+            worker_threads: 0,
+            max_blocking_threads: 0,
+            thread_name: DEFAULT_THREAD_NAME.to_string(),
+        }
+    }
+}
+
+impl RuntimeTuning {
+    pub fn build(&self) -> io::Result<Runtime> {
+        let mut builder = Builder::new_multi_thread();
+        builder.enable_all();
+        let name = self.thread_name.trim();
+        builder.thread_name(if name.is_empty() {
+            DEFAULT_THREAD_NAME
+        } else {
+            name
+        });
+        if self.worker_threads > 0 {
+            builder.worker_threads(self.worker_threads.min(MAX_WORKER_THREADS));
+        }
+        if self.max_blocking_threads > 0 {
+            builder.max_blocking_threads(self.max_blocking_threads.min(MAX_BLOCKING_THREADS));
+        }
+        builder.build()
+    }
+}
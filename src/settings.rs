@@ -0,0 +1,313 @@
+use anyhow::{Result, anyhow};
+use dirs::config_dir;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::api::{ApiToken, DEFAULT_API_BIND_ADDRESSES, DEFAULT_API_PORT};
+use crate::downloader::remux_pool::DEFAULT_REMUX_WORKERS;
+use crate::queue::FinishAction;
+use crate::retention::RetentionPolicy;
+use crate::runtime::RuntimeTuning;
+
+/// Redraw interval of the low-power mode while a task is running
+pub const DEFAULT_LOW_POWER_REFRESH_MS: u64 = 500;
+
+/// Marks a settings export, so other JSON files are not mistaken for one
+const EXPORT_FORMAT: &str = "hls-downloader-settings";
+
+/// Request headers that carry credentials; left out of exports without secrets
+const SECRET_HEADERS: [&str; 5] = [
+    "authorization",
+    "cookie",
+    "proxy-authorization",
+    "x-api-key",
+    "x-auth-token",
+];
+
+/// File written by "Export settings": the settings plus whether secrets are in it.
+#[derive(Serialize, Deserialize)]
+struct SettingsExport {
+    format: String,
+    secrets_included: bool,
+    settings: AppSettings,
+}
+
+/// User preferences persisted between runs (`<config_dir>/hls-downloader/settings.json`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AppSettings {
+    /// Keep the system awake while a download job is running
+    pub prevent_sleep: bool,
+    /// Lower the process CPU/disk priority while a job is running
+    pub background_priority: bool,
+    /// Request headers (cookies, user agent, referer, ...) remembered per hostname
+    pub host_profiles: BTreeMap<String, Vec<(String, String)>>,
+    /// Serve the local HTTP API (metrics, status, job submission) on `api_bind_addresses`
+    pub api_enabled: bool,
+    pub api_port: u16,
+    /// Comma-separated local addresses the API listens on (e.g. `127.0.0.1, 192.168.1.20`)
+    pub api_bind_addresses: String,
+    /// Access tokens with their scopes; without any, only loopback clients are served
+    pub api_tokens: Vec<ApiToken>,
+    /// Action taken when the queue runs out of pending jobs
+    pub queue_finish_action: FinishAction,
+    /// FFmpeg remuxes of queued jobs that run at once, alongside the next download
+    pub remux_workers: usize,
+    /// Queued downloads that run at once
+    pub queue_parallel_jobs: usize,
+    /// Redraw on a fixed interval while a task runs (instead of on every update) and not at
+    /// all when idle
+    pub low_power_mode: bool,
+    pub low_power_refresh_ms: u64,
+    /// Named quality ladders for batch imports: rungs (`1080p`, `720p`, `best`, ...) tried in order
+    pub quality_profiles: BTreeMap<String, Vec<String>>,
+    /// Profile applied to the rows of a batch import (None = best variant)
+    pub batch_quality_profile: Option<String>,
+    /// Automatic deletion of old history, caches and workspaces
+    pub retention: RetentionPolicy,
+    /// Sub-folder template for every job, e.g. `{host}/{yyyy-mm}` (empty = output location itself)
+    pub output_subfolder: String,
+    /// Worker/blocking thread counts of the async runtime (applied at startup)
+    pub runtime: RuntimeTuning,
+}
+
+impl Default for AppSettings {
+    fn default() -> Self {
+        Self {
+            prevent_sleep: true,
+            background_priority: false,
+            host_profiles: BTreeMap::new(),
+            api_enabled: false,
+            api_port: DEFAULT_API_PORT,
+            api_bind_addresses: DEFAULT_API_BIND_ADDRESSES.to_string(),
+            api_tokens: Vec::new(),
+            queue_finish_action: FinishAction::default(),
+            remux_workers: DEFAULT_REMUX_WORKERS,
+            queue_parallel_jobs: 1,
+            low_power_mode: false,
+            low_power_refresh_ms: DEFAULT_LOW_POWER_REFRESH_MS,
+            quality_profiles: BTreeMap::new(),
+            batch_quality_profile: None,
+            output_subfolder: String::new(),
+            retention: RetentionPolicy::default(),
+            runtime: RuntimeTuning::default(),
+        }
+    }
+}
+
+fn settings_path() -> Result<PathBuf> {
+    let base = config_dir().ok_or_else(|| anyhow!("Could not determine config directory"))?;
+    Ok(base.join("hls-downloader").join("settings.json"))
+}
+
+impl AppSettings {
+    /// Loads settings from disk, falling back to defaults if missing or unreadable.
+    pub fn load() -> Self {
+        settings_path()
+            .ok()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    /// Finds the header profile for the URL's host, falling back to parent domains
+    /// (a profile for `example.com` also applies to `cdn.example.com`).
+    pub fn host_profile_for(&self, url: &str) -> Option<(&str, &[(String, String)])> {
+        let host = host_of(url)?;
+        let mut candidate = host.as_str();
+        loop {
+            if let Some((key, headers)) = self.host_profiles.get_key_value(candidate) {
+                return Some((key.as_str(), headers.as_slice()));
+            }
+            candidate = candidate.split_once('.')?.1;
+            if !candidate.contains('.') {
+                return None;
+            }
+        }
+    }
+
+    /// Ladder of the quality profile selected for batch imports, if it still exists.
+    pub fn batch_quality_ladder(&self) -> Option<&[String]> {
+        let name = self.batch_quality_profile.as_ref()?;
+        self.quality_profiles.get(name).map(Vec::as_slice)
+    }
+
+    /// Writes the settings (quality profiles, host profiles, templates, ...) to a JSON file for
+    /// another machine or a backup. Without `include_secrets`, API tokens and credential
+    /// headers of host profiles are left out.
+    pub fn export_to(&self, path: &Path, include_secrets: bool) -> Result<()> {
+        let mut settings = self.clone();
+        if !include_secrets {
+            settings.api_tokens.clear();
+            for headers in settings.host_profiles.values_mut() {
+                headers.retain(|(name, _)| !is_secret_header(name));
+            }
+            settings
+                .host_profiles
+                .retain(|_, headers| !headers.is_empty());
+        }
+        let export = SettingsExport {
+            format: EXPORT_FORMAT.to_string(),
+            secrets_included: include_secrets,
+            settings,
+        };
+        fs::write(path, serde_json::to_string_pretty(&export)?)?;
+        Ok(())
+    }
+
+    /// Settings from an exported file. If the export left secrets out, the API tokens and
+    /// credential headers of `self` are kept instead of being wiped. Whether and where the
+    /// API listens is never imported, so a file from another machine cannot expose it on an
+    /// unexpected interface.
+    pub fn import_from(&self, path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)?;
+        let export: SettingsExport =
+            serde_json::from_str(&text).map_err(|e| anyhow!("Not a settings export: {}", e))?;
+        if export.format != EXPORT_FORMAT {
+            return Err(anyhow!("Not a settings export ({})", export.format));
+        }
+        let mut settings = export.settings;
+        settings.api_enabled = self.api_enabled;
+        settings.api_bind_addresses = self.api_bind_addresses.clone();
+        if !export.secrets_included {
+            settings.api_tokens = self.api_tokens.clone();
+            for (host, headers) in &self.host_profiles {
+                let secrets = headers.iter().filter(|(name, _)| is_secret_header(name));
+                let profile = settings.host_profiles.entry(host.clone()).or_default();
+                for (name, value) in secrets {
+                    if !profile
+                        .iter()
+                        .any(|(other, _)| other.eq_ignore_ascii_case(name))
+                    {
+                        profile.push((name.clone(), value.clone()));
+                    }
+                }
+            }
+            settings
+                .host_profiles
+                .retain(|_, headers| !headers.is_empty());
+        }
+        Ok(settings)
+    }
+
+    /// Writes settings to disk, creating the config directory if needed.
+    pub fn save(&self) -> Result<()> {
+        let path = settings_path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Whether a request header carries credentials (cookies, authorization, API keys).
+pub fn is_secret_header(name: &str) -> bool {
+    SECRET_HEADERS
+        .iter()
+        .any(|secret| name.trim().eq_ignore_ascii_case(secret))
+}
+
+/// Lowercase hostname of a URL, used as the key of header profiles.
+pub fn host_of(url: &str) -> Option<String> {
+    Url::parse(url.trim())
+        .ok()?
+        .host_str()
+        .map(|host| host.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::ApiScope;
+
+    fn with_secrets() -> AppSettings {
+        let mut settings = AppSettings::default();
+        settings.api_tokens.push(ApiToken {
+            name: "phone".to_string(),
+            token: "t0ken".to_string(),
+            scope: ApiScope::Submit,
+        });
+        settings.host_profiles.insert(
+            "example.com".to_string(),
+            vec![
+                ("Referer".to_string(), "https://example.com/".to_string()),
+                ("Cookie".to_string(), "session=s3cret".to_string()),
+            ],
+        );
+        settings.host_profiles.insert(
+            "private.example.com".to_string(),
+            vec![("Authorization".to_string(), "Bearer s3cret".to_string())],
+        );
+        settings.output_subfolder = "{host}".to_string();
+        settings
+    }
+
+    #[test]
+    fn export_without_secrets_keeps_the_local_ones() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        with_secrets().export_to(&path, false).unwrap();
+        assert!(!fs::read_to_string(&path).unwrap().contains("s3cret"));
+
+        // A machine without secrets gets none
+        let imported = AppSettings::default().import_from(&path).unwrap();
+        assert!(imported.api_tokens.is_empty());
+        assert_eq!(
+            imported.host_profiles["example.com"],
+            [("Referer".to_string(), "https://example.com/".to_string())]
+        );
+        assert!(!imported.host_profiles.contains_key("private.example.com"));
+        assert_eq!(imported.output_subfolder, "{host}");
+
+        // One with secrets keeps them
+        let local = with_secrets();
+        let imported = local.import_from(&path).unwrap();
+        assert_eq!(imported.api_tokens.len(), 1);
+        assert_eq!(imported.host_profiles, local.host_profiles);
+    }
+
+    #[test]
+    fn export_with_secrets_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        let original = with_secrets();
+        original.export_to(&path, true).unwrap();
+
+        let imported = AppSettings::default().import_from(&path).unwrap();
+        assert_eq!(imported.api_tokens[0].token, "t0ken");
+        assert_eq!(imported.host_profiles, original.host_profiles);
+    }
+
+    #[test]
+    fn import_keeps_the_local_api_binding() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        let remote = AppSettings {
+            api_enabled: true,
+            api_bind_addresses: "0.0.0.0".to_string(),
+            ..AppSettings::default()
+        };
+        remote.export_to(&path, true).unwrap();
+
+        let local = AppSettings::default();
+        let imported = local.import_from(&path).unwrap();
+        assert!(!imported.api_enabled);
+        assert_eq!(imported.api_bind_addresses, local.api_bind_addresses);
+    }
+
+    #[test]
+    fn other_json_is_not_imported() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        fs::write(
+            &path,
+            r#"{"format": "something-else", "secrets_included": false, "settings": {}}"#,
+        )
+        .unwrap();
+        assert!(AppSettings::default().import_from(&path).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+// Dashboard: transfer counters and runtime load across all jobs.
+
+use std::time::Duration;
+
+use crate::HlsDownloaderApp;
+use crate::downloader::stats::{self, format_bytes};
+
+impl HlsDownloaderApp {
+    /// Re-samples the global transfer counters about once per second.
+    pub(crate) fn refresh_stats(&mut self) {
+        let elapsed = self.stats_snapshot.taken_at.elapsed();
+        if elapsed >= Duration::from_secs(1) {
+            // Inside the runtime so the snapshot includes its load
+            let _runtime = self.runtime.enter();
+            let snapshot = stats::global().snapshot();
+            self.stats_rates = snapshot.rates_since(&self.stats_snapshot);
+            self.stats_snapshot = snapshot;
+        }
+    }
+
+    /// Aggregated view over all jobs: connections, throughput, disk writes and per-host usage.
+    pub(crate) fn show_dashboard(&self, ui: &mut egui::Ui) {
+        let snapshot = &self.stats_snapshot;
+        let rates = &self.stats_rates;
+        egui::Grid::new("dashboard_totals")
+            .num_columns(2)
+            .spacing([40.0, 4.0])
+            .show(ui, |ui| {
+                ui.label("Active connections:");
+                ui.label(snapshot.active_connections.to_string());
+                ui.end_row();
+
+                ui.label("Download throughput:");
+                ui.label(format!("{}/s", format_bytes(rates.download_bytes_per_sec)));
+                ui.end_row();
+
+                ui.label("Disk write rate:");
+                ui.label(format!("{}/s", format_bytes(rates.write_bytes_per_sec)));
+                ui.end_row();
+
+                ui.label("Downloaded (session):");
+                ui.label(format_bytes(snapshot.bytes_downloaded as f64));
+                ui.end_row();
+
+                if let Some(runtime) = &snapshot.runtime {
+                    ui.label("Runtime workers:");
+                    ui.label(match rates.runtime_busy {
+                        Some(busy) => format!("{} ({:.0}% busy)", runtime.workers, busy * 100.0),
+                        None => runtime.workers.to_string(),
+                    });
+                    ui.end_row();
+
+                    ui.label("Runtime tasks:");
+                    ui.label(format!(
+                        "{} alive, {} queued",
+                        runtime.alive_tasks, runtime.global_queue_depth
+                    ));
+                    ui.end_row();
+                }
+            });
+
+        if snapshot.hosts.is_empty() {
+            return;
+        }
+        ui.add_space(5.0);
+        egui::Grid::new("dashboard_hosts")
+            .num_columns(4)
+            .striped(true)
+            .show(ui, |ui| {
+                ui.strong("Host");
+                ui.strong("Active");
+                ui.strong("Rate");
+                ui.strong("Total");
+                ui.end_row();
+
+                for (host, counters) in &snapshot.hosts {
+                    let rate = rates.host_bytes_per_sec.get(host).copied().unwrap_or(0.0);
+                    ui.label(host);
+                    ui.label(counters.active_connections.to_string());
+                    ui.label(format!("{}/s", format_bytes(rate)));
+                    ui.label(format_bytes(counters.bytes_downloaded as f64));
+                    ui.end_row();
+                }
+            });
+    }
+}
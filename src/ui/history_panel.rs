@@ -0,0 +1,225 @@
+// History panel: finished downloads with their notes and tags, searchable, plus the small
+// window that edits the note and tags of a queue or history entry.
+
+use crate::HlsDownloaderApp;
+use crate::history::JobNotes;
+
+/// History rows drawn at most (newest first); the search narrows down the rest
+const MAX_HISTORY_ROWS: usize = 300;
+
+/// Entry whose notes are being edited
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum NotesTarget {
+    Queue(usize),
+    History(usize),
+}
+
+/// State of the "Note and tags" window
+pub(crate) struct NotesEdit {
+    target: NotesTarget,
+    /// Playlist URL of the entry, to notice when the list has changed meanwhile
+    playlist_url: String,
+    note: String,
+    tags: String,
+}
+
+impl HlsDownloaderApp {
+    /// Note and tags from the input form, given to the next download or queued job.
+    pub(crate) fn current_notes(&self) -> JobNotes {
+        JobNotes::new(&self.job_note, &self.job_tags)
+    }
+
+    pub(crate) fn save_history(&mut self) {
+        if let Err(e) = self.history.save() {
+            self.job
+                .logs
+                .push(format!("⚠️ Warning: Failed to save history: {}", e));
+        }
+    }
+
+    /// Searchable list of finished downloads, newest first.
+    pub(crate) fn show_history(&mut self, ui: &mut egui::Ui) {
+        if self.history.entries.is_empty() {
+            ui.label("No finished downloads yet.");
+            return;
+        }
+        ui.horizontal(|ui| {
+            ui.label("Search:");
+            ui.add(
+                egui::TextEdit::singleline(&mut self.history_search)
+                    .hint_text("name, URL, note or #tag")
+                    .desired_width(300.0),
+            );
+            if !self.history_search.is_empty() && ui.small_button("✖").clicked() {
+                self.history_search.clear();
+            }
+        });
+
+        let matching: Vec<usize> = (0..self.history.entries.len())
+            .rev()
+            .filter(|&i| self.history.entries[i].matches(&self.history_search))
+            .collect();
+        if matching.len() > MAX_HISTORY_ROWS {
+            ui.label(
+                egui::RichText::new(format!(
+                    "Showing the newest {} of {} matches.",
+                    MAX_HISTORY_ROWS,
+                    matching.len()
+                ))
+                .weak(),
+            );
+        }
+
+        let mut edit_notes = None;
+        let mut requeue = None;
+        let mut remove = None;
+        egui::ScrollArea::vertical()
+            .id_source("history_entries")
+            .max_height(300.0)
+            .show(ui, |ui| {
+                egui::Grid::new("history_entries_grid")
+                    .num_columns(4)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for &i in matching.iter().take(MAX_HISTORY_ROWS) {
+                            let entry = &self.history.entries[i];
+                            ui.label(&entry.finished_on);
+                            match &entry.error {
+                                None => ui.label("✅"),
+                                Some(e) => ui.label("❌").on_hover_text(e),
+                            };
+                            ui.horizontal(|ui| {
+                                ui.label(entry.name())
+                                    .on_hover_text(&entry.config.playlist_url);
+                                if !entry.notes.is_empty() {
+                                    ui.label(egui::RichText::new(entry.notes.summary()).weak());
+                                }
+                            });
+                            ui.horizontal(|ui| {
+                                if ui
+                                    .small_button("📝")
+                                    .on_hover_text("Note and tags")
+                                    .clicked()
+                                {
+                                    edit_notes = Some(i);
+                                }
+                                if ui
+                                    .small_button("➕ Queue again")
+                                    .on_hover_text("Add the same job to the queue")
+                                    .clicked()
+                                {
+                                    requeue = Some(i);
+                                }
+                                if ui.small_button("🗑").on_hover_text("Remove").clicked() {
+                                    remove = Some(i);
+                                }
+                            });
+                            ui.end_row();
+                        }
+                    });
+            });
+
+        if let Some(i) = edit_notes {
+            let notes = self.history.entries[i].notes.clone();
+            self.open_notes_editor(NotesTarget::History(i), &notes);
+        } else if let Some(i) = requeue {
+            let entry = self.history.entries[i].clone();
+            self.queue.push(entry.config.clone(), entry.notes.clone());
+            self.save_queue();
+            self.job.logs.push(format!(
+                "-> Queued {} again ({} pending).",
+                entry.name(),
+                self.queue.pending_count()
+            ));
+        } else if let Some(i) = remove {
+            self.history.entries.remove(i);
+            // Indices of an open editor would point at the wrong entry now
+            self.notes_editor = None;
+            self.save_history();
+        }
+    }
+
+    pub(crate) fn open_notes_editor(&mut self, target: NotesTarget, notes: &JobNotes) {
+        let playlist_url = match target {
+            NotesTarget::Queue(i) => &self.queue.jobs[i].config.playlist_url,
+            NotesTarget::History(i) => &self.history.entries[i].config.playlist_url,
+        };
+        self.notes_editor = Some(NotesEdit {
+            target,
+            playlist_url: playlist_url.clone(),
+            note: notes.note.clone(),
+            tags: notes.tags_text(),
+        });
+    }
+
+    /// "Note and tags" window of a queue or history entry.
+    pub(crate) fn show_notes_editor_window(&mut self, ctx: &egui::Context) {
+        let Some(edit) = self.notes_editor.as_mut() else {
+            return;
+        };
+        let mut open = true;
+        let mut save = false;
+        let mut cancel = false;
+        egui::Window::new("Note and tags")
+            .open(&mut open)
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(egui::RichText::new(&edit.playlist_url).weak());
+                ui.label("Note:");
+                ui.add(
+                    egui::TextEdit::multiline(&mut edit.note)
+                        .desired_rows(3)
+                        .desired_width(400.0),
+                );
+                ui.label("Tags (comma-separated):");
+                ui.add(
+                    egui::TextEdit::singleline(&mut edit.tags)
+                        .hint_text("episode 12, needs subs")
+                        .desired_width(400.0),
+                );
+                ui.horizontal(|ui| {
+                    save = ui.button("💾 Save").clicked();
+                    cancel = ui.button("Cancel").clicked();
+                });
+            });
+        if save {
+            self.apply_notes_edit();
+        } else if cancel || !open {
+            self.notes_editor = None;
+        }
+    }
+
+    fn apply_notes_edit(&mut self) {
+        let Some(edit) = self.notes_editor.take() else {
+            return;
+        };
+        let notes = JobNotes::new(&edit.note, &edit.tags);
+        let slot = match edit.target {
+            NotesTarget::Queue(i) => self
+                .queue
+                .jobs
+                .get_mut(i)
+                .filter(|job| job.config.playlist_url == edit.playlist_url)
+                .map(|job| &mut job.notes),
+            NotesTarget::History(i) => self
+                .history
+                .entries
+                .get_mut(i)
+                .filter(|entry| entry.config.playlist_url == edit.playlist_url)
+                .map(|entry| &mut entry.notes),
+        };
+        let Some(slot) = slot else {
+            self.job.logs.push(
+                "⚠️ Warning: The entry changed while its note was edited; the note was not saved."
+                    .to_string(),
+            );
+            return;
+        };
+        *slot = notes;
+        match edit.target {
+            NotesTarget::Queue(_) => self.save_queue(),
+            NotesTarget::History(_) => self.save_history(),
+        }
+    }
+}
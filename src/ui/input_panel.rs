@@ -0,0 +1,857 @@
+// Input panel: the job form (source, headers, output, options) and the action buttons below it.
+
+use crate::downloader::DownloadMessage;
+use crate::downloader::http::{format_header_lines, parse_header_lines};
+use crate::downloader::live::LiveStart;
+use crate::downloader::pause::PauseGate;
+use crate::downloader::preview::PreviewKind;
+use crate::downloader::probe::{StreamInfo, unanalyzed_format_warning};
+use crate::downloader::subtitles::SubtitleMode;
+use crate::{HlsDownloaderApp, OUTPUT_FORMATS, cli, curl_import, settings};
+
+/// Answer to the "Output format" question before a download
+enum FormatChoice {
+    Reencode,
+    Mkv,
+    Cancel,
+}
+
+impl HlsDownloaderApp {
+    /// Job form; locked while a task other than a download runs (another download can be
+    /// started next to one).
+    pub(crate) fn show_input_panel(&mut self, ui: &mut egui::Ui) {
+        ui.add_enabled_ui(!self.job.running || self.job.download.is_some(), |ui| {
+            // 使用 Grid 確保標籤和輸入框垂直對齊
+            egui::Grid::new("input_grid")
+                .num_columns(2) // 兩欄: 標籤 和 Widget
+                .spacing([20.0, 10.0]) // [水平間距, 垂直間距]
+                .striped(true) // 增加條紋背景以提高可讀性
+                .show(ui, |ui| {
+                    // --- Playlist source ---
+                    ui.label("Source:");
+                    ui.horizontal(|ui| {
+                        ui.radio_value(&mut self.use_pasted_playlist, false, "Playlist URL");
+                        ui.radio_value(&mut self.use_pasted_playlist, true, "Pasted content");
+                    });
+                    ui.end_row();
+
+                    // --- M3U8 URL ---
+                    // 第一欄: 標籤
+                    ui.label(if self.use_pasted_playlist {
+                        "Base URL:"
+                    } else {
+                        "M3U8 URL:"
+                    })
+                    .on_hover_text(if self.use_pasted_playlist {
+                        "Relative segment and key URIs in the pasted playlist resolve against this URL"
+                    } else {
+                        "Address of the .m3u8 playlist"
+                    });
+                    // 第二欄: 輸入框
+                    if ui.text_edit_singleline(&mut self.m3u8_url).changed() {
+                        self.apply_host_profile();
+                    }
+                    ui.end_row();
+
+                    if self.use_pasted_playlist {
+                        ui.label("Playlist:");
+                        egui::ScrollArea::vertical()
+                            .id_source("pasted_playlist")
+                            .max_height(120.0)
+                            .show(ui, |ui| {
+                                ui.add(
+                                    egui::TextEdit::multiline(&mut self.pasted_playlist)
+                                        .desired_rows(4)
+                                        .code_editor()
+                                        .hint_text("#EXTM3U ... (paste the response body from devtools)"),
+                                );
+                            });
+                        ui.end_row();
+                    }
+
+                    // --- Request Headers ---
+                    ui.label("Headers:");
+                    ui.vertical(|ui| {
+                        ui.add(
+                            egui::TextEdit::multiline(&mut self.request_headers)
+                                .desired_rows(2)
+                                .hint_text("Name: value (one per line)"),
+                        );
+                        ui.horizontal(|ui| {
+                            if ui.button("Paste curl command...").clicked() {
+                                self.curl_import_open = true;
+                            }
+                            self.show_host_profile_buttons(ui);
+                        });
+                        ui.checkbox(
+                            &mut self.propagate_query,
+                            "Add playlist query (?token=...) to segment and key URLs",
+                        )
+                        .on_hover_text(
+                            "For CDNs that expect the playlist's signed query string on every request",
+                        );
+                    });
+                    ui.end_row();
+
+                    ui.label("URL rewrites:");
+                    ui.add(
+                        egui::TextEdit::multiline(&mut self.url_rewrites)
+                            .desired_rows(1)
+                            .code_editor()
+                            .hint_text("regex => replacement (one per line)"),
+                    )
+                    .on_hover_text(
+                        "Applied to segment URLs before fetching, e.g. \
+                         `//edge1\\.cdn\\.com/ => //edge2.cdn.com/` swaps a host, \
+                         `[?&]bad=[^&]* =>` strips a parameter; $1 inserts a capture group",
+                    );
+                    ui.end_row();
+
+                    // --- Output Filename (標籤與輸入框平行) ---
+                    ui.label("Output Filename:"); // 第一欄: 標籤
+                    ui.text_edit_singleline(&mut self.output_filename);
+                    ui.end_row();
+
+                    ui.label("Output Location:"); // 第一欄: 標籤
+                    ui.horizontal(|ui| {
+                        // 第二欄: 輸入框 + 按鈕
+                        ui.add(egui::TextEdit::singleline(&mut self.output_location));
+
+                        // "Browse" 按鈕和 rfd 邏輯
+                        if ui.button("Browse...").clicked() {
+                            let current_location = self.output_location.clone();
+                            // 使用 self.sender (現在已在結構體中定義)
+                            let sender_clone = self.sender.clone();
+
+                            // 由於 rfd::FileDialog::save_file() 是阻塞的，必須在 blocking thread 中運行
+                            self.runtime.handle().clone().spawn_blocking(move || {
+                                if let Some(path) = rfd::FileDialog::new()
+                                    .set_directory(&current_location)
+                                    .pick_folder()
+                                {
+                                    let full_path = path.to_string_lossy().into_owned();
+                                    // 使用 blocking_send 傳回結果給 GUI
+                                    let _ = sender_clone.blocking_send(
+                                        DownloadMessage::OutputPathSelected(full_path),
+                                    );
+                                }
+                            });
+                        }
+
+                        // 只有當 output_location 非空時才啟用
+                        ui.add_enabled(
+                            !self.output_location.is_empty(),
+                            |ui: &mut egui::Ui| {
+                                // 創建一個 scope，並返回 scope 的 Response
+                                ui.scope(|ui| {
+                                    let button_response = ui.button("Open Folder");
+
+                                    if button_response.clicked() {
+                                        let path_to_open = self.output_location.clone();
+                                        // opener 函式庫用於在作業系統中開啟檔案或資料夾
+                                        if let Err(e) = opener::open(&path_to_open) {
+                                            // 錯誤處理
+                                            eprintln!(
+                                                "Failed to open directory {}: {}",
+                                                path_to_open, e
+                                            );
+                                        }
+                                    }
+
+                                    // 返回按鈕的 Response，這成為 scope 的返回值
+                                    button_response
+                                })
+                                .response
+                                // .response 是 Scope 結構體的屬性，包含其內部 Widget 的整體響應。
+                            },
+                        );
+                    });
+                    ui.end_row();
+
+                    // --- Concurrent Downloads & Output Format (放在同一行，但屬於 Grid 的單元格) ---
+                    // 這裡我們需要將兩個控制項擠入 Grid 的第二個單元格
+                    ui.label("Concurrent Downloads / Format:"); // 佔用第一欄的標籤
+
+                    ui.horizontal(|ui| {
+                        // 1. Concurrent Downloads
+                        ui.add(
+                            egui::DragValue::new(&mut self.concurrent_downloads)
+                                .speed(1.0)
+                                .clamp_range(1..=16)
+                                .prefix("x "),
+                        )
+                        .on_hover_text(
+                            "Segments downloaded at the same time by this job; parallel \
+                             downloads each use their own limit",
+                        );
+
+                        ui.separator(); // 視覺分隔符
+
+                        // Output Format (Dropdown)
+                        ui.label("Format:"); // 在水平佈局中再次加入標籤
+
+                        let stream_info = self.analyzed_stream_info().cloned();
+                        egui::ComboBox::from_label("")
+                            .selected_text(&self.output_format)
+                            .width(70.0)
+                            .show_ui(ui, |ui| {
+                                for format in OUTPUT_FORMATS {
+                                    // Formats that cannot hold the analyzed codecs are greyed out
+                                    let problem = stream_info
+                                        .as_ref()
+                                        .and_then(|info| info.incompatibility(format));
+                                    let response = ui.add_enabled(
+                                        problem.is_none(),
+                                        egui::SelectableLabel::new(
+                                            self.output_format == format,
+                                            format,
+                                        ),
+                                    );
+                                    if response.clicked() {
+                                        self.output_format = format.to_string();
+                                    }
+                                    if let Some(problem) = problem {
+                                        response.on_disabled_hover_text(problem);
+                                    }
+                                }
+                            });
+                        if let Some(info) = &stream_info {
+                            ui.label(egui::RichText::new(info.summary()).weak());
+                        }
+                    });
+                    ui.end_row();
+
+                    // --- Subtitles ---
+                    ui.label("Subtitles:");
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.subtitle_url)
+                                .hint_text("Optional .vtt/.srt URL, subtitle playlist or file")
+                                .desired_width(300.0),
+                        );
+                        egui::ComboBox::from_id_source("subtitle_mode")
+                            .selected_text(self.subtitle_mode.label())
+                            .show_ui(ui, |ui| {
+                                for mode in SubtitleMode::ALL {
+                                    ui.selectable_value(
+                                        &mut self.subtitle_mode,
+                                        mode,
+                                        mode.label(),
+                                    );
+                                }
+                            })
+                            .response
+                            .on_hover_text(
+                                "Burning in re-encodes the video so players without subtitle \
+                                 support still show them. Sidecar files need no FFmpeg; without \
+                                 a URL the master playlist's subtitle rendition is saved",
+                            );
+                    });
+                    ui.end_row();
+
+                    // --- Manual decryption key ---
+                    ui.label("Decryption key:");
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.key_hex)
+                                .hint_text("Optional key (hex)")
+                                .desired_width(280.0),
+                        )
+                        .on_hover_text(
+                            "Used instead of downloading the playlist's key file, e.g. when \
+                             the key endpoint is protected",
+                        );
+                        if ui
+                            .button("Key file...")
+                            .on_hover_text("Load a raw 16/32-byte key file")
+                            .clicked()
+                        {
+                            let sender_clone = self.sender.clone();
+                            self.runtime.handle().clone().spawn_blocking(move || {
+                                if let Some(path) = rfd::FileDialog::new().pick_file() {
+                                    let full_path = path.to_string_lossy().into_owned();
+                                    let _ = sender_clone.blocking_send(
+                                        DownloadMessage::KeyFileSelected(full_path),
+                                    );
+                                }
+                            });
+                        }
+                        ui.label("IV:");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.iv_hex)
+                                .hint_text("Optional (hex)")
+                                .desired_width(280.0),
+                        )
+                        .on_hover_text("Replaces the IV from the playlist");
+                    });
+                    ui.end_row();
+
+                    // --- Wall-clock clip (#EXT-X-PROGRAM-DATE-TIME) ---
+                    ui.label("Clip (wall clock):");
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.clip_start)
+                                .hint_text("From, e.g. 2024-05-01T20:00:00Z")
+                                .desired_width(230.0),
+                        );
+                        ui.label("to");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.clip_end)
+                                .hint_text("Until (optional)")
+                                .desired_width(230.0),
+                        );
+                    })
+                    .response
+                    .on_hover_text(
+                        "Only download the segments inside this window, by the playlist's \
+                         #EXT-X-PROGRAM-DATE-TIME. Without a zone the time is UTC; a live \
+                         recording stops at the end time",
+                    );
+                    ui.end_row();
+
+                    // --- Time range (summed #EXTINF) ---
+                    ui.label("Time range:");
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.time_start)
+                                .hint_text("Start, e.g. 1:02:30")
+                                .desired_width(140.0),
+                        );
+                        ui.label("to");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.time_end)
+                                .hint_text("End (optional)")
+                                .desired_width(140.0),
+                        );
+                        ui.checkbox(&mut self.exact_trim, "Trim exactly")
+                            .on_hover_text(
+                                "Cut the output at these times with FFmpeg instead of keeping \
+                                 whole segments; re-encodes the video and audio",
+                            );
+                    })
+                    .response
+                    .on_hover_text(
+                        "Only download the segments spanning this part of the playlist, \
+                         timed by the #EXTINF durations ([[hh:]mm:]ss)",
+                    );
+                    ui.end_row();
+
+                    // --- Post-processing options ---
+                    ui.label("Post-processing:");
+                    ui.checkbox(&mut self.fix_timestamps, "Fix timestamp discontinuities")
+                        .on_hover_text(
+                            "Repair PTS/DTS jumps and wraparounds in the merged stream \
+                             (fixes seeking and A/V drift for streams with resets)",
+                        );
+                    ui.end_row();
+
+                    ui.label("");
+                    ui.checkbox(&mut self.fill_gaps, "Fill gaps with black/silence")
+                        .on_hover_text(
+                            "Segments marked #EXT-X-GAP are always skipped; this inserts \
+                             generated H.264/AAC filler of the same length in their place",
+                        );
+                    ui.end_row();
+
+                    ui.label("");
+                    ui.checkbox(&mut self.skip_ads, "Skip ad breaks")
+                        .on_hover_text(
+                            "Leave out segments between #EXT-X-CUE-OUT and #EXT-X-CUE-IN, or in \
+                             an #EXT-X-DATERANGE with SCTE35-OUT (Analyze marks them AD)",
+                        );
+                    ui.end_row();
+
+                    ui.label("");
+                    ui.checkbox(&mut self.reencode, "Re-encode if the format can't hold the codecs")
+                        .on_hover_text(
+                            "Probe the merged stream before the remux; codecs the container \
+                             can't take (e.g. H.264/AAC in webm) are re-encoded (VP9/Opus for \
+                             webm, H.264/AAC otherwise) instead of failing the stream copy",
+                        );
+                    ui.end_row();
+
+                    ui.label("");
+                    ui.checkbox(&mut self.normalize_audio, "Normalize loudness (EBU R128)")
+                        .on_hover_text(
+                            "Two-pass FFmpeg loudnorm after the download; re-encodes the audio \
+                             only and keeps the original until normalization succeeds",
+                        );
+                    ui.end_row();
+
+                    ui.label("");
+                    ui.checkbox(&mut self.extract_captions, "Extract closed captions (.cc.srt)")
+                        .on_hover_text(
+                            "Decodes CEA-608/708 captions embedded in the video stream (US \
+                             broadcast style) into an SRT file next to the output",
+                        );
+                    ui.end_row();
+
+                    ui.label("");
+                    ui.checkbox(&mut self.stream_to_player, "Stream to player while downloading")
+                        .on_hover_text(
+                            "Serve the downloaded part as a live TS on localhost; the URL for \
+                             mpv/VLC appears in the log",
+                        );
+                    ui.end_row();
+
+                    ui.label("");
+                    ui.checkbox(&mut self.live, "Record live stream")
+                        .on_hover_text(
+                            "Keep reloading a live playlist and record new segments until the \
+                             stream ends or you press Stop recording",
+                        );
+                    ui.end_row();
+
+                    if self.live {
+                        ui.label("Live start:");
+                        egui::ComboBox::from_id_source("live_start")
+                            .selected_text(self.live_start.label())
+                            .show_ui(ui, |ui| {
+                                for start in LiveStart::ALL {
+                                    ui.selectable_value(&mut self.live_start, start, start.label());
+                                }
+                            })
+                            .response
+                            .on_hover_text(
+                                "Event playlists can list hours of history: record all of it, \
+                                 or only from the newest segment on",
+                            );
+                        ui.end_row();
+
+                        ui.label("");
+                        ui.checkbox(&mut self.append_recording, "Append to earlier recording")
+                            .on_hover_text(
+                                "Continue a stopped or crashed recording of the same output: \
+                                 segments already in its workspace are kept, overlapping ones \
+                                 are skipped, and one combined file is written",
+                            );
+                        ui.end_row();
+
+                        ui.label("");
+                        ui.checkbox(&mut self.patient_live, "Patient mode (wait out outages)")
+                            .on_hover_text(
+                                "When the origin keeps answering 5xx or timing out, pause \
+                                 polling for a few minutes and resume, instead of finishing \
+                                 the recording; failed segments get a second attempt",
+                            );
+                        ui.end_row();
+                    }
+
+                    ui.label("");
+                    ui.checkbox(&mut self.keep_workspace, "Keep workspace (allows repair)")
+                        .on_hover_text(
+                            "Keep segments and a manifest in <name>.hlsdl/ next to the output \
+                             so failed segments can be re-downloaded later",
+                        );
+                    ui.end_row();
+
+                    ui.label("");
+                    ui.checkbox(&mut self.verify_concat, "Verify concatenated chunks")
+                        .on_hover_text(
+                            "Read every segment back from the merged file and compare it \
+                             (slower, catches disk write errors)",
+                        );
+                    ui.end_row();
+
+                    ui.label("");
+                    ui.checkbox(&mut self.save_report, "Save segment report (CSV)")
+                        .on_hover_text(
+                            "Write URL, size, fetch time, retries and host of every segment \
+                             to <name>.segments.csv next to the output",
+                        );
+                    ui.end_row();
+
+                    // --- Completion hooks ---
+                    ui.label("On complete:");
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.on_complete)
+                                .hint_text("Optional command")
+                                .desired_width(280.0),
+                        )
+                        .on_hover_text(
+                            "Run through the shell after a successful download; HLSDL_OUTPUT, \
+                             HLSDL_DURATION, HLSDL_RESOLUTION, HLSDL_HOST, HLSDL_BYTES and \
+                             HLSDL_JOB_JSON describe the job",
+                        );
+                        ui.label("Webhook:");
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.webhook_url)
+                                .hint_text("Optional URL")
+                                .desired_width(280.0),
+                        )
+                        .on_hover_text("Receives the same job context as a JSON POST");
+                    });
+                    ui.end_row();
+
+                    // --- Notes kept in the history ---
+                    ui.label("Notes / tags:");
+                    ui.horizontal(|ui| {
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.job_note)
+                                .hint_text("Optional note")
+                                .desired_width(280.0),
+                        );
+                        ui.add(
+                            egui::TextEdit::singleline(&mut self.job_tags)
+                                .hint_text("Tags, comma-separated")
+                                .desired_width(280.0),
+                        );
+                    })
+                    .response
+                    .on_hover_text(
+                        "Kept with the queued job and its history entry; search the history \
+                         by words or #tag",
+                    );
+                    ui.end_row();
+                });
+        });
+    }
+
+    /// Start / queue / save / analyze buttons for the job in the form.
+    pub(crate) fn show_actions(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        ui.add_space(10.0);
+        ui.horizontal(|ui| {
+            let download_btn = if self.job.running {
+                ui.add_enabled(
+                    self.job.download.is_some(),
+                    egui::Button::new("🚀 Start another download"),
+                )
+                .on_hover_text("Runs next to the current download, with its own log tab")
+            } else {
+                ui.button("🚀 Start Download")
+            };
+
+            if download_btn.clicked() {
+                // Clear state and start the task
+                self.start_download_task(ctx.clone());
+            }
+
+            if let Some(stop) = &self.job.live_stop
+                && ui
+                    .add_enabled(!stop.is_cancelled(), egui::Button::new("⏹ Stop recording"))
+                    .on_hover_text("Stop reloading the live playlist and save what was recorded")
+                    .clicked()
+            {
+                stop.cancel();
+            }
+
+            if let Some(paused) = self.job.pause.as_ref().map(PauseGate::is_paused)
+                && ui
+                    .button(if paused { "▶ Resume" } else { "⏸ Pause" })
+                    .on_hover_text(
+                        "Hold the segment downloads; the ones in flight finish and nothing \
+                         downloaded is lost",
+                    )
+                    .clicked()
+            {
+                self.job.toggle_pause();
+            }
+
+            if ui
+                .button("➕ Add to queue")
+                .on_hover_text("Run this job after the ones already queued")
+                .clicked()
+            {
+                self.enqueue_current_job();
+            }
+
+            if ui
+                .add_enabled(
+                    !self.job.running && !self.m3u8_url.trim().is_empty(),
+                    egui::Button::new("🔖 Save for later"),
+                )
+                .on_hover_text(
+                    "Fetch the playlist and key now, while the session is valid, and \
+                     download them later from the Watch later list",
+                )
+                .clicked()
+            {
+                self.start_capture_task(ctx.clone());
+            }
+
+            if ui
+                .add_enabled(
+                    !self.job.running && !self.m3u8_url.trim().is_empty(),
+                    egui::Button::new("🔍 Analyze"),
+                )
+                .on_hover_text("Probe the first segment to find which formats can hold it")
+                .clicked()
+            {
+                self.start_analyze_task(ctx.clone());
+            }
+
+            if ui
+                .add_enabled(
+                    !self.job.running && !self.m3u8_url.trim().is_empty(),
+                    egui::Button::new("📋 Inspect"),
+                )
+                .on_hover_text(
+                    "Dry run: parse the playlist without downloading media and show the segment \
+                     count, duration, encryption, estimated size and segment URLs",
+                )
+                .clicked()
+            {
+                self.start_inspect_task(ctx.clone());
+            }
+
+            if ui
+                .add_enabled(
+                    !self.job.running && !self.m3u8_url.trim().is_empty(),
+                    egui::Button::new("🖼 Quick preview"),
+                )
+                .on_hover_text(
+                    "Render a few key frames from the master playlist's I-frame playlist, \
+                     without downloading the video",
+                )
+                .clicked()
+            {
+                self.start_preview_task(ctx.clone());
+            }
+            egui::ComboBox::from_id_source("preview_kind")
+                .selected_text(self.preview_kind.label())
+                .show_ui(ui, |ui| {
+                    for kind in PreviewKind::ALL {
+                        ui.selectable_value(&mut self.preview_kind, kind, kind.label());
+                    }
+                });
+
+            if ui
+                .add_enabled(
+                    !self.job.running && !self.m3u8_url.trim().is_empty(),
+                    egui::Button::new("🎞 Download sample"),
+                )
+                .on_hover_text(
+                    "Download and remux only the first segments (e.g. 3) or seconds (e.g. 30s) \
+                     as <name>.sample.<format>, to check quality and language first",
+                )
+                .clicked()
+            {
+                self.start_sample_task(ctx.clone());
+            }
+            ui.add(egui::TextEdit::singleline(&mut self.sample_length).desired_width(40.0));
+
+            let segment_label = match self.analyzed_segments() {
+                Some(rows) => format!(
+                    "✂ Segments ({}/{})",
+                    rows.iter().filter(|row| !row.excluded).count(),
+                    rows.len()
+                ),
+                None => "✂ Segments".to_string(),
+            };
+            if ui
+                .add_enabled(
+                    self.analyzed_segments().is_some(),
+                    egui::Button::new(segment_label),
+                )
+                .on_hover_text("Exclude segments (e.g. ads or broken ranges) after Analyze")
+                .clicked()
+            {
+                self.segment_editor_open = true;
+            }
+
+            if ui
+                .add_enabled(
+                    !self.m3u8_url.trim().is_empty(),
+                    egui::Button::new("📋 Copy as command"),
+                )
+                .on_hover_text("Copy an equivalent command-line invocation of this job")
+                .clicked()
+            {
+                match self.current_config() {
+                    Ok(config) => {
+                        let command = cli::to_command_line(&config);
+                        ui.output_mut(|o| o.copied_text = command.clone());
+                        self.job
+                            .logs
+                            .push(format!("-> Copied to clipboard: {}", command));
+                    }
+                    Err(e) => self.job.logs.push(format!("⚠️ {}", e)),
+                }
+            }
+        });
+    }
+
+    /// Analyze result for the playlist currently entered, if any.
+    /// Why `format` will likely fail the final stream copy: from the analyzed codecs, or for
+    /// webm before Analyze (HLS is rarely VP8/VP9).
+    pub(crate) fn format_warning(&self, format: &str) -> Option<String> {
+        match self.analyzed_stream_info() {
+            Some(info) => info.incompatibility(format),
+            None => unanalyzed_format_warning(format).map(str::to_string),
+        }
+    }
+
+    /// Asks what to do with a job whose output format can't hold its codecs.
+    pub(crate) fn show_format_confirm_window(&mut self, ctx: &egui::Context) {
+        let Some((config, _)) = &self.format_confirm else {
+            return;
+        };
+        let problem = self
+            .format_warning(&config.output_format)
+            .unwrap_or_default();
+        let mut choice = None;
+        egui::Window::new("Output format")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(format!("{}.", problem));
+                ui.label(
+                    "Re-encoding is slower and loses some quality; MKV takes the stream as is.",
+                );
+                ui.horizontal(|ui| {
+                    if ui.button("Re-encode").clicked() {
+                        choice = Some(FormatChoice::Reencode);
+                    }
+                    if ui.button("Use MKV instead").clicked() {
+                        choice = Some(FormatChoice::Mkv);
+                    }
+                    if ui.button("Cancel").clicked() {
+                        choice = Some(FormatChoice::Cancel);
+                    }
+                });
+            });
+        let Some(choice) = choice else {
+            return;
+        };
+        let Some((mut config, notes)) = self.format_confirm.take() else {
+            return;
+        };
+        match choice {
+            FormatChoice::Reencode => config.reencode = true,
+            FormatChoice::Mkv => {
+                config.output_format = "mkv".to_string();
+                self.output_format = "mkv".to_string();
+            }
+            FormatChoice::Cancel => return,
+        }
+        self.launch_download(ctx.clone(), config, notes);
+    }
+
+    pub(crate) fn analyzed_stream_info(&self) -> Option<&StreamInfo> {
+        self.stream_info
+            .as_ref()
+            .filter(|(url, _)| *url == self.m3u8_url.trim())
+            .map(|(_, info)| info)
+    }
+
+    /// Fills the key field with the contents of a raw key file.
+    pub(crate) fn load_key_file(&mut self, path: &str) {
+        match std::fs::read(path) {
+            Ok(bytes) if matches!(bytes.len(), 16 | 32) => {
+                self.key_hex = hex::encode(&bytes);
+                self.job
+                    .logs
+                    .push(format!("-> Loaded {}-byte key from {}.", bytes.len(), path));
+            }
+            Ok(bytes) => self.job.logs.push(format!(
+                "⚠️ {} is not a key file: expected 16 or 32 bytes, got {}.",
+                path,
+                bytes.len()
+            )),
+            Err(e) => self
+                .job
+                .logs
+                .push(format!("⚠️ Failed to read key file {}: {}", path, e)),
+        }
+    }
+
+    /// Window for importing URL and headers from a devtools "Copy as cURL" string.
+    pub(crate) fn show_curl_import_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.curl_import_open;
+        let mut imported = false;
+        egui::Window::new("Paste curl command")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ui.label(
+                    "Paste a request copied with \"Copy as cURL (bash)\" from browser devtools:",
+                );
+                egui::ScrollArea::vertical()
+                    .max_height(200.0)
+                    .show(ui, |ui| {
+                        ui.add(
+                            egui::TextEdit::multiline(&mut self.curl_import_text)
+                                .desired_width(f32::INFINITY)
+                                .desired_rows(6)
+                                .code_editor(),
+                        );
+                    });
+                if ui.button("Import").clicked() {
+                    match curl_import::parse_curl_command(&self.curl_import_text) {
+                        Ok(import) => {
+                            self.job.logs.push(format!(
+                                "-> Imported URL and {} header(s) from curl command.",
+                                import.headers.len()
+                            ));
+                            self.m3u8_url = import.url;
+                            self.request_headers = format_header_lines(&import.headers);
+                            // Headers from the curl command take precedence over a stored profile
+                            self.applied_profile_host = self
+                                .settings
+                                .host_profile_for(&self.m3u8_url)
+                                .map(|(host, _)| host.to_string());
+                            self.curl_import_text.clear();
+                            imported = true;
+                        }
+                        Err(e) => self.job.logs.push(format!("⚠️ curl import failed: {}", e)),
+                    }
+                }
+            });
+        self.curl_import_open = open && !imported;
+    }
+
+    /// Fills in the stored headers when the URL switches to a host that has a profile.
+    pub(crate) fn apply_host_profile(&mut self) {
+        let Some((host, headers)) = self.settings.host_profile_for(&self.m3u8_url) else {
+            self.applied_profile_host = None;
+            return;
+        };
+        if self.applied_profile_host.as_deref() == Some(host) {
+            return;
+        }
+        self.request_headers = format_header_lines(headers);
+        self.job.logs.push(format!(
+            "-> Applied header profile for {} ({} header(s)).",
+            host,
+            headers.len()
+        ));
+        self.applied_profile_host = Some(host.to_string());
+    }
+
+    /// "Save for host" / "Forget host" buttons below the headers field.
+    pub(crate) fn show_host_profile_buttons(&mut self, ui: &mut egui::Ui) {
+        let Some(host) = settings::host_of(&self.m3u8_url) else {
+            return;
+        };
+        let headers = parse_header_lines(&self.request_headers);
+        if ui
+            .add_enabled(
+                matches!(&headers, Ok(h) if !h.is_empty()),
+                egui::Button::new("💾 Save for host"),
+            )
+            .on_hover_text(format!(
+                "Apply these headers automatically to URLs from {}",
+                host
+            ))
+            .clicked()
+            && let Ok(headers) = headers
+        {
+            self.job
+                .logs
+                .push(format!("-> Saved header profile for {}.", host));
+            self.settings.host_profiles.insert(host.clone(), headers);
+            self.applied_profile_host = Some(host.clone());
+            self.save_settings();
+        }
+        if self.settings.host_profiles.contains_key(&host) && ui.button("Forget host").clicked() {
+            self.settings.host_profiles.remove(&host);
+            self.applied_profile_host = None;
+            self.job
+                .logs
+                .push(format!("-> Removed header profile for {}.", host));
+            self.save_settings();
+        }
+    }
+}
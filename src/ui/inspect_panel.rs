@@ -0,0 +1,47 @@
+// "Inspection" view: the dry-run report of the entered playlist (segment count, duration,
+// encryption, estimated size) and every segment URL a download would request.
+
+use crate::HlsDownloaderApp;
+use crate::downloader::inspect::PlaylistInfo;
+
+impl HlsDownloaderApp {
+    /// Report of the last Inspect run, if it was for the entered URL.
+    fn inspected_playlist(&self) -> Option<&PlaylistInfo> {
+        self.inspection
+            .as_ref()
+            .filter(|(url, _)| *url == self.m3u8_url.trim())
+            .map(|(_, info)| info)
+    }
+
+    /// Collapsible "Inspection" section, once Inspect parsed the playlist.
+    pub(crate) fn show_inspection_panel(&mut self, ui: &mut egui::Ui) {
+        let Some(info) = self.inspected_playlist() else {
+            return;
+        };
+        ui.add_space(5.0);
+        egui::CollapsingHeader::new("Inspection")
+            .id_source("inspection")
+            .default_open(true)
+            .show(ui, |ui| {
+                for line in info.summary_lines() {
+                    ui.label(line);
+                }
+                ui.horizontal(|ui| {
+                    ui.label(format!("Segment URLs ({}):", info.segment_urls.len()));
+                    if ui.small_button("📋 Copy").clicked() {
+                        ui.output_mut(|o| o.copied_text = info.segment_urls.join("\n"));
+                    }
+                });
+                let row_height = ui.text_style_height(&egui::TextStyle::Monospace);
+                egui::ScrollArea::both()
+                    .id_source("inspection_urls")
+                    .max_height(200.0)
+                    .auto_shrink([false, true])
+                    .show_rows(ui, row_height, info.segment_urls.len(), |ui, visible| {
+                        for url in &info.segment_urls[visible] {
+                            ui.monospace(url);
+                        }
+                    });
+            });
+    }
+}
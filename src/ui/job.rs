@@ -0,0 +1,573 @@
+// Job model: the state of the background task the window is attached to (download, analyze,
+// remux, ...) and the channel its messages arrive on. The panels read it; only task start and
+// message polling change it.
+
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+use crate::diagnostics::JobDiagnostics;
+use crate::downloader::pause::PauseGate;
+use crate::downloader::segment_io::RemuxOptions;
+use crate::downloader::{DownloadConfig, DownloadMessage};
+use crate::history::JobNotes;
+
+/// Capacity of the per-task message channel
+const TASK_CHANNEL_CAPACITY: usize = 100;
+/// Time without progress (and without retries) after which a download shows as stalled
+const STALL_AFTER: Duration = Duration::from_secs(20);
+/// Logged when a task is cancelled; its stages stop at their next check
+const CANCELLING_LOG: &str = "-> Cancelling... the running step stops and cleans up.";
+
+pub(crate) struct JobModel {
+    /// A task is running; the job form and tools are locked meanwhile
+    pub(crate) running: bool,
+    /// 0.0 to 1.0
+    pub(crate) progress: f32,
+    pub(crate) logs: Vec<String>,
+    /// Per-segment retry warnings of the current task
+    pub(crate) warnings: Vec<String>,
+    /// Next attempt of each segment that is backing off after a failed request
+    backoff: HashMap<usize, Instant>,
+    last_progress: Instant,
+    last_retry: Option<Instant>,
+    /// The last failed remux, offered as "Retry remux"
+    pub(crate) remux_retry: Option<RemuxRetry>,
+    /// Stops the running live recording (it is still saved); None when no recording runs
+    pub(crate) live_stop: Option<CancellationToken>,
+    /// Pauses the segment downloads of the running download; None for other tasks and live
+    /// recordings
+    pub(crate) pause: Option<PauseGate>,
+    /// Aborts the running task; None while no task runs
+    pub(crate) cancel: Option<CancellationToken>,
+    /// The download the task runs, recorded in the history once it finishes
+    pub(crate) download: Option<(DownloadConfig, JobNotes)>,
+    /// Job and error summaries of the task, kept after it ends for "Save diagnostics"
+    pub(crate) diagnostics: JobDiagnostics,
+    /// Remuxes of earlier downloads that continue after their task finished
+    pub(crate) background: Vec<BackgroundRemux>,
+    /// Downloads running next to the task (started while it ran, or by the queue), by task id
+    pub(crate) parallel: BTreeMap<TaskId, ParallelDownload>,
+    next_task_id: TaskId,
+    /// Log tab on display: a parallel download, or None for the task
+    pub(crate) log_tab: Option<TaskId>,
+    /// Receiver of the current task (recreated on each start)
+    receiver: Option<mpsc::Receiver<DownloadMessage>>,
+}
+
+/// Output stage of a finished download, running in a remux worker
+pub(crate) struct BackgroundRemux {
+    pub(crate) output_path: PathBuf,
+    /// 0.0 to 1.0
+    pub(crate) progress: f32,
+    /// Stops the remux (its partial output is removed); the job's own token
+    pub(crate) cancel: CancellationToken,
+    receiver: mpsc::Receiver<DownloadMessage>,
+}
+
+impl BackgroundRemux {
+    /// Aborts the remux; its result arrives once FFmpeg has stopped.
+    pub(crate) fn cancel(&mut self) {
+        self.cancel.cancel();
+    }
+}
+
+/// A remux that failed: the retained TS, the intended output and the job's remux options
+/// (timestamp fix, audio rendition, subtitles), so a retry does not depend on the form
+pub(crate) struct RemuxRetry {
+    pub(crate) ts_path: PathBuf,
+    pub(crate) output_path: PathBuf,
+    pub(crate) options: RemuxOptions,
+}
+
+/// Identifies a parallel download; numbered per session in start order
+pub(crate) type TaskId = u64;
+
+/// A download that runs next to the window's own task, with its own progress and log tab
+pub(crate) struct ParallelDownload {
+    /// Queue job it runs, if the queue started it
+    pub(crate) queue_id: Option<u64>,
+    pub(crate) name: String,
+    /// 0.0 to 1.0
+    pub(crate) progress: f32,
+    pub(crate) logs: Vec<String>,
+    /// Stops the live recording; None for on-demand downloads
+    pub(crate) live_stop: Option<CancellationToken>,
+    /// Pauses its segment downloads; None for live recordings
+    pub(crate) pause: Option<PauseGate>,
+    pub(crate) cancel: CancellationToken,
+    /// Set once the download ended; the tab stays until it is closed
+    pub(crate) result: Option<Result<(), String>>,
+    download: Option<(DownloadConfig, JobNotes)>,
+    pub(crate) diagnostics: JobDiagnostics,
+    receiver: mpsc::Receiver<DownloadMessage>,
+}
+
+impl ParallelDownload {
+    pub(crate) fn is_running(&self) -> bool {
+        self.result.is_none()
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.pause.as_ref().is_some_and(PauseGate::is_paused)
+    }
+
+    /// Pauses or resumes the download and notes it in its log.
+    pub(crate) fn toggle_pause(&mut self) {
+        if let Some(pause) = &self.pause {
+            toggle_pause(pause, &mut self.logs);
+        }
+    }
+
+    /// Aborts the download; its result arrives once every stage has stopped.
+    pub(crate) fn cancel(&mut self) {
+        if self.is_running() && !self.cancel.is_cancelled() {
+            self.cancel.cancel();
+            self.logs.push(CANCELLING_LOG.to_string());
+        }
+    }
+}
+
+/// A parallel download that ended, to be settled in the queue and the history
+pub(crate) struct FinishedDownload {
+    pub(crate) queue_id: Option<u64>,
+    pub(crate) download: (DownloadConfig, JobNotes),
+    pub(crate) result: Result<(), String>,
+}
+
+/// What the progress bar shows besides the percentage
+pub(crate) enum ProgressState {
+    Running,
+    /// Paused by the user; no new segment starts until it is resumed
+    Paused,
+    /// No progress since the last retry; every request in flight is waiting out its backoff
+    Retrying {
+        next_attempt: Duration,
+        segments: usize,
+    },
+    /// No progress and no retries for a while: the server or connection may hang
+    Stalled(Duration),
+}
+
+impl JobModel {
+    pub(crate) fn new(logs: Vec<String>) -> Self {
+        Self {
+            running: false,
+            progress: 0.0,
+            logs,
+            warnings: Vec::new(),
+            backoff: HashMap::new(),
+            last_progress: Instant::now(),
+            last_retry: None,
+            remux_retry: None,
+            live_stop: None,
+            pause: None,
+            cancel: None,
+            download: None,
+            diagnostics: JobDiagnostics::default(),
+            background: Vec::new(),
+            parallel: BTreeMap::new(),
+            next_task_id: 0,
+            log_tab: None,
+            receiver: None,
+        }
+    }
+
+    /// Resets the state for a new task that starts with `first_log` and returns the token that
+    /// cancels it.
+    pub(crate) fn begin(&mut self, first_log: &str) -> CancellationToken {
+        let cancel = CancellationToken::new();
+        self.cancel = Some(cancel.clone());
+        self.running = true;
+        self.progress = 0.0;
+        self.remux_retry = None;
+        self.download = None;
+        self.diagnostics = JobDiagnostics::default();
+        self.pause = None;
+        self.logs.clear();
+        self.warnings.clear();
+        self.backoff.clear();
+        self.last_progress = Instant::now();
+        self.last_retry = None;
+        self.logs.push(first_log.to_string());
+        cancel
+    }
+
+    /// Aborts the running task; the temp files of a download are removed as it stops.
+    pub(crate) fn cancel(&mut self) {
+        if let Some(cancel) = &self.cancel
+            && !cancel.is_cancelled()
+        {
+            cancel.cancel();
+            self.logs.push(CANCELLING_LOG.to_string());
+        }
+    }
+
+    /// Cancels the task, every parallel download and every background remux, e.g. when the
+    /// window closes.
+    pub(crate) fn cancel_all(&mut self) {
+        self.cancel();
+        for task in self.parallel.values_mut() {
+            task.cancel();
+        }
+        for remux in &mut self.background {
+            remux.cancel();
+        }
+    }
+
+    /// Creates the channel of the new task; its messages are read with `try_recv`.
+    pub(crate) fn open_channel(&mut self) -> mpsc::Sender<DownloadMessage> {
+        let (sender, receiver) = mpsc::channel(TASK_CHANNEL_CAPACITY);
+        self.receiver = Some(receiver);
+        sender
+    }
+
+    /// Next pending message of the task, without blocking.
+    pub(crate) fn try_recv(&mut self) -> Option<DownloadMessage> {
+        self.receiver.as_mut()?.try_recv().ok()
+    }
+
+    /// Applies a message that only concerns the task's own state (log, progress, warnings,
+    /// remux retry). Anything else is handed back for the app to route.
+    pub(crate) fn apply(&mut self, msg: DownloadMessage) -> Option<DownloadMessage> {
+        match msg {
+            DownloadMessage::Log(s) => self.logs.push(s),
+            DownloadMessage::SegmentProgress { completed, total } => self
+                .logs
+                .push(DownloadMessage::segment_progress_line(completed, total)),
+            DownloadMessage::ErrorSummary(table) => {
+                self.logs.push(table.clone());
+                self.diagnostics.error_summaries.push(table);
+            }
+            DownloadMessage::Progress(p) => {
+                if p != self.progress {
+                    self.last_progress = Instant::now();
+                }
+                self.progress = p;
+            }
+            DownloadMessage::RetryWarning {
+                segment,
+                attempt,
+                reason,
+                delay,
+            } => {
+                let now = Instant::now();
+                self.backoff.insert(segment, now + delay);
+                self.last_retry = Some(now);
+                self.warnings.push(format!(
+                    "Segment {} (attempt {}): {}",
+                    segment, attempt, reason
+                ));
+            }
+            DownloadMessage::RemuxFailed {
+                ts_path,
+                output_path,
+                options,
+            } => {
+                self.remux_retry = Some(RemuxRetry {
+                    ts_path,
+                    output_path,
+                    options,
+                })
+            }
+            DownloadMessage::OutputStageDetached {
+                output_path,
+                receiver,
+                cancel,
+            } => self.background.push(BackgroundRemux {
+                output_path,
+                progress: 0.0,
+                cancel,
+                receiver,
+            }),
+            other => return Some(other),
+        }
+        None
+    }
+
+    /// Whether the running task is waiting out retry backoffs or has stopped making progress,
+    /// so a 429 storm can be told apart from a hang.
+    pub(crate) fn progress_state(&self) -> ProgressState {
+        if self.running && self.pause.as_ref().is_some_and(PauseGate::is_paused) {
+            return ProgressState::Paused;
+        }
+        let now = Instant::now();
+        // Progress after the last retry means other requests still get through
+        let retrying = self.running
+            && self
+                .last_retry
+                .is_some_and(|retry| retry >= self.last_progress);
+        let waiting = self.backoff.values().filter(|until| **until > now);
+        if retrying && let Some(next) = waiting.clone().min() {
+            return ProgressState::Retrying {
+                next_attempt: *next - now,
+                segments: waiting.count(),
+            };
+        }
+        let idle = now
+            - self
+                .last_progress
+                .max(self.last_retry.unwrap_or(self.last_progress));
+        // Live recordings and the steps before the first segment have no steady progress
+        if self.running
+            && self.live_stop.is_none()
+            && self.progress > 0.0
+            && self.progress < 1.0
+            && idle >= STALL_AFTER
+        {
+            return ProgressState::Stalled(idle);
+        }
+        ProgressState::Running
+    }
+
+    /// Pauses or resumes the task's download and notes it in the log.
+    pub(crate) fn toggle_pause(&mut self) {
+        if let Some(pause) = &self.pause {
+            toggle_pause(pause, &mut self.logs);
+            // Time spent paused doesn't count towards a stall
+            self.last_progress = Instant::now();
+        }
+    }
+
+    /// Creates the channel of a download that runs in parallel to the task.
+    pub(crate) fn open_parallel(
+        &mut self,
+        queue_id: Option<u64>,
+        name: String,
+        download: (DownloadConfig, JobNotes),
+        live_stop: Option<CancellationToken>,
+        pause: Option<PauseGate>,
+        cancel: CancellationToken,
+    ) -> mpsc::Sender<DownloadMessage> {
+        let (sender, receiver) = mpsc::channel(TASK_CHANNEL_CAPACITY);
+        self.next_task_id += 1;
+        let diagnostics = JobDiagnostics {
+            config: Some(download.0.clone()),
+            ..Default::default()
+        };
+        self.parallel.insert(
+            self.next_task_id,
+            ParallelDownload {
+                queue_id,
+                name,
+                progress: 0.0,
+                logs: Vec::new(),
+                live_stop,
+                pause,
+                cancel,
+                result: None,
+                download: Some(download),
+                diagnostics,
+                receiver,
+            },
+        );
+        sender
+    }
+
+    /// The task or any parallel download is running.
+    pub(crate) fn busy(&self) -> bool {
+        self.running || self.parallel.values().any(ParallelDownload::is_running)
+    }
+
+    /// Queue jobs among the running parallel downloads
+    pub(crate) fn parallel_queue_jobs(&self) -> usize {
+        self.parallel
+            .values()
+            .filter(|task| task.is_running() && task.queue_id.is_some())
+            .count()
+    }
+
+    /// Closes the log tab of a parallel download that ended.
+    pub(crate) fn close_parallel(&mut self, id: TaskId) {
+        if self
+            .parallel
+            .get(&id)
+            .is_some_and(|task| !task.is_running())
+        {
+            self.parallel.remove(&id);
+            if self.log_tab == Some(id) {
+                self.log_tab = None;
+            }
+        }
+    }
+
+    /// Reads the messages of the running parallel downloads into their own logs and returns
+    /// the ones that ended (also noted in the task's log). Returns whether anything arrived.
+    pub(crate) fn poll_parallel(&mut self) -> (bool, Vec<FinishedDownload>) {
+        let mut received = false;
+        let mut finished = Vec::new();
+        for task in self.parallel.values_mut().filter(|task| task.is_running()) {
+            let result = loop {
+                let msg = match task.receiver.try_recv() {
+                    Ok(msg) => msg,
+                    Err(mpsc::error::TryRecvError::Empty) => break None,
+                    Err(mpsc::error::TryRecvError::Disconnected) => {
+                        break Some(Err("The download task ended unexpectedly".to_string()));
+                    }
+                };
+                received = true;
+                match msg {
+                    DownloadMessage::Log(s) => task.logs.push(s),
+                    DownloadMessage::SegmentProgress { completed, total } => task
+                        .logs
+                        .push(DownloadMessage::segment_progress_line(completed, total)),
+                    DownloadMessage::ErrorSummary(table) => {
+                        task.logs.push(table.clone());
+                        task.diagnostics.error_summaries.push(table);
+                    }
+                    DownloadMessage::Progress(p) => task.progress = p,
+                    DownloadMessage::RetryWarning {
+                        segment,
+                        attempt,
+                        reason,
+                        ..
+                    } => task.logs.push(format!(
+                        "⚠️ Segment {} (attempt {}): {}",
+                        segment, attempt, reason
+                    )),
+                    DownloadMessage::OutputStageDetached {
+                        output_path,
+                        receiver,
+                        cancel,
+                    } => self.background.push(BackgroundRemux {
+                        output_path,
+                        progress: 0.0,
+                        cancel,
+                        receiver,
+                    }),
+                    // "Retry remux" sits under the main log, as for a failed background remux
+                    DownloadMessage::RemuxFailed {
+                        ts_path,
+                        output_path,
+                        options,
+                    } => {
+                        self.logs.push(format!(
+                            "-> [{}] Remux failed; use \"Retry remux\" to run it again.",
+                            task.name
+                        ));
+                        self.remux_retry = Some(RemuxRetry {
+                            ts_path,
+                            output_path,
+                            options,
+                        });
+                    }
+                    DownloadMessage::Finished(result) => break Some(result),
+                    _ => {}
+                }
+            };
+            let Some(result) = result else {
+                continue;
+            };
+            let summary = match &result {
+                Err(_) if task.cancel.is_cancelled() => {
+                    format!("⏹ [{}] Download cancelled.", task.name)
+                }
+                Ok(()) => format!("✅ [{}] Download finished.", task.name),
+                Err(e) => format!("❌ [{}] Download failed: {}", task.name, e),
+            };
+            task.logs.push(summary.clone());
+            self.logs.push(summary);
+            task.live_stop = None;
+            task.pause = None;
+            if let Some(download) = task.download.take() {
+                finished.push(FinishedDownload {
+                    queue_id: task.queue_id,
+                    download,
+                    result: result.clone(),
+                });
+            }
+            task.result = Some(result);
+        }
+        (received, finished)
+    }
+
+    /// Reads the messages of the background remuxes into the log, prefixed with their file
+    /// name; finished ones are dropped. Returns whether anything arrived.
+    pub(crate) fn poll_background(&mut self) -> bool {
+        let mut received = false;
+        let logs = &mut self.logs;
+        let remux_retry = &mut self.remux_retry;
+        self.background.retain_mut(|remux| {
+            let name = remux
+                .output_path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned();
+            loop {
+                match remux.receiver.try_recv() {
+                    Ok(msg) => {
+                        received = true;
+                        match msg {
+                            DownloadMessage::Log(s) => logs.push(format!("[{}] {}", name, s)),
+                            DownloadMessage::Progress(p) => remux.progress = p,
+                            DownloadMessage::RemuxFailed {
+                                ts_path,
+                                output_path,
+                                options,
+                            } => {
+                                *remux_retry = Some(RemuxRetry {
+                                    ts_path,
+                                    output_path,
+                                    options,
+                                })
+                            }
+                            DownloadMessage::Finished(Ok(())) => {
+                                logs.push(format!("✅ [{}] Background remux finished.", name));
+                                return false;
+                            }
+                            DownloadMessage::Finished(Err(_)) if remux.cancel.is_cancelled() => {
+                                logs.push(format!("⏹ [{}] Background remux cancelled.", name));
+                                return false;
+                            }
+                            DownloadMessage::Finished(Err(e)) => {
+                                logs.push(format!("❌ [{}] Background remux failed: {}", name, e));
+                                return false;
+                            }
+                            _ => {}
+                        }
+                    }
+                    Err(mpsc::error::TryRecvError::Empty) => return true,
+                    // The stage task ended without reporting (panicked)
+                    Err(mpsc::error::TryRecvError::Disconnected) => return false,
+                }
+            }
+        });
+        received
+    }
+
+    /// Ends the task with its result and closes the channel.
+    pub(crate) fn finish(&mut self, result: &Result<(), String>) {
+        self.running = false;
+        self.live_stop = None;
+        self.pause = None;
+        self.receiver = None;
+        let cancelled = self
+            .cancel
+            .take()
+            .is_some_and(|cancel| cancel.is_cancelled());
+        match result {
+            Err(_) if cancelled => self.logs.push("⏹ Task cancelled.".to_string()),
+            Ok(_) => self
+                .logs
+                .push("✅ Task completed successfully!".to_string()),
+            Err(e) => self.logs.push(format!("❌ Task failed: {}", e)),
+        }
+    }
+}
+
+/// Flips `pause` and logs the change.
+fn toggle_pause(pause: &PauseGate, logs: &mut Vec<String>) {
+    if pause.is_paused() {
+        pause.resume();
+        logs.push("▶ Download resumed.".to_string());
+    } else {
+        pause.pause();
+        logs.push(
+            "⏸ Download paused; segments already in flight still finish, nothing is lost."
+                .to_string(),
+        );
+    }
+}
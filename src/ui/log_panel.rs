@@ -0,0 +1,259 @@
+// Log panel: progress of the current task, its retry warnings and log output, plus a tab per
+// download running in parallel.
+
+use std::time::Duration;
+
+use crate::HlsDownloaderApp;
+use crate::diagnostics::{self, JobDiagnostics};
+use crate::ui::job::{ProgressState, TaskId};
+
+/// Progress bar color of a paused download
+const PAUSED_FILL: egui::Color32 = egui::Color32::from_rgb(90, 110, 140);
+const CANCEL_HINT: &str = "Abort the task; FFmpeg is stopped and temporary segments are deleted (a kept workspace stays \
+     for a later resume)";
+
+impl HlsDownloaderApp {
+    /// Progress bar, warnings and log of the current task, or of the parallel download whose
+    /// tab is selected.
+    pub(crate) fn show_log_panel(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        if !self.job.parallel.is_empty() {
+            ui.add_space(10.0);
+            self.show_log_tabs(ui);
+        }
+        if let Some(id) = self.job.log_tab
+            && self.job.parallel.contains_key(&id)
+        {
+            self.show_parallel_log(ui, id);
+            return;
+        }
+
+        if self.job.remux_retry.is_some() && !self.job.running {
+            ui.add_space(5.0);
+            if ui.button("🔁 Retry remux").clicked() {
+                self.start_remux_retry_task(ctx.clone());
+            }
+        }
+
+        ui.add_space(10.0);
+        let bar = egui::ProgressBar::new(self.job.progress);
+        let bar = match self.job.progress_state() {
+            ProgressState::Running => bar.show_percentage(),
+            ProgressState::Paused => bar
+                .text(format!("{:.0}% · paused", self.job.progress * 100.0))
+                .fill(PAUSED_FILL),
+            ProgressState::Retrying {
+                next_attempt,
+                segments,
+            } => bar
+                .text(format!(
+                    "{:.0}% · retrying {} segment(s) — next attempt in {}s",
+                    self.job.progress * 100.0,
+                    segments,
+                    next_attempt.as_secs() + 1
+                ))
+                .fill(egui::Color32::from_rgb(200, 140, 0)),
+            ProgressState::Stalled(idle) => bar
+                .text(format!(
+                    "{:.0}% · no progress for {}s",
+                    self.job.progress * 100.0,
+                    idle.as_secs()
+                ))
+                .fill(egui::Color32::from_rgb(170, 60, 60)),
+        };
+        ui.add(bar);
+        if self.job.running
+            && ui
+                .add_enabled(
+                    self.job
+                        .cancel
+                        .as_ref()
+                        .is_some_and(|cancel| !cancel.is_cancelled()),
+                    egui::Button::new("✖ Cancel"),
+                )
+                .on_hover_text(CANCEL_HINT)
+                .clicked()
+        {
+            self.job.cancel();
+        }
+        if self.job.running {
+            // Keeps the countdown and the stall timer moving between messages
+            ctx.request_repaint_after(Duration::from_secs(1));
+        }
+
+        // Retry warnings, collapsed so they don't push the log out of view
+        if !self.job.warnings.is_empty() {
+            ui.add_space(10.0);
+            egui::CollapsingHeader::new(
+                egui::RichText::new(format!("⚠️ Warnings ({})", self.job.warnings.len()))
+                    .color(egui::Color32::YELLOW),
+            )
+            .id_source("warnings")
+            .show(ui, |ui| {
+                egui::ScrollArea::vertical()
+                    .id_source("warnings_scroll")
+                    .stick_to_bottom(true)
+                    .max_height(120.0)
+                    .show(ui, |ui| {
+                        for warning in &self.job.warnings {
+                            ui.label(warning);
+                        }
+                    });
+            });
+        }
+
+        // 3. Log Output Block
+        ui.add_space(15.0);
+        ui.horizontal(|ui| {
+            ui.label("Log Output:");
+            if let Some(message) = diagnostics_button(ui, &self.job.diagnostics, &self.job.logs) {
+                self.job.logs.push(message);
+            }
+        });
+        show_log_lines(ui, "log", &self.job.logs);
+    }
+
+    /// One tab for the current task and one per parallel download; ended downloads can be
+    /// closed.
+    fn show_log_tabs(&mut self, ui: &mut egui::Ui) {
+        let mut close = None;
+        ui.horizontal_wrapped(|ui| {
+            if ui
+                .selectable_label(self.job.log_tab.is_none(), "Current task")
+                .clicked()
+            {
+                self.job.log_tab = None;
+            }
+            for (id, task) in &self.job.parallel {
+                let status = match &task.result {
+                    None if task.is_paused() => format!("⏸ {:.0}%", task.progress * 100.0),
+                    None => format!("{:.0}%", task.progress * 100.0),
+                    Some(Ok(())) => "✅".to_string(),
+                    Some(Err(_)) => "❌".to_string(),
+                };
+                if ui
+                    .selectable_label(
+                        self.job.log_tab == Some(*id),
+                        format!("{} ({})", task.name, status),
+                    )
+                    .clicked()
+                {
+                    self.job.log_tab = Some(*id);
+                }
+                if !task.is_running()
+                    && ui
+                        .small_button("✖")
+                        .on_hover_text("Close this log")
+                        .clicked()
+                {
+                    close = Some(*id);
+                }
+            }
+        });
+        if let Some(id) = close {
+            self.job.close_parallel(id);
+        }
+    }
+
+    /// Progress, pause and stop buttons and log of a parallel download.
+    fn show_parallel_log(&mut self, ui: &mut egui::Ui, id: TaskId) {
+        let Some(task) = self.job.parallel.get_mut(&id) else {
+            return;
+        };
+        ui.add_space(10.0);
+        let bar = egui::ProgressBar::new(task.progress);
+        ui.add(if task.is_paused() {
+            bar.text(format!("{:.0}% · paused", task.progress * 100.0))
+                .fill(PAUSED_FILL)
+        } else {
+            bar.show_percentage()
+        });
+        if let Some(stop) = &task.live_stop
+            && ui
+                .add_enabled(!stop.is_cancelled(), egui::Button::new("⏹ Stop recording"))
+                .on_hover_text("Stop reloading the live playlist and save what was recorded")
+                .clicked()
+        {
+            stop.cancel();
+        }
+        if task.is_running()
+            && task.pause.is_some()
+            && ui
+                .button(if task.is_paused() {
+                    "▶ Resume"
+                } else {
+                    "⏸ Pause"
+                })
+                .clicked()
+        {
+            task.toggle_pause();
+        }
+        if task.is_running()
+            && ui
+                .add_enabled(!task.cancel.is_cancelled(), egui::Button::new("✖ Cancel"))
+                .on_hover_text(CANCEL_HINT)
+                .clicked()
+        {
+            task.cancel();
+        }
+        ui.add_space(15.0);
+        ui.horizontal(|ui| {
+            ui.label(format!("Log Output ({}):", task.name));
+            if let Some(message) = diagnostics_button(ui, &task.diagnostics, &task.logs) {
+                task.logs.push(message);
+            }
+        });
+        show_log_lines(ui, ("parallel_log", id), &task.logs);
+    }
+}
+
+/// "Save diagnostics" for a log; returns the line to log once a bundle was saved (or failed).
+fn diagnostics_button(
+    ui: &mut egui::Ui,
+    diagnostics: &JobDiagnostics,
+    logs: &[String],
+) -> Option<String> {
+    if !ui
+        .small_button("🩺 Save diagnostics...")
+        .on_hover_text(
+            "Version, platform, the job without credentials, the segment error summary and \
+             this log, as one text file for a bug report",
+        )
+        .clicked()
+    {
+        return None;
+    }
+    let path = rfd::FileDialog::new()
+        .set_file_name(diagnostics::default_file_name())
+        .add_filter("Text", &["txt"])
+        .save_file()?;
+    Some(match diagnostics.write_bundle(logs, &path) {
+        Ok(()) => format!("✅ Diagnostics saved to {}", path.display()),
+        Err(e) => format!("❌ Failed to save diagnostics: {}", e),
+    })
+}
+
+/// Log lines colored by their prefix, latest at the bottom.
+fn show_log_lines(ui: &mut egui::Ui, id_source: impl std::hash::Hash, logs: &[String]) {
+    egui::ScrollArea::vertical()
+        .id_source(id_source)
+        .stick_to_bottom(true)
+        .max_height(250.0)
+        .show(ui, |ui| {
+            // Display latest logs at the bottom
+            for log in logs {
+                let text = egui::RichText::new(log);
+                // Color based on log content (simplified)
+                let colored_text = if log.starts_with("❌") {
+                    text.color(egui::Color32::RED)
+                } else if log.starts_with("✅") || log.starts_with("📦") || log.starts_with("🔑")
+                {
+                    text.color(egui::Color32::GREEN)
+                } else if log.starts_with("⚠️") {
+                    text.color(egui::Color32::YELLOW)
+                } else {
+                    text.color(egui::Color32::WHITE)
+                };
+                ui.label(colored_text);
+            }
+        });
+}
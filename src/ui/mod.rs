@@ -0,0 +1,14 @@
+// GUI panels. Each module adds the drawing code of one part of the window to
+// `HlsDownloaderApp`; the state of the running task lives in `job::JobModel`.
+
+pub(crate) mod dashboard;
+pub(crate) mod history_panel;
+pub(crate) mod input_panel;
+pub(crate) mod inspect_panel;
+pub(crate) mod job;
+pub(crate) mod log_panel;
+pub(crate) mod queue_panel;
+pub(crate) mod raw_playlist;
+pub(crate) mod segment_editor;
+pub(crate) mod settings_panel;
+pub(crate) mod tools_panel;
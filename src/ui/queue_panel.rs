@@ -0,0 +1,834 @@
+// Queue panel: the persisted download queue, the Watch later list and the batch importer that
+// fills the queue, plus the logic that works the queue off.
+
+use crate::downloader::batch::{BatchEntry, run_batch_analyze_job};
+use crate::downloader::http::{normalize_url, parse_header_lines};
+use crate::downloader::remux_pool;
+use crate::downloader::stats::format_bytes;
+use crate::history::JobNotes;
+use crate::queue::{FinishAction, QueueState};
+use crate::settings::AppSettings;
+use crate::ui::history_panel::NotesTarget;
+use crate::ui::segment_editor::format_duration;
+use crate::{HlsDownloaderApp, power};
+
+/// Output name of API jobs submitted without one
+const API_JOB_NAME_TEMPLATE: &str = "{host}_{name}";
+
+impl HlsDownloaderApp {
+    /// Collapsible "Queue", "Watch later" and "History" sections.
+    pub(crate) fn show_queue_panel(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        egui::CollapsingHeader::new(format!("Queue ({})", self.queue.jobs.len())).show(ui, |ui| {
+            if ui
+                .button("📋 Batch import...")
+                .on_hover_text("Analyze a list of URLs, pick qualities and queue them")
+                .clicked()
+            {
+                self.batch_open = true;
+            }
+            self.show_queue(ui);
+        });
+
+        // Watch Later Block
+        egui::CollapsingHeader::new(format!("Watch later ({})", self.watch_later.jobs.len())).show(
+            ui,
+            |ui| {
+                self.show_watch_later(ui, ctx);
+            },
+        );
+
+        egui::CollapsingHeader::new(format!("History ({})", self.history.entries.len()))
+            .show(ui, |ui| self.show_history(ui));
+    }
+
+    /// Startup dialog offering to continue the queue saved by the last session.
+    pub(crate) fn show_queue_resume_prompt(&mut self, ctx: &egui::Context) {
+        egui::Window::new("Resume queue?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{} queued download(s) from the last session are waiting.",
+                    self.queue.pending_count()
+                ));
+                ui.horizontal(|ui| {
+                    if ui.button("▶ Resume queue").clicked() {
+                        self.queue_active = true;
+                        self.queue_resume_prompt = false;
+                    }
+                    if ui
+                        .button("Later")
+                        .on_hover_text("Keep the jobs queued without starting them")
+                        .clicked()
+                    {
+                        self.queue_resume_prompt = false;
+                    }
+                    if ui.button("🗑 Discard").clicked() {
+                        self.queue.jobs.clear();
+                        self.save_queue();
+                        self.queue_resume_prompt = false;
+                    }
+                });
+            });
+    }
+
+    pub(crate) fn save_watch_later(&mut self) {
+        if let Err(e) = self.watch_later.save() {
+            self.job.logs.push(format!(
+                "⚠️ Warning: Failed to save watch later list: {}",
+                e
+            ));
+        }
+    }
+
+    /// Captured jobs with their metadata; each can be downloaded now or moved to the queue.
+    pub(crate) fn show_watch_later(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        if self.watch_later.jobs.is_empty() {
+            ui.label("Nothing saved. \"Save for later\" captures the current job.");
+            return;
+        }
+        let mut start = None;
+        let mut enqueue = None;
+        let mut remove = None;
+        egui::Grid::new("watch_later_jobs")
+            .num_columns(3)
+            .striped(true)
+            .show(ui, |ui| {
+                for (i, job) in self.watch_later.jobs.iter().enumerate() {
+                    let name = if job.config.output_filename.is_empty() {
+                        job.config.playlist_url.as_str()
+                    } else {
+                        job.config.output_filename.as_str()
+                    };
+                    ui.label(name).on_hover_text(&job.config.playlist_url);
+                    ui.label(format!(
+                        "{} · {} segments{} · {}",
+                        format_duration(job.duration_secs),
+                        job.segment_count,
+                        if job.key_saved { " · key saved" } else { "" },
+                        job.captured_on
+                    ));
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add_enabled(!self.job.running, egui::Button::new("▶ Download"))
+                            .clicked()
+                        {
+                            start = Some(i);
+                        }
+                        if ui.small_button("➕ Queue").clicked() {
+                            enqueue = Some(i);
+                        }
+                        if ui.small_button("🗑 Remove").clicked() {
+                            remove = Some(i);
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+        if let Some(i) = start {
+            let job = self.watch_later.jobs.remove(i);
+            self.save_watch_later();
+            self.launch_download(ctx.clone(), job.config, JobNotes::default());
+        } else if let Some(i) = enqueue {
+            let job = self.watch_later.jobs.remove(i);
+            self.queue.push(job.config, JobNotes::default());
+            self.save_queue();
+            self.save_watch_later();
+        } else if let Some(i) = remove {
+            self.watch_later.jobs.remove(i);
+            self.save_watch_later();
+        }
+    }
+
+    /// Window for analyzing a list of URLs and queueing them with a per-row quality.
+    pub(crate) fn show_batch_import_window(&mut self, ctx: &egui::Context) {
+        let mut open = self.batch_open;
+        let mut enqueue = false;
+        let mut start = false;
+        let mut profile_changed = false;
+        let mut parallel_changed = false;
+        egui::Window::new("Batch import")
+            .open(&mut open)
+            .collapsible(false)
+            .default_width(640.0)
+            .show(ctx, |ui| {
+                ui.label(
+                    "One playlist URL per line, optionally followed by an output name. The current headers and output settings apply to every job.",
+                );
+                if ui
+                    .button("📂 Load list...")
+                    .on_hover_text("Append the lines of a text file")
+                    .clicked()
+                    && let Some(path) = rfd::FileDialog::new()
+                        .add_filter("URL list", &["txt", "list"])
+                        .pick_file()
+                {
+                    match std::fs::read_to_string(&path) {
+                        Ok(text) => {
+                            if !self.batch_text.is_empty() && !self.batch_text.ends_with('\n') {
+                                self.batch_text.push('\n');
+                            }
+                            self.batch_text.push_str(&text);
+                        }
+                        Err(e) => self
+                            .job
+                            .logs
+                            .push(format!("⚠️ Could not read {}: {}", path.display(), e)),
+                    }
+                }
+                egui::ScrollArea::vertical()
+                    .id_source("batch_text")
+                    .max_height(120.0)
+                    .show(ui, |ui| {
+                        ui.add(
+                            egui::TextEdit::multiline(&mut self.batch_text)
+                                .desired_width(f32::INFINITY)
+                                .desired_rows(4),
+                        );
+                    });
+                if ui
+                    .add_enabled(
+                        !self.job.running && !self.batch_text.trim().is_empty(),
+                        egui::Button::new("🔍 Analyze all"),
+                    )
+                    .clicked()
+                {
+                    self.start_batch_analyze_task(ctx.clone());
+                }
+                ui.horizontal(|ui| {
+                    ui.label("Quality profile:");
+                    let selected = &mut self.settings.batch_quality_profile;
+                    egui::ComboBox::from_id_source("batch_quality_profile")
+                        .selected_text(selected.as_deref().unwrap_or("Best"))
+                        .show_ui(ui, |ui| {
+                            profile_changed |=
+                                ui.selectable_value(selected, None, "Best").changed();
+                            for name in self.settings.quality_profiles.keys() {
+                                profile_changed |= ui
+                                    .selectable_value(selected, Some(name.clone()), name)
+                                    .changed();
+                            }
+                        })
+                        .response
+                        .on_hover_text(
+                            "Applied to every row whose quality was not picked by hand; \
+                             profiles are edited under Settings",
+                        );
+                });
+                if self.batch_rows.is_empty() {
+                    return;
+                }
+
+                ui.separator();
+                egui::ScrollArea::vertical()
+                    .id_source("batch_rows")
+                    .max_height(300.0)
+                    .show(ui, |ui| {
+                        egui::Grid::new("batch_rows")
+                            .num_columns(7)
+                            .striped(true)
+                            .show(ui, |ui| {
+                                for header in
+                                    ["#", "URL", "Name", "Duration", "Quality", "Est. size", ""]
+                                {
+                                    ui.strong(header);
+                                }
+                                ui.end_row();
+                                for (i, row) in self.batch_rows.iter_mut().enumerate() {
+                                    ui.label((i + 1).to_string());
+                                    ui.add(egui::Label::new(&row.url).truncate(true))
+                                        .on_hover_text(&row.url);
+                                    ui.add(
+                                        egui::TextEdit::singleline(&mut row.name)
+                                            .hint_text("numbered")
+                                            .desired_width(120.0),
+                                    );
+                                    match &row.analysis {
+                                        None => {
+                                            ui.label("…");
+                                            ui.label("");
+                                            ui.label("");
+                                            ui.spinner();
+                                        }
+                                        Some(Err(e)) => {
+                                            ui.label("");
+                                            ui.label("");
+                                            ui.label("");
+                                            ui.label("❌").on_hover_text(e);
+                                        }
+                                        Some(Ok(entry)) => {
+                                            ui.label(format_duration(entry.duration_secs));
+                                            match &entry.master {
+                                                Some(master) => {
+                                                    egui::ComboBox::from_id_source(("batch_variant", i))
+                                                        .selected_text(
+                                                            master.variants[row.variant].label(),
+                                                        )
+                                                        .show_ui(ui, |ui| {
+                                                            for (v, variant) in
+                                                                master.variants.iter().enumerate()
+                                                            {
+                                                                if ui
+                                                                    .selectable_value(
+                                                                        &mut row.variant,
+                                                                        v,
+                                                                        variant.label(),
+                                                                    )
+                                                                    .clicked()
+                                                                {
+                                                                    row.variant_chosen = true;
+                                                                }
+                                                            }
+                                                        });
+                                                }
+                                                None => {
+                                                    ui.label("Single stream");
+                                                }
+                                            }
+                                            ui.label(
+                                                entry
+                                                    .estimated_size(row.variant)
+                                                    .map_or("?".to_string(), |size| {
+                                                        format_bytes(size as f64)
+                                                    }),
+                                            );
+                                            ui.label("✅").on_hover_text(format!(
+                                                "{} segments",
+                                                entry.segment_count
+                                            ));
+                                        }
+                                    }
+                                    ui.end_row();
+                                }
+                            });
+                    });
+
+                let analyzed: Vec<&BatchEntry> = self
+                    .batch_rows
+                    .iter()
+                    .filter_map(|row| row.analysis.as_ref()?.as_ref().ok())
+                    .collect();
+                let total_duration: f64 = analyzed.iter().map(|entry| entry.duration_secs).sum();
+                let total_size: u64 = self
+                    .batch_rows
+                    .iter()
+                    .filter_map(|row| match &row.analysis {
+                        Some(Ok(entry)) => entry.estimated_size(row.variant),
+                        _ => None,
+                    })
+                    .sum();
+                ui.label(format!(
+                    "{} of {} playlists ready, total duration {}, about {}",
+                    analyzed.len(),
+                    self.batch_rows.len(),
+                    format_duration(total_duration),
+                    format_bytes(total_size as f64)
+                ));
+                let pending = self.batch_rows.iter().any(|row| row.analysis.is_none());
+                let ready = !analyzed.is_empty() && !pending;
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(
+                            ready,
+                            egui::Button::new(format!("➕ Add {} to queue", analyzed.len())),
+                        )
+                        .clicked()
+                    {
+                        enqueue = true;
+                    }
+                    if ui
+                        .add_enabled(ready, egui::Button::new("▶ Download all"))
+                        .on_hover_text("Queue them and run the queue")
+                        .clicked()
+                    {
+                        enqueue = true;
+                        start = true;
+                    }
+                    ui.label("Parallel downloads:");
+                    parallel_changed = ui
+                        .add(
+                            egui::DragValue::new(&mut self.settings.queue_parallel_jobs)
+                                .clamp_range(1..=8),
+                        )
+                        .changed();
+                });
+            });
+        if parallel_changed {
+            self.save_settings();
+        }
+        if profile_changed {
+            for row in &mut self.batch_rows {
+                if let Some(Ok(entry)) = &row.analysis
+                    && !row.variant_chosen
+                {
+                    row.variant = batch_default_variant(&self.settings, entry);
+                }
+            }
+            self.save_settings();
+        }
+        if enqueue {
+            self.enqueue_batch();
+            self.queue_active |= start;
+            open = false;
+        }
+        self.batch_open = open;
+    }
+
+    /// Analyzes every URL of the batch text concurrently.
+    pub(crate) fn start_batch_analyze_task(&mut self, ctx: egui::Context) {
+        let ctx = self.task_context(ctx);
+        let headers = match parse_header_lines(&self.request_headers) {
+            Ok(headers) => headers,
+            Err(e) => {
+                self.job.logs.push(format!("⚠️ {}", e));
+                return;
+            }
+        };
+        self.batch_rows = parse_batch_lines(&self.batch_text)
+            .into_iter()
+            .map(|(url, name)| BatchRow {
+                url,
+                name,
+                analysis: None,
+                variant: 0,
+                variant_chosen: false,
+            })
+            .collect();
+        let urls: Vec<String> = self.batch_rows.iter().map(|row| row.url.clone()).collect();
+
+        let cancel = self.begin_task(&format!(
+            "Analyzing {} playlist(s)...",
+            self.batch_rows.len()
+        ));
+        let batch_sender = self.open_task_channel();
+        self.spawn_task(ctx.clone(), batch_sender.clone(), async move {
+            run_batch_analyze_job(urls, headers, cancel, batch_sender, ctx).await
+        });
+    }
+
+    /// Queues a URL submitted through the local HTTP API with the current output settings and
+    /// starts the queue.
+    pub(crate) fn enqueue_api_job(&mut self, url: String, name: String) {
+        let mut config = match self.current_config() {
+            Ok(config) => config,
+            Err(e) => {
+                self.job
+                    .logs
+                    .push(format!("⚠️ API job {} not queued: {}", url, e));
+                return;
+            }
+        };
+        config.playlist_url = url;
+        config.playlist_text = None;
+        config.subtitle_url = None;
+        config.excluded_segments.clear();
+        config.output_filename = if name.is_empty() {
+            API_JOB_NAME_TEMPLATE.to_string()
+        } else {
+            name
+        };
+        self.job.logs.push(format!(
+            "-> Queued from the HTTP API: {}",
+            config.playlist_url
+        ));
+        self.queue.push(config, JobNotes::default());
+        self.save_queue();
+        self.queue_active = true;
+    }
+
+    /// Removes the queue job at `position` (as listed at `/status`) for the HTTP API; a running
+    /// job has to be cancelled instead.
+    pub(crate) fn remove_api_job(&mut self, position: usize) {
+        match self.queue.jobs.get(position) {
+            Some(job) if job.state == QueueState::Running => self.job.logs.push(format!(
+                "⚠️ Warning: The HTTP API cannot remove queue job {}, it is running.",
+                position
+            )),
+            Some(_) => {
+                let job = self.queue.jobs.remove(position);
+                self.job.logs.push(format!(
+                    "-> Removed from the queue through the HTTP API: {}",
+                    job.config.playlist_url
+                ));
+                self.save_queue();
+            }
+            None => self.job.logs.push(format!(
+                "⚠️ Warning: The HTTP API asked to remove queue job {}, which does not exist.",
+                position
+            )),
+        }
+    }
+
+    /// Queues every analyzed batch row with its chosen variant.
+    pub(crate) fn enqueue_batch(&mut self) {
+        let base = match self.current_config() {
+            Ok(config) => config,
+            Err(e) => {
+                self.job.logs.push(format!("⚠️ {}", e));
+                return;
+            }
+        };
+        let base_name = if base.output_filename.is_empty() {
+            "output".to_string()
+        } else {
+            base.output_filename.clone()
+        };
+        let notes = self.current_notes();
+        let mut added = 0;
+        for (i, row) in self.batch_rows.drain(..).enumerate() {
+            let Some(Ok(entry)) = row.analysis else {
+                continue;
+            };
+            let mut config = base.clone();
+            config.playlist_url = row.url;
+            config.playlist_text = None;
+            config.subtitle_url = None;
+            config.output_filename = if row.name.is_empty() {
+                format!("{}_{:02}", base_name, i + 1)
+            } else {
+                row.name
+            };
+            if let Some(master) = &entry.master
+                && let Some(variant) = master.variants.get(row.variant)
+            {
+                config.playlist_url = variant.uri.to_string();
+                let audio = master.default_audio(variant);
+                config.audio_url = audio
+                    .and_then(|audio| audio.uri.as_ref())
+                    .map(|uri| uri.to_string());
+                config.audio_track = audio.map(|audio| audio.track_metadata());
+            }
+            self.queue.push(config, notes.clone());
+            added += 1;
+        }
+        self.batch_text.clear();
+        self.job.logs.push(format!(
+            "-> Queued {} job(s) from the batch ({} pending).",
+            added,
+            self.queue.pending_count()
+        ));
+        self.save_queue();
+    }
+
+    /// Starts pending queue jobs while no other task runs, up to the parallel job limit.
+    pub(crate) fn run_queue(&mut self, ctx: &egui::Context) {
+        if !self.queue_active {
+            return;
+        }
+        // Further jobs run in parallel to a queue job, never next to another task
+        let running = usize::from(self.queue_job.is_some()) + self.job.parallel_queue_jobs();
+        if (self.job.running && self.queue_job.is_none())
+            || running >= self.settings.queue_parallel_jobs.max(1)
+        {
+            return;
+        }
+        match self.queue.start_next() {
+            Some((id, mut config, notes)) => {
+                self.save_queue();
+                // The next job may start downloading while this one remuxes
+                config.background_remux = true;
+                if self.job.running {
+                    self.launch_parallel_download(ctx.clone(), Some(id), config, notes);
+                } else {
+                    self.queue_job = Some(id);
+                    self.launch_download(ctx.clone(), config, notes);
+                }
+            }
+            // The queue is only done once the last downloads and remuxes are
+            None if running > 0 || !self.job.background.is_empty() => {}
+            None => {
+                self.queue_active = false;
+                self.job.logs.push("✅ Queue finished.".to_string());
+                self.run_finish_action(ctx);
+            }
+        }
+    }
+
+    /// Executes the configured "when all jobs finish" action.
+    pub(crate) fn run_finish_action(&mut self, ctx: &egui::Context) {
+        match self.settings.queue_finish_action {
+            FinishAction::Nothing => {}
+            FinishAction::Notify => {
+                ctx.send_viewport_cmd(egui::ViewportCommand::RequestUserAttention(
+                    egui::UserAttentionType::Critical,
+                ));
+            }
+            FinishAction::Quit => {
+                self.job
+                    .logs
+                    .push("-> Quitting after the queue.".to_string());
+                ctx.send_viewport_cmd(egui::ViewportCommand::Close);
+            }
+            FinishAction::Suspend => {
+                self.job
+                    .logs
+                    .push("-> Suspending the system...".to_string());
+                if let Err(e) = power::suspend_system() {
+                    self.job.logs.push(format!("❌ Failed to suspend: {}", e));
+                }
+            }
+        }
+    }
+
+    pub(crate) fn save_queue(&mut self) {
+        if let Err(e) = self.queue.save() {
+            self.job
+                .logs
+                .push(format!("⚠️ Warning: Failed to save queue: {}", e));
+        }
+    }
+
+    /// Queue list with pause/resume/remove per job, plus the queue start/stop toggle.
+    pub(crate) fn show_queue(&mut self, ui: &mut egui::Ui) {
+        if self.queue.jobs.is_empty() {
+            ui.label("No queued downloads. Use \"Add to queue\" to collect jobs.");
+            return;
+        }
+        let mut remove = None;
+        let mut edit_notes = None;
+        let mut moved = None;
+        let mut changed = false;
+        let last = self.queue.jobs.len() - 1;
+        egui::Grid::new("queue_jobs")
+            .num_columns(4)
+            .striped(true)
+            .show(ui, |ui| {
+                for (i, job) in self.queue.jobs.iter_mut().enumerate() {
+                    ui.horizontal(|ui| {
+                        if ui
+                            .add_enabled(i > 0, egui::Button::new("⬆").small())
+                            .on_hover_text("Run earlier")
+                            .clicked()
+                        {
+                            moved = Some((i, -1));
+                        }
+                        if ui
+                            .add_enabled(i < last, egui::Button::new("⬇").small())
+                            .on_hover_text("Run later")
+                            .clicked()
+                        {
+                            moved = Some((i, 1));
+                        }
+                    });
+                    let name = if job.config.output_filename.is_empty() {
+                        job.config.playlist_url.as_str()
+                    } else {
+                        job.config.output_filename.as_str()
+                    };
+                    ui.horizontal(|ui| {
+                        ui.label(name).on_hover_text(&job.config.playlist_url);
+                        if !job.notes.is_empty() {
+                            ui.label(egui::RichText::new(job.notes.summary()).weak());
+                        }
+                    });
+                    match job.state {
+                        QueueState::Pending => {
+                            ui.label("Pending");
+                        }
+                        QueueState::Running => {
+                            // The window's own task, or one of the parallel downloads
+                            let progress = if self.queue_job == Some(job.id) {
+                                Some(self.job.progress)
+                            } else {
+                                self.job
+                                    .parallel
+                                    .values()
+                                    .find(|task| task.is_running() && task.queue_id == Some(job.id))
+                                    .map(|task| task.progress)
+                            };
+                            match progress {
+                                Some(progress) => ui.add(
+                                    egui::ProgressBar::new(progress)
+                                        .desired_width(120.0)
+                                        .show_percentage(),
+                                ),
+                                None => ui.label("Running"),
+                            };
+                        }
+                        QueueState::Paused => {
+                            ui.label("Paused");
+                        }
+                    }
+                    ui.horizontal(|ui| match job.state {
+                        QueueState::Running => {
+                            if ui
+                                .small_button("📝")
+                                .on_hover_text("Note and tags")
+                                .clicked()
+                            {
+                                edit_notes = Some(i);
+                            }
+                        }
+                        QueueState::Pending => {
+                            if ui.small_button("⏸ Pause").clicked() {
+                                job.state = QueueState::Paused;
+                                changed = true;
+                            }
+                            if ui
+                                .small_button("📝")
+                                .on_hover_text("Note and tags")
+                                .clicked()
+                            {
+                                edit_notes = Some(i);
+                            }
+                            if ui.small_button("🗑 Remove").clicked() {
+                                remove = Some(i);
+                            }
+                        }
+                        QueueState::Paused => {
+                            if ui.small_button("▶ Resume").clicked() {
+                                job.state = QueueState::Pending;
+                                changed = true;
+                            }
+                            if ui
+                                .small_button("📝")
+                                .on_hover_text("Note and tags")
+                                .clicked()
+                            {
+                                edit_notes = Some(i);
+                            }
+                            if ui.small_button("🗑 Remove").clicked() {
+                                remove = Some(i);
+                            }
+                        }
+                    });
+                    ui.end_row();
+                }
+            });
+        if let Some(i) = remove {
+            self.queue.jobs.remove(i);
+            changed = true;
+        }
+        if let Some((i, offset)) = moved {
+            self.queue.move_job(i, offset);
+            changed = true;
+        }
+        if let Some(i) = edit_notes {
+            self.open_notes_editor(NotesTarget::Queue(i), &self.queue.jobs[i].notes.clone());
+        }
+        if changed {
+            self.save_queue();
+        }
+
+        if !self.job.background.is_empty() {
+            ui.label("Remuxing in the background:");
+            for remux in &mut self.job.background {
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(!remux.cancel.is_cancelled(), egui::Button::new("✖ Cancel"))
+                        .on_hover_text("Stop this remux; its partial output is removed")
+                        .clicked()
+                    {
+                        remux.cancel();
+                    }
+                    ui.add(
+                        egui::ProgressBar::new(remux.progress)
+                            .text(remux.output_path.display().to_string()),
+                    );
+                });
+            }
+        }
+        ui.horizontal(|ui| {
+            ui.label("Parallel downloads:");
+            if ui
+                .add(
+                    egui::DragValue::new(&mut self.settings.queue_parallel_jobs).clamp_range(1..=8),
+                )
+                .on_hover_text(
+                    "Queued jobs that download at the same time; 1 runs them one after another",
+                )
+                .changed()
+            {
+                self.save_settings();
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Parallel remuxes:");
+            let response = ui
+                .add(egui::DragValue::new(&mut self.settings.remux_workers).clamp_range(1..=16))
+                .on_hover_text(
+                    "FFmpeg remuxes of finished jobs that run at once while the next job downloads",
+                );
+            if response.changed() {
+                remux_pool::set_workers(self.settings.remux_workers);
+                self.save_settings();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.label("When all jobs finish:");
+            let before = self.settings.queue_finish_action;
+            egui::ComboBox::from_id_source("queue_finish_action")
+                .selected_text(before.label())
+                .show_ui(ui, |ui| {
+                    for action in FinishAction::ALL {
+                        ui.selectable_value(
+                            &mut self.settings.queue_finish_action,
+                            action,
+                            action.label(),
+                        );
+                    }
+                });
+            if self.settings.queue_finish_action != before {
+                self.save_settings();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            if self.queue_active {
+                if ui
+                    .button("⏹ Stop after current job")
+                    .on_hover_text("Finish the running download, then stop starting new ones")
+                    .clicked()
+                {
+                    self.queue_active = false;
+                }
+            } else if ui
+                .add_enabled(
+                    self.queue.pending_count() > 0,
+                    egui::Button::new("▶ Run queue"),
+                )
+                .clicked()
+            {
+                self.queue_active = true;
+            }
+        });
+    }
+}
+
+/// One URL of the batch import window
+pub(crate) struct BatchRow {
+    pub(crate) url: String,
+    /// Output name from the list line; empty for a numbered default
+    pub(crate) name: String,
+    /// None while the analysis is running
+    pub(crate) analysis: Option<Result<BatchEntry, String>>,
+    /// Chosen variant (index into the master playlist's variants)
+    pub(crate) variant: usize,
+    /// Picked by hand, so the batch quality profile leaves it alone
+    pub(crate) variant_chosen: bool,
+}
+
+/// `(url, output name)` of every line of a batch list: the URL is the first word, the rest of
+/// the line an optional name. Blank lines and `#` comments are skipped.
+fn parse_batch_lines(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let (url, name) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            // Shown normalized in the table, so a completed scheme is visible before queueing
+            let url = normalize_url(url).unwrap_or_else(|_| url.to_string());
+            (url, name.trim().to_string())
+        })
+        .collect()
+}
+
+/// Variant of a freshly analyzed batch row: picked by the selected quality profile, else the best.
+pub(crate) fn batch_default_variant(settings: &AppSettings, entry: &BatchEntry) -> usize {
+    match settings.batch_quality_ladder() {
+        Some(ladder) => entry.ladder_variant(ladder),
+        None => entry.best_variant(),
+    }
+}
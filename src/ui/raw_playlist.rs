@@ -0,0 +1,120 @@
+// "Raw playlist" view: the M3U8 text fetched by Analyze, exactly as the server returned it,
+// with tags, attributes and URIs highlighted.
+
+use egui::text::LayoutJob;
+use egui::{Color32, FontId, TextFormat};
+
+use crate::HlsDownloaderApp;
+
+const TAG_COLOR: Color32 = Color32::from_rgb(110, 170, 255);
+const ATTRIBUTE_COLOR: Color32 = Color32::from_rgb(200, 160, 255);
+const STRING_COLOR: Color32 = Color32::from_rgb(230, 180, 100);
+const URI_COLOR: Color32 = Color32::from_rgb(120, 210, 140);
+
+/// One fetched playlist, split into lines for drawing only the visible rows
+pub(crate) struct RawPlaylist {
+    pub(crate) url: String,
+    pub(crate) lines: Vec<String>,
+}
+
+impl RawPlaylist {
+    pub(crate) fn new(url: String, text: &str) -> Self {
+        Self {
+            url,
+            lines: text.lines().map(str::to_string).collect(),
+        }
+    }
+}
+
+impl HlsDownloaderApp {
+    /// Playlists fetched for the entered URL, if it was analyzed.
+    fn analyzed_raw_playlists(&self) -> Option<&[RawPlaylist]> {
+        self.raw_playlists
+            .as_ref()
+            .filter(|(url, _)| *url == self.m3u8_url.trim())
+            .map(|(_, playlists)| playlists.as_slice())
+    }
+
+    /// Collapsible "Raw playlist" section, once Analyze fetched the playlist.
+    pub(crate) fn show_raw_playlist_panel(&mut self, ui: &mut egui::Ui) {
+        let Some(playlists) = self.analyzed_raw_playlists() else {
+            return;
+        };
+        let font = egui::TextStyle::Monospace.resolve(ui.style());
+        let default_color = ui.visuals().text_color();
+        ui.add_space(5.0);
+        egui::CollapsingHeader::new("Raw playlist")
+            .id_source("raw_playlist")
+            .show(ui, |ui| {
+                for (n, playlist) in playlists.iter().enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label(egui::RichText::new(&playlist.url).weak());
+                        if ui.small_button("📋 Copy").clicked() {
+                            ui.output_mut(|o| o.copied_text = playlist.lines.join("\n"));
+                        }
+                    });
+                    let row_height = ui.fonts(|f| f.row_height(&font));
+                    egui::ScrollArea::both()
+                        .id_source(("raw_playlist_lines", n))
+                        .max_height(250.0)
+                        .auto_shrink([false, true])
+                        .show_rows(ui, row_height, playlist.lines.len(), |ui, visible| {
+                            for line in &playlist.lines[visible] {
+                                ui.label(highlight_line(line, &font, default_color));
+                            }
+                        });
+                    ui.add_space(5.0);
+                }
+            });
+    }
+}
+
+/// Colors one playlist line: `#EXT` tags with their attribute names and quoted values, URIs,
+/// and other `#` lines as comments.
+fn highlight_line(line: &str, font: &FontId, default_color: Color32) -> LayoutJob {
+    let mut job = LayoutJob::default();
+    let mut push = |text: &str, color: Color32| {
+        job.append(text, 0.0, TextFormat::simple(font.clone(), color));
+    };
+    if !line.starts_with('#') {
+        push(line, URI_COLOR);
+        return job;
+    }
+    if !line.starts_with("#EXT") {
+        push(line, Color32::GRAY);
+        return job;
+    }
+    let Some((tag, attributes)) = line.split_once(':') else {
+        push(line, TAG_COLOR);
+        return job;
+    };
+    push(tag, TAG_COLOR);
+    push(":", default_color);
+
+    // NAME=value pairs; commas inside quotes belong to the value
+    let mut rest = attributes;
+    while !rest.is_empty() {
+        if let Some(quoted) = rest.strip_prefix('"') {
+            let end = quoted.find('"').map_or(rest.len(), |i| i + 2);
+            push(&rest[..end], STRING_COLOR);
+            rest = &rest[end..];
+        } else if let Some(end) = rest.find(['=', ',', '"']) {
+            let (color, end) = match rest.as_bytes()[end] {
+                b'=' => (ATTRIBUTE_COLOR, end),
+                b',' => (default_color, end + 1),
+                _ => (default_color, end),
+            };
+            push(&rest[..end], color);
+            if color == ATTRIBUTE_COLOR {
+                push("=", default_color);
+                rest = &rest[end + 1..];
+            } else {
+                rest = &rest[end..];
+            }
+        } else {
+            push(rest, default_color);
+            rest = "";
+        }
+    }
+    job
+}
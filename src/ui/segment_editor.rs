@@ -0,0 +1,133 @@
+// "Segments" window: the segments found by Analyze, with per-segment and range exclusion.
+
+use crate::HlsDownloaderApp;
+use crate::downloader::probe::SegmentEntry;
+
+impl HlsDownloaderApp {
+    /// Segment list of the playlist currently entered, if it was analyzed.
+    pub(crate) fn analyzed_segments(&self) -> Option<&[SegmentRow]> {
+        self.segment_rows
+            .as_ref()
+            .filter(|(url, _)| *url == self.m3u8_url.trim())
+            .map(|(_, rows)| rows.as_slice())
+    }
+
+    /// Window listing the analyzed segments; unchecked ones are left out of the download.
+    pub(crate) fn show_segment_editor_window(&mut self, ctx: &egui::Context) {
+        let url = self.m3u8_url.trim().to_string();
+        let Some((_, rows)) = self.segment_rows.as_mut().filter(|(u, _)| *u == url) else {
+            return;
+        };
+        let mut open = self.segment_editor_open;
+        let range = &mut self.segment_range;
+        egui::Window::new("Segments")
+            .open(&mut open)
+            .default_width(560.0)
+            .show(ctx, |ui| {
+                let kept = rows.iter().filter(|row| !row.excluded);
+                let kept_duration: f64 = kept.clone().map(|row| row.entry.duration).sum();
+                ui.label(format!(
+                    "{} of {} segments ({}) will be downloaded.",
+                    kept.count(),
+                    rows.len(),
+                    format_duration(kept_duration)
+                ));
+                ui.horizontal(|ui| {
+                    if ui.button("Include all").clicked() {
+                        rows.iter_mut().for_each(|row| row.excluded = false);
+                    }
+                    if let Some(first) = rows.iter().skip(1).position(|row| row.entry.discontinuity)
+                        && ui
+                            .button("Exclude pre-roll")
+                            .on_hover_text("Exclude everything before the first discontinuity")
+                            .clicked()
+                    {
+                        rows.iter_mut()
+                            .take(first + 1)
+                            .for_each(|row| row.excluded = true);
+                    }
+                });
+                ui.horizontal(|ui| {
+                    let first = rows.first().map_or(0, |row| row.entry.index);
+                    let last = rows.last().map_or(0, |row| row.entry.index);
+                    ui.label("Range");
+                    ui.add(egui::DragValue::new(&mut range.0).clamp_range(first..=last));
+                    ui.label("to");
+                    ui.add(egui::DragValue::new(&mut range.1).clamp_range(first..=last));
+                    for (label, excluded) in [("Exclude", true), ("Include", false)] {
+                        if ui.button(label).clicked() {
+                            let selected = range.0.min(range.1)..=range.0.max(range.1);
+                            rows.iter_mut()
+                                .filter(|row| selected.contains(&row.entry.index))
+                                .for_each(|row| row.excluded = excluded);
+                        }
+                    }
+                });
+                ui.separator();
+
+                let mut start_times = Vec::with_capacity(rows.len());
+                rows.iter().fold(0.0, |start, row| {
+                    start_times.push(start);
+                    start + row.entry.duration
+                });
+                let row_height = ui.spacing().interact_size.y;
+                egui::ScrollArea::vertical()
+                    .id_source("segment_rows")
+                    .max_height(360.0)
+                    .show_rows(ui, row_height, rows.len(), |ui, visible| {
+                        for i in visible {
+                            let row = &mut rows[i];
+                            ui.horizontal(|ui| {
+                                let mut included = !row.excluded;
+                                if ui
+                                    .checkbox(&mut included, format!("#{}", row.entry.index))
+                                    .changed()
+                                {
+                                    row.excluded = !included;
+                                }
+                                ui.monospace(format!(
+                                    "{:>8} {:>6.2}s",
+                                    format_duration(start_times[i]),
+                                    row.entry.duration
+                                ));
+                                if row.entry.discontinuity {
+                                    ui.colored_label(egui::Color32::YELLOW, "DISC")
+                                        .on_hover_text("#EXT-X-DISCONTINUITY before this segment");
+                                }
+                                if row.entry.gap {
+                                    ui.colored_label(egui::Color32::GRAY, "GAP")
+                                        .on_hover_text("#EXT-X-GAP: no media, always skipped");
+                                }
+                                if row.entry.ad {
+                                    ui.colored_label(egui::Color32::LIGHT_RED, "AD")
+                                        .on_hover_text(
+                                            "Inside an ad break (SCTE-35 / CUE-OUT); left out \
+                                             with Skip ad breaks",
+                                        );
+                                }
+                                ui.label(&row.entry.uri).on_hover_text(&row.entry.uri);
+                            });
+                        }
+                    });
+            });
+        self.segment_editor_open = open;
+    }
+}
+
+/// One row of the "Segments" window
+pub(crate) struct SegmentRow {
+    pub(crate) entry: SegmentEntry,
+    /// Left out of the download
+    pub(crate) excluded: bool,
+}
+
+/// `h:mm:ss` (or `m:ss` below an hour)
+pub(crate) fn format_duration(secs: f64) -> String {
+    let total = secs.round() as u64;
+    let (hours, minutes, seconds) = (total / 3600, total / 60 % 60, total % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
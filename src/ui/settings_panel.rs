@@ -0,0 +1,404 @@
+// Settings panel: persisted preferences (output sub-folders, power, API, runtime, retention,
+// quality profiles) and their export/import. Every change is saved immediately.
+
+use std::path::Path;
+
+use crate::api::{ApiScope, ApiToken};
+use crate::downloader::remux_pool;
+use crate::downloader::stats::format_bytes;
+use crate::{HlsDownloaderApp, retention, runtime};
+
+impl HlsDownloaderApp {
+    /// API tokens with their scopes; sent as `Authorization: Bearer <token>`.
+    fn show_api_tokens(&mut self, ui: &mut egui::Ui) {
+        let mut changed = false;
+        let mut remove = None;
+        egui::Grid::new("api_tokens").striped(true).show(ui, |ui| {
+            for (i, token) in self.settings.api_tokens.iter_mut().enumerate() {
+                changed |= ui
+                    .add(egui::TextEdit::singleline(&mut token.name).desired_width(100.0))
+                    .lost_focus();
+                let before = token.scope;
+                egui::ComboBox::from_id_source(("api_token_scope", i))
+                    .selected_text(before.label())
+                    .show_ui(ui, |ui| {
+                        for scope in ApiScope::ALL {
+                            ui.selectable_value(&mut token.scope, scope, scope.label());
+                        }
+                    });
+                changed |= token.scope != before;
+                ui.monospace(&token.token);
+                if ui.small_button("📋").on_hover_text("Copy token").clicked() {
+                    ui.output_mut(|o| o.copied_text = token.token.clone());
+                }
+                if ui.small_button("🗑").on_hover_text("Revoke token").clicked() {
+                    remove = Some(i);
+                }
+                ui.end_row();
+            }
+        });
+        if ui
+            .button("➕ New API token")
+            .on_hover_text(
+                "Read only: /metrics and /status; Submit only: job submission; Admin: every \
+                 endpoint, including cancelling downloads and removing queue jobs",
+            )
+            .clicked()
+        {
+            let name = format!("token {}", self.settings.api_tokens.len() + 1);
+            match ApiToken::generate(&name, ApiScope::Read) {
+                Ok(token) => {
+                    self.settings.api_tokens.push(token);
+                    changed = true;
+                }
+                Err(e) => self
+                    .job
+                    .logs
+                    .push(format!("⚠️ Warning: Failed to create an API token: {}", e)),
+            }
+        }
+        if let Some(i) = remove {
+            self.settings.api_tokens.remove(i);
+            changed = true;
+        }
+        if changed {
+            self.update_api_server();
+            self.save_settings();
+        }
+    }
+
+    /// Collapsible "Settings" section.
+    pub(crate) fn show_settings_panel(&mut self, ui: &mut egui::Ui) {
+        ui.add_space(5.0);
+        egui::CollapsingHeader::new("Settings").show(ui, |ui| {
+            ui.horizontal(|ui| {
+                ui.label("Sub-folder per job:");
+                if ui
+                    .add(
+                        egui::TextEdit::singleline(&mut self.settings.output_subfolder)
+                            .hint_text("e.g. {host}/{yyyy-mm}")
+                            .desired_width(200.0),
+                    )
+                    .on_hover_text(
+                        "Created inside the output location on demand; {host}, {date}, \
+                         {yyyy-mm}, {yyyy}, {mm} and {name} are filled in per job",
+                    )
+                    .changed()
+                {
+                    self.save_settings();
+                }
+            });
+
+            if ui
+                .checkbox(
+                    &mut self.settings.prevent_sleep,
+                    "Prevent system sleep while downloading",
+                )
+                .changed()
+            {
+                self.update_sleep_inhibitor();
+                self.save_settings();
+            }
+
+            if ui
+                .checkbox(
+                    &mut self.settings.background_priority,
+                    "Background priority while downloading",
+                )
+                .on_hover_text(
+                    "Lower CPU and disk priority so large downloads don't slow down the \
+                     desktop (on Linux the priority can only be restored by restarting)",
+                )
+                .changed()
+            {
+                self.update_background_priority();
+                self.save_settings();
+            }
+
+            ui.horizontal(|ui| {
+                let toggled = ui
+                    .checkbox(
+                        &mut self.settings.api_enabled,
+                        "Enable local HTTP API on port",
+                    )
+                    .on_hover_text("Serves a submit page for phones, /status and /metrics (Prometheus format) on the addresses below")
+                    .changed();
+                let port_changed = ui
+                    .add(
+                        egui::DragValue::new(&mut self.settings.api_port).clamp_range(1024..=65535),
+                    )
+                    .changed();
+                if toggled || port_changed {
+                    self.update_api_server();
+                    self.save_settings();
+                }
+                if let Some(url) = self
+                    .api_server
+                    .as_ref()
+                    .and_then(|s| s.config().urls().first().cloned())
+                {
+                    ui.hyperlink_to("submit page", format!("{}/", url));
+                    ui.hyperlink_to("metrics", format!("{}/metrics", url));
+                }
+            });
+            ui.horizontal(|ui| {
+                ui.label("API bind addresses:");
+                if ui
+                    .add(
+                        egui::TextEdit::singleline(&mut self.settings.api_bind_addresses)
+                            .hint_text("127.0.0.1")
+                            .desired_width(200.0),
+                    )
+                    .on_hover_text(
+                        "Comma-separated local addresses to listen on, e.g. a LAN address for \
+                         a phone; other clients than this computer need a token",
+                    )
+                    .lost_focus()
+                {
+                    self.update_api_server();
+                    self.save_settings();
+                }
+            });
+            self.show_api_tokens(ui);
+
+            ui.horizontal(|ui| {
+                let tuning = &mut self.settings.runtime;
+                ui.label("Runtime threads:");
+                let mut changed = ui
+                    .add(
+                        egui::DragValue::new(&mut tuning.worker_threads)
+                            .clamp_range(0..=runtime::MAX_WORKER_THREADS)
+                            .custom_formatter(|n, _| {
+                                if n == 0.0 {
+                                    "auto".to_string()
+                                } else {
+                                    format!("{} workers", n)
+                                }
+                            }),
+                    )
+                    .on_hover_text("Async worker threads; auto = one per CPU core")
+                    .changed();
+                changed |= ui
+                    .add(
+                        egui::DragValue::new(&mut tuning.max_blocking_threads)
+                            .clamp_range(0..=runtime::MAX_BLOCKING_THREADS)
+                            .custom_formatter(|n, _| {
+                                if n == 0.0 {
+                                    "default".to_string()
+                                } else {
+                                    format!("{} blocking", n)
+                                }
+                            }),
+                    )
+                    .on_hover_text(
+                        "Upper bound of the pool for decryption, file writes and FFmpeg; \
+                         default = 512",
+                    )
+                    .changed();
+                ui.label("named");
+                changed |= ui
+                    .add(egui::TextEdit::singleline(&mut tuning.thread_name).desired_width(100.0))
+                    .changed();
+                if changed {
+                    self.save_settings();
+                }
+                ui.label(egui::RichText::new("(applies after a restart)").weak());
+            });
+
+            ui.horizontal(|ui| {
+                let mut changed = ui
+                    .checkbox(&mut self.settings.low_power_mode, "Low-power mode")
+                    .on_hover_text(
+                        "Redraw at a fixed interval while a task runs and not at all when idle",
+                    )
+                    .changed();
+                ui.add_enabled_ui(self.settings.low_power_mode, |ui| {
+                    ui.label("Refresh every");
+                    changed |= ui
+                        .add(
+                            egui::DragValue::new(&mut self.settings.low_power_refresh_ms)
+                                .clamp_range(100..=5000)
+                                .speed(10)
+                                .suffix(" ms"),
+                        )
+                        .changed();
+                });
+                if changed {
+                    self.save_settings();
+                }
+            });
+
+            ui.separator();
+            let retention = &mut self.settings.retention;
+            let mut changed = false;
+            ui.horizontal(|ui| {
+                ui.label("Delete old data after");
+                changed |= ui
+                    .add(
+                        egui::DragValue::new(&mut retention.max_age_days)
+                            .clamp_range(0..=3650)
+                            .suffix(" days"),
+                    )
+                    .on_hover_text("Checked at startup and after each task; 0 keeps everything")
+                    .changed();
+            });
+            ui.add_enabled_ui(retention.max_age_days > 0, |ui| {
+                ui.horizontal(|ui| {
+                    changed |= ui
+                        .checkbox(&mut retention.history, "History")
+                        .on_hover_text("Entries of downloads that finished before then")
+                        .changed();
+                    changed |= ui
+                        .checkbox(&mut retention.watch_later, "Watch later")
+                        .changed();
+                    changed |= ui
+                        .checkbox(&mut retention.temp_dirs, "Unfinished segment folders")
+                        .changed();
+                    changed |= ui
+                        .checkbox(&mut retention.workspaces, "Kept workspaces")
+                        .on_hover_text("*.hlsdl folders below the output location")
+                        .changed();
+                    changed |= ui
+                        .checkbox(&mut retention.ffmpeg_builds, "Old FFmpeg builds")
+                        .changed();
+                });
+            });
+            if changed {
+                self.save_settings();
+            }
+            ui.horizontal(|ui| {
+                if ui
+                    .add_enabled(!self.job.running, egui::Button::new("🧹 Clear caches"))
+                    .on_hover_text(
+                        "Delete segment folders left by interrupted jobs and FFmpeg \
+                         extractions of other versions",
+                    )
+                    .clicked()
+                {
+                    self.cache_cleanup = Some(retention::clear_caches());
+                }
+                if let Some(report) = &self.cache_cleanup {
+                    ui.label(format!(
+                        "Reclaimed {} ({} folder(s))",
+                        format_bytes(report.bytes as f64),
+                        report.removed
+                    ));
+                }
+            });
+
+            ui.separator();
+            ui.label("Quality profiles for batch imports (rungs tried in order):");
+            let mut removed = None;
+            for (name, ladder) in &self.settings.quality_profiles {
+                ui.horizontal(|ui| {
+                    ui.label(format!("{}: {}", name, ladder.join(", ")));
+                    if ui
+                        .small_button("🗑")
+                        .on_hover_text("Delete profile")
+                        .clicked()
+                    {
+                        removed = Some(name.clone());
+                    }
+                });
+            }
+            if let Some(name) = removed {
+                self.settings.quality_profiles.remove(&name);
+                self.save_settings();
+            }
+            ui.horizontal(|ui| {
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.quality_profile_name)
+                        .hint_text("Name")
+                        .desired_width(100.0),
+                );
+                ui.add(
+                    egui::TextEdit::singleline(&mut self.quality_profile_ladder)
+                        .hint_text("1080p, 720p, best"),
+                );
+                let ladder: Vec<String> = self
+                    .quality_profile_ladder
+                    .split(',')
+                    .map(|rung| rung.trim().to_string())
+                    .filter(|rung| !rung.is_empty())
+                    .collect();
+                let name = self.quality_profile_name.trim().to_string();
+                if ui
+                    .add_enabled(
+                        !name.is_empty() && !ladder.is_empty(),
+                        egui::Button::new("Save profile"),
+                    )
+                    .clicked()
+                {
+                    self.settings.quality_profiles.insert(name, ladder);
+                    self.quality_profile_name.clear();
+                    self.quality_profile_ladder.clear();
+                    self.save_settings();
+                }
+            });
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                let mut export = None;
+                if ui
+                    .button("📤 Export settings...")
+                    .on_hover_text("Without API tokens, cookies and authorization headers")
+                    .clicked()
+                {
+                    export = Some(false);
+                }
+                if ui
+                    .button("Export with secrets...")
+                    .on_hover_text("Includes API tokens and credential headers of host profiles")
+                    .clicked()
+                {
+                    export = Some(true);
+                }
+                if let Some(include_secrets) = export
+                    && let Some(path) = rfd::FileDialog::new()
+                        .set_file_name("hls-downloader-settings.json")
+                        .add_filter("JSON", &["json"])
+                        .save_file()
+                {
+                    match self.settings.export_to(&path, include_secrets) {
+                        Ok(()) => self
+                            .job
+                            .logs
+                            .push(format!("✅ Settings exported to {}", path.display())),
+                        Err(e) => self
+                            .job
+                            .logs
+                            .push(format!("❌ Failed to export settings: {}", e)),
+                    }
+                }
+                if ui.button("📥 Import settings...").clicked()
+                    && let Some(path) = rfd::FileDialog::new()
+                        .add_filter("JSON", &["json"])
+                        .pick_file()
+                {
+                    self.import_settings(&path);
+                }
+            });
+        });
+    }
+
+    /// Replaces the settings with an exported file and applies the ones that act immediately.
+    fn import_settings(&mut self, path: &Path) {
+        match self.settings.import_from(path) {
+            Ok(settings) => {
+                self.settings = settings;
+                self.save_settings();
+                remux_pool::set_workers(self.settings.remux_workers);
+                self.update_sleep_inhibitor();
+                self.update_api_server();
+                self.job.logs.push(format!(
+                    "✅ Settings imported from {} (API enabling and bind addresses kept; runtime threads apply after a restart)",
+                    path.display()
+                ));
+            }
+            Err(e) => self
+                .job
+                .logs
+                .push(format!("❌ Failed to import settings: {}", e)),
+        }
+    }
+}
@@ -0,0 +1,118 @@
+// Tools panel: remux, verify and repair of files that were already downloaded.
+
+use crate::HlsDownloaderApp;
+use crate::downloader::DownloadMessage;
+
+impl HlsDownloaderApp {
+    /// Collapsible "Tools" section; locked while a task runs.
+    pub(crate) fn show_tools_panel(&mut self, ui: &mut egui::Ui, ctx: &egui::Context) {
+        egui::CollapsingHeader::new("Tools").show(ui, |ui| {
+            ui.add_enabled_ui(!self.job.running, |ui| {
+                ui.label("Remux existing file:");
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.remux_input)
+                            .hint_text("Path to .ts / .mkv / .mp4"),
+                    );
+                    if ui.button("Browse...").clicked() {
+                        let sender_clone = self.sender.clone();
+                        self.runtime.handle().clone().spawn_blocking(move || {
+                            if let Some(path) = rfd::FileDialog::new()
+                                .add_filter("Video", &["ts", "mkv", "mp4", "webm"])
+                                .pick_file()
+                            {
+                                let full_path = path.to_string_lossy().into_owned();
+                                let _ = sender_clone
+                                    .blocking_send(DownloadMessage::RemuxInputSelected(full_path));
+                            }
+                        });
+                    }
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Container:");
+                    egui::ComboBox::from_id_source("remux_format")
+                        .selected_text(&self.remux_format)
+                        .width(70.0)
+                        .show_ui(ui, |ui| {
+                            for format in ["mp4", "mkv", "webm", "ts"] {
+                                ui.selectable_value(
+                                    &mut self.remux_format,
+                                    format.to_string(),
+                                    format,
+                                );
+                            }
+                        });
+                    ui.checkbox(&mut self.remux_fix_timestamps, "Fix timestamps");
+                    if ui
+                        .add_enabled(
+                            !self.remux_input.trim().is_empty(),
+                            egui::Button::new("Run remux"),
+                        )
+                        .clicked()
+                    {
+                        self.start_remux_file_task(ctx.clone());
+                    }
+                });
+
+                ui.separator();
+                ui.label("Verify download against playlist:");
+                ui.add(egui::TextEdit::singleline(&mut self.verify_url).hint_text("Playlist URL"));
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.verify_file)
+                            .hint_text("Downloaded file"),
+                    );
+                    if ui.button("Browse...").clicked() {
+                        let sender_clone = self.sender.clone();
+                        self.runtime.handle().clone().spawn_blocking(move || {
+                            if let Some(path) = rfd::FileDialog::new().pick_file() {
+                                let full_path = path.to_string_lossy().into_owned();
+                                let _ = sender_clone
+                                    .blocking_send(DownloadMessage::VerifyFileSelected(full_path));
+                            }
+                        });
+                    }
+                    if ui
+                        .add_enabled(
+                            !self.verify_url.trim().is_empty()
+                                && !self.verify_file.trim().is_empty(),
+                            egui::Button::new("Verify"),
+                        )
+                        .clicked()
+                    {
+                        self.start_verify_task(ctx.clone());
+                    }
+                });
+
+                ui.separator();
+                ui.label("Repair kept workspace (re-download failed segments only):");
+                ui.horizontal(|ui| {
+                    ui.add(
+                        egui::TextEdit::singleline(&mut self.repair_workspace)
+                            .hint_text("<name>.hlsdl folder"),
+                    );
+                    if ui.button("Browse...").clicked() {
+                        let sender_clone = self.sender.clone();
+                        self.runtime.handle().clone().spawn_blocking(move || {
+                            if let Some(path) = rfd::FileDialog::new().pick_folder() {
+                                let full_path = path.to_string_lossy().into_owned();
+                                let _ = sender_clone.blocking_send(
+                                    DownloadMessage::RepairWorkspaceSelected(full_path),
+                                );
+                            }
+                        });
+                    }
+                    if ui
+                        .add_enabled(
+                            !self.repair_workspace.trim().is_empty(),
+                            egui::Button::new("Repair"),
+                        )
+                        .clicked()
+                    {
+                        self.start_repair_task(ctx.clone());
+                    }
+                });
+            });
+        });
+    }
+}
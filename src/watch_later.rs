@@ -0,0 +1,40 @@
+use anyhow::{Result, anyhow};
+use dirs::config_dir;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+use crate::downloader::capture::CapturedJob;
+
+/// Jobs captured with "Save for later", persisted between runs
+/// (`<config_dir>/hls-downloader/watch_later.json`). Unlike the queue, they only run when the
+/// user starts or queues them.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WatchLater {
+    pub jobs: Vec<CapturedJob>,
+}
+
+fn watch_later_path() -> Result<PathBuf> {
+    let base = config_dir().ok_or_else(|| anyhow!("Could not determine config directory"))?;
+    Ok(base.join("hls-downloader").join("watch_later.json"))
+}
+
+impl WatchLater {
+    pub fn load() -> Self {
+        watch_later_path()
+            .ok()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|text| serde_json::from_str(&text).ok())
+            .unwrap_or_default()
+    }
+
+    /// Writes the list to disk, creating the config directory if needed.
+    pub fn save(&self) -> Result<()> {
+        let path = watch_later_path()?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,2 @@
+( C2(#k~n*Mk;Z܁ݴA\Tdqr)]uHֽ+WJV)TYN^B#_VWЉ7M +ܮP%8X{~[ޥ\G&"=G3/Lb_ێjKBZp;=<=MF`J'S <LaՌLvnO<l	ŸIĮ :̜IFю?يu۔2p.a/fڱ	!U"_5g<&LrPZi
+dw*pARB_զ$,q[Ю]IJ7[N.Vl]*?jK-Mhp^5u
\ No newline at end of file